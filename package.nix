@@ -28,6 +28,7 @@ rustPlatform.buildRustPackage {
   postInstall = ''
     mkdir -p $out/share/trix/nix
     cp src/resources/*.nix $out/share/trix/nix/
+    cp -r src/resources/templates $out/share/trix/nix/
     cp direnvrc $out/share/trix/
   '';
 
@@ -0,0 +1,153 @@
+//! Index of the builds trix has run (`trix builds list`, `trix log --last`).
+//!
+//! Each `trix build` of a local flake appends a record to
+//! `$XDG_STATE_HOME/trix/builds.json`: what was built, when, how long it
+//! took, whether it worked and which derivation it was, so its log can be
+//! found later without the store path. Only the newest records are kept.
+//! Concurrent builds take turns appending through an flock on
+//! `builds.lock` next to the index.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Number of builds remembered.
+const MAX_RECORDS: usize = 200;
+
+/// One build trix ran.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildRecord {
+    /// Attribute path, e.g. "packages.x86_64-linux.default"
+    pub attr: String,
+    pub flake_dir: String,
+    /// Unset when the build failed before a derivation was known
+    pub drv_path: Option<String>,
+    /// Where nix wrote the build log, if it built the derivation here
+    #[serde(default)]
+    pub log_path: Option<String>,
+    /// Unix time the build started
+    pub started: i64,
+    pub seconds: f64,
+    pub ok: bool,
+}
+
+/// Get the path of the build index.
+fn get_index_path() -> Option<PathBuf> {
+    crate::paths::state_dir().map(|d| d.join("builds.json"))
+}
+
+fn load_from(path: &Path) -> Vec<BuildRecord> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Recorded builds, oldest first.
+pub fn load() -> Vec<BuildRecord> {
+    get_index_path()
+        .map(|path| load_from(&path))
+        .unwrap_or_default()
+}
+
+/// Take the exclusive lock on the index at `path`, released when the
+/// returned file is dropped. Without locking support, trix carries on.
+fn lock_index(path: &Path) -> Option<fs::File> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))
+        .ok()?;
+    // SAFETY: flock only operates on the descriptor, which `file` keeps open
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+        Some(file)
+    } else {
+        tracing::debug!(
+            "Could not lock the build index: {}",
+            std::io::Error::last_os_error()
+        );
+        None
+    }
+}
+
+/// Append `record` to the index at `path`, dropping the oldest beyond `max`.
+fn record_to(path: &Path, record: BuildRecord, max: usize) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let _lock = lock_index(path);
+
+    let mut records = load_from(path);
+    records.push(record);
+    let excess = records.len().saturating_sub(max);
+    records.drain(..excess);
+
+    fs::write(path, serde_json::to_string_pretty(&records)?)?;
+    Ok(())
+}
+
+/// Remember a build.
+pub fn record(record: BuildRecord) -> Result<()> {
+    let Some(path) = get_index_path() else {
+        return Ok(());
+    };
+    record_to(&path, record, MAX_RECORDS)
+}
+
+/// The `n`th most recent build (1 is the latest).
+pub fn nth_latest(records: &[BuildRecord], n: usize) -> Option<&BuildRecord> {
+    records.iter().rev().nth(n.checked_sub(1)?)
+}
+
+/// Where nix keeps the log of `drv_path`, if it has one on this machine.
+pub fn log_file(drv_path: &str) -> Option<PathBuf> {
+    let base = Path::new(drv_path).file_name()?.to_str()?;
+    if base.len() < 3 {
+        return None;
+    }
+    let log_dir = std::env::var_os("NIX_LOG_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/nix/var/log/nix"));
+    let dir = log_dir.join("drvs").join(&base[..2]);
+    [format!("{}.bz2", &base[2..]), base[2..].to_string()]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(attr: &str, started: i64) -> BuildRecord {
+        BuildRecord {
+            attr: attr.to_string(),
+            flake_dir: "/src/project".to_string(),
+            drv_path: Some(format!("/nix/store/aaaa-{}.drv", attr)),
+            log_path: None,
+            started,
+            seconds: 1.5,
+            ok: true,
+        }
+    }
+
+    #[test]
+    fn test_record_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state/builds.json");
+        for (i, attr) in ["a", "b", "c"].iter().enumerate() {
+            record_to(&path, build(attr, i as i64), 2).unwrap();
+        }
+        let records = load_from(&path);
+        assert_eq!(records, vec![build("b", 1), build("c", 2)]);
+
+        assert_eq!(nth_latest(&records, 1), Some(&build("c", 2)));
+        assert_eq!(nth_latest(&records, 2), Some(&build("b", 1)));
+        assert_eq!(nth_latest(&records, 3), None);
+        assert_eq!(nth_latest(&records, 0), None);
+    }
+}
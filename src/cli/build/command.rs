@@ -1,8 +1,11 @@
 use super::common::build_resolved_attribute;
-use crate::flake::{resolve_attr_path, resolve_installable};
-use crate::nix::{get_system, BuildOptions};
-use anyhow::Result;
+use crate::flake::{resolve_attr_path, resolve_installable, ResolvedInstallable};
+use crate::nix::{find_kept_build_dir, get_derivation_path, get_system, BuildOptions};
+use anyhow::{Context, Result};
 use clap::Args;
+use std::collections::BTreeMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 
 enum BuildSource {
     File(String),
@@ -38,6 +41,28 @@ pub struct BuildArgs {
     /// Use specified store URL
     #[arg(long)]
     pub store: Option<String>,
+
+    /// Evaluate and build in a throwaway store rooted at DIR (created if
+    /// needed) instead of the system store; delete DIR to discard it
+    #[arg(long, value_name = "DIR", conflicts_with = "store")]
+    pub store_dir: Option<PathBuf>,
+
+    /// Keep the build directory of failed builds for inspection
+    #[arg(short = 'K', long)]
+    pub keep_failed: bool,
+
+    /// Build again and check the result is bit-for-bit identical to the existing outputs
+    #[arg(long, conflicts_with = "nix_file")]
+    pub rebuild: bool,
+
+    /// Print the output paths, one per line, as the only output on stdout
+    #[arg(long, conflicts_with = "rebuild")]
+    pub print_out_paths: bool,
+
+    /// Build nothing; exit 0 if the outputs are already in the store or can
+    /// be fetched from a binary cache, 1 if anything would have to be built
+    #[arg(long, conflicts_with_all = ["rebuild", "print_out_paths", "keep_failed"])]
+    pub check_cache_only: bool,
 }
 
 fn parse_arg_pairs(args: &[String]) -> Vec<(String, String)> {
@@ -53,6 +78,38 @@ fn parse_arg_pairs(args: &[String]) -> Vec<(String, String)> {
 }
 
 pub fn cmd_build(args: BuildArgs) -> Result<()> {
+    if let Some(dir) = args.store_dir.clone() {
+        crate::command::set_store(Some(chroot_store(&dir)?));
+        let result = cmd_build_in_store(args);
+        crate::command::set_store(None);
+        if result.is_ok() {
+            eprintln!(
+                "note: outputs are in the store at {}; their files are under {}",
+                dir.display(),
+                dir.join("nix/store").display()
+            );
+        }
+        return result;
+    }
+    cmd_build_in_store(args)
+}
+
+/// Store URL for a chroot store rooted at `dir`, creating the directory.
+///
+/// Store paths keep their usual /nix/store names; nix maps them into
+/// `dir`, building in a user namespace where needed.
+fn chroot_store(dir: &Path) -> Result<String> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create store directory {}", dir.display()))?;
+    let dir = dir.canonicalize()?;
+    Ok(format!("local?root={}", dir.display()))
+}
+
+fn cmd_build_in_store(args: BuildArgs) -> Result<()> {
+    if args.check_cache_only {
+        return check_cache_only(&args);
+    }
+
     // If -f is specified, bypass flake machinery entirely
     if let Some(ref file) = args.nix_file {
         return cmd_build_legacy(
@@ -66,6 +123,7 @@ pub fn cmd_build(args: BuildArgs) -> Result<()> {
             parse_arg_pairs(&args.extra_args),
             parse_arg_pairs(&args.extra_argstrs),
             args.store.as_deref(),
+            args.keep_failed,
         );
     }
 
@@ -83,10 +141,11 @@ pub fn cmd_build(args: BuildArgs) -> Result<()> {
         // If it looks like a flake, use nix build
         if crate::nix::check_is_flake(std::path::Path::new(flake_ref)) {
             // Passthrough to nix build
-            let full_ref = format!("{}#{}", flake_ref, resolved.attr_part);
+            let full_ref = resolved.full_ref();
 
             let mut cmd = crate::command::NixCommand::new("nix");
             cmd.arg("build").arg(&full_ref);
+            cmd.args(crate::registry::override_flake_args());
 
             if args.no_link {
                 cmd.arg("--no-link");
@@ -98,6 +157,18 @@ pub fn cmd_build(args: BuildArgs) -> Result<()> {
                 cmd.args(["--store", s]);
             }
 
+            if args.keep_failed {
+                cmd.arg("--keep-failed");
+            }
+
+            if args.rebuild {
+                cmd.arg("--rebuild");
+            }
+
+            if args.print_out_paths {
+                cmd.arg("--print-out-paths");
+            }
+
             for (name, expr) in parse_arg_pairs(&args.extra_args) {
                 cmd.args(["--arg", &name, &expr]);
             }
@@ -122,6 +193,7 @@ pub fn cmd_build(args: BuildArgs) -> Result<()> {
                 parse_arg_pairs(&args.extra_args),
                 parse_arg_pairs(&args.extra_argstrs),
                 args.store.as_deref(),
+                args.keep_failed,
             );
         }
     }
@@ -131,6 +203,9 @@ pub fn cmd_build(args: BuildArgs) -> Result<()> {
     // Resolve attribute path
     let attr = resolve_attr_path(&resolved.attr_part, "packages", &system);
 
+    // nix-build links the derivation it builds, so the build index learns
+    // it without evaluating the attribute again
+    let drv_dir = crate::scratch::tempdir().ok();
     let options = BuildOptions {
         out_link: if args.no_link {
             None
@@ -140,13 +215,308 @@ pub fn cmd_build(args: BuildArgs) -> Result<()> {
         extra_args: parse_arg_pairs(&args.extra_args),
         extra_argstrs: parse_arg_pairs(&args.extra_argstrs),
         store: args.store.clone(),
+        keep_failed: args.keep_failed,
+        drv_link: drv_dir.as_ref().map(|dir| dir.path().join("drv")),
     };
 
-    build_resolved_attribute(&resolved, &attr, &options, false)?;
+    // nix-build prints the output paths on stdout and everything else on
+    // stderr; GitHub Actions groups are the only other stdout output
+    let group = !args.print_out_paths;
+    if group {
+        crate::cli::gha::group(&format!("building {}", attr));
+    }
+    let started = chrono::Utc::now().timestamp();
+    let timer = std::time::Instant::now();
+    let result = build_resolved_attribute(&resolved, &attr, &options, false);
+    if group {
+        crate::cli::gha::end_group();
+    }
+    record_build(
+        &resolved,
+        &attr,
+        &options,
+        started,
+        timer.elapsed().as_secs_f64(),
+        result.is_ok(),
+    );
+
+    if let Err(e) = result {
+        if args.keep_failed {
+            report_kept_build_dir(&resolved, &attr);
+        }
+        return Err(e);
+    }
+
+    if args.rebuild {
+        let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+        check_rebuild(&get_derivation_path(flake_dir, &attr)?)?;
+    }
 
     Ok(())
 }
 
+/// Instantiate the derivations `args` would build.
+fn derivations_to_build(args: &BuildArgs) -> Result<Vec<String>> {
+    if let Some(file) = &args.nix_file {
+        let mut cmd = crate::command::NixCommand::new("nix-instantiate");
+        cmd.arg(file);
+        let attr = args.installable.as_str();
+        let attr = attr.strip_prefix(".#").unwrap_or(attr);
+        if !matches!(attr, "" | "." | "default") {
+            cmd.args(["-A", attr]);
+        }
+        for (name, expr) in parse_arg_pairs(&args.extra_args) {
+            cmd.args(["--arg", &name, &expr]);
+        }
+        for (name, value) in parse_arg_pairs(&args.extra_argstrs) {
+            cmd.args(["--argstr", &name, &value]);
+        }
+        return Ok(cmd.output()?.lines().map(str::to_string).collect());
+    }
+
+    let resolved = resolve_installable(&args.installable);
+    if !resolved.is_local {
+        let flake_ref = resolved.flake_ref.as_deref().unwrap_or("");
+        if !crate::nix::check_is_flake(Path::new(flake_ref)) {
+            anyhow::bail!("--check-cache-only does not support non-flake repositories");
+        }
+        let mut cmd = crate::command::NixCommand::new("nix");
+        cmd.args(["eval", "--raw", &format!("{}.drvPath", resolved.full_ref())]);
+        cmd.args(crate::registry::override_flake_args());
+        return Ok(vec![cmd.output()?]);
+    }
+
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+    crate::flake::ensure_lock(flake_dir, None)?;
+    let attr = resolve_attr_path(&resolved.attr_part, "packages", &get_system()?);
+    Ok(get_derivation_path(flake_dir, &attr)?
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Report whether the outputs of `args` would be fetched or built, failing
+/// if anything would have to be built.
+fn check_cache_only(args: &BuildArgs) -> Result<()> {
+    let drvs = derivations_to_build(args)?;
+    let missing = crate::progress::with_status("querying binary caches", || {
+        crate::nix::query_missing(&drvs)
+    })?;
+
+    if missing.will_build.is_empty() {
+        if missing.will_fetch.is_empty() {
+            eprintln!("{} is already in the store", args.installable);
+        } else {
+            eprintln!(
+                "{} is cached: {} path(s) would be fetched",
+                args.installable,
+                missing.will_fetch.len()
+            );
+        }
+        return Ok(());
+    }
+
+    eprintln!("These derivations would be built:");
+    for drv in &missing.will_build {
+        eprintln!("  {}", drv);
+    }
+    anyhow::bail!(
+        "{} is not cached: {} derivation(s) would be built",
+        args.installable,
+        missing.will_build.len()
+    )
+}
+
+/// Rebuild a derivation whose outputs are already in the store and report
+/// any output that comes out different.
+fn check_rebuild(drv_path: &str) -> Result<()> {
+    let mut outputs = crate::command::NixCommand::new("nix-store");
+    outputs.args(["--query", "--outputs", drv_path]);
+    let outputs: Vec<String> = outputs.output()?.lines().map(str::to_string).collect();
+
+    eprintln!("Rebuilding {} to check determinism...", drv_path);
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args([
+        "--realise",
+        drv_path,
+        "--check",
+        "--keep-failed",
+        "--option",
+        "substitute",
+        "false",
+    ]);
+    let Err(err) = cmd.output() else {
+        eprintln!("{} rebuilt identically", outputs.join(", "));
+        return Ok(());
+    };
+
+    // With --keep-failed, nix keeps a differing rebuild next to the output as <out>.check
+    let mut nondeterministic = 0;
+    for out in &outputs {
+        let check = format!("{}.check", out);
+        if !Path::new(&check).exists() {
+            continue;
+        }
+        nondeterministic += 1;
+        eprintln!("{} differs when rebuilt:", out);
+        for file in diff_trees(Path::new(out), Path::new(&check))? {
+            eprintln!("  {}", file);
+        }
+        eprintln!("  (rebuilt output kept at {})", check);
+    }
+
+    if nondeterministic > 0 {
+        anyhow::bail!(
+            "{} of {} output(s) of {} are not deterministic",
+            nondeterministic,
+            outputs.len(),
+            drv_path
+        );
+    }
+    Err(err.context("Rebuild failed"))
+}
+
+/// One filesystem entry, as compared by diff_trees. Files with the same
+/// metadata still need their contents compared.
+#[derive(PartialEq)]
+enum TreeEntry {
+    File { executable: bool, size: u64 },
+    Symlink(PathBuf),
+    Directory,
+}
+
+fn tree_entries(root: &Path) -> Result<BTreeMap<PathBuf, TreeEntry>> {
+    let mut entries = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(root).min_depth(1) {
+        let entry = entry?;
+        let rel = entry.path().strip_prefix(root)?.to_path_buf();
+        let file_type = entry.file_type();
+        let value = if file_type.is_symlink() {
+            TreeEntry::Symlink(std::fs::read_link(entry.path())?)
+        } else if file_type.is_dir() {
+            TreeEntry::Directory
+        } else {
+            let metadata = entry.metadata()?;
+            TreeEntry::File {
+                executable: metadata.permissions().mode() & 0o111 != 0,
+                size: metadata.len(),
+            }
+        };
+        entries.insert(rel, value);
+    }
+    Ok(entries)
+}
+
+/// Whether two files have the same contents, read a block at a time so
+/// large outputs are never held in memory.
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    use std::io::Read;
+    let mut a = std::io::BufReader::new(std::fs::File::open(a)?);
+    let mut b = std::io::BufReader::new(std::fs::File::open(b)?);
+    let mut buf_a = [0u8; 64 * 1024];
+    let mut buf_b = [0u8; 64 * 1024];
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        if b.read_exact(&mut buf_b[..n]).is_err() || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// List the paths that differ between two store trees, relative to their roots.
+fn diff_trees(a: &Path, b: &Path) -> Result<Vec<String>> {
+    if a.is_file() || b.is_file() {
+        let same = a.is_file()
+            && b.is_file()
+            && a.metadata()?.len() == b.metadata()?.len()
+            && same_contents(a, b)?;
+        return Ok(if same { vec![] } else { vec![".".to_string()] });
+    }
+
+    let (root_a, root_b) = (a, b);
+    let a = tree_entries(a)?;
+    let b = tree_entries(b)?;
+    let mut paths: Vec<&PathBuf> = a.keys().chain(b.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    let mut differ = Vec::new();
+    for path in paths {
+        let note = match (a.get(path), b.get(path)) {
+            (Some(x @ TreeEntry::File { .. }), Some(y)) if x == y => {
+                if same_contents(&root_a.join(path), &root_b.join(path))? {
+                    continue;
+                }
+                ""
+            }
+            (Some(x), Some(y)) if x == y => continue,
+            (Some(_), Some(_)) => "",
+            (Some(_), None) => " (missing from rebuild)",
+            (None, Some(_)) => " (only in rebuild)",
+            (None, None) => continue,
+        };
+        differ.push(format!("{}{}", path.display(), note));
+    }
+    Ok(differ)
+}
+
+/// Add a local build to the index behind `trix builds list` and `trix log --last`.
+fn record_build(
+    resolved: &ResolvedInstallable,
+    attr: &str,
+    options: &BuildOptions,
+    started: i64,
+    seconds: f64,
+    ok: bool,
+) {
+    let Some(flake_dir) = resolved.flake_dir.as_ref() else {
+        return;
+    };
+    let drv_path = options
+        .drv_link
+        .as_ref()
+        .and_then(|link| std::fs::read_link(link).ok())
+        .map(|drv| drv.display().to_string());
+    let log_path = drv_path
+        .as_deref()
+        .and_then(crate::builds::log_file)
+        .map(|log| log.display().to_string());
+
+    let record = crate::builds::BuildRecord {
+        attr: attr.to_string(),
+        flake_dir: flake_dir.display().to_string(),
+        drv_path,
+        log_path,
+        started,
+        seconds,
+        ok,
+    };
+    if let Err(e) = crate::builds::record(record) {
+        tracing::debug!("Failed to record build: {}", e);
+    }
+}
+
+/// Point the user at the build directory kept by --keep-failed.
+fn report_kept_build_dir(resolved: &ResolvedInstallable, attr: &str) {
+    let Some(flake_dir) = resolved.flake_dir.as_ref() else {
+        return;
+    };
+    let Ok(drv_path) = get_derivation_path(flake_dir, attr) else {
+        return;
+    };
+
+    match find_kept_build_dir(&drv_path) {
+        Some(dir) => {
+            eprintln!("note: build directory kept at {}", dir.display());
+            eprintln!("note: run `trix debug-build {}` to enter it", drv_path);
+        }
+        None => tracing::debug!("No kept build directory found for {}", drv_path),
+    }
+}
+
 /// Build from a plain Nix file (bypasses flake machinery).
 fn cmd_build_legacy(
     source: BuildSource,
@@ -155,6 +525,7 @@ fn cmd_build_legacy(
     extra_args: Vec<(String, String)>,
     extra_argstrs: Vec<(String, String)>,
     store: Option<&str>,
+    keep_failed: bool,
 ) -> Result<()> {
     let mut cmd = crate::command::NixCommand::new("nix-build");
 
@@ -188,6 +559,10 @@ fn cmd_build_legacy(
         cmd.args(["--store", s]);
     }
 
+    if keep_failed {
+        cmd.arg("--keep-failed");
+    }
+
     match out_link {
         Some(link) => {
             cmd.args(["-o", link]);
@@ -199,3 +574,36 @@ fn cmd_build_legacy(
 
     cmd.run()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_trees() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        for dir in [a.path(), b.path()] {
+            std::fs::create_dir_all(dir.join("bin")).unwrap();
+            std::fs::write(dir.join("bin/hello"), "same").unwrap();
+            std::os::unix::fs::symlink("hello", dir.join("bin/hi")).unwrap();
+        }
+        std::fs::write(a.path().join("stamp"), "built at 10:00").unwrap();
+        std::fs::write(b.path().join("stamp"), "built at 10:05").unwrap();
+        std::fs::write(a.path().join("size"), "short").unwrap();
+        std::fs::write(b.path().join("size"), "longer").unwrap();
+        std::fs::write(a.path().join("old"), "").unwrap();
+        std::fs::write(b.path().join("new"), "").unwrap();
+
+        assert_eq!(
+            diff_trees(a.path(), b.path()).unwrap(),
+            vec![
+                "new (only in rebuild)",
+                "old (missing from rebuild)",
+                "size",
+                "stamp"
+            ]
+        );
+        assert!(diff_trees(a.path(), a.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,48 @@
+use crate::builds::BuildRecord;
+use crate::cli::common::format_table;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+
+/// List recent builds
+pub fn cmd_list(limit: usize, json: bool) -> Result<()> {
+    let records = crate::builds::load();
+    let recent: Vec<&BuildRecord> = records.iter().rev().take(limit).collect();
+
+    if json {
+        println!(
+            "{}",
+            crate::schema::BUILDS_LIST.to_json(&serde_json::json!({ "builds": recent }))?
+        );
+        return Ok(());
+    }
+
+    if recent.is_empty() {
+        println!("No builds recorded yet.");
+        return Ok(());
+    }
+
+    let rows: Vec<Vec<String>> = recent
+        .iter()
+        .enumerate()
+        .map(|(i, build)| {
+            vec![
+                (i + 1).to_string(),
+                DateTime::from_timestamp(build.started, 0)
+                    .map(|dt| {
+                        dt.with_timezone(&Local)
+                            .format("%Y-%m-%d %H:%M")
+                            .to_string()
+                    })
+                    .unwrap_or_else(|| "-".to_string()),
+                format!("{:.1}s", build.seconds),
+                if build.ok { "ok" } else { "FAILED" }.to_string(),
+                format!("{}#{}", build.flake_dir, build.attr),
+            ]
+        })
+        .collect();
+    print!(
+        "{}",
+        format_table(&["#", "STARTED", "TOOK", "STATUS", "BUILD"], &rows)
+    );
+    Ok(())
+}
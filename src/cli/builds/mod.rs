@@ -0,0 +1,27 @@
+use anyhow::Result;
+use clap::Subcommand;
+
+#[path = "list/command.rs"]
+pub mod list;
+
+pub use list::cmd_list;
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum BuildsCommands {
+    /// List recent builds, newest first (see `trix log --build N`)
+    List {
+        /// Show at most N builds
+        #[arg(short = 'n', long, value_name = "N", default_value_t = 20)]
+        limit: usize,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+pub fn cmd_builds(cmd: BuildsCommands) -> Result<()> {
+    match cmd {
+        BuildsCommands::List { limit, json } => cmd_list(limit, json),
+    }
+}
@@ -1,7 +1,73 @@
 use anyhow::{Context, Result};
+use std::path::Path;
 
-use crate::flake::{ensure_lock, ResolvedInstallable};
-use crate::nix::{run_nix_build, BuildOptions};
+use crate::cli::style::{display_width, pad, terminal_width, truncate, Stream};
+use crate::common::closest_matches;
+use crate::flake::{
+    ensure_lock, format_attribute_not_found_error, join_attr_path, split_attr_path,
+    ResolvedInstallable,
+};
+use crate::nix::{eval_flake_attr_names, flake_has_attr, run_nix_build, BuildOptions};
+
+/// Quote a string for use in a bash script.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// The short name of this machine (up to the first dot), as Home Manager
+/// uses in `user@host`.
+pub fn short_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the pointer and length describe `buf`, which gethostname
+    // writes at most `buf.len()` bytes into
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if ret != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    let name = String::from_utf8_lossy(&buf[..len]).into_owned();
+    Some(name.split('.').next().unwrap_or_default().to_string())
+}
+
+/// Left-aligned columns separated by two spaces; the last one unpadded,
+/// and cut to fit when stdout is a terminal.
+pub fn format_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    table_with_width(header, rows, terminal_width(Stream::Stdout))
+}
+
+/// [`format_table`], fitting lines to `width` columns if given.
+fn table_with_width(header: &[&str], rows: &[Vec<String>], width: Option<usize>) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| display_width(h)).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(display_width(cell));
+        }
+    }
+    let line = |cells: Vec<&str>| {
+        let last = cells.len() - 1;
+        let mut out = String::new();
+        for (i, cell) in cells.into_iter().enumerate() {
+            if i == last {
+                // Whatever room the other columns leave, at least one column
+                let room = width.map(|w| w.saturating_sub(display_width(&out)).max(1));
+                match room {
+                    Some(room) => out.push_str(&truncate(cell, room)),
+                    None => out.push_str(cell),
+                }
+            } else {
+                out.push_str(&pad(cell, widths[i]));
+                out.push_str("  ");
+            }
+        }
+        out.push('\n');
+        out
+    };
+    let mut out = line(header.to_vec());
+    for row in rows {
+        out.push_str(&line(row.iter().map(String::as_str).collect()));
+    }
+    out
+}
 
 /// Build a resolved flake attribute.
 ///
@@ -9,6 +75,9 @@ use crate::nix::{run_nix_build, BuildOptions};
 /// 1. Getting the flake directory
 /// 2. Ensuring the lock file exists
 /// 3. Running nix-build
+///
+/// If the build fails because the attribute does not exist, the error
+/// suggests close matches from the flake's actual outputs.
 pub fn build_resolved_attribute(
     resolved: &ResolvedInstallable,
     attr: &str,
@@ -20,5 +89,92 @@ pub fn build_resolved_attribute(
     // Ensure lock exists
     ensure_lock(flake_dir, None)?;
 
-    run_nix_build(flake_dir, attr, options, capture_output)
+    run_nix_build(flake_dir, attr, options, capture_output).map_err(|e| {
+        match attribute_not_found_error(flake_dir, &resolved.attr_part, attr) {
+            Some(msg) => anyhow::anyhow!(msg),
+            None => e,
+        }
+    })
+}
+
+/// Build a "did you mean" error if `attr` is missing from the flake outputs.
+///
+/// Returns None if the attribute exists (the failure was something else)
+/// or if the outputs could not be inspected.
+fn attribute_not_found_error(flake_dir: &Path, attr_part: &str, attr: &str) -> Option<String> {
+    if flake_has_attr(flake_dir, attr).unwrap_or(true) {
+        return None;
+    }
+
+    let mut parts = split_attr_path(attr);
+    let name = parts.pop()?;
+    if parts.is_empty() {
+        return None;
+    }
+
+    // For packages.<system>, also consider legacyPackages and apps
+    let mut parents = vec![join_attr_path(&parts)];
+    if parts.len() == 2 && parts[0] == "packages" {
+        parents.push(format!("legacyPackages.{}", parts[1]));
+        parents.push(format!("apps.{}", parts[1]));
+    }
+
+    let mut names = Vec::new();
+    for p in &parents {
+        names.extend(eval_flake_attr_names(flake_dir, p).ok()?);
+    }
+
+    let suggestions: Vec<String> = closest_matches(&name, names.iter().map(|s| s.as_str()))
+        .into_iter()
+        .take(3)
+        .collect();
+
+    Some(format_attribute_not_found_error(
+        attr_part,
+        &[attr.to_string()],
+        &suggestions,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_table() {
+        let rows = vec![
+            vec!["hello".to_string(), "2.12.1".to_string(), "-".to_string()],
+            vec![
+                "ripgrep".to_string(),
+                "14.1.0".to_string(),
+                "nixpkgs".to_string(),
+            ],
+        ];
+        assert_eq!(
+            format_table(&["NAME", "VERSION", "ORIGIN"], &rows),
+            "NAME     VERSION  ORIGIN\n\
+             hello    2.12.1   -\n\
+             ripgrep  14.1.0   nixpkgs\n"
+        );
+    }
+
+    #[test]
+    fn test_format_table_wide_and_fitted() {
+        let rows = vec![
+            vec!["日本語".to_string(), "/nix/store/abc-nihongo".to_string()],
+            vec!["hi".to_string(), "/nix/store/def-hi".to_string()],
+        ];
+        assert_eq!(
+            table_with_width(&["NAME", "PATH"], &rows, None),
+            "NAME    PATH\n\
+             日本語  /nix/store/abc-nihongo\n\
+             hi      /nix/store/def-hi\n"
+        );
+        assert_eq!(
+            table_with_width(&["NAME", "PATH"], &rows, Some(18)),
+            "NAME    PATH\n\
+             日本語  /nix/stor…\n\
+             hi      /nix/stor…\n"
+        );
+    }
 }
@@ -16,6 +16,10 @@ pub struct CopyArgs {
     /// Don't check signatures
     #[arg(long)]
     pub no_check_sigs: bool,
+
+    /// Extra ssh option for ssh:// destinations, added to NIX_SSHOPTS (repeatable)
+    #[arg(long = "ssh-option", value_name = "OPTION")]
+    pub ssh_options: Vec<String>,
 }
 
 /// Copy a package to another store
@@ -25,17 +29,17 @@ pub fn cmd_copy(args: CopyArgs) -> Result<()> {
 
     if !resolved.is_local {
         // Passthrough to nix copy
-        let flake_ref = resolved.flake_ref.as_deref().unwrap_or("");
-        let full_ref = format!("{}#{}", flake_ref, resolved.attr_part);
+        let full_ref = resolved.full_ref();
 
         let mut cmd = crate::command::NixCommand::new("nix");
         cmd.args(["copy", "--to", &args.to, &full_ref]);
+        cmd.ssh_options(&args.ssh_options);
 
         if args.no_check_sigs {
             cmd.arg("--no-check-sigs");
         }
 
-        return cmd.run();
+        return crate::retry::with_retry("nix copy", || cmd.run_keeping_stderr());
     }
 
     let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
@@ -54,10 +58,12 @@ pub fn cmd_copy(args: CopyArgs) -> Result<()> {
     // Copy to destination
     let mut cmd = crate::command::NixCommand::new("nix");
     cmd.args(["copy", "--to", &args.to, &store_path]);
+    cmd.ssh_options(&args.ssh_options);
 
     if args.no_check_sigs {
         cmd.arg("--no-check-sigs");
     }
 
-    cmd.run()
+    // Paths that already made it are skipped, so a retry resumes the copy
+    crate::retry::with_retry("nix copy", || cmd.run_keeping_stderr())
 }
@@ -0,0 +1,20 @@
+use anyhow::Result;
+use clap::Subcommand;
+
+#[path = "rebuild/command.rs"]
+pub mod rebuild;
+
+pub use rebuild::cmd_rebuild;
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum DarwinCommands {
+    /// Build a nix-darwin configuration and activate it, like
+    /// `darwin-rebuild switch --flake`
+    Rebuild(rebuild::RebuildArgs),
+}
+
+pub fn cmd_darwin(cmd: DarwinCommands) -> Result<()> {
+    match cmd {
+        DarwinCommands::Rebuild(args) => cmd_rebuild(args),
+    }
+}
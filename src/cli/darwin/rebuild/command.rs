@@ -0,0 +1,126 @@
+use crate::flake::{ensure_lock, join_attr_path, resolve_installable};
+use crate::nix::{flake_attr_exists, run_nix_build, BuildOptions};
+use anyhow::{Context, Result};
+use clap::Args;
+use std::path::Path;
+
+/// The profile nix-darwin keeps system generations in.
+const SYSTEM_PROFILE: &str = "/nix/var/nix/profiles/system";
+
+#[derive(Args, Clone, Debug)]
+pub struct RebuildArgs {
+    /// Flake reference, optionally with #CONFIG (default: this machine's LocalHostName)
+    #[arg(default_value = ".")]
+    pub flake_ref: String,
+
+    /// Build the system and print its path, without making it current
+    #[arg(long)]
+    pub build_only: bool,
+
+    /// Print the expression trix would build and exit
+    #[arg(long)]
+    pub print_expr: bool,
+}
+
+/// The name nix-darwin looks this machine up by: its LocalHostName, or
+/// the short host name where scutil is not available.
+fn local_host_name() -> Option<String> {
+    std::process::Command::new("scutil")
+        .args(["--get", "LocalHostName"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(crate::cli::common::short_hostname)
+}
+
+/// The `darwinConfigurations` entry to build; `config` is what followed
+/// `#` in the flake reference, if anything.
+fn config_name(flake_dir: &Path, config: Option<&str>) -> Result<String> {
+    if let Some(config) = config.filter(|c| !c.is_empty()) {
+        let name = config
+            .strip_prefix("darwinConfigurations.")
+            .unwrap_or(config);
+        return Ok(name.trim_matches('"').to_string());
+    }
+    let host = local_host_name().context("Could not determine the host name; pass flake#CONFIG")?;
+    if !flake_attr_exists(flake_dir, &join_attr_path(&["darwinConfigurations", &host]))? {
+        anyhow::bail!(
+            "No darwinConfigurations.{} in the flake; pass flake#CONFIG",
+            host
+        );
+    }
+    Ok(host)
+}
+
+/// Run `program` as root, through sudo unless trix already is root.
+fn run_as_root(program: &str, args: &[&str]) -> Result<()> {
+    // SAFETY: geteuid takes no arguments and cannot fail
+    let is_root = unsafe { libc::geteuid() } == 0;
+    // Not NixCommand: sudo and darwin-rebuild take none of nix's verbosity flags
+    let mut cmd = if is_root {
+        std::process::Command::new(program)
+    } else {
+        let mut sudo = std::process::Command::new("sudo");
+        sudo.arg(program);
+        sudo
+    };
+    let status = cmd
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        anyhow::bail!("{} {} failed", program, args.join(" "));
+    }
+    Ok(())
+}
+
+/// Build a nix-darwin configuration and activate it
+pub fn cmd_rebuild(args: RebuildArgs) -> Result<()> {
+    let resolved = resolve_installable(&args.flake_ref);
+    if !resolved.is_local {
+        anyhow::bail!(
+            "trix darwin rebuild needs a local flake; use `darwin-rebuild switch --flake {}`",
+            args.flake_ref
+        );
+    }
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+    ensure_lock(flake_dir, None)?;
+
+    let config = args.flake_ref.split_once('#').map(|(_, attr)| attr);
+    let name = config_name(flake_dir, config)?;
+    let attr = join_attr_path(&["darwinConfigurations", &name, "system"]);
+
+    if args.print_expr {
+        println!("{}", crate::nix::eval_call_expr(flake_dir, &attr)?);
+        return Ok(());
+    }
+    if !args.build_only && !cfg!(target_os = "macos") {
+        anyhow::bail!(
+            "nix-darwin configurations can only be activated on macOS; pass --build-only"
+        );
+    }
+
+    let options = BuildOptions {
+        out_link: None,
+        ..Default::default()
+    };
+    let output = run_nix_build(flake_dir, &attr, &options, true)?.context("Build failed")?;
+    let system = output
+        .lines()
+        .last()
+        .context("nix-build printed no output path")?
+        .trim()
+        .to_string();
+
+    if args.build_only {
+        println!("{}", system);
+        return Ok(());
+    }
+
+    // What `darwin-rebuild switch` does once the system is built
+    run_as_root("nix-env", &["-p", SYSTEM_PROFILE, "--set", &system])?;
+    run_as_root(&format!("{}/sw/bin/darwin-rebuild", system), &["activate"])
+        .with_context(|| format!("Activating {} failed", name))
+}
@@ -0,0 +1,94 @@
+use crate::cli::common::shell_quote;
+use crate::flake::{resolve_attr_path, resolve_installable};
+use crate::nix::{find_kept_build_dir, get_derivation_env, get_derivation_path, get_system};
+use anyhow::{Context, Result};
+use clap::Args;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Args, Clone, Debug)]
+pub struct DebugBuildArgs {
+    /// Derivation path or installable whose failed build to inspect
+    #[arg(default_value = ".#default")]
+    pub target: String,
+
+    /// Build directory to enter (defaults to the newest one kept by --keep-failed)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+}
+
+/// Enter a shell in a failed build's directory with the derivation's environment
+pub fn cmd_debug_build(args: DebugBuildArgs) -> Result<()> {
+    let drv_path = if args.target.ends_with(".drv") {
+        args.target.clone()
+    } else {
+        get_target_drv_path(&args.target)?
+    };
+
+    let build_dir = match args.dir {
+        Some(dir) => dir,
+        None => find_kept_build_dir(&drv_path).with_context(|| {
+            format!(
+                "No kept build directory found for {}\nRebuild with `trix build --keep-failed` first",
+                drv_path
+            )
+        })?,
+    };
+
+    let mut env = get_derivation_env(&drv_path)?;
+
+    // The build ran in a sandbox; point the usual variables at the kept directory
+    let build_top = build_dir.display().to_string();
+    for var in ["NIX_BUILD_TOP", "TMPDIR", "TEMPDIR", "TMP", "TEMP", "HOME"] {
+        env.insert(var.to_string(), build_top.clone());
+    }
+
+    // Load env-vars (written by stdenv at build start) and stdenv's setup,
+    // then return to the build directory
+    let mut rcfile = crate::scratch::named_tempfile()?;
+    writeln!(
+        rcfile,
+        r#"[ -e env-vars ] && source env-vars
+export NIX_BUILD_TOP={dir} TMPDIR={dir} TEMPDIR={dir} TMP={dir} TEMP={dir} HOME={dir}
+[ -n "$stdenv" ] && [ -e "$stdenv/setup" ] && source "$stdenv/setup"
+cd {dir}
+PS1='\[\e[0;1;31m\][debug-build:\w]$\[\e[0m\] '"#,
+        dir = shell_quote(&build_top)
+    )?;
+
+    eprintln!("Entering {}", build_dir.display());
+
+    let status = std::process::Command::new("bash")
+        .args(["--rcfile"])
+        .arg(rcfile.path())
+        .current_dir(&build_dir)
+        .envs(&env)
+        .status()
+        .context("Failed to run bash")?;
+
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+/// Resolve an installable to its derivation path.
+fn get_target_drv_path(installable: &str) -> Result<String> {
+    let resolved = resolve_installable(installable);
+
+    if !resolved.is_local {
+        let full_ref = format!("{}.drvPath", resolved.full_ref());
+
+        let mut cmd = crate::command::NixCommand::new("nix");
+        cmd.args(["eval", "--raw", &full_ref]);
+
+        return cmd.output();
+    }
+
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+    let system = get_system()?;
+    let attr = resolve_attr_path(&resolved.attr_part, "packages", &system);
+
+    get_derivation_path(flake_dir, &attr)
+}
@@ -1,7 +1,74 @@
+use crate::cli::common::shell_quote;
+use crate::cli::style::bold;
 use crate::flake::{ensure_lock, resolve_attr_path, resolve_installable};
-use crate::nix::{get_system, run_nix_shell, ShellOptions};
+use crate::nix::{
+    get_derivation_path, get_store_path_from_drv, get_system, run_nix_shell, ShellOptions,
+};
 use anyhow::{Context, Result};
-use clap::Args;
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+
+/// Nix function extracting what the banner shows from a devShell.
+///
+/// `meta.banner` is free text for teams to explain the shell; packages are
+/// the shell's (native) build inputs, as added by `mkShell { packages }`.
+const SHELL_INFO_FN: &str = r#"shell: {
+  description = shell.meta.description or null;
+  banner = shell.meta.banner or null;
+  packages = map (p: p.pname or (builtins.parseDrvName p.name).name) (
+    builtins.filter (p: builtins.isAttrs p && p ? name) (
+      (shell.nativeBuildInputs or [ ]) ++ (shell.buildInputs or [ ])
+    )
+  );
+}"#;
+
+/// Nix function listing the names and versions of a devShell's inputs.
+const SHELL_PACKAGES_FN: &str = r#"shell:
+let
+  describe = p: {
+    name = p.pname or (builtins.parseDrvName p.name).name;
+    version = p.version or (builtins.parseDrvName p.name).version;
+  };
+  packages = inputs: map describe (builtins.filter (p: builtins.isAttrs p && p ? name) inputs);
+in
+{
+  nativeBuildInputs = packages (shell.nativeBuildInputs or [ ]);
+  buildInputs = packages (shell.buildInputs or [ ]);
+}"#;
+
+/// Variables describing the host or the nix-shell session, left out of images.
+const HOST_VARIABLES: &[&str] = &[
+    "HOME",
+    "HOSTNAME",
+    "LOGNAME",
+    "NIX_BUILD_TOP",
+    "NIX_LOG_FD",
+    "OLDPWD",
+    "PWD",
+    "SHLVL",
+    "TEMP",
+    "TEMPDIR",
+    "TERM",
+    "TMP",
+    "TMPDIR",
+    "USER",
+    "_",
+];
+
+/// Packages listed in the banner before summarising the rest.
+const BANNER_MAX_PACKAGES: usize = 12;
+
+/// Shell syntax for `--format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EnvFormat {
+    Bash,
+    Zsh,
+    Fish,
+    /// The `nix print-dev-env --json` layout
+    Json,
+}
 
 #[derive(Args, Clone, Debug)]
 pub struct DevelopArgs {
@@ -36,6 +103,52 @@ pub struct DevelopArgs {
     /// Use specified store URL
     #[arg(long)]
     pub store: Option<String>,
+
+    /// Don't print the shell's description and packages on entry
+    #[arg(long)]
+    pub quiet_banner: bool,
+
+    /// Replace INSTALLABLE's store path with the local directory PATH in the
+    /// shell's environment (repeatable)
+    #[arg(long, num_args = 2, value_names = ["INSTALLABLE", "PATH"])]
+    pub redirect: Vec<String>,
+
+    /// Print the shell's environment in this syntax instead of entering it
+    #[arg(long, value_enum, conflicts_with_all = ["command", "interpreter"])]
+    pub format: Option<EnvFormat>,
+
+    /// List the packages the shell is made of instead of entering it
+    #[arg(long, conflicts_with_all = ["command", "interpreter", "format"])]
+    pub print_packages: bool,
+
+    /// Write the shell's closure and environment as an OCI image layout to
+    /// DIR (for devcontainers; load it with skopeo or podman)
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["command", "interpreter", "format", "print_packages", "redirect"])]
+    pub containerize: Option<std::path::PathBuf>,
+}
+
+/// What the entry banner shows about a devShell.
+#[derive(Debug, Default, Deserialize)]
+struct ShellInfo {
+    description: Option<String>,
+    banner: Option<String>,
+    #[serde(default)]
+    packages: Vec<String>,
+}
+
+/// A package in a devShell's inputs.
+#[derive(Debug, Deserialize)]
+struct ShellPackage {
+    name: String,
+    version: String,
+}
+
+/// The packages a devShell is made of (`--print-packages`).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ShellPackages {
+    native_build_inputs: Vec<ShellPackage>,
+    build_inputs: Vec<ShellPackage>,
 }
 
 fn parse_arg_pairs(args: &[String]) -> Vec<(String, String)> {
@@ -68,6 +181,289 @@ fn build_interpreter_command(interpreter: &str, script: &str, script_args: &[Str
         .join(" ")
 }
 
+/// Get the output store path of a `--redirect` installable without building it.
+fn redirect_source(installable: &str) -> Result<String> {
+    if installable.starts_with('/') {
+        return Ok(installable.trim_end_matches('/').to_string());
+    }
+
+    let resolved = resolve_installable(installable);
+    if !resolved.is_local {
+        let mut cmd = crate::command::NixCommand::new("nix");
+        cmd.args(["eval", "--raw", &format!("{}.outPath", resolved.full_ref())]);
+        return cmd.output();
+    }
+
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+    ensure_lock(flake_dir, None)?;
+    let attr = resolve_attr_path(&resolved.attr_part, "packages", &get_system()?);
+    get_store_path_from_drv(&get_derivation_path(flake_dir, &attr)?)
+}
+
+/// Resolve `--redirect` pairs to (store path, local directory).
+fn resolve_redirects(args: &[String]) -> Result<Vec<(String, String)>> {
+    parse_arg_pairs(args)
+        .into_iter()
+        .map(|(installable, path)| {
+            let from = redirect_source(&installable)
+                .with_context(|| format!("Failed to resolve '{}' for --redirect", installable))?;
+            let to = std::fs::canonicalize(&path)
+                .with_context(|| format!("Redirect target '{}' does not exist", path))?;
+            Ok((from, to.display().to_string()))
+        })
+        .collect()
+}
+
+/// Bash that rewrites every exported variable, replacing each store path with its redirect.
+fn redirect_script(redirects: &[(String, String)]) -> String {
+    let mut script =
+        String::from("for __trix_var in $(compgen -e); do\n  __trix_val=${!__trix_var}\n");
+    for (from, to) in redirects {
+        script.push_str(&format!(
+            "  __trix_val=${{__trix_val//{}/{}}}\n",
+            shell_quote(from),
+            shell_quote(to)
+        ));
+    }
+    script.push_str(
+        "  [ \"$__trix_val\" != \"${!__trix_var}\" ] && export \"$__trix_var=$__trix_val\"\n\
+         done\n\
+         unset __trix_var __trix_val",
+    );
+    script
+}
+
+/// Quote a string for fish, where only `\\` and `'` are special in single quotes.
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Render a dev shell environment for another shell to load.
+fn format_env(env: &BTreeMap<String, String>, format: EnvFormat) -> Result<String> {
+    let mut out = String::new();
+    match format {
+        EnvFormat::Bash | EnvFormat::Zsh => {
+            for (name, value) in env {
+                out.push_str(&format!("export {}={}\n", name, shell_quote(value)));
+            }
+        }
+        EnvFormat::Fish => {
+            for (name, value) in env {
+                out.push_str(&format!("set -gx {} {}\n", name, fish_quote(value)));
+            }
+        }
+        EnvFormat::Json => {
+            let variables: serde_json::Map<String, serde_json::Value> = env
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.clone(),
+                        serde_json::json!({ "type": "exported", "value": value }),
+                    )
+                })
+                .collect();
+            out = serde_json::to_string_pretty(&serde_json::json!({ "variables": variables }))?;
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// The environment of a dev shell image, as `NAME=value` entries, and the
+/// store paths it refers to.
+///
+/// Host variables are dropped and PATH keeps only its store entries, since
+/// nothing else exists in the image.
+fn container_env(env: &BTreeMap<String, String>, store_dir: &str) -> (Vec<String>, Vec<String>) {
+    // Nix's base-32 alphabet for the hash and its character set for names
+    let store_path = regex::Regex::new(&format!(
+        r"{}/[0-9a-df-np-sv-z]{{32}}-[a-zA-Z0-9+\-._?=]+",
+        regex::escape(store_dir)
+    ))
+    .unwrap();
+
+    let mut entries = Vec::new();
+    let mut roots = std::collections::BTreeSet::new();
+    for (name, value) in env {
+        if HOST_VARIABLES.contains(&name.as_str()) {
+            continue;
+        }
+        let value = if name == "PATH" {
+            value
+                .split(':')
+                .filter(|dir| dir.starts_with(store_dir))
+                .collect::<Vec<_>>()
+                .join(":")
+        } else {
+            value.clone()
+        };
+        roots.extend(store_path.find_iter(&value).map(|m| m.as_str().to_string()));
+        entries.push(format!("{}={}", name, value));
+    }
+    (entries, roots.into_iter().collect())
+}
+
+/// Write a dev shell's closure and environment as an OCI image (`--containerize`).
+fn containerize(
+    flake_dir: &std::path::Path,
+    attr: &str,
+    args: &DevelopArgs,
+    layout: &std::path::Path,
+) -> Result<()> {
+    // The image says "linux"; a closure built here would not run in it
+    if !cfg!(target_os = "linux") {
+        anyhow::bail!(
+            "--containerize needs Linux: the image would hold {} binaries",
+            std::env::consts::OS
+        );
+    }
+    let options = ShellOptions {
+        extra_args: parse_arg_pairs(&args.extra_args),
+        extra_argstrs: parse_arg_pairs(&args.extra_argstrs),
+        store: args.store.clone(),
+        ..Default::default()
+    };
+    let env = crate::progress::with_status("Entering dev shell", || {
+        crate::nix::capture_nix_shell_env(flake_dir, attr, &options)
+    })?;
+    let store_dir = crate::nix::get_store_dir()?;
+    let (env_entries, roots) = container_env(&env, &store_dir);
+
+    let closure = if roots.is_empty() {
+        Vec::new()
+    } else {
+        let mut cmd = crate::command::NixCommand::new("nix-store");
+        cmd.args(["--query", "--requisites"]);
+        cmd.args(&roots);
+        cmd.output()?.lines().map(str::to_string).collect()
+    };
+
+    // The shell stdenv sets up is the natural entry point
+    let cmd = env
+        .get("SHELL")
+        .filter(|shell| shell.starts_with(&store_dir))
+        .map(|shell| vec![shell.clone()])
+        .unwrap_or_default();
+    let config = crate::oci::ImageConfig {
+        env: env_entries,
+        cmd,
+        tag: "latest".to_string(),
+    };
+    let digest = crate::progress::with_status("Writing image", || {
+        crate::oci::write_image(layout, &closure, &config)
+    })?;
+
+    eprintln!(
+        "Wrote an OCI image of {} store paths to {} ({})",
+        closure.len(),
+        layout.display(),
+        digest
+    );
+    eprintln!(
+        "Load it with: skopeo copy oci:{}:latest docker-daemon:devshell:latest",
+        layout.display()
+    );
+    Ok(())
+}
+
+/// Evaluate the banner information for a devShell, if possible.
+fn shell_info(flake_dir: &std::path::Path, attr: &str) -> Option<ShellInfo> {
+    let options = crate::nix::EvalOptions {
+        output_json: true,
+        apply_fn: Some(SHELL_INFO_FN.to_string()),
+        quiet: true,
+        ..Default::default()
+    };
+    match crate::nix::run_nix_eval(Some(flake_dir), attr, &options) {
+        Ok(json) => serde_json::from_str(&json).ok(),
+        Err(e) => {
+            tracing::debug!("Failed to evaluate shell banner: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Evaluate the packages of a devShell without instantiating it.
+fn shell_packages(flake_dir: &std::path::Path, attr: &str) -> Result<ShellPackages> {
+    let options = crate::nix::EvalOptions {
+        output_json: true,
+        apply_fn: Some(SHELL_PACKAGES_FN.to_string()),
+        ..Default::default()
+    };
+    let json = crate::nix::run_nix_eval(Some(flake_dir), attr, &options)?;
+    serde_json::from_str(&json).context("Failed to parse shell packages")
+}
+
+/// Render `--print-packages` output: one aligned `name version` line per
+/// package, grouped by input list.
+fn format_packages(packages: &ShellPackages) -> String {
+    let groups = [
+        ("nativeBuildInputs", &packages.native_build_inputs),
+        ("buildInputs", &packages.build_inputs),
+    ];
+    let width = groups
+        .iter()
+        .flat_map(|(_, list)| list.iter().map(|p| p.name.len()))
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::new();
+    for (group, list) in groups {
+        if list.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("{}:\n", group));
+        for package in list {
+            out.push_str(
+                format!(
+                    "  {:width$}  {}",
+                    package.name,
+                    package.version,
+                    width = width
+                )
+                .trim_end(),
+            );
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Render the entry banner, or None if the shell has nothing to say.
+fn format_banner(info: &ShellInfo) -> Option<String> {
+    let mut lines = Vec::new();
+    if let Some(description) = info.description.as_deref().filter(|d| !d.is_empty()) {
+        lines.push(bold(description));
+    }
+    if let Some(banner) = info.banner.as_deref().filter(|b| !b.is_empty()) {
+        lines.push(banner.trim_end().to_string());
+    }
+
+    let mut packages: Vec<&str> = Vec::new();
+    for name in &info.packages {
+        if !packages.contains(&name.as_str()) {
+            packages.push(name);
+        }
+    }
+    if !packages.is_empty() {
+        let mut listed = packages
+            .iter()
+            .take(BANNER_MAX_PACKAGES)
+            .copied()
+            .collect::<Vec<_>>()
+            .join(", ");
+        if packages.len() > BANNER_MAX_PACKAGES {
+            listed.push_str(&format!(
+                " and {} more",
+                packages.len() - BANNER_MAX_PACKAGES
+            ));
+        }
+        lines.push(format!("packages: {}", listed));
+    }
+
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
 /// Enter a development shell from flake.nix
 pub fn cmd_develop(args: DevelopArgs) -> Result<()> {
     // Determine the effective command to run
@@ -91,8 +487,7 @@ pub fn cmd_develop(args: DevelopArgs) -> Result<()> {
 
     if !resolved.is_local {
         // Passthrough to nix develop
-        let flake_ref = resolved.flake_ref.as_deref().unwrap_or("");
-        let full_ref = format!("{}#{}", flake_ref, resolved.attr_part);
+        let full_ref = resolved.full_ref();
 
         let mut cmd = crate::command::NixCommand::new("nix");
         cmd.arg("develop").arg(&full_ref);
@@ -113,6 +508,40 @@ pub fn cmd_develop(args: DevelopArgs) -> Result<()> {
             cmd.args(["--argstr", &name, &value]);
         }
 
+        for (installable, path) in parse_arg_pairs(&args.redirect) {
+            cmd.args(["--redirect", &installable, &path]);
+        }
+
+        if args.print_packages {
+            anyhow::bail!("--print-packages is only supported for local flakes");
+        }
+
+        if args.containerize.is_some() {
+            anyhow::bail!("--containerize is only supported for local flakes");
+        }
+
+        if let Some(format) = args.format {
+            // nix print-dev-env speaks bash and JSON only
+            let mut print = crate::command::NixCommand::new("nix");
+            print.args(["print-dev-env", &full_ref]);
+            match format {
+                EnvFormat::Bash => {}
+                EnvFormat::Json => {
+                    print.arg("--json");
+                }
+                EnvFormat::Zsh | EnvFormat::Fish => {
+                    anyhow::bail!("--format zsh and fish are only supported for local flakes")
+                }
+            }
+            if let Some(s) = &args.store {
+                print.args(["--store", s]);
+            }
+            for (installable, path) in parse_arg_pairs(&args.redirect) {
+                print.args(["--redirect", &installable, &path]);
+            }
+            return print.run();
+        }
+
         return cmd.exec();
     }
 
@@ -125,8 +554,58 @@ pub fn cmd_develop(args: DevelopArgs) -> Result<()> {
     // Resolve attribute path for devShells
     let attr = resolve_attr_path(&resolved.attr_part, "devShells", &system);
 
+    // Only greet interactive shells; commands and scripts stay quiet
+    let interactive = effective_command.is_none();
+
+    if args.print_packages {
+        let packages = shell_packages(flake_dir, &attr)?;
+        let out = format_packages(&packages);
+        if out.is_empty() {
+            eprintln!("The shell has no packages");
+        }
+        print!("{}", out);
+        return Ok(());
+    }
+
+    if let Some(layout) = &args.containerize {
+        return containerize(flake_dir, &attr, &args, layout);
+    }
+
+    let redirects = resolve_redirects(&args.redirect)?;
+
+    if let Some(format) = args.format {
+        let options = ShellOptions {
+            extra_args: parse_arg_pairs(&args.extra_args),
+            extra_argstrs: parse_arg_pairs(&args.extra_argstrs),
+            store: args.store.clone(),
+            ..Default::default()
+        };
+        let mut env = crate::progress::with_status("Entering dev shell", || {
+            crate::nix::capture_nix_shell_env(flake_dir, &attr, &options)
+        })?;
+        for value in env.values_mut() {
+            for (from, to) in &redirects {
+                *value = value.replace(from.as_str(), to);
+            }
+        }
+        print!("{}", format_env(&env, format)?);
+        return Ok(());
+    }
+
+    // Apply redirects before the command, or before handing over to the
+    // interactive shell ('return' leaves nix-shell's --command at a prompt)
+    let effective_command = if redirects.is_empty() {
+        effective_command
+    } else {
+        let script = redirect_script(&redirects);
+        Some(match effective_command {
+            Some(command) => format!("{}\n{}", script, command),
+            None => format!("{}\nreturn", script),
+        })
+    };
+
     // Get nixConfig
-    let nix_config = crate::flake::get_nix_config(flake_dir, true);
+    let nix_config = crate::flake::get_nix_config(flake_dir);
 
     let options = ShellOptions {
         command: effective_command,
@@ -142,5 +621,138 @@ pub fn cmd_develop(args: DevelopArgs) -> Result<()> {
             .map(|s| s.to_string()),
     };
 
+    if interactive && !args.quiet_banner && std::io::stderr().is_terminal() {
+        if let Some(banner) = shell_info(flake_dir, &attr)
+            .as_ref()
+            .and_then(format_banner)
+        {
+            eprintln!("{}", banner);
+        }
+    }
+
     run_nix_shell(flake_dir, &attr, &options)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_banner() {
+        assert_eq!(format_banner(&ShellInfo::default()), None);
+
+        let info = ShellInfo {
+            description: None,
+            banner: Some("Run `make` to build.\n".to_string()),
+            packages: vec!["cargo".into(), "rustc".into(), "cargo".into()],
+        };
+        assert_eq!(
+            format_banner(&info).unwrap(),
+            "Run `make` to build.\npackages: cargo, rustc"
+        );
+
+        let info = ShellInfo {
+            packages: (0..15).map(|i| format!("p{}", i)).collect(),
+            ..Default::default()
+        };
+        assert!(format_banner(&info).unwrap().ends_with("p11 and 3 more"));
+    }
+
+    #[test]
+    fn test_format_packages() {
+        let package = |name: &str, version: &str| ShellPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+        };
+        let packages = ShellPackages {
+            native_build_inputs: vec![package("cargo", "1.80.0"), package("pkg-config", "")],
+            build_inputs: vec![package("openssl", "3.0.14")],
+        };
+        assert_eq!(
+            format_packages(&packages),
+            "nativeBuildInputs:\n  cargo       1.80.0\n  pkg-config\nbuildInputs:\n  openssl     3.0.14\n"
+        );
+
+        let empty = ShellPackages {
+            native_build_inputs: Vec::new(),
+            build_inputs: Vec::new(),
+        };
+        assert_eq!(format_packages(&empty), "");
+    }
+
+    #[test]
+    fn test_format_env() {
+        let env = BTreeMap::from([
+            ("GREETING".to_string(), "it's \\ fine".to_string()),
+            (
+                "PATH".to_string(),
+                "/nix/store/abc-cargo/bin:/usr/bin".to_string(),
+            ),
+        ]);
+        assert_eq!(
+            format_env(&env, EnvFormat::Bash).unwrap(),
+            "export GREETING='it'\\''s \\ fine'\nexport PATH='/nix/store/abc-cargo/bin:/usr/bin'\n"
+        );
+        assert_eq!(
+            format_env(&env, EnvFormat::Fish).unwrap(),
+            "set -gx GREETING 'it\\'s \\\\ fine'\nset -gx PATH '/nix/store/abc-cargo/bin:/usr/bin'\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&format_env(&env, EnvFormat::Json).unwrap()).unwrap();
+        assert_eq!(
+            json["variables"]["PATH"],
+            serde_json::json!({ "type": "exported", "value": "/nix/store/abc-cargo/bin:/usr/bin" })
+        );
+    }
+
+    #[test]
+    fn test_container_env() {
+        let env = BTreeMap::from([
+            ("HOME".to_string(), "/home/me".to_string()),
+            (
+                "PATH".to_string(),
+                "/nix/store/00000000000000000000000000000000-cargo-1.80/bin:/usr/bin".to_string(),
+            ),
+            (
+                "SHELL".to_string(),
+                "/nix/store/11111111111111111111111111111111-bash-5.2/bin/bash".to_string(),
+            ),
+            ("RUST_LOG".to_string(), "debug".to_string()),
+            (
+                "GEM_PATH".to_string(),
+                "'/nix/store/22222222222222222222222222222222-gem-a=b-1.0'".to_string(),
+            ),
+        ]);
+        let (entries, roots) = container_env(&env, "/nix/store");
+        assert_eq!(
+            entries,
+            vec![
+                "GEM_PATH='/nix/store/22222222222222222222222222222222-gem-a=b-1.0'",
+                "PATH=/nix/store/00000000000000000000000000000000-cargo-1.80/bin",
+                "RUST_LOG=debug",
+                "SHELL=/nix/store/11111111111111111111111111111111-bash-5.2/bin/bash",
+            ]
+        );
+        assert_eq!(
+            roots,
+            vec![
+                "/nix/store/00000000000000000000000000000000-cargo-1.80",
+                "/nix/store/11111111111111111111111111111111-bash-5.2",
+                "/nix/store/22222222222222222222222222222222-gem-a=b-1.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redirect_script() {
+        let script = redirect_script(&[(
+            "/nix/store/abc-libfoo-1.0".to_string(),
+            "/home/me/it's/libfoo".to_string(),
+        )]);
+        assert!(script.starts_with("for __trix_var in $(compgen -e); do\n"));
+        assert!(script.contains(
+            "  __trix_val=${__trix_val//'/nix/store/abc-libfoo-1.0'/'/home/me/it'\\''s/libfoo'}\n"
+        ));
+        assert!(script.ends_with("done\nunset __trix_var __trix_val"));
+    }
+}
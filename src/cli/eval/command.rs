@@ -1,5 +1,8 @@
+use crate::cli::common::shell_quote;
 use crate::flake::{ensure_lock, resolve_installable};
-use crate::nix::{run_nix_eval, EvalOptions};
+use crate::nix::{
+    eval_flake_attr_names, flake_attr_exists, run_nix_eval, run_nix_eval_file, EvalOptions,
+};
 use anyhow::{Context, Result};
 use clap::Args;
 
@@ -13,6 +16,18 @@ pub struct EvalArgs {
     #[arg(long)]
     pub expr: Option<String>,
 
+    /// Evaluate a Nix file instead of flake.nix, like nix-instantiate --eval
+    #[arg(short = 'f', long = "file", conflicts_with_all = ["expr", "apply", "attr_names", "paths"])]
+    pub nix_file: Option<String>,
+
+    /// Attribute of the --file result to evaluate (e.g. 'pkgs.hello.version')
+    #[arg(short = 'A', long, requires = "nix_file")]
+    pub attr: Option<String>,
+
+    /// Force the --file result deeply, as nix-instantiate --strict (--json always does)
+    #[arg(long, requires = "nix_file")]
+    pub strict: bool,
+
     /// Output as JSON
     #[arg(long)]
     pub json: bool,
@@ -36,6 +51,100 @@ pub struct EvalArgs {
     /// Use specified store URL
     #[arg(long)]
     pub store: Option<String>,
+
+    /// Print only the attribute names of the result
+    #[arg(long, conflicts_with_all = ["expr", "raw", "apply", "paths"])]
+    pub attr_names: bool,
+
+    /// Print only the store paths of the result, or of the derivations it contains
+    #[arg(long, conflicts_with_all = ["expr", "raw", "apply"])]
+    pub paths: bool,
+
+    /// Show the full evaluation trace on errors
+    #[arg(long)]
+    pub show_trace: bool,
+
+    /// Print a flat attrset as PREFIX_<name>='value' lines for a shell's `eval`
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["json", "raw", "attr_names", "paths"])]
+    pub out_env: Option<String>,
+
+    /// Print nothing; exit 0 if the attribute exists, 1 if not and 2 on errors, without evaluating it
+    #[arg(long, conflicts_with_all = ["expr", "nix_file", "json", "raw", "apply", "attr_names", "paths", "out_env"])]
+    pub exists: bool,
+}
+
+/// Collect the output paths of a derivation, or of the derivations directly
+/// inside an attrset, without evaluating anything else.
+const OUT_PATHS_FN: &str = r#"v:
+  if v ? outPath then [ v.outPath ]
+  else if builtins.isAttrs v then
+    builtins.concatMap (n:
+      let r = builtins.tryEval (v.${n}.outPath or null);
+      in if r.success && r.value != null then [ r.value ] else [ ]
+    ) (builtins.attrNames v)
+  else [ ]"#;
+
+/// Turn a flat attrset into `PREFIX_name='value'` shell assignments.
+///
+/// Characters not allowed in variable names become `_`; null is empty and
+/// booleans and numbers print as in JSON. Nested values are refused.
+fn format_env(prefix: &str, value: &serde_json::Value) -> Result<String> {
+    use serde_json::Value;
+    let attrs = value
+        .as_object()
+        .context("--out-env needs the result to be an attribute set")?;
+    let mut out = String::new();
+    for (name, value) in attrs {
+        let value = match value {
+            Value::String(s) => s.clone(),
+            Value::Null => String::new(),
+            Value::Bool(_) | Value::Number(_) => value.to_string(),
+            Value::Array(_) | Value::Object(_) => {
+                anyhow::bail!("--out-env cannot print attribute '{}': it is not a string, number, boolean or null", name)
+            }
+        };
+        let var: String = format!("{}_{}", prefix, name)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        out.push_str(&format!("{}={}\n", var, shell_quote(&value)));
+    }
+    Ok(out)
+}
+
+/// Print an evaluation result, which is JSON under `--out-env`.
+fn print_result(result: &str, out_env: Option<&str>) -> Result<()> {
+    match out_env {
+        Some(prefix) => print!("{}", format_env(prefix, &serde_json::from_str(result)?)?),
+        None => println!("{}", result),
+    }
+    Ok(())
+}
+
+/// Print a list of strings, one per line or as JSON.
+/// Exit for `--exists`: 0 if the attribute exists, 1 if it does not, and 2
+/// if that could not be determined, so scripts can tell errors apart.
+fn exit_for_exists(exists: Result<bool>) -> Result<()> {
+    match exists {
+        Ok(true) => Ok(()),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            crate::cli::gha::error(&format!("{:#}", e));
+            tracing::error!("Error: {:#}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn print_list(items: &[String], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string(items)?);
+    } else {
+        for item in items {
+            println!("{}", item);
+        }
+    }
+    Ok(())
 }
 
 fn parse_arg_pairs(args: &[String]) -> Vec<(String, String)> {
@@ -51,8 +160,25 @@ fn parse_arg_pairs(args: &[String]) -> Vec<(String, String)> {
 }
 
 /// Evaluate a flake attribute or Nix expression
-/// Evaluate a flake attribute or Nix expression
-pub fn cmd_eval(args: EvalArgs) -> Result<()> {
+pub fn cmd_eval(mut args: EvalArgs) -> Result<()> {
+    let out_env = args.out_env.clone();
+    args.json |= out_env.is_some();
+
+    // If -f is specified, bypass flake machinery entirely
+    if let Some(file) = &args.nix_file {
+        let options = EvalOptions {
+            output_json: args.json,
+            raw: args.raw,
+            extra_args: parse_arg_pairs(&args.extra_args),
+            extra_argstrs: parse_arg_pairs(&args.extra_argstrs),
+            store: args.store.clone(),
+            show_trace: args.show_trace,
+            ..Default::default()
+        };
+        let result = run_nix_eval_file(file, args.attr.as_deref(), args.strict, &options)?;
+        return print_result(&result, out_env.as_deref());
+    }
+
     if let Some(expression) = &args.expr {
         // Raw expression evaluation
         let options = EvalOptions {
@@ -64,11 +190,11 @@ pub fn cmd_eval(args: EvalArgs) -> Result<()> {
             expr: Some(expression.clone()),
             store: args.store.clone(),
             quiet: false,
+            show_trace: args.show_trace,
         };
 
         let result = run_nix_eval(None, "", &options)?;
-        println!("{}", result);
-        return Ok(());
+        return print_result(&result, out_env.as_deref());
     }
 
     let installable = args.installable.as_deref().unwrap_or(".#");
@@ -76,11 +202,34 @@ pub fn cmd_eval(args: EvalArgs) -> Result<()> {
 
     if !resolved.is_local {
         // Passthrough to nix eval
-        let flake_ref = resolved.flake_ref.as_deref().unwrap_or("");
-        let full_ref = format!("{}#{}", flake_ref, resolved.attr_part);
+        let full_ref = resolved.full_ref();
 
         let mut cmd = crate::command::NixCommand::new("nix");
         cmd.args(["eval", &full_ref]);
+        cmd.args(crate::registry::override_flake_args());
+
+        if args.exists {
+            // nix has no existence check; a missing attribute fails to
+            // evaluate, while `_: true` leaves an existing one unevaluated.
+            cmd.args(["--apply", "_: true"]);
+            return exit_for_exists(match cmd.output() {
+                Ok(_) => Ok(true),
+                Err(e) if e.to_string().contains("does not provide attribute") => Ok(false),
+                Err(e) => Err(e),
+            });
+        }
+
+        if args.attr_names || args.paths {
+            let apply_fn = if args.attr_names {
+                "builtins.attrNames"
+            } else {
+                OUT_PATHS_FN
+            };
+            cmd.args(["--json", "--apply", apply_fn]);
+
+            let items: Vec<String> = cmd.json()?;
+            return print_list(&items, args.json);
+        }
 
         if args.json {
             cmd.arg("--json");
@@ -98,6 +247,10 @@ pub fn cmd_eval(args: EvalArgs) -> Result<()> {
             cmd.args(["--store", s]);
         }
 
+        if args.show_trace {
+            cmd.arg("--show-trace");
+        }
+
         for (name, expr) in parse_arg_pairs(&args.extra_args) {
             cmd.args(["--arg", &name, &expr]);
         }
@@ -106,14 +259,44 @@ pub fn cmd_eval(args: EvalArgs) -> Result<()> {
             cmd.args(["--argstr", &name, &value]);
         }
 
+        if out_env.is_some() {
+            return print_result(&cmd.output()?, out_env.as_deref());
+        }
         return cmd.run();
     }
 
     let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
 
+    if args.exists {
+        return exit_for_exists(
+            ensure_lock(flake_dir, None)
+                .and_then(|()| flake_attr_exists(flake_dir, &resolved.attr_part)),
+        );
+    }
+
     // Ensure lock exists
     ensure_lock(flake_dir, None)?;
 
+    if args.attr_names {
+        let names = eval_flake_attr_names(flake_dir, &resolved.attr_part)?;
+        return print_list(&names, args.json);
+    }
+
+    if args.paths {
+        let options = EvalOptions {
+            output_json: true,
+            apply_fn: Some(OUT_PATHS_FN.to_string()),
+            extra_args: parse_arg_pairs(&args.extra_args),
+            extra_argstrs: parse_arg_pairs(&args.extra_argstrs),
+            store: args.store.clone(),
+            show_trace: args.show_trace,
+            ..Default::default()
+        };
+        let result = run_nix_eval(Some(flake_dir), &resolved.attr_part, &options)?;
+        let paths: Vec<String> = serde_json::from_str(&result)?;
+        return print_list(&paths, args.json);
+    }
+
     let options = EvalOptions {
         output_json: args.json,
         raw: args.raw,
@@ -123,10 +306,38 @@ pub fn cmd_eval(args: EvalArgs) -> Result<()> {
         expr: None,
         store: args.store.clone(),
         quiet: false,
+        show_trace: args.show_trace,
     };
 
     let result = run_nix_eval(Some(flake_dir), &resolved.attr_part, &options)?;
-    println!("{}", result);
+    print_result(&result, out_env.as_deref())
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_env() {
+        let value = json!({
+            "host": "db.example.com",
+            "port": 5432,
+            "tls": true,
+            "note": null,
+            "motd": "it's up",
+            "max-conns": 20
+        });
+        assert_eq!(
+            format_env("CFG", &value).unwrap(),
+            "CFG_host='db.example.com'\n\
+             CFG_max_conns='20'\n\
+             CFG_motd='it'\\''s up'\n\
+             CFG_note=''\n\
+             CFG_port='5432'\n\
+             CFG_tls='true'\n"
+        );
+        assert!(format_env("CFG", &json!({ "nested": { "a": 1 } })).is_err());
+        assert!(format_env("CFG", &json!("flat")).is_err());
+    }
 }
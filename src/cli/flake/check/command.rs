@@ -1,10 +1,84 @@
-use crate::flake::{ensure_lock, resolve_installable};
-use crate::nix::{eval_flake_outputs, get_system};
+use crate::flake::{ensure_lock, join_attr_path, resolve_installable, validate_output_schema};
+use crate::nix::{eval_flake_outputs, get_derivation_path, get_system, run_nix_eval, EvalOptions};
 use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
 use rayon::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use std::path::Path;
+
+/// Output categories whose derivations are evaluated (but not built) by check.
+const EVALUATED_CATEGORIES: &[&str] = &["packages", "devShells"];
+
+/// Non-derivation output categories given a shallow smoke test, with the
+/// smoke_check.nix function applied to each of their entries.
+const SMOKE_CATEGORIES: &[(&str, &str)] =
+    &[("overlays", "overlay"), ("nixosModules", "nixosModule")];
+
+/// A single problem found while checking a flake.
+#[derive(Debug, Serialize)]
+struct CheckFailure {
+    /// Output attribute, or `file:line:col` for problems in the source
+    attr: String,
+    /// "parse", "schema", "eval", "smoke", "build" or "budget"
+    kind: &'static str,
+    message: String,
+}
+
+/// An impure access found with `--impure-warnings`.
+#[derive(Debug, Serialize)]
+struct CheckWarning {
+    /// Output attribute whose evaluation made the access; empty for the
+    /// outputs function itself and for accesses found by scanning
+    attr: String,
+    /// The file that made the access, or `file:line:col` when scanning
+    location: String,
+    /// "impure" when seen during evaluation, "impure-scan" when found by
+    /// scanning the sources because evaluation couldn't be traced
+    kind: &'static str,
+    message: String,
+}
+
+/// How long evaluating, smoke testing or building one output took.
+#[derive(Debug, Serialize)]
+struct CheckTiming {
+    attr: String,
+    /// "eval", "smoke" or "build"
+    kind: &'static str,
+    seconds: f64,
+    /// Whether this step counted as passed
+    #[serde(skip)]
+    passed: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct CheckReport {
+    passed: usize,
+    failed: usize,
+    failures: Vec<CheckFailure>,
+    timings: Vec<CheckTiming>,
+    /// Impure accesses, with `--impure-warnings`; these don't fail the check
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<CheckWarning>,
+}
 
 /// Run flake checks
-pub fn cmd_check(flake_ref: Option<&str>, all_systems: bool) -> Result<()> {
+///
+/// All checks are run to completion; evaluation and build errors are
+/// collected and reported together at the end, with the `slowest` checks.
+/// With `fail_slower_than`, anything taking longer than that many seconds
+/// fails too. With `impure_warnings`, the evaluated outputs and checks are
+/// evaluated again with traced builtins to find what pure evaluation would
+/// reject, which is reported but not failed.
+pub fn cmd_check(
+    flake_ref: Option<&str>,
+    all_systems: bool,
+    strict: bool,
+    json: bool,
+    slowest: usize,
+    fail_slower_than: Option<f64>,
+    impure_warnings: bool,
+) -> Result<()> {
     let flake_ref = flake_ref.unwrap_or(".");
     let resolved = resolve_installable(flake_ref);
 
@@ -19,66 +93,843 @@ pub fn cmd_check(flake_ref: Option<&str>, all_systems: bool) -> Result<()> {
     }
 
     let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+
+    // Syntax errors are found in seconds; don't wait for evaluation to trip
+    // over them, or miss them in files nothing imports yet
+    let parse_failures =
+        crate::progress::with_status("Parsing .nix files", || parse_nix_files(flake_dir))?;
+    if !parse_failures.is_empty() {
+        let report = CheckReport {
+            passed: 0,
+            failed: parse_failures.len(),
+            failures: parse_failures,
+            timings: Vec::new(),
+            warnings: Vec::new(),
+        };
+        return finish(report, json, slowest);
+    }
+
     let system = get_system()?;
 
     // Ensure lock exists
     ensure_lock(flake_dir, None)?;
 
-    // Get checks for current system
-    let checks_attr = format!("checks.{}", system);
-
-    // Build all checks
-    let outputs = eval_flake_outputs(flake_dir, all_systems, false)?;
-
-    if let Some(ref outputs) = outputs {
-        if let Some(checks) = outputs.get("checks").and_then(|c| c.get(&system)) {
-            if let Some(check_names) = checks.as_object() {
-                let mut passed = 0;
-                let mut failed = 0;
-
-                let names: Vec<String> = check_names.keys().cloned().collect();
-                let results: Vec<(String, Result<()>)> = names
-                    .into_par_iter()
-                    .map(|name| {
-                        let attr = format!("{}.{}", checks_attr, name);
-                        let options = crate::nix::BuildOptions {
-                            out_link: None,
-                            ..Default::default()
-                        };
-
-                        let res = crate::nix::run_nix_build(flake_dir, &attr, &options, true);
-                        (name, res.map(|_| ()))
-                    })
-                    .collect();
-
-                for (name, res) in results {
-                    print!("checking {}: ", name);
-                    match res {
-                        Ok(_) => {
-                            println!("ok");
-                            passed += 1;
-                        }
-                        Err(e) => {
-                            println!("FAILED");
-                            tracing::debug!("  Error: {}", e);
-                            failed += 1;
-                        }
+    let outputs = eval_flake_outputs(flake_dir, all_systems, false)?
+        .context("Failed to evaluate flake outputs")?;
+
+    let mut failures = Vec::new();
+    let mut timings = Vec::new();
+    let mut passed = 0;
+
+    for warning in check_output_schema(flake_dir, &outputs) {
+        if strict {
+            failures.push(CheckFailure {
+                attr: String::new(),
+                kind: "schema",
+                message: warning,
+            });
+        } else {
+            crate::nix::warn(&warning);
+        }
+    }
+
+    // Evaluate packages and devShells for the current system
+    let mut eval_attrs = Vec::new();
+    for category in EVALUATED_CATEGORIES {
+        if let Some(items) = outputs
+            .get(*category)
+            .and_then(|c| c.get(&system))
+            .and_then(|c| c.as_object())
+        {
+            for name in items.keys().filter(|k| !k.starts_with('_')) {
+                eval_attrs.push(join_attr_path(&[*category, system.as_str(), name.as_str()]));
+            }
+        }
+    }
+
+    let mut traced_attrs = eval_attrs.clone();
+
+    let eval_results: Vec<(String, f64, Result<String>)> = eval_attrs
+        .into_par_iter()
+        .map(|attr| {
+            let start = std::time::Instant::now();
+            let res = get_derivation_path(flake_dir, &attr);
+            (attr, start.elapsed().as_secs_f64(), res)
+        })
+        .collect();
+
+    for (attr, seconds, res) in eval_results {
+        timings.push(CheckTiming {
+            attr: attr.clone(),
+            kind: "eval",
+            seconds,
+            passed: res.is_ok(),
+        });
+        match res {
+            Ok(_) => passed += 1,
+            Err(e) => failures.push(CheckFailure {
+                attr,
+                kind: "eval",
+                message: clean_error(&e),
+            }),
+        }
+    }
+
+    // Apply overlays and evaluate modules on their own
+    let nix_dir = crate::nix::get_nix_dir()?;
+    let mut smoke_attrs = Vec::new();
+    for (category, check) in SMOKE_CATEGORIES {
+        if let Some(items) = outputs.get(*category).and_then(|c| c.as_object()) {
+            for name in items.keys().filter(|k| !k.starts_with('_')) {
+                smoke_attrs.push((join_attr_path(&[*category, name.as_str()]), *check));
+            }
+        }
+    }
+
+    let smoke_results: Vec<(String, f64, Result<String>)> = smoke_attrs
+        .into_par_iter()
+        .map(|(attr, check)| {
+            let options = EvalOptions {
+                output_json: true,
+                apply_fn: Some(format!(
+                    "(import ({} + \"/smoke_check.nix\") {{ inherit (context) inputs; }}).{}",
+                    crate::nix::nix_path(&nix_dir),
+                    check
+                )),
+                quiet: true,
+                ..Default::default()
+            };
+            let start = std::time::Instant::now();
+            let res = run_nix_eval(Some(flake_dir), &attr, &options);
+            (attr, start.elapsed().as_secs_f64(), res)
+        })
+        .collect();
+
+    for (attr, seconds, res) in smoke_results {
+        timings.push(CheckTiming {
+            attr: attr.clone(),
+            kind: "smoke",
+            seconds,
+            passed: matches!(&res, Ok(out) if out.trim() != "null"),
+        });
+        match res {
+            Ok(out) if out.trim() == "null" => {
+                tracing::debug!(
+                    "Skipping {}: no nixpkgs input to evaluate modules with",
+                    attr
+                );
+            }
+            Ok(_) => passed += 1,
+            Err(e) => failures.push(CheckFailure {
+                attr,
+                kind: "smoke",
+                message: clean_error(&e),
+            }),
+        }
+    }
+
+    // Build all checks for the current system
+    let check_names: Vec<String> = outputs
+        .get("checks")
+        .and_then(|c| c.get(&system))
+        .and_then(|c| c.as_object())
+        .map(|c| c.keys().filter(|k| !k.starts_with('_')).cloned().collect())
+        .unwrap_or_default();
+
+    if check_names.is_empty() && !json {
+        println!("No checks found for {}", system);
+    }
+
+    let warnings = if impure_warnings {
+        traced_attrs.extend(
+            check_names
+                .iter()
+                .map(|name| join_attr_path(&["checks", &system, name])),
+        );
+        crate::progress::with_status("Tracing impure accesses", || {
+            impurity_warnings(flake_dir, &traced_attrs)
+        })?
+    } else {
+        Vec::new()
+    };
+
+    let results: Vec<(String, f64, Result<()>)> = check_names
+        .into_par_iter()
+        .map(|name| {
+            let attr = join_attr_path(&["checks", &system, &name]);
+            let options = crate::nix::BuildOptions {
+                out_link: None,
+                ..Default::default()
+            };
+
+            let start = std::time::Instant::now();
+            let res = crate::nix::run_nix_build(flake_dir, &attr, &options, true);
+            (name, start.elapsed().as_secs_f64(), res.map(|_| ()))
+        })
+        .collect();
+
+    // Remember durations of successful builds for `flake plan`
+    let times: Vec<(String, f64)> = results
+        .iter()
+        .filter(|(_, _, res)| res.is_ok())
+        .map(|(name, secs, _)| (join_attr_path(&["checks", &system, name]), *secs))
+        .collect();
+    if let Err(e) = crate::plan::record_build_times(&times) {
+        tracing::debug!("Failed to record build times: {}", e);
+    }
+
+    for (name, seconds, res) in results {
+        timings.push(CheckTiming {
+            attr: join_attr_path(&["checks", &system, &name]),
+            kind: "build",
+            seconds,
+            passed: res.is_ok(),
+        });
+        crate::cli::gha::group(&format!("checking {}", name));
+        let status = match res {
+            Ok(_) => {
+                passed += 1;
+                "ok"
+            }
+            Err(e) => {
+                tracing::debug!("  Error: {}", e);
+                crate::cli::gha::error(&format!("{:#}", e));
+                let message = clean_error(&e);
+                let kind = if is_build_failure(&message) {
+                    "build"
+                } else {
+                    "eval"
+                };
+                failures.push(CheckFailure {
+                    attr: join_attr_path(&["checks", &system, &name]),
+                    kind,
+                    message,
+                });
+                "FAILED"
+            }
+        };
+        if !json {
+            println!("checking {}: {}", name, status);
+        }
+        crate::cli::gha::end_group();
+    }
+
+    timings.sort_by(|a, b| b.seconds.total_cmp(&a.seconds));
+    if let Some(budget) = fail_slower_than {
+        // Slow steps that passed fail instead
+        let slow = over_budget(&timings, budget);
+        passed -= slow.len();
+        failures.extend(slow);
+    }
+
+    let report = CheckReport {
+        passed,
+        failed: failures.len(),
+        failures,
+        timings,
+        warnings,
+    };
+    finish(report, json, slowest)
+}
+
+/// Print the report and fail if anything failed.
+fn finish(report: CheckReport, json: bool, slowest: usize) -> Result<()> {
+    if json {
+        println!("{}", crate::schema::FLAKE_CHECK.to_json(&report)?);
+    } else {
+        print_slowest(&report.timings, slowest);
+        print_report(&report);
+    }
+
+    if report.failed > 0 {
+        anyhow::bail!("{} check(s) failed", report.failed);
+    }
+
+    Ok(())
+}
+
+/// Whether `entry` is a `result` or `result-*` link left by a build.
+fn is_result_link(entry: &walkdir::DirEntry) -> bool {
+    let name = entry.file_name().to_string_lossy();
+    entry.path_is_symlink() && (name == "result" || name.starts_with("result-"))
+}
+
+/// The `.nix` files of the flake at `flake_dir`, relative to it: those git
+/// knows about and still in the work tree, or every one outside hidden
+/// directories and build result links for a flake outside git.
+fn nix_files(flake_dir: &Path) -> Vec<std::path::PathBuf> {
+    let is_nix = |path: &Path| path.extension().is_some_and(|e| e == "nix");
+    if let Some(files) = crate::git::tracked_files(flake_dir) {
+        // The index still lists files deleted but not yet staged
+        return files
+            .into_iter()
+            .filter(|f| is_nix(f) && flake_dir.join(f).is_file())
+            .collect();
+    }
+    walkdir::WalkDir::new(flake_dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            !entry.file_name().to_string_lossy().starts_with('.') && !is_result_link(entry)
+        })
+        .flatten()
+        .filter(|entry| entry.file_type().is_file() && is_nix(entry.path()))
+        .filter_map(|entry| {
+            entry
+                .path()
+                .strip_prefix(flake_dir)
+                .ok()
+                .map(Path::to_path_buf)
+        })
+        .collect()
+}
+
+/// Parse every `.nix` file of the flake, returning a failure per file with
+/// a syntax error.
+///
+/// Only nix-instantiate rejecting a file is a syntax error; failing to run
+/// it at all is an error of the check itself.
+fn parse_nix_files(flake_dir: &Path) -> Result<Vec<CheckFailure>> {
+    let results: Vec<Result<Option<CheckFailure>>> = nix_files(flake_dir)
+        .into_par_iter()
+        .map(|file| {
+            let mut cmd = crate::command::NixCommand::new("nix-instantiate");
+            cmd.arg("--parse").arg(flake_dir.join(&file));
+            let Err(err) = cmd.output() else {
+                return Ok(None);
+            };
+            if !format!("{:#}", err).starts_with("Command failed:") {
+                return Err(err);
+            }
+            Ok(Some(CheckFailure {
+                attr: file.display().to_string(),
+                kind: "parse",
+                message: clean_error(&err),
+            }))
+        })
+        .collect();
+    let mut failures = Vec::new();
+    for result in results {
+        failures.extend(result?);
+    }
+    failures.sort_by(|a, b| a.attr.cmp(&b.attr));
+    Ok(failures)
+}
+
+/// Which bytes of a Nix file are code, as opposed to comments and string
+/// contents. Antiquotations inside strings count as code.
+fn code_mask(source: &str) -> Vec<bool> {
+    #[derive(Clone, Copy)]
+    enum Ctx {
+        /// Code, with the number of braces opened in it
+        Code(usize),
+        Str,
+        IndStr,
+    }
+    let bytes = source.as_bytes();
+    let mut mask = vec![false; bytes.len()];
+    let mut stack = vec![Ctx::Code(0)];
+    let at = |i: usize, s: &[u8]| bytes[i..].starts_with(s);
+    let mut i = 0;
+    while i < bytes.len() {
+        match *stack.last().unwrap() {
+            Ctx::Code(depth) => match bytes[i] {
+                b'#' => {
+                    while i < bytes.len() && bytes[i] != b'\n' {
+                        i += 1;
                     }
+                    continue;
+                }
+                b'/' if at(i, b"/*") => {
+                    i = source[i + 2..]
+                        .find("*/")
+                        .map_or(bytes.len(), |end| i + end + 4);
+                    continue;
+                }
+                b'"' => stack.push(Ctx::Str),
+                b'\'' if at(i, b"''") => {
+                    stack.push(Ctx::IndStr);
+                    i += 1;
+                }
+                b'{' => {
+                    *stack.last_mut().unwrap() = Ctx::Code(depth + 1);
+                    mask[i] = true;
+                }
+                b'}' if depth == 0 && stack.len() > 1 => {
+                    stack.pop();
+                }
+                b'}' => {
+                    *stack.last_mut().unwrap() = Ctx::Code(depth.saturating_sub(1));
+                    mask[i] = true;
+                }
+                _ => mask[i] = true,
+            },
+            Ctx::Str => match bytes[i] {
+                b'\\' => i += 1,
+                b'"' => {
+                    stack.pop();
+                }
+                b'$' if at(i, b"${") => {
+                    stack.push(Ctx::Code(0));
+                    i += 1;
+                }
+                _ => {}
+            },
+            Ctx::IndStr => {
+                if at(i, b"'''") || at(i, b"''$") || at(i, b"''\\") {
+                    i += 2;
+                } else if at(i, b"''") {
+                    stack.pop();
+                    i += 1;
+                } else if at(i, b"${") {
+                    stack.push(Ctx::Code(0));
+                    i += 1;
                 }
+            }
+        }
+        i += 1;
+    }
+    mask
+}
+
+/// Byte offset of the `}` closing the `{` at `open`, counting code only.
+fn closing_brace(source: &str, mask: &[bool], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, b) in source.bytes().enumerate().skip(open) {
+        if !mask[i] {
+            continue;
+        }
+        match b {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+static GET_ENV: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\b(?:builtins\.)?getEnv\b(?:\s+"([^"$\\]*)")?"#).unwrap());
+static IMPURE_BUILTIN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\bbuiltins\.(currentSystem|currentTime|nixPath|storePath)\b").unwrap()
+});
+static SEARCH_PATH: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[\w.+-]+(?:/[\w.+-]+)*>").unwrap());
+static PATH_LITERAL: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:\.\.?)?(?:/[\w.+-]+)+").unwrap());
+static FETCHER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b(builtins\.(?:fetchTarball|fetchurl|fetchGit|fetchTree|fetchMercurial)|fetchTarball|fetchGit)\b\s*").unwrap()
+});
+static HASH_ATTR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(sha256|hash|narHash|rev)\s*=").unwrap());
+
+/// Impure accesses in the Nix file `file`, a source file of the flake in
+/// `flake_dir`, as (byte offset, message) pairs in file order.
+///
+/// These are what pure evaluation (as `nix flake check` does it) rejects:
+/// environment variables, the search path, paths outside the flake and
+/// fetches without a hash. This is the fallback for when evaluation can't
+/// be traced: a textual scan misses accesses built from computed names and
+/// anything in inputs, and reports files nothing imports all the same.
+fn find_impurities(
+    source: &str,
+    file: &Path,
+    flake_dir: &Path,
+    store_dir: &str,
+) -> Vec<(usize, String)> {
+    let mask = code_mask(source);
+    let is_code = |m: &regex::Match| mask[m.start()];
+    let before = |m: &regex::Match| source[..m.start()].chars().next_back();
+    let mut found = Vec::new();
+
+    for caps in GET_ENV.captures_iter(source) {
+        let m = caps.get(0).unwrap();
+        if !is_code(&m) || before(&m) == Some('.') && !m.as_str().starts_with("builtins") {
+            continue;
+        }
+        let message = match caps.get(1) {
+            Some(var) => format!("reads environment variable {}", var.as_str()),
+            None => "reads an environment variable".to_string(),
+        };
+        found.push((m.start(), message));
+    }
 
-                println!();
-                println!("{} passed, {} failed", passed, failed);
+    for caps in IMPURE_BUILTIN.captures_iter(source) {
+        let m = caps.get(0).unwrap();
+        if is_code(&m) {
+            found.push((m.start(), format!("uses {}", m.as_str())));
+        }
+    }
 
-                if failed > 0 {
-                    anyhow::bail!("{} test(s) failed", failed);
+    for m in SEARCH_PATH.find_iter(source) {
+        if is_code(&m) && !before(&m).is_some_and(|c| c.is_alphanumeric()) {
+            found.push((m.start(), format!("looks up {} in NIX_PATH", m.as_str())));
+        }
+    }
+
+    let base = flake_dir.join(file);
+    let base = base.parent().unwrap_or(flake_dir);
+    for m in PATH_LITERAL.find_iter(source) {
+        let starts_token = before(&m).is_none_or(|c| c.is_whitespace() || "([{=;".contains(c));
+        if !is_code(&m) || !starts_token {
+            continue;
+        }
+        let text = m.as_str();
+        if text.starts_with('/') {
+            if !text.starts_with(store_dir) && !Path::new(text).starts_with(flake_dir) {
+                found.push((m.start(), format!("uses path {} outside the flake", text)));
+            }
+        } else if text.starts_with("..") {
+            // Resolve lexically: the path need not exist yet
+            let mut resolved = base.to_path_buf();
+            for part in Path::new(text).components() {
+                match part {
+                    std::path::Component::ParentDir => {
+                        resolved.pop();
+                    }
+                    std::path::Component::Normal(p) => resolved.push(p),
+                    _ => {}
                 }
+            }
+            if !resolved.starts_with(flake_dir) {
+                found.push((m.start(), format!("uses path {} outside the flake", text)));
+            }
+        }
+    }
 
-                return Ok(());
+    for caps in FETCHER.captures_iter(source) {
+        let m = caps.get(0).unwrap();
+        if !is_code(&m) || before(&m) == Some('.') {
+            continue;
+        }
+        let fetcher = caps.get(1).unwrap().as_str();
+        let hashed = match source[m.end()..].chars().next() {
+            Some('"') => false,
+            Some('{') => closing_brace(source, &mask, m.end()).is_none_or(|close| {
+                let args = &source[m.end()..close];
+                HASH_ATTR.captures_iter(args).any(|c| {
+                    let name = &c[1];
+                    name != "rev" || fetcher.ends_with("fetchGit") || fetcher.ends_with("fetchTree")
+                })
+            }),
+            // The argument is computed elsewhere; give it the benefit of the doubt
+            _ => true,
+        };
+        if !hashed {
+            found.push((m.start(), format!("calls {} without a hash", fetcher)));
+        }
+    }
+
+    found.sort_by_key(|(offset, _)| *offset);
+    found
+}
+
+/// 1-based line and column of byte `offset` in `source`.
+fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before[before.rfind('\n').map_or(0, |n| n + 1)..]
+        .chars()
+        .count()
+        + 1;
+    (line, col)
+}
+
+/// Warnings for the impure accesses evaluating `attrs` makes, or, if
+/// evaluation can't be traced, for those found in every `.nix` file of the
+/// flake.
+fn impurity_warnings(flake_dir: &Path, attrs: &[String]) -> Result<Vec<CheckWarning>> {
+    match crate::nix::trace_impurities(flake_dir, attrs) {
+        Ok(accesses) => Ok(accesses
+            .into_iter()
+            .map(|access| {
+                let file = Path::new(&access.file);
+                CheckWarning {
+                    attr: access.attr,
+                    location: file
+                        .strip_prefix(flake_dir)
+                        .unwrap_or(file)
+                        .display()
+                        .to_string(),
+                    kind: "impure",
+                    message: access.message,
+                }
+            })
+            .collect()),
+        Err(e) => {
+            tracing::debug!("Failed to trace evaluation: {:#}", e);
+            crate::nix::warn(
+                "Could not trace evaluation for impure accesses; scanning the sources instead",
+            );
+            scanned_impurity_warnings(flake_dir)
+        }
+    }
+}
+
+/// Warnings for impure accesses in every `.nix` file of the flake.
+fn scanned_impurity_warnings(flake_dir: &Path) -> Result<Vec<CheckWarning>> {
+    let store_dir = crate::nix::get_store_dir()?;
+    let mut warnings = Vec::new();
+    for file in nix_files(flake_dir) {
+        let Ok(source) = std::fs::read_to_string(flake_dir.join(&file)) else {
+            continue;
+        };
+        for (offset, message) in find_impurities(&source, &file, flake_dir, &store_dir) {
+            let (line, col) = line_col(&source, offset);
+            warnings.push(CheckWarning {
+                attr: String::new(),
+                location: format!("{}:{}:{}", file.display(), line, col),
+                kind: "impure-scan",
+                message,
+            });
+        }
+    }
+    Ok(warnings)
+}
+
+/// Failures for everything that passed but took longer than `budget` seconds.
+///
+/// Steps that already failed are left alone, so nothing fails twice.
+fn over_budget(timings: &[CheckTiming], budget: f64) -> Vec<CheckFailure> {
+    timings
+        .iter()
+        .filter(|t| t.passed && t.seconds > budget)
+        .map(|t| CheckFailure {
+            attr: t.attr.clone(),
+            kind: "budget",
+            message: format!(
+                "{} took {:.1}s, over the budget of {}s",
+                t.kind, t.seconds, budget
+            ),
+        })
+        .collect()
+}
+
+/// Print the `n` slowest entries of `timings`, which are sorted slowest first.
+fn print_slowest(timings: &[CheckTiming], n: usize) {
+    if n == 0 || timings.is_empty() {
+        return;
+    }
+    println!();
+    println!("Slowest:");
+    for timing in timings.iter().take(n) {
+        println!(
+            "  {:>7.1}s  {} ({})",
+            timing.seconds, timing.attr, timing.kind
+        );
+    }
+}
+
+/// Print failures grouped by kind, followed by a summary line.
+fn print_report(report: &CheckReport) {
+    let groups = [
+        ("parse", "Syntax errors"),
+        ("schema", "Output schema errors"),
+        ("eval", "Evaluation errors"),
+        ("smoke", "Overlays and modules that fail to apply"),
+        ("build", "Build failures"),
+        ("budget", "Over the time budget"),
+    ];
+
+    let warning_groups = [
+        ("impure", "Impure accesses during evaluation"),
+        (
+            "impure-scan",
+            "Possible impure accesses, from scanning the sources",
+        ),
+    ];
+    for (kind, title) in warning_groups {
+        let warnings: Vec<&CheckWarning> =
+            report.warnings.iter().filter(|w| w.kind == kind).collect();
+        if warnings.is_empty() {
+            continue;
+        }
+
+        println!();
+        println!("{} ({}):", title, warnings.len());
+        for warning in warnings {
+            if warning.attr.is_empty() {
+                println!("  {}: {}", warning.location, warning.message);
+            } else {
+                println!(
+                    "  {}: {} (evaluating {})",
+                    warning.location, warning.message, warning.attr
+                );
             }
         }
     }
 
-    println!("No checks found for {}", system);
+    for (kind, title) in groups {
+        let failures: Vec<&CheckFailure> =
+            report.failures.iter().filter(|f| f.kind == kind).collect();
+        if failures.is_empty() {
+            continue;
+        }
 
-    Ok(())
+        println!();
+        println!("{} ({}):", title, failures.len());
+        for failure in failures {
+            if failure.attr.is_empty() {
+                println!("  {}", failure.message);
+                continue;
+            }
+            println!("  {}", failure.attr);
+            for line in failure.message.lines() {
+                println!("    {}", line);
+            }
+        }
+    }
+
+    println!();
+    println!("{} passed, {} failed", report.passed, report.failed);
+}
+
+/// Strip the command wrapper prefix from a captured nix error.
+fn clean_error(e: &anyhow::Error) -> String {
+    let msg = format!("{:#}", e);
+    msg.strip_prefix("Command failed:")
+        .unwrap_or(&msg)
+        .trim()
+        .to_string()
+}
+
+/// Whether a nix error came from a failed build rather than evaluation.
+fn is_build_failure(message: &str) -> bool {
+    message.contains("builder for")
+        || message.contains("Cannot build")
+        || message.contains("build of")
+}
+
+/// Validate top-level outputs against the known schemas.
+///
+/// Returns the problems found, which `--strict` turns into failures.
+fn check_output_schema(flake_dir: &Path, outputs: &serde_json::Value) -> Vec<String> {
+    // Outputs declared by the flake's own `schemas` output are accepted too
+    let extra_known: Vec<String> = if outputs.get("schemas").is_some() {
+        let options = EvalOptions {
+            output_json: true,
+            apply_fn: Some("builtins.attrNames".to_string()),
+            quiet: true,
+            ..Default::default()
+        };
+        run_nix_eval(Some(flake_dir), "schemas", &options)
+            .ok()
+            .and_then(|out| serde_json::from_str(&out).ok())
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    validate_output_schema(outputs, &extra_known)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nix_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("pkgs/hello")).unwrap();
+        std::fs::create_dir_all(root.join(".direnv")).unwrap();
+        std::fs::create_dir_all(root.join("result-parser")).unwrap();
+        for file in [
+            "flake.nix",
+            "results.nix",
+            "result-parser/default.nix",
+            "pkgs/hello/default.nix",
+            "pkgs/hello/README.md",
+            ".direnv/env.nix",
+        ] {
+            std::fs::write(root.join(file), "{ }").unwrap();
+        }
+        let out = tempfile::tempdir().unwrap();
+        std::fs::write(out.path().join("default.nix"), "{ }").unwrap();
+        std::os::unix::fs::symlink(out.path(), root.join("result")).unwrap();
+        std::os::unix::fs::symlink(out.path(), root.join("result-dev")).unwrap();
+        assert_eq!(
+            nix_files(root),
+            vec![
+                std::path::PathBuf::from("flake.nix"),
+                std::path::PathBuf::from("pkgs/hello/default.nix"),
+                std::path::PathBuf::from("result-parser/default.nix"),
+                std::path::PathBuf::from("results.nix"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_impurities() {
+        let source = r#"{ pkgs, fetchurl }:
+let
+  # builtins.getEnv "COMMENTED"
+  home = builtins.getEnv "HOME";
+  cfg = import /etc/site.nix;
+  local = import ./lib/default.nix;
+  shared = import ../../shared.nix;
+  pinned = fetchTarball { url = "https://example.com/a.tar.gz"; sha256 = "abc"; };
+  loose = fetchTarball "https://example.com/b.tar.gz";
+  repo = builtins.fetchGit { url = "https://example.com/c.git"; ref = "main"; };
+  fine = fetchurl { url = "https://example.com/d"; };
+  text = ''
+    see <nixpkgs> and /usr/bin ${toString <nixpkgs>}
+  '';
+  date = "${builtins.currentTime}";
+in pkgs.lib.recursiveUpdate local { }
+"#;
+        let found: Vec<(usize, String)> = find_impurities(
+            source,
+            Path::new("nix/default.nix"),
+            Path::new("/src/project"),
+            "/nix/store",
+        )
+        .into_iter()
+        .map(|(offset, message)| (line_col(source, offset).0, message))
+        .collect();
+        assert_eq!(
+            found,
+            vec![
+                (4, "reads environment variable HOME".to_string()),
+                (5, "uses path /etc/site.nix outside the flake".to_string()),
+                (
+                    7,
+                    "uses path ../../shared.nix outside the flake".to_string()
+                ),
+                (9, "calls fetchTarball without a hash".to_string()),
+                (10, "calls builtins.fetchGit without a hash".to_string()),
+                (13, "looks up <nixpkgs> in NIX_PATH".to_string()),
+                (15, "uses builtins.currentTime".to_string()),
+            ]
+        );
+        assert_eq!(line_col(source, source.find("home").unwrap()), (4, 3));
+    }
+
+    #[test]
+    fn test_over_budget() {
+        let timing = |attr: &str, seconds, passed| CheckTiming {
+            attr: attr.to_string(),
+            kind: "build",
+            seconds,
+            passed,
+        };
+        let timings = [
+            timing("checks.x86_64-linux.integration", 95.0, true),
+            timing("checks.x86_64-linux.broken", 300.0, false),
+            timing("checks.x86_64-linux.unit", 12.5, true),
+        ];
+        let failures = over_budget(&timings, 60.0);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].attr, "checks.x86_64-linux.integration");
+        assert_eq!(failures[0].kind, "budget");
+        assert_eq!(
+            failures[0].message,
+            "build took 95.0s, over the budget of 60s"
+        );
+        assert!(over_budget(&timings, 120.0).is_empty());
+    }
 }
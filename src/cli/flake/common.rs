@@ -1,22 +1,36 @@
+use crate::cli::style::{paint, Stream};
 use anyhow::{Context, Result};
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
 
-/// Wrap text in ANSI bold codes.
+/// Bold text for stdout, unless color is off.
 pub fn bold(text: &str) -> String {
-    format!("\x1b[1m{}\x1b[0m", text)
+    paint(Stream::Stdout, "1", text)
 }
 
 /// Format a magenta+bold string (for type labels like "Nixpkgs overlay")
 pub fn magenta_bold(text: &str) -> String {
-    format!("\x1b[35;1m{}\x1b[0m", text)
+    paint(Stream::Stdout, "35;1", text)
 }
 
-pub fn run_template_copy(
-    target_dir: &std::path::Path,
-    template_ref: &str,
-    is_new: bool,
-) -> Result<()> {
+/// Templates shipped with trix, usable as `builtin:NAME`.
+///
+/// They need no network access and no evaluation, so they are plain
+/// directories next to the Nix support files.
+fn builtin_template(name: &str) -> Result<(std::path::PathBuf, String)> {
+    let template_path = crate::nix::get_nix_dir()?.join("templates").join(name);
+    if !template_path.is_dir() {
+        anyhow::bail!("No builtin template named '{}'", name);
+    }
+    let welcome_text = match name {
+        "trix-plugin" => "Build and install it with `trix profile add .`, then run `trix hello`.",
+        _ => "",
+    };
+    Ok((template_path, welcome_text.to_string()))
+}
+
+/// Fetch a template from a flake and return its path and welcome text.
+fn fetch_template(template_ref: &str) -> Result<(std::path::PathBuf, String)> {
     let (flake_ref, template_name) = if let Some(idx) = template_ref.rfind('#') {
         (&template_ref[..idx], &template_ref[idx + 1..])
     } else {
@@ -63,7 +77,7 @@ pub fn run_template_copy(
     let
       flake = import {};
       lock = {};
-      inputs = import {}/inputs.nix {{
+      inputs = import ({} + "/inputs.nix") {{
         inherit lock;
         flakeDirPath = {};
         selfInfo = {{}};
@@ -72,10 +86,10 @@ pub fn run_template_copy(
       template = {};
     in "${{template.path}}@@@${{template.description or ""}}@@@${{template.welcomeText or ""}}"
     "#,
-        flake_nix_path.display(),
+        crate::nix::nix_path(&flake_nix_path),
         lock_expr,
-        nix_dir.display(),
-        flake_path.display(),
+        crate::nix::nix_path(&nix_dir),
+        crate::nix::nix_path(flake_path),
         template_selector
     );
 
@@ -111,12 +125,26 @@ pub fn run_template_copy(
     let _template_description = parts[1];
     let template_welcome_text = parts[2];
 
-    let template_path = std::path::Path::new(template_path_str);
+    let template_path = std::path::PathBuf::from(template_path_str);
 
     if !template_path.exists() {
         anyhow::bail!("Template path does not exist: {}", template_path_str);
     }
 
+    Ok((template_path, template_welcome_text.to_string()))
+}
+
+pub fn run_template_copy(
+    target_dir: &std::path::Path,
+    template_ref: &str,
+    is_new: bool,
+) -> Result<()> {
+    let (template_path, template_welcome_text) = match template_ref.strip_prefix("builtin:") {
+        Some(name) => builtin_template(name)?,
+        None => fetch_template(template_ref)?,
+    };
+    let template_path = template_path.as_path();
+
     // Copy files
     let mut copied_count = 0;
     let mut skipped_count = 0;
@@ -150,18 +178,18 @@ pub fn run_template_copy(
 
     if copied_count > 0 {
         if is_new {
-            println!("Created {} in {}", template_ref, target_dir.display());
+            eprintln!("Created {} in {}", template_ref, target_dir.display());
         } else {
-            println!("Initialized {} in current directory", template_ref);
+            eprintln!("Initialized {} in current directory", template_ref);
         }
     }
 
     if skipped_count > 0 {
-        println!("(skipped {} existing files)", skipped_count);
+        eprintln!("(skipped {} existing files)", skipped_count);
     }
 
     if !template_welcome_text.is_empty() {
-        println!("\n{}", template_welcome_text);
+        eprintln!("\n{}", template_welcome_text);
     }
 
     Ok(())
@@ -0,0 +1,371 @@
+use super::common::bold;
+use crate::flake::{get_flake_inputs, resolve_installable};
+use crate::lock::{resolve_follows, LockFile, LockNode};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::Path;
+
+/// Fields compared between flake.nix input specs and the lock's `original`.
+const ORIGINAL_FIELDS: &[&str] = &["type", "owner", "repo", "ref", "url", "path"];
+
+/// A problem found by `flake doctor`.
+#[derive(Debug, PartialEq)]
+struct Finding {
+    input: String,
+    /// Errors make doctor fail; warnings are informational
+    error: bool,
+    message: String,
+    fix: Option<String>,
+}
+
+impl Finding {
+    fn error(input: &str, message: String, fix: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            error: true,
+            message,
+            fix: Some(fix.to_string()),
+        }
+    }
+
+    fn warning(input: &str, message: String, fix: Option<&str>) -> Self {
+        Self {
+            input: input.to_string(),
+            error: false,
+            message,
+            fix: fix.map(|f| f.to_string()),
+        }
+    }
+}
+
+/// Diagnose a local flake's inputs without fetching anything
+pub fn cmd_doctor(flake_ref: Option<&str>) -> Result<()> {
+    let flake_ref = flake_ref.unwrap_or(".");
+    let resolved = resolve_installable(flake_ref);
+    if !resolved.is_local {
+        anyhow::bail!("flake doctor only works on local flakes");
+    }
+
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+    let flake_lock = flake_dir.join("flake.lock");
+    if !flake_lock.exists() {
+        println!("{} has no flake.lock", flake_dir.display());
+        println!("  fix: run `trix flake lock`");
+        anyhow::bail!("flake doctor found 1 problem");
+    }
+
+    let lock: LockFile = serde_json::from_str(
+        &std::fs::read_to_string(&flake_lock)
+            .with_context(|| format!("Failed to read {}", flake_lock.display()))?,
+    )
+    .with_context(|| format!("{} is not valid JSON", flake_lock.display()))?;
+    let declared = get_flake_inputs(flake_dir)?;
+    let store_dir = crate::nix::get_store_dir()?;
+
+    let mut findings = check_declared_inputs(&declared, &lock);
+    findings.extend(check_follows(&lock));
+    findings.extend(check_store(flake_dir, &lock, &store_dir));
+    findings.extend(check_git(&lock));
+
+    let errors = findings.iter().filter(|f| f.error).count();
+    let warnings = findings.len() - errors;
+
+    if findings.is_empty() {
+        println!("No problems found in {}", flake_dir.display());
+        return Ok(());
+    }
+
+    for finding in &findings {
+        let label = if finding.error { "error" } else { "warning" };
+        println!("{} {}: {}", bold(&finding.input), label, finding.message);
+        if let Some(fix) = &finding.fix {
+            println!("  fix: {}", fix);
+        }
+    }
+    println!();
+    println!("{} problem(s), {} warning(s)", errors, warnings);
+
+    if errors > 0 {
+        anyhow::bail!("flake doctor found {} problem(s)", errors);
+    }
+    Ok(())
+}
+
+/// Compare the inputs declared in flake.nix with the lock's root inputs.
+fn check_declared_inputs(declared: &Value, lock: &LockFile) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let empty = serde_json::Map::new();
+    let declared = declared.as_object().unwrap_or(&empty);
+    let locked_inputs = lock
+        .nodes
+        .get(&lock.root)
+        .and_then(|root| root.inputs.clone())
+        .unwrap_or_default();
+
+    let mut names: Vec<&String> = declared.keys().collect();
+    names.sort();
+    for name in names {
+        let spec = &declared[name.as_str()];
+        let Some(target) = locked_inputs.get(name.as_str()) else {
+            findings.push(Finding::error(
+                name,
+                "declared in flake.nix but missing from flake.lock".to_string(),
+                "run `trix flake lock`",
+            ));
+            continue;
+        };
+
+        if spec["type"] == "follows" {
+            if !target.is_array() {
+                findings.push(Finding::error(
+                    name,
+                    "flake.nix makes this input follow another, but flake.lock locks it"
+                        .to_string(),
+                    "run `trix flake lock`",
+                ));
+            }
+            continue;
+        }
+
+        let Some(original) = target
+            .as_str()
+            .and_then(|node| lock.nodes.get(node))
+            .and_then(|node| node.original.as_ref())
+        else {
+            continue;
+        };
+        for field in ORIGINAL_FIELDS {
+            let (Some(want), Some(have)) = (spec[*field].as_str(), original[*field].as_str())
+            else {
+                continue;
+            };
+            if want != have {
+                findings.push(Finding::error(
+                    name,
+                    format!(
+                        "flake.nix has {} '{}' but flake.lock was made for '{}'",
+                        field, want, have
+                    ),
+                    &format!("run `trix flake update {}`", name),
+                ));
+            }
+        }
+    }
+
+    let mut stale: Vec<&String> = locked_inputs
+        .keys()
+        .filter(|name| !declared.contains_key(name.as_str()))
+        .collect();
+    stale.sort();
+    for name in stale {
+        findings.push(Finding::warning(
+            name,
+            "locked but no longer declared in flake.nix".to_string(),
+            Some("run `trix flake lock` to drop it"),
+        ));
+    }
+
+    findings
+}
+
+/// Check that every follows in the lock file resolves to a node.
+fn check_follows(lock: &LockFile) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut node_names: Vec<&String> = lock.nodes.keys().collect();
+    node_names.sort();
+
+    for node_name in node_names {
+        let Some(inputs) = &lock.nodes[node_name].inputs else {
+            continue;
+        };
+        let mut input_names: Vec<&String> = inputs.keys().collect();
+        input_names.sort();
+        for input in input_names {
+            let Value::Array(path) = &inputs[input] else {
+                continue;
+            };
+            if resolve_follows(lock, path).is_some() {
+                continue;
+            }
+            let display = if *node_name == lock.root {
+                input.clone()
+            } else {
+                format!("{}/{}", node_name, input)
+            };
+            let target: Vec<&str> = path.iter().filter_map(|p| p.as_str()).collect();
+            findings.push(Finding::error(
+                &display,
+                format!("follows '{}', which does not exist", target.join("/")),
+                "fix the `follows` in flake.nix, then run `trix flake lock`",
+            ));
+        }
+    }
+
+    findings
+}
+
+/// Check which locked inputs are already in the store.
+fn check_store(flake_dir: &Path, lock: &LockFile, store_dir: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut names: Vec<&String> = lock.nodes.keys().collect();
+    names.sort();
+
+    for name in names {
+        let node: &LockNode = &lock.nodes[name];
+        let Some(locked) = &node.locked else {
+            continue;
+        };
+
+        if locked.lock_type == "path" {
+            let path = flake_dir.join(locked.path.as_deref().unwrap_or(""));
+            if !path.exists() {
+                findings.push(Finding::error(
+                    name,
+                    format!("path input {} does not exist", path.display()),
+                    &format!(
+                        "create it or point the input elsewhere and run `trix flake update {}`",
+                        name
+                    ),
+                ));
+            }
+            continue;
+        }
+
+        let Some(nar_hash) = &locked.nar_hash else {
+            findings.push(Finding::error(
+                name,
+                "locked without a narHash, so it cannot be fetched reproducibly".to_string(),
+                &format!("run `trix flake update {}`", name),
+            ));
+            continue;
+        };
+
+        match crate::hash::source_store_path(store_dir, nar_hash, "source") {
+            Some(path) if Path::new(&path).exists() => {}
+            Some(path) => findings.push(Finding::warning(
+                name,
+                format!(
+                    "not in the store ({}), it will be fetched on first use",
+                    path
+                ),
+                Some("use `trix --native-fetch` to see download progress"),
+            )),
+            None => {}
+        }
+    }
+
+    findings
+}
+
+/// Check that locally available git inputs still contain their locked revision.
+fn check_git(lock: &LockFile) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut names: Vec<&String> = lock.nodes.keys().collect();
+    names.sort();
+
+    for name in names {
+        let node = &lock.nodes[name];
+        let Some(locked) = &node.locked else {
+            continue;
+        };
+        if locked.lock_type != "git" {
+            continue;
+        }
+
+        let original_ref = node.original.as_ref().and_then(|o| o["ref"].as_str());
+        if let (Some(want), Some(have)) = (original_ref, locked.git_ref.as_deref()) {
+            if want != have {
+                findings.push(Finding::error(
+                    name,
+                    format!("locked from ref '{}' but requests ref '{}'", have, want),
+                    &format!("run `trix flake update {}`", name),
+                ));
+            }
+        }
+
+        let url = locked.url.as_deref().unwrap_or("");
+        let repo_path = if let Some(path) = url.strip_prefix("file://") {
+            path
+        } else if url.starts_with('/') {
+            url
+        } else {
+            continue;
+        };
+        let (Ok(repo), Some(rev)) = (git2::Repository::open(repo_path), locked.rev.as_deref())
+        else {
+            continue;
+        };
+        let found = git2::Oid::from_str(rev)
+            .ok()
+            .and_then(|oid| repo.find_commit(oid).ok())
+            .is_some();
+        if !found {
+            findings.push(Finding::error(
+                name,
+                format!("locked revision {} is not in {}", rev, repo_path),
+                &format!("run `trix flake update {}`", name),
+            ));
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lock_from(value: Value) -> LockFile {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_check_declared_inputs() {
+        let lock = lock_from(json!({
+            "version": 7,
+            "root": "root",
+            "nodes": {
+                "root": { "inputs": { "nixpkgs": "nixpkgs", "old": "old" } },
+                "nixpkgs": {
+                    "locked": { "type": "github", "owner": "NixOS", "repo": "nixpkgs" },
+                    "original": { "type": "github", "owner": "NixOS", "repo": "nixpkgs", "ref": "nixos-24.05" }
+                },
+                "old": { "locked": { "type": "path", "path": "./old" } }
+            }
+        }));
+        let declared = json!({
+            "nixpkgs": { "type": "github", "owner": "NixOS", "repo": "nixpkgs", "ref": "nixos-24.11" },
+            "utils": { "type": "github", "owner": "numtide", "repo": "flake-utils" }
+        });
+
+        let findings = check_declared_inputs(&declared, &lock);
+        let summary: Vec<(&str, bool)> = findings
+            .iter()
+            .map(|f| (f.input.as_str(), f.error))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("nixpkgs", true), ("utils", true), ("old", false)]
+        );
+        assert!(findings[0].message.contains("nixos-24.11"));
+    }
+
+    #[test]
+    fn test_check_follows() {
+        let lock = lock_from(json!({
+            "version": 7,
+            "root": "root",
+            "nodes": {
+                "root": { "inputs": { "nixpkgs": "nixpkgs", "foo": "foo" } },
+                "nixpkgs": {},
+                "foo": { "inputs": { "nixpkgs": ["nixpkgs"], "utils": ["missing"] } }
+            }
+        }));
+
+        let findings = check_follows(&lock);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].input, "foo/utils");
+        assert!(findings[0].message.contains("'missing'"));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::flake::{ensure_lock, resolve_installable};
+use crate::lock::{resolve_follows, LockFile};
+use crate::nix::{eval_flake_outputs, get_derivation_path, run_nix_eval, EvalOptions};
+use crate::plan::collect_buildable_attrs;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Which inputs each output references, and which inputs each input uses.
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct Graph {
+    /// Output attribute -> lock nodes whose source it was built or evaluated from
+    outputs: BTreeMap<String, BTreeSet<String>>,
+    /// Lock node -> lock nodes it takes as inputs (follows resolved)
+    inputs: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Map each input's source store path to its lock node name.
+fn input_sources(lock: &LockFile, store_dir: &str) -> BTreeMap<String, String> {
+    lock.nodes
+        .iter()
+        .filter(|(name, _)| **name != lock.root)
+        .filter_map(|(name, node)| {
+            let nar_hash = node.locked.as_ref()?.nar_hash.as_deref()?;
+            let path = crate::hash::source_store_path(store_dir, nar_hash, "source")?;
+            Some((path, name.clone()))
+        })
+        .collect()
+}
+
+/// Files in the store that evaluating `attr` read its definition from: its
+/// `meta.position`, and where its stdenv's `mkDerivation` is defined.
+///
+/// An input that is only evaluated, as nixpkgs usually is, never shows up
+/// in the derivation closure; this is how such inputs are found.
+fn evaluated_files(flake_dir: &std::path::Path, attr: &str) -> Vec<String> {
+    let options = EvalOptions {
+        output_json: true,
+        apply_fn: Some(
+            r#"drv:
+              let
+                pos = name: set:
+                  let p = builtins.unsafeGetAttrPos name set;
+                  in if p == null then [ ] else [ p.file ];
+              in
+              (if drv ? meta.position then [ drv.meta.position ] else [ ])
+              ++ (if drv ? stdenv && builtins.isAttrs drv.stdenv then pos "mkDerivation" drv.stdenv else [ ])"#
+                .to_string(),
+        ),
+        quiet: true,
+        ..Default::default()
+    };
+    run_nix_eval(Some(flake_dir), attr, &options)
+        .ok()
+        .and_then(|output| serde_json::from_str(&output).ok())
+        .unwrap_or_default()
+}
+
+/// Whether `path` is the input source `source` or a file inside it.
+fn within(path: &str, source: &str) -> bool {
+    path.strip_prefix(source)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Build the graph from the lock file and, for each output, the paths it
+/// was built or evaluated from: its derivation closure and `evaluated_files`.
+fn build_graph(
+    lock: &LockFile,
+    closures: &[(String, HashSet<String>)],
+    sources: &BTreeMap<String, String>,
+) -> Graph {
+    let mut graph = Graph::default();
+
+    for (attr, closure) in closures {
+        let referenced = sources
+            .iter()
+            .filter(|(source, _)| closure.iter().any(|path| within(path, source)))
+            .map(|(_, node)| node.clone())
+            .collect();
+        graph.outputs.insert(attr.clone(), referenced);
+    }
+
+    for (name, node) in &lock.nodes {
+        if *name == lock.root {
+            continue;
+        }
+        let targets = node
+            .inputs
+            .iter()
+            .flatten()
+            .filter_map(|(_, target)| match target {
+                Value::String(target) => Some(target.clone()),
+                Value::Array(path) => resolve_follows(lock, path),
+                _ => None,
+            })
+            .collect();
+        graph.inputs.insert(name.clone(), targets);
+    }
+
+    graph
+}
+
+fn dot_id(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Render the graph in Graphviz DOT format.
+fn format_dot(graph: &Graph) -> String {
+    let mut dot = String::from("digraph flake {\n  rankdir=LR;\n");
+    for attr in graph.outputs.keys() {
+        dot.push_str(&format!("  {} [shape=box];\n", dot_id(attr)));
+    }
+    for input in graph.inputs.keys() {
+        dot.push_str(&format!("  {} [shape=ellipse];\n", dot_id(input)));
+    }
+    for (from, targets) in graph.outputs.iter().chain(graph.inputs.iter()) {
+        for to in targets {
+            dot.push_str(&format!("  {} -> {};\n", dot_id(from), dot_id(to)));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Show which flake outputs reference which inputs
+pub fn cmd_graph(flake_ref: Option<&str>, dot: bool, json: bool) -> Result<()> {
+    let flake_ref = flake_ref.unwrap_or(".");
+    let resolved = resolve_installable(flake_ref);
+    if !resolved.is_local {
+        anyhow::bail!("flake graph only supports local flakes");
+    }
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+
+    ensure_lock(flake_dir, None)?;
+    let lock: LockFile = serde_json::from_str(
+        &std::fs::read_to_string(flake_dir.join("flake.lock"))
+            .context("Failed to read flake.lock")?,
+    )
+    .context("flake.lock is not valid JSON")?;
+    let sources = input_sources(&lock, &crate::nix::get_store_dir()?);
+
+    let outputs =
+        eval_flake_outputs(flake_dir, false, false)?.context("Failed to evaluate flake outputs")?;
+
+    let mut closures = Vec::new();
+    for attr in collect_buildable_attrs(&outputs) {
+        let drv_path = get_derivation_path(flake_dir, &attr)?;
+        let mut cmd = crate::command::NixCommand::new("nix-store");
+        cmd.args(["--query", "--requisites", &drv_path]);
+        let mut paths: HashSet<String> = cmd.output()?.lines().map(str::to_string).collect();
+        paths.extend(evaluated_files(flake_dir, &attr));
+        closures.push((attr, paths));
+    }
+
+    let graph = build_graph(&lock, &closures, &sources);
+
+    if json {
+        println!("{}", crate::schema::FLAKE_GRAPH.to_json(&graph)?);
+    } else if dot {
+        print!("{}", format_dot(&graph));
+    } else {
+        for (attr, inputs) in &graph.outputs {
+            let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+            if inputs.is_empty() {
+                println!("{}: (no input sources)", attr);
+            } else {
+                println!("{}: {}", attr, inputs.join(", "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_graph() {
+        let lock: LockFile = serde_json::from_value(json!({
+            "version": 7,
+            "root": "root",
+            "nodes": {
+                "root": { "inputs": { "nixpkgs": "nixpkgs", "tool": "tool" } },
+                "nixpkgs": {},
+                "tool": { "inputs": { "nixpkgs": ["nixpkgs"] } }
+            }
+        }))
+        .unwrap();
+        let sources = BTreeMap::from([
+            ("/nix/store/aaa-source".to_string(), "nixpkgs".to_string()),
+            ("/nix/store/bbb-source".to_string(), "tool".to_string()),
+        ]);
+        let closures = vec![
+            (
+                "packages.x86_64-linux.app".to_string(),
+                HashSet::from([
+                    "/nix/store/bbb-source".to_string(),
+                    "/nix/store/ccc-app.drv".to_string(),
+                ]),
+            ),
+            (
+                "packages.x86_64-linux.doc".to_string(),
+                HashSet::from(["/nix/store/ddd-doc.drv".to_string()]),
+            ),
+            (
+                "packages.x86_64-linux.hello".to_string(),
+                HashSet::from([
+                    "/nix/store/eee-hello.drv".to_string(),
+                    "/nix/store/aaa-source/pkgs/stdenv/generic/make-derivation.nix".to_string(),
+                    "/nix/store/aaa-sourcery/default.nix".to_string(),
+                ]),
+            ),
+        ];
+
+        let graph = build_graph(&lock, &closures, &sources);
+        assert_eq!(
+            graph.outputs["packages.x86_64-linux.app"],
+            BTreeSet::from(["tool".to_string()])
+        );
+        assert!(graph.outputs["packages.x86_64-linux.doc"].is_empty());
+        assert_eq!(
+            graph.outputs["packages.x86_64-linux.hello"],
+            BTreeSet::from(["nixpkgs".to_string()])
+        );
+        assert_eq!(
+            graph.inputs["tool"],
+            BTreeSet::from(["nixpkgs".to_string()])
+        );
+
+        let dot = format_dot(&graph);
+        assert!(dot.contains("\"packages.x86_64-linux.app\" -> \"tool\";"));
+        assert!(dot.contains("\"tool\" -> \"nixpkgs\";"));
+    }
+}
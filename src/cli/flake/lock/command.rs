@@ -10,7 +10,7 @@ pub fn cmd_lock(flake_ref: Option<&str>) -> Result<()> {
     let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
 
     sync_inputs(flake_dir, None)?;
-    println!("Wrote flake.lock");
+    eprintln!("Wrote flake.lock");
 
     Ok(())
 }
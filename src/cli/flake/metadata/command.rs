@@ -1,5 +1,7 @@
 use super::common::bold;
+use crate::cli::style::{fit, Stream};
 use crate::flake::{get_flake_description, get_flake_inputs, resolve_installable};
+use crate::git::TreeState;
 use anyhow::{Context, Result};
 use chrono::{DateTime, Local};
 use std::os::unix::fs::MetadataExt;
@@ -26,16 +28,51 @@ pub fn cmd_metadata(flake_ref: Option<&str>) -> Result<()> {
         anyhow::bail!("No flake.nix found in {}", flake_dir.display());
     }
 
+    let tree = crate::git::tree_state(flake_dir);
+    match &tree {
+        TreeState::NotGit => crate::nix::warn(&format!(
+            "'{}' is not in a git repository; it is a path flake without a revision, \
+             and all of its files are part of its source",
+            flake_dir.display()
+        )),
+        TreeState::Dirty(_) if !crate::git::warn_dirty() => {}
+        _ => {
+            if let Some(warning) = tree.warning() {
+                crate::nix::warn(&warning);
+            }
+        }
+    }
+    let git_info = match tree {
+        TreeState::Clean(_) | TreeState::Dirty(_) => {
+            crate::git::get_git_info(flake_dir).unwrap_or_default()
+        }
+        _ => Default::default(),
+    };
+
     // Show description
     if let Some(desc) = get_flake_description(flake_dir) {
-        println!("{} {}", bold("Description:"), desc);
+        println!(
+            "{}",
+            fit(
+                Stream::Stdout,
+                &format!("{} {}", bold("Description:"), desc)
+            )
+        );
     }
 
     println!("{} {}", bold("Path:"), flake_dir.display());
 
-    // Show last modified from flake.nix mtime
-    if let Ok(metadata) = flake_nix.metadata() {
-        let mtime = metadata.mtime();
+    if let Some(rev) = &git_info.rev {
+        println!("{} {}", bold("Revision:"), rev);
+    } else if let Some(rev) = &git_info.dirty_rev {
+        println!("{} {}", bold("Dirty revision:"), rev);
+    }
+
+    // Show last modified from the last commit, like nix, or else flake.nix's mtime
+    let last_modified = git_info
+        .last_modified
+        .or_else(|| flake_nix.metadata().ok().map(|m| m.mtime()));
+    if let Some(mtime) = last_modified {
         let datetime = DateTime::from_timestamp(mtime, 0)
             .map(|dt| dt.with_timezone(&Local))
             .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
@@ -88,7 +125,8 @@ pub fn cmd_metadata(flake_ref: Option<&str>) -> Result<()> {
                     };
                     let spec = &input_map[*name];
                     let url = format_unlocked_input(spec);
-                    println!("{}{}: {}", branch, bold(name), url);
+                    let line = format!("{}{}: {}", branch, bold(name), url);
+                    println!("{}", fit(Stream::Stdout, &line));
                 }
             }
         }
@@ -210,7 +248,8 @@ fn print_input(
 
     if let Some(node) = node {
         let url = format_input_url(node);
-        println!("{}{}{}: {}", prefix, branch, bold(name), url);
+        let line = format!("{}{}{}: {}", prefix, branch, bold(name), url);
+        println!("{}", fit(Stream::Stdout, &line));
 
         // Print transitive inputs
         if let Some(node_inputs) = node.get("inputs").and_then(|i| i.as_object()) {
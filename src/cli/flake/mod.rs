@@ -6,6 +6,12 @@ pub mod common;
 #[path = "check/command.rs"]
 pub mod check;
 
+#[path = "doctor/command.rs"]
+pub mod doctor;
+
+#[path = "graph/command.rs"]
+pub mod graph;
+
 #[path = "init/command.rs"]
 pub mod init;
 
@@ -18,6 +24,12 @@ pub mod metadata;
 #[path = "new/command.rs"]
 pub mod new;
 
+#[path = "outdated/command.rs"]
+pub mod outdated;
+
+#[path = "plan/command.rs"]
+pub mod plan;
+
 #[path = "show/command.rs"]
 pub mod show;
 
@@ -25,10 +37,14 @@ pub mod show;
 pub mod update;
 
 pub use check::cmd_check;
+pub use doctor::cmd_doctor;
+pub use graph::cmd_graph;
 pub use init::cmd_init;
 pub use lock::cmd_lock;
 pub use metadata::cmd_metadata;
 pub use new::cmd_new;
+pub use outdated::cmd_outdated;
+pub use plan::cmd_plan;
 pub use show::cmd_show;
 pub use update::cmd_update;
 
@@ -54,6 +70,18 @@ pub enum FlakeCommands {
         /// Use legacy nix command behavior if true
         #[arg(long, hide = true)]
         legacy: bool,
+
+        /// Also show the top-level outputs of each input
+        #[arg(long, conflicts_with = "json")]
+        include_inputs: bool,
+
+        /// Print the outputs as JSON, like nix flake show --json
+        #[arg(long)]
+        json: bool,
+
+        /// Print only what changed since a --json snapshot in FILE; exit 1 if anything did
+        #[arg(long, value_name = "FILE", requires = "json")]
+        compare: Option<std::path::PathBuf>,
     },
 
     /// Update flake inputs
@@ -64,6 +92,24 @@ pub enum FlakeCommands {
         /// Override input (e.g. nixpkgs=github:NixOS/nixpkgs/nixos-unstable)
         #[arg(long, num_args = 2, value_names = ["INPUT", "REF"])]
         override_input: Vec<String>,
+
+        /// Update inputs even if that moves them off their `# trix: follow-branch` branch
+        #[arg(long)]
+        allow_branch_change: bool,
+
+        /// Lock the input to REV (a commit hash, tag or branch), which must be on
+        /// the ref flake.nix declares unless --allow-branch-change is given
+        #[arg(
+            long,
+            value_name = "REV",
+            requires = "input_name",
+            conflicts_with = "override_input"
+        )]
+        to: Option<String>,
+
+        /// Leave INPUT alone when updating all inputs (repeatable)
+        #[arg(long, value_name = "INPUT")]
+        exclude: Vec<String>,
     },
 
     /// Check flake health
@@ -71,6 +117,77 @@ pub enum FlakeCommands {
         /// Flake reference
         #[arg(default_value = ".")]
         flake_ref: Option<String>,
+
+        /// Treat output schema warnings as errors
+        #[arg(long)]
+        strict: bool,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Show the N slowest checks at the end (0 to hide)
+        #[arg(long, value_name = "N", default_value_t = 5)]
+        slowest: usize,
+
+        /// Fail any check that takes longer than SECS to evaluate or build
+        #[arg(long, value_name = "SECS", value_parser = positive_seconds)]
+        fail_slower_than: Option<f64>,
+
+        /// Warn about environment variables, NIX_PATH lookups, paths outside
+        /// the flake and unhashed fetches, which pure evaluation rejects,
+        /// by evaluating again with traced builtins. If that fails, the .nix
+        /// sources are scanned instead
+        #[arg(long)]
+        impure_warnings: bool,
+    },
+
+    /// Diagnose problems with the flake's inputs and lock file
+    Doctor {
+        /// Flake reference
+        #[arg(default_value = ".")]
+        flake_ref: Option<String>,
+    },
+
+    /// Show how far each locked input is behind upstream, without updating
+    Outdated {
+        /// Flake reference
+        #[arg(default_value = ".")]
+        flake_ref: Option<String>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show which outputs reference which inputs' sources
+    Graph {
+        /// Flake reference
+        #[arg(default_value = ".")]
+        flake_ref: Option<String>,
+
+        /// Print the graph in Graphviz DOT format
+        #[arg(long, conflicts_with = "json")]
+        dot: bool,
+
+        /// Print the graph as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Split buildable outputs into balanced CI shards (JSON)
+    Plan {
+        /// Flake reference
+        #[arg(default_value = ".")]
+        flake_ref: Option<String>,
+
+        /// Number of shards to produce
+        #[arg(long, default_value_t = 1)]
+        shards: usize,
+
+        /// Include outputs for all systems
+        #[arg(long)]
+        all_systems: bool,
     },
 
     /// Create or update flake.lock
@@ -97,19 +214,41 @@ pub enum FlakeCommands {
     },
 }
 
+/// Parse a time budget, which must be a positive number of seconds.
+fn positive_seconds(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs > 0.0 => Ok(secs),
+        Ok(_) => Err("must be a positive number of seconds".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 pub fn cmd_flake(cmd: FlakeCommands) -> Result<()> {
     match cmd {
         FlakeCommands::Show {
             flake_ref,
             all_systems,
             legacy,
-        } => cmd_show(flake_ref.as_deref(), all_systems, legacy),
+            include_inputs,
+            json,
+            compare,
+        } => cmd_show(
+            flake_ref.as_deref(),
+            all_systems,
+            legacy,
+            include_inputs,
+            json,
+            compare.as_deref(),
+        ),
 
         FlakeCommands::Metadata { flake_ref } => cmd_metadata(flake_ref.as_deref()),
 
         FlakeCommands::Update {
             input_name,
             override_input,
+            allow_branch_change,
+            to,
+            exclude,
         } => {
             let override_inputs: std::collections::HashMap<String, String> = override_input
                 .chunks(2)
@@ -126,12 +265,49 @@ pub fn cmd_flake(cmd: FlakeCommands) -> Result<()> {
             } else {
                 Some(&override_inputs)
             };
-            cmd_update(input_name.as_deref(), override_ref)
+            cmd_update(
+                input_name.as_deref(),
+                override_ref,
+                allow_branch_change,
+                to.as_deref(),
+                &exclude,
+            )
         }
 
         FlakeCommands::Lock { flake_ref } => cmd_lock(flake_ref.as_deref()),
 
-        FlakeCommands::Check { flake_ref } => cmd_check(flake_ref.as_deref(), false),
+        FlakeCommands::Check {
+            flake_ref,
+            strict,
+            json,
+            slowest,
+            fail_slower_than,
+            impure_warnings,
+        } => cmd_check(
+            flake_ref.as_deref(),
+            false,
+            strict,
+            json,
+            slowest,
+            fail_slower_than,
+            impure_warnings,
+        ),
+
+        FlakeCommands::Doctor { flake_ref } => cmd_doctor(flake_ref.as_deref()),
+
+        FlakeCommands::Outdated { flake_ref, json } => cmd_outdated(flake_ref.as_deref(), json),
+
+        FlakeCommands::Graph {
+            flake_ref,
+            dot,
+            json,
+        } => cmd_graph(flake_ref.as_deref(), dot, json),
+
+        FlakeCommands::Plan {
+            flake_ref,
+            shards,
+            all_systems,
+        } => cmd_plan(flake_ref.as_deref(), shards, all_systems),
 
         FlakeCommands::Init { template } => cmd_init(&template),
 
@@ -0,0 +1,214 @@
+use super::common::bold;
+use crate::flake::resolve_installable;
+use crate::lock::{LockFile, LockNode};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+
+/// How far a locked input is behind its upstream ref.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Freshness {
+    input: String,
+    #[serde(rename = "type")]
+    input_type: String,
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    git_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locked_rev: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest_rev: Option<String>,
+    /// Commits between the locked and latest revs, when the forge can tell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commits_behind: Option<u64>,
+    /// Days between the locked and latest commit times
+    #[serde(skip_serializing_if = "Option::is_none")]
+    days_behind: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Freshness {
+    fn is_outdated(&self) -> bool {
+        matches!((&self.locked_rev, &self.latest_rev), (Some(a), Some(b)) if a != b)
+    }
+
+    /// One-line status for the text report.
+    fn describe(&self) -> String {
+        if let Some(error) = &self.error {
+            return format!("unknown ({})", error);
+        }
+        let Some(latest) = &self.latest_rev else {
+            return "not tracked upstream".to_string();
+        };
+        if !self.is_outdated() {
+            return "up to date".to_string();
+        }
+        let mut behind = Vec::new();
+        if let Some(commits) = self.commits_behind {
+            behind.push(format!(
+                "{} commit{}",
+                commits,
+                if commits == 1 { "" } else { "s" }
+            ));
+        }
+        if let Some(days) = self.days_behind {
+            behind.push(format!("{} day{}", days, if days == 1 { "" } else { "s" }));
+        }
+        let short = &latest[..latest.len().min(7)];
+        if behind.is_empty() {
+            format!("outdated, latest {}", short)
+        } else {
+            format!("{} behind, latest {}", behind.join(", "), short)
+        }
+    }
+}
+
+/// Query upstream for the latest rev of a locked input.
+fn check_input(input: &str, node: &LockNode) -> Freshness {
+    let locked = node.locked.clone().unwrap_or_default();
+    let git_ref = node
+        .original
+        .as_ref()
+        .and_then(|o| o["ref"].as_str())
+        .map(str::to_string);
+    let mut freshness = Freshness {
+        input: input.to_string(),
+        input_type: locked.lock_type.clone(),
+        git_ref: git_ref.clone(),
+        locked_rev: locked.rev.clone(),
+        ..Default::default()
+    };
+    // Inputs pinned to a rev in flake.nix never move
+    let pinned = node.original.as_ref().is_some_and(|o| o["rev"].is_string());
+    if pinned {
+        freshness.latest_rev = locked.rev.clone();
+        return freshness;
+    }
+
+    let result = match locked.lock_type.as_str() {
+        "github" => {
+            let owner = locked.owner.as_deref().unwrap_or("");
+            let repo = locked.repo.as_deref().unwrap_or("");
+            crate::lock::github_commit(owner, repo, git_ref.as_deref().unwrap_or("HEAD")).and_then(
+                |(rev, last_modified)| {
+                    if let (Some(old), Some(new)) = (locked.last_modified, last_modified) {
+                        freshness.days_behind = Some((new - old).max(0) / 86400);
+                    }
+                    if let Some(old) = locked.rev.as_deref().filter(|old| *old != rev) {
+                        freshness.commits_behind =
+                            Some(crate::lock::github_commits_between(owner, repo, old, &rev)?);
+                    }
+                    Ok(rev)
+                },
+            )
+        }
+        "git" => crate::git::remote_rev(locked.url.as_deref().unwrap_or(""), git_ref.as_deref()),
+        _ => return freshness,
+    };
+
+    match result {
+        Ok(rev) => freshness.latest_rev = Some(rev),
+        Err(e) => freshness.error = Some(format!("{:#}", e)),
+    }
+    freshness
+}
+
+/// Report how far each locked input is behind upstream
+pub fn cmd_outdated(flake_ref: Option<&str>, json: bool) -> Result<()> {
+    let flake_ref = flake_ref.unwrap_or(".");
+    let resolved = resolve_installable(flake_ref);
+    if !resolved.is_local {
+        anyhow::bail!("flake outdated only supports local flakes");
+    }
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+
+    let flake_lock = flake_dir.join("flake.lock");
+    let lock: LockFile = serde_json::from_str(
+        &std::fs::read_to_string(&flake_lock)
+            .with_context(|| format!("No flake.lock in {}", flake_dir.display()))?,
+    )
+    .with_context(|| format!("{} is not valid JSON", flake_lock.display()))?;
+
+    let root_inputs = lock
+        .nodes
+        .get(&lock.root)
+        .and_then(|root| root.inputs.clone())
+        .unwrap_or_default();
+    let mut inputs: Vec<(&String, &LockNode)> = root_inputs
+        .iter()
+        .filter_map(|(name, target)| Some((name, lock.nodes.get(target.as_str()?)?)))
+        .collect();
+    inputs.sort_by_key(|(name, _)| name.as_str());
+
+    let report: Vec<Freshness> = inputs
+        .par_iter()
+        .map(|(name, node)| check_input(name, node))
+        .collect();
+
+    if json {
+        println!(
+            "{}",
+            crate::schema::FLAKE_OUTDATED.to_json(&serde_json::json!({ "inputs": report }))?
+        );
+        return Ok(());
+    }
+
+    if report.is_empty() {
+        println!("No locked inputs");
+        return Ok(());
+    }
+    let width = report.iter().map(|f| f.input.len()).max().unwrap_or(0);
+    for freshness in &report {
+        let padded = format!("{:<width$}", freshness.input);
+        let name = if freshness.is_outdated() {
+            bold(&padded)
+        } else {
+            padded
+        };
+        println!("{}  {}", name, freshness.describe());
+    }
+
+    let outdated = report.iter().filter(|f| f.is_outdated()).count();
+    if outdated > 0 {
+        println!();
+        println!(
+            "{} input(s) can be updated with `trix flake update`",
+            outdated
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let mut freshness = Freshness {
+            input: "nixpkgs".to_string(),
+            input_type: "github".to_string(),
+            locked_rev: Some("a".repeat(40)),
+            latest_rev: Some("a".repeat(40)),
+            ..Default::default()
+        };
+        assert_eq!(freshness.describe(), "up to date");
+
+        freshness.latest_rev = Some("b".repeat(40));
+        freshness.commits_behind = Some(1);
+        freshness.days_behind = Some(12);
+        assert!(freshness.is_outdated());
+        assert_eq!(
+            freshness.describe(),
+            "1 commit, 12 days behind, latest bbbbbbb"
+        );
+
+        freshness.commits_behind = None;
+        freshness.days_behind = None;
+        assert_eq!(freshness.describe(), "outdated, latest bbbbbbb");
+
+        freshness.latest_rev = None;
+        assert_eq!(freshness.describe(), "not tracked upstream");
+    }
+}
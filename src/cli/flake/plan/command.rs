@@ -0,0 +1,39 @@
+use crate::flake::{ensure_lock, resolve_installable};
+use crate::nix::eval_flake_outputs;
+use crate::plan::{collect_buildable_attrs, load_build_times, plan_shards, weigh_attrs};
+use anyhow::{Context, Result};
+
+/// Split buildable outputs into balanced CI shards
+pub fn cmd_plan(flake_ref: Option<&str>, shards: usize, all_systems: bool) -> Result<()> {
+    if shards == 0 {
+        anyhow::bail!("--shards must be at least 1");
+    }
+
+    let flake_ref = flake_ref.unwrap_or(".");
+    let resolved = resolve_installable(flake_ref);
+
+    if !resolved.is_local {
+        anyhow::bail!("flake plan only supports local flakes");
+    }
+
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+
+    // Ensure lock exists
+    ensure_lock(flake_dir, None)?;
+
+    let outputs = eval_flake_outputs(flake_dir, all_systems, false)?
+        .context("Failed to evaluate flake outputs")?;
+
+    let attrs = collect_buildable_attrs(&outputs);
+    tracing::debug!("Found {} buildable outputs", attrs.len());
+
+    let items = weigh_attrs(&attrs, &load_build_times());
+    let plan = plan_shards(&items, shards);
+
+    println!(
+        "{}",
+        crate::schema::FLAKE_PLAN.to_json(&serde_json::json!({ "shards": plan }))?
+    );
+
+    Ok(())
+}
@@ -1,10 +1,32 @@
 use super::common::{bold, magenta_bold};
+use crate::cli::style::{fit, paint, Stream};
 use crate::flake::{ensure_lock, resolve_installable};
-use crate::nix::eval_flake_outputs;
+use crate::lock::LockFile;
+use crate::nix::{eval_flake_outputs, nix_string};
 use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Names listed for each output of an input before summarising the rest.
+const INPUT_MAX_NAMES: usize = 6;
+
+/// Top-level outputs of an input and the attribute names below each, or
+/// None if the input is not a flake.
+type InputSummary = Option<BTreeMap<String, Vec<String>>>;
 
 /// Show flake outputs structure
-pub fn cmd_show(flake_ref: Option<&str>, all_systems: bool, legacy: bool) -> Result<()> {
+///
+/// With `compare`, prints only the differences to that earlier
+/// `--json` snapshot and fails if there are any.
+pub fn cmd_show(
+    flake_ref: Option<&str>,
+    all_systems: bool,
+    legacy: bool,
+    include_inputs: bool,
+    json: bool,
+    compare: Option<&Path>,
+) -> Result<()> {
     let flake_ref = flake_ref.unwrap_or(".");
     let resolved = resolve_installable(flake_ref);
 
@@ -23,6 +45,19 @@ pub fn cmd_show(flake_ref: Option<&str>, all_systems: bool, legacy: bool) -> Res
             cmd.arg("--legacy");
         }
 
+        if include_inputs {
+            crate::nix::warn("--include-inputs is only supported for local flakes");
+        }
+
+        if json {
+            cmd.arg("--json");
+            let current: Value = cmd.json()?;
+            if let Some(compare) = compare {
+                return print_comparison(compare, &current);
+            }
+            return print_outputs_json(&current);
+        }
+
         return cmd.run();
     }
 
@@ -31,6 +66,16 @@ pub fn cmd_show(flake_ref: Option<&str>, all_systems: bool, legacy: bool) -> Res
     // Ensure lock exists
     ensure_lock(flake_dir, None)?;
 
+    if json {
+        let outputs = eval_flake_outputs(flake_dir, all_systems, legacy)?
+            .context("Failed to evaluate flake outputs")?;
+        let current = outputs_to_json(&outputs, None);
+        if let Some(compare) = compare {
+            return print_comparison(compare, &current);
+        }
+        return print_outputs_json(&current);
+    }
+
     // Print flake URL header (bold, like nix)
     let canonical_path = flake_dir
         .canonicalize()
@@ -48,9 +93,18 @@ pub fn cmd_show(flake_ref: Option<&str>, all_systems: bool, legacy: bool) -> Res
             .map(|o| o.status.success())
             .unwrap_or(false);
     if is_git {
-        println!("\x1b[1mgit+file://{}\x1b[0m", canonical_path.display());
+        println!(
+            "{}",
+            bold(&format!(
+                "git+file://{}",
+                crate::flake::url_path(&canonical_path)
+            ))
+        );
     } else {
-        println!("\x1b[1mpath:{}\x1b[0m", canonical_path.display());
+        println!(
+            "{}",
+            bold(&format!("path:{}", crate::flake::url_path(&canonical_path)))
+        );
     }
 
     // Get outputs structure
@@ -62,9 +116,199 @@ pub fn cmd_show(flake_ref: Option<&str>, all_systems: bool, legacy: bool) -> Res
         anyhow::bail!("Failed to evaluate flake outputs");
     }
 
+    if include_inputs {
+        let summaries = input_summaries(flake_dir)?;
+        if !summaries.is_empty() {
+            println!();
+            println!("{}", bold("inputs"));
+            for line in format_input_tree(&summaries) {
+                println!("{}", line);
+            }
+        }
+    }
+
     Ok(())
 }
 
+/// Get the path of the narHash -> input summary cache.
+fn get_input_summaries_path() -> Option<PathBuf> {
+    crate::paths::cache_dir().map(|d| d.join("input-outputs.json"))
+}
+
+fn load_input_summaries() -> BTreeMap<String, InputSummary> {
+    get_input_summaries_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn record_input_summaries(cache: &BTreeMap<String, InputSummary>) -> Result<()> {
+    let Some(path) = get_input_summaries_path() else {
+        return Ok(());
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(cache)?)?;
+    Ok(())
+}
+
+/// The narHash of the lock node a root input resolves to, if it is locked by hash.
+fn input_nar_hash(lock: &LockFile, name: &str) -> Option<String> {
+    let target = lock.nodes.get(&lock.root)?.inputs.as_ref()?.get(name)?;
+    let node = match target {
+        serde_json::Value::String(node) => node.clone(),
+        serde_json::Value::Array(path) => crate::lock::resolve_follows(lock, path)?,
+        _ => return None,
+    };
+    lock.nodes.get(&node)?.locked.as_ref()?.nar_hash.clone()
+}
+
+/// Evaluate the top-level outputs of the flake's direct inputs.
+///
+/// Inputs locked by narHash never change, so their summaries are cached;
+/// path inputs are evaluated every time.
+fn input_summaries(flake_dir: &Path) -> Result<BTreeMap<String, InputSummary>> {
+    let lock: LockFile = serde_json::from_str(
+        &std::fs::read_to_string(flake_dir.join("flake.lock"))
+            .context("Failed to read flake.lock")?,
+    )
+    .context("flake.lock is not valid JSON")?;
+    let mut names: Vec<String> = lock
+        .nodes
+        .get(&lock.root)
+        .and_then(|root| root.inputs.as_ref())
+        .map(|inputs| inputs.keys().cloned().collect())
+        .unwrap_or_default();
+    names.sort();
+
+    let mut cache = load_input_summaries();
+    let mut summaries = BTreeMap::new();
+    let mut missing = Vec::new();
+    for name in names {
+        match input_nar_hash(&lock, &name).and_then(|hash| cache.get(&hash)) {
+            Some(summary) => {
+                summaries.insert(name, summary.clone());
+            }
+            None => missing.push(name),
+        }
+    }
+    if missing.is_empty() {
+        return Ok(summaries);
+    }
+
+    let expr = format!(
+        r#"
+        let
+          {preamble}
+          summarize = input:
+            if (input._type or null) == "flake" then
+              builtins.mapAttrs (_: value:
+                if builtins.isAttrs value && (value.type or null) != "derivation"
+                then builtins.attrNames value
+                else [ ]
+              ) input.outputs
+            else null;
+        in builtins.listToAttrs (map (name: {{
+          inherit name;
+          value = summarize context.inputs.${{name}};
+        }}) [ {names} ])
+        "#,
+        preamble = crate::nix::get_eval_preamble(flake_dir)?,
+        names = missing
+            .iter()
+            .map(|n| nix_string(n))
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    let options = crate::nix::EvalOptions {
+        output_json: true,
+        expr: Some(expr),
+        ..Default::default()
+    };
+    let output = crate::progress::with_status("Evaluating inputs", || {
+        crate::nix::run_nix_eval(None, "", &options)
+    })
+    .context("Failed to evaluate flake inputs")?;
+    let evaluated: BTreeMap<String, InputSummary> =
+        serde_json::from_str(&output).context("Failed to parse input outputs")?;
+
+    for (name, summary) in evaluated {
+        if let Some(hash) = input_nar_hash(&lock, &name) {
+            cache.insert(hash, summary.clone());
+        }
+        summaries.insert(name, summary);
+    }
+    if let Err(e) = record_input_summaries(&cache) {
+        tracing::debug!("Failed to save input summaries: {:#}", e);
+    }
+
+    Ok(summaries)
+}
+
+/// List attribute names, summarising long lists.
+fn format_names(names: &[String]) -> String {
+    if names.len() <= INPUT_MAX_NAMES {
+        return names.join(", ");
+    }
+    format!(
+        "{}, ... ({} total)",
+        names[..INPUT_MAX_NAMES].join(", "),
+        names.len()
+    )
+}
+
+/// Tree connector for an entry, green+bold like nix.
+fn connector(is_last: bool) -> String {
+    paint(
+        Stream::Stdout,
+        "32;1",
+        if is_last {
+            "└───"
+        } else {
+            "├───"
+        },
+    )
+}
+
+/// Indentation under an entry, continuing the tree line if more follow.
+fn indent(is_last: bool) -> String {
+    if is_last {
+        "    ".to_string()
+    } else {
+        format!("{}   ", paint(Stream::Stdout, "32;1", "│"))
+    }
+}
+
+/// Render input summaries as a tree, like the outputs above them.
+fn format_input_tree(summaries: &BTreeMap<String, InputSummary>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (i, (name, summary)) in summaries.iter().enumerate() {
+        let is_last = i == summaries.len() - 1;
+        let (connector, prefix) = (connector(is_last), indent(is_last));
+        let Some(outputs) = summary else {
+            lines.push(format!("{}{} (not a flake)", connector, bold(name)));
+            continue;
+        };
+        lines.push(format!("{}{}", connector, bold(name)));
+        for (j, (output, names)) in outputs.iter().enumerate() {
+            let inner = self::connector(j == outputs.len() - 1);
+            if names.is_empty() {
+                lines.push(format!("{}{}{}", prefix, inner, bold(output)));
+            } else {
+                lines.push(format!(
+                    "{}{}{}: {}",
+                    prefix,
+                    inner,
+                    bold(output),
+                    format_names(names)
+                ));
+            }
+        }
+    }
+    lines
+}
+
 /// Check if a value has any displayable content (not empty at all levels)
 fn has_displayable_content(value: &serde_json::Value) -> bool {
     if let Some(obj) = value.as_object() {
@@ -105,18 +349,8 @@ fn print_flake_outputs(outputs: &serde_json::Value, prefix: &str) -> Result<()>
 
         for (i, key) in displayable_keys.iter().enumerate() {
             let is_last = i == len - 1;
-            // Green+bold for tree characters (matches nix)
-            let connector = if is_last {
-                "\x1b[32;1m└───\x1b[0m"
-            } else {
-                "\x1b[32;1m├───\x1b[0m"
-            };
-            let child_prefix = if is_last {
-                format!("{}    ", prefix)
-            } else {
-                format!("{}\x1b[32;1m│\x1b[0m   ", prefix)
-            };
-            // Note: The escape codes add visual spacing but the actual spacing matches nix
+            let connector = connector(is_last);
+            let child_prefix = format!("{}{}", prefix, indent(is_last));
 
             let value = &obj[*key];
 
@@ -125,23 +359,26 @@ fn print_flake_outputs(outputs: &serde_json::Value, prefix: &str) -> Result<()>
                 if inner.contains_key("_omitted") {
                     // Magenta+bold for "omitted" (matches nix)
                     println!(
-                        "{}{}{} \x1b[35;1momitted\x1b[0m (use '--all-systems' to show)",
+                        "{}{}{} {} (use '--all-systems' to show)",
                         prefix,
                         connector,
-                        bold(key)
+                        bold(key),
+                        magenta_bold("omitted")
                     );
                 } else if inner.contains_key("_legacyOmitted") {
                     println!(
-                        "{}{}{} \x1b[35;1momitted\x1b[0m (use '--legacy' to show)",
+                        "{}{}{} {} (use '--legacy' to show)",
                         prefix,
                         connector,
-                        bold(key)
+                        bold(key),
+                        magenta_bold("omitted")
                     );
                 } else if inner.contains_key("_unknown") {
                     println!("{}{}{}: unknown", prefix, connector, bold(key));
                 } else if inner.contains_key("_type") {
                     let description = format_output_description(inner);
-                    println!("{}{}{}: {}", prefix, connector, bold(key), description);
+                    let line = format!("{}{}{}: {}", prefix, connector, bold(key), description);
+                    println!("{}", fit(Stream::Stdout, &line));
                 } else if inner.values().all(|v| v.is_null()) {
                     // Object with all null values = leaf nodes that should be printed
                     // This is how legacyPackages.x86_64-linux looks: {"cargo-clippy": null, ...}
@@ -165,6 +402,153 @@ fn print_flake_outputs(outputs: &serde_json::Value, prefix: &str) -> Result<()>
     Ok(())
 }
 
+/// Convert the evaluated outputs tree to the layout of `nix flake show --json`.
+///
+/// Leaves become `{ "type": ..., "name": ... }`; outputs left out for
+/// other systems become `{}`, as nix prints them.
+fn outputs_to_json(outputs: &Value, category: Option<&str>) -> Value {
+    let Some(obj) = outputs.as_object() else {
+        return json!({});
+    };
+    if obj.contains_key("_omitted") || obj.contains_key("_legacyOmitted") {
+        return json!({});
+    }
+    if obj.contains_key("_unknown") {
+        return json!({ "type": "unknown" });
+    }
+    if let Some(type_val) = obj.get("_type").and_then(|v| v.as_str()) {
+        let category = obj.get("_category").and_then(|v| v.as_str()).or(category);
+        let nix_type = match (type_val, category) {
+            ("derivation" | "formatter", _) => "derivation",
+            ("module", _) => "nixos-module",
+            ("overlay", _) => "nixpkgs-overlay",
+            ("configuration", None | Some("nixosConfigurations")) => "nixos-configuration",
+            ("app" | "template", _) => type_val,
+            _ => "unknown",
+        };
+        let mut leaf = json!({ "type": nix_type });
+        if let Some(name) = obj.get("_name").and_then(|v| v.as_str()) {
+            leaf["name"] = json!(name);
+        }
+        return leaf;
+    }
+    Value::Object(
+        obj.iter()
+            .map(|(key, value)| {
+                (
+                    key.clone(),
+                    outputs_to_json(value, category.or(Some(key.as_str()))),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Print an outputs tree as versioned `flake show --json` output.
+fn print_outputs_json(outputs: &Value) -> Result<()> {
+    println!(
+        "{}",
+        crate::schema::FLAKE_SHOW.to_json(&json!({ "outputs": outputs }))?
+    );
+    Ok(())
+}
+
+/// The outputs tree of a `--json` snapshot, which is either trix's
+/// versioned output or the bare tree `nix flake show --json` prints.
+fn snapshot_outputs(snapshot: Value) -> Value {
+    match snapshot {
+        Value::Object(mut obj) if obj.contains_key("schema") && obj.contains_key("outputs") => {
+            obj.remove("outputs").unwrap_or_default()
+        }
+        other => other,
+    }
+}
+
+/// Flatten a `flake show --json` tree to attribute path -> leaf.
+fn flatten_outputs(value: &Value, path: &mut Vec<String>, out: &mut BTreeMap<String, Value>) {
+    match value.as_object() {
+        Some(obj) if !obj.is_empty() && !obj.contains_key("type") => {
+            for (key, child) in obj {
+                path.push(key.clone());
+                flatten_outputs(child, path, out);
+                path.pop();
+            }
+        }
+        _ => {
+            if !path.is_empty() {
+                out.insert(crate::flake::join_attr_path(path), value.clone());
+            }
+        }
+    }
+}
+
+/// Outputs added, removed and changed between two `flake show --json` trees.
+///
+/// Systems that were not evaluated show up as `{}`; everything below an
+/// attribute that is `{}` on either side is left out, so a snapshot taken
+/// on another system (or without `--all-systems`) only compares the
+/// systems both sides evaluated.
+fn compare_outputs(old: &Value, new: &Value) -> Value {
+    let (mut old_leaves, mut new_leaves) = (BTreeMap::new(), BTreeMap::new());
+    flatten_outputs(old, &mut Vec::new(), &mut old_leaves);
+    flatten_outputs(new, &mut Vec::new(), &mut new_leaves);
+
+    let omitted: Vec<String> = old_leaves
+        .iter()
+        .chain(&new_leaves)
+        .filter(|(_, leaf)| leaf.as_object().is_some_and(|o| o.is_empty()))
+        .map(|(path, _)| path.clone())
+        .collect();
+    let evaluated = |path: &String| {
+        !omitted
+            .iter()
+            .any(|o| path == o || path.starts_with(&format!("{}.", o)))
+    };
+    old_leaves.retain(|path, _| evaluated(path));
+    new_leaves.retain(|path, _| evaluated(path));
+
+    let mut added = serde_json::Map::new();
+    let mut removed = serde_json::Map::new();
+    let mut changed = serde_json::Map::new();
+    for (path, old_leaf) in &old_leaves {
+        match new_leaves.get(path) {
+            None => {
+                removed.insert(path.clone(), old_leaf.clone());
+            }
+            Some(new_leaf) if new_leaf != old_leaf => {
+                changed.insert(path.clone(), json!({ "old": old_leaf, "new": new_leaf }));
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, new_leaf) in &new_leaves {
+        if !old_leaves.contains_key(path) {
+            added.insert(path.clone(), new_leaf.clone());
+        }
+    }
+    json!({ "added": added, "removed": removed, "changed": changed })
+}
+
+/// Print the differences to the snapshot in `compare`, failing if there are any.
+fn print_comparison(compare: &Path, current: &Value) -> Result<()> {
+    let old: Value = serde_json::from_str(
+        &std::fs::read_to_string(compare)
+            .with_context(|| format!("Failed to read {}", compare.display()))?,
+    )
+    .with_context(|| format!("{} is not flake show --json output", compare.display()))?;
+
+    let diff = compare_outputs(&snapshot_outputs(old), current);
+    println!("{}", crate::schema::FLAKE_SHOW_COMPARE.to_json(&diff)?);
+
+    let differs = ["added", "removed", "changed"]
+        .iter()
+        .any(|k| diff[k].as_object().is_some_and(|m| !m.is_empty()));
+    if differs {
+        anyhow::bail!("Flake outputs differ from {}", compare.display());
+    }
+    Ok(())
+}
+
 /// Format a description for a flake output based on its type, name, and category
 fn format_output_description(info: &serde_json::Map<String, serde_json::Value>) -> String {
     let type_val = info
@@ -200,7 +584,130 @@ fn format_output_description(info: &serde_json::Map<String, serde_json::Value>)
         "overlay" => magenta_bold("Nixpkgs overlay"),
         "module" => magenta_bold("NixOS module"),
         "template" => "template".to_string(),
-        "configuration" => "NixOS configuration".to_string(),
+        "configuration" => match category {
+            Some("darwinConfigurations") => "nix-darwin configuration".to_string(),
+            Some("homeConfigurations") => "Home Manager configuration".to_string(),
+            _ => "NixOS configuration".to_string(),
+        },
         _ => type_val.to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outputs_to_json() {
+        let outputs = json!({
+            "packages": {
+                "x86_64-linux": {
+                    "hello": { "_type": "derivation", "_name": "hello-2.12", "_category": "packages" }
+                },
+                "aarch64-linux": { "_omitted": true }
+            },
+            "nixosModules": { "default": { "_type": "module" } },
+            "lib": { "_unknown": true }
+        });
+        assert_eq!(
+            outputs_to_json(&outputs, None),
+            json!({
+                "packages": {
+                    "x86_64-linux": { "hello": { "type": "derivation", "name": "hello-2.12" } },
+                    "aarch64-linux": {}
+                },
+                "nixosModules": { "default": { "type": "nixos-module" } },
+                "lib": { "type": "unknown" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_compare_outputs() {
+        let old = json!({
+            "packages": { "x86_64-linux": {
+                "hello": { "type": "derivation", "name": "hello-2.12" },
+                "tool": { "type": "derivation", "name": "tool-1.0" }
+            } },
+            "devShells": { "x86_64-linux": { "default": { "type": "derivation", "name": "shell" } } }
+        });
+        let new = json!({
+            "packages": { "x86_64-linux": {
+                "hello": { "type": "derivation", "name": "hello-2.13" },
+                "tool": { "type": "derivation", "name": "tool-1.0" }
+            } },
+            "devShells": { "x86_64-linux": { "dev": { "type": "derivation", "name": "shell" } } }
+        });
+        assert_eq!(
+            compare_outputs(&old, &new),
+            json!({
+                "added": { "devShells.x86_64-linux.dev": { "type": "derivation", "name": "shell" } },
+                "removed": { "devShells.x86_64-linux.default": { "type": "derivation", "name": "shell" } },
+                "changed": { "packages.x86_64-linux.hello": {
+                    "old": { "type": "derivation", "name": "hello-2.12" },
+                    "new": { "type": "derivation", "name": "hello-2.13" }
+                } }
+            })
+        );
+        assert_eq!(
+            compare_outputs(&old, &old),
+            json!({ "added": {}, "removed": {}, "changed": {} })
+        );
+
+        // Systems only one side evaluated are not compared
+        let all = json!({ "packages": {
+            "x86_64-linux": { "hello": { "type": "derivation", "name": "hello-2.12" } },
+            "aarch64-darwin": { "hello": { "type": "derivation", "name": "hello-2.12" } }
+        } });
+        let linux_only = json!({ "packages": {
+            "x86_64-linux": { "hello": { "type": "derivation", "name": "hello-2.12" } },
+            "aarch64-darwin": {}
+        } });
+        let empty = json!({ "added": {}, "removed": {}, "changed": {} });
+        assert_eq!(compare_outputs(&all, &linux_only), empty);
+        assert_eq!(compare_outputs(&linux_only, &all), empty);
+    }
+
+    #[test]
+    fn test_format_names() {
+        let names: Vec<String> = ["a", "b"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(format_names(&names), "a, b");
+
+        let names: Vec<String> = (0..10).map(|i| format!("n{}", i)).collect();
+        assert_eq!(
+            format_names(&names),
+            "n0, n1, n2, n3, n4, n5, ... (10 total)"
+        );
+    }
+
+    #[test]
+    fn test_format_input_tree() {
+        let mut summaries = BTreeMap::new();
+        summaries.insert(
+            "nixpkgs".to_string(),
+            Some(BTreeMap::from([
+                ("lib".to_string(), vec!["mkIf".to_string()]),
+                ("overlays".to_string(), vec![]),
+            ])),
+        );
+        summaries.insert("src".to_string(), None);
+
+        let lines: Vec<String> = format_input_tree(&summaries)
+            .iter()
+            .map(|l| {
+                l.replace("\x1b[32;1m", "")
+                    .replace("\x1b[1m", "")
+                    .replace("\x1b[0m", "")
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "├───nixpkgs",
+                "│   ├───lib: mkIf",
+                "│   └───overlays",
+                "└───src (not a flake)",
+            ]
+        );
+    }
+}
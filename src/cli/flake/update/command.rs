@@ -1,26 +1,201 @@
-use crate::lock::update_lock;
+use crate::lock::{is_commit_hash, update_lock};
 use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
 
 /// Update flake.lock to latest versions
 pub fn cmd_update(
     input_name: Option<&str>,
-    override_inputs: Option<&std::collections::HashMap<String, String>>,
+    override_inputs: Option<&HashMap<String, String>>,
+    allow_branch_change: bool,
+    to: Option<&str>,
+    exclude: &[String],
 ) -> Result<()> {
     let flake_dir = std::env::current_dir().context("Could not get current directory")?;
 
-    let updates = update_lock(&flake_dir, input_name, override_inputs)?;
+    // `--to` is an override of the input with its declared source at another rev
+    let pinned;
+    let (input_name, override_inputs) = match (input_name, to) {
+        (Some(name), Some(rev)) => {
+            let inputs = crate::flake::get_flake_inputs(&flake_dir)?;
+            let spec = inputs
+                .get(name)
+                .with_context(|| format!("input '{}' not found in flake.nix", name))?;
+            let declared = crate::flake::declared_input_url(&flake_dir, name)?
+                .with_context(|| format!("input '{}' has no URL in flake.nix", name))?;
+            let flake_ref = crate::progress::with_status(&format!("resolving {}", rev), || {
+                pinned_ref(name, spec, &declared, rev, !allow_branch_change)
+            })?;
+            pinned = HashMap::from([(name.to_string(), flake_ref)]);
+            (None, Some(&pinned))
+        }
+        _ => (input_name, override_inputs),
+    };
+
+    let updates = update_lock(
+        &flake_dir,
+        input_name,
+        override_inputs,
+        allow_branch_change,
+        exclude,
+    )?;
 
     if let Some(updates) = updates {
         if updates.is_empty() {
             if input_name.is_some() {
-                println!("Input is already up to date.");
+                eprintln!("Input is already up to date.");
             } else if override_inputs.map(|o| o.is_empty()).unwrap_or(true) {
-                println!("All inputs are up to date.");
+                eprintln!("All inputs are up to date.");
             }
         } else {
-            println!("Updated {} input(s).", updates.len());
+            eprintln!("Updated {} input(s).", updates.len());
         }
     }
 
     Ok(())
 }
+
+/// The flake ref locking input `name`, declared as `spec` (parsed) and
+/// `declared` (the URL itself), to `rev`.
+///
+/// With `check_ref`, `rev` must be reachable from the ref the input
+/// declares. GitHub is asked directly, which also confirms a bare commit
+/// hash exists; for git inputs the fetch checks both.
+fn pinned_ref(
+    name: &str,
+    spec: &Value,
+    declared: &str,
+    rev: &str,
+    check_ref: bool,
+) -> Result<String> {
+    let declared_ref = spec["ref"].as_str().filter(|_| check_ref);
+    match spec["type"].as_str().unwrap_or("unknown") {
+        "github" => {
+            let owner = spec["owner"].as_str().unwrap_or("");
+            let repo = spec["repo"].as_str().unwrap_or("");
+            // The ref check below fails for a missing commit too
+            let commit = if is_commit_hash(rev) && declared_ref.is_some() {
+                rev.to_string()
+            } else {
+                crate::lock::github_commit(owner, repo, rev)?.0
+            };
+            if let Some(git_ref) = declared_ref {
+                if !crate::lock::github_rev_on_ref(owner, repo, &commit, git_ref)? {
+                    anyhow::bail!(
+                        "{} is not on '{}', the ref input '{}' declares\n\
+                         Pass --allow-branch-change to lock it anyway",
+                        rev,
+                        git_ref,
+                        name
+                    );
+                }
+            }
+            Ok(with_rev(declared, &commit, None))
+        }
+        "git" => {
+            let url = spec["url"].as_str().unwrap_or("");
+            let commit = if is_commit_hash(rev) {
+                rev.to_string()
+            } else {
+                crate::git::remote_rev(url, Some(rev))?
+            };
+            // Fetching a rev under a ref fails unless the ref contains it
+            let scope = match declared_ref {
+                Some(git_ref) => ("ref", git_ref),
+                None => ("allRefs", "1"),
+            };
+            Ok(with_rev(declared, &commit, Some(scope)))
+        }
+        other => anyhow::bail!(
+            "--to only works for github and git inputs, and '{}' is a {} input",
+            name,
+            other
+        ),
+    }
+}
+
+/// `declared` with its rev (and any ref) replaced by `commit`, keeping
+/// every other parameter. Git URLs get `scope` (`ref=` or `allRefs=1`)
+/// in front of the rev; for github-style URLs the commit goes in the path.
+fn with_rev(declared: &str, commit: &str, scope: Option<(&str, &str)>) -> String {
+    let (base, params) = crate::flake::split_flake_ref_query(declared);
+    let mut params: Vec<(String, String)> = params
+        .into_iter()
+        .filter(|(k, _)| !matches!(k.as_str(), "rev" | "ref" | "allRefs"))
+        .collect();
+
+    let base = if base.starts_with("git+") {
+        if let Some((key, value)) = scope {
+            params.push((key.to_string(), value.to_string()));
+        }
+        params.push(("rev".to_string(), commit.to_string()));
+        base.to_string()
+    } else {
+        // github:owner/repo[/ref-or-rev]
+        let (scheme, path) = base.split_once(':').unwrap_or(("github", base));
+        let owner_repo: Vec<&str> = path.splitn(3, '/').take(2).collect();
+        format!("{}:{}/{}", scheme, owner_repo.join("/"), commit)
+    };
+
+    if params.is_empty() {
+        base
+    } else {
+        let query: Vec<String> = params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        format!("{}?{}", base, query.join("&"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pinned_ref() {
+        let rev = "0123456789abcdef0123456789abcdef01234567";
+        let git = json!({ "type": "git", "url": "https://example.com/repo.git", "ref": "main" });
+        let declared = "git+https://example.com/repo.git?ref=main&dir=sub&submodules=1";
+        assert_eq!(
+            pinned_ref("repo", &git, declared, rev, true).unwrap(),
+            format!(
+                "git+https://example.com/repo.git?dir=sub&submodules=1&ref=main&rev={}",
+                rev
+            )
+        );
+        assert_eq!(
+            pinned_ref("repo", &git, declared, rev, false).unwrap(),
+            format!(
+                "git+https://example.com/repo.git?dir=sub&submodules=1&allRefs=1&rev={}",
+                rev
+            )
+        );
+
+        let path = json!({ "type": "path", "path": "./sub" });
+        assert!(pinned_ref("sub", &path, "path:./sub", rev, true).is_err());
+    }
+
+    #[test]
+    fn test_with_rev() {
+        let rev = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(
+            with_rev("github:NixOS/nixpkgs", rev, None),
+            format!("github:NixOS/nixpkgs/{}", rev)
+        );
+        assert_eq!(
+            with_rev(
+                "github:acme/mono/release?dir=nix&host=git.acme.dev",
+                rev,
+                None
+            ),
+            format!("github:acme/mono/{}?dir=nix&host=git.acme.dev", rev)
+        );
+        assert_eq!(
+            with_rev(
+                "git+ssh://git@host/repo?rev=deadbeef",
+                rev,
+                Some(("allRefs", "1"))
+            ),
+            format!("git+ssh://git@host/repo?allRefs=1&rev={}", rev)
+        );
+    }
+}
@@ -1,8 +1,11 @@
 use super::common::build_resolved_attribute;
-use crate::flake::resolve_installable;
-use crate::nix::{get_package_main_program, get_system, BuildOptions};
+use crate::flake::{resolve_attr_path, resolve_installable};
+use crate::nix::{
+    build_input_attr, get_input_main_program, get_package_main_program, get_system, BuildOptions,
+};
 use anyhow::{Context, Result};
 use clap::Args;
+use std::path::Path;
 
 #[derive(Args, Clone, Debug)]
 pub struct FmtArgs {
@@ -17,9 +20,99 @@ pub struct FmtArgs {
     /// Use specified store URL
     #[arg(long)]
     pub store: Option<String>,
+
+    /// Formatter to use instead of the flake's formatter output
+    /// (e.g., 'nixpkgs#nixfmt-rfc-style')
+    #[arg(long, value_name = "INSTALLABLE")]
+    pub formatter: Option<String>,
+}
+
+/// The locked root input of the flake in `flake_dir` that the registry
+/// name in `formatter` refers to, and the attribute to take from it.
+///
+/// `nixpkgs#nixfmt-rfc-style` then formats with the nixpkgs revision the
+/// project already uses.
+fn formatter_input(formatter: &str, flake_dir: &Path) -> Option<(String, String)> {
+    let (ref_part, attr) = formatter.split_once('#').unwrap_or((formatter, ""));
+    if !crate::registry::is_registry_name(ref_part) {
+        return None;
+    }
+    crate::lock::locked_input_ref(flake_dir, ref_part)?;
+    let attr = if attr.is_empty() { "default" } else { attr };
+    Some((ref_part.to_string(), attr.to_string()))
+}
+
+/// Build and run an explicitly chosen formatter over `args.args`.
+fn cmd_fmt_with(formatter: &str, args: &FmtArgs) -> Result<()> {
+    let build_options = BuildOptions {
+        out_link: None,
+        store: args.store.clone(),
+        ..Default::default()
+    };
+
+    let target = resolve_installable(&args.installable);
+    if let Some(flake_dir) = target.flake_dir.as_deref().filter(|_| target.is_local) {
+        if let Some((input, attr)) = formatter_input(formatter, flake_dir) {
+            let store_path = build_input_attr(flake_dir, &input, &attr, &build_options)?;
+            let main_program = get_input_main_program(flake_dir, &input, &attr)?;
+            return run_formatter(&format!("{}/bin/{}", store_path, main_program), &args.args);
+        }
+    }
+
+    let resolved = resolve_installable(formatter);
+    if !resolved.is_local {
+        // Not an input of the project: passthrough to nix run
+        let mut cmd = crate::command::NixCommand::new("nix");
+        cmd.args(["run", &resolved.full_ref()]);
+
+        if let Some(s) = &args.store {
+            cmd.args(["--store", s]);
+        }
+
+        if !args.args.is_empty() {
+            cmd.arg("--");
+            cmd.args(&args.args);
+        }
+
+        return cmd.exec();
+    }
+
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+    let attr = resolve_attr_path(&resolved.attr_part, "packages", &get_system()?);
+
+    let store_path = build_resolved_attribute(&resolved, &attr, &build_options, true)?
+        .context("Build failed")?;
+
+    let main_program = get_package_main_program(flake_dir, &attr)?;
+    run_formatter(&format!("{}/bin/{}", store_path, main_program), &args.args)
+}
+
+/// Run a built formatter executable with the given arguments.
+fn run_formatter(exe_path: &str, args: &[String]) -> Result<()> {
+    let mut cmd = std::process::Command::new(exe_path);
+    cmd.args(args);
+
+    tracing::debug!("+ {} {}", exe_path, args.join(" "));
+
+    let status = cmd
+        .status()
+        .context(format!("Failed to run {}", exe_path))?;
+
+    if !status.success() {
+        anyhow::bail!(
+            "Command failed with exit code: {}",
+            status.code().unwrap_or(1)
+        );
+    }
+
+    Ok(())
 }
 
 pub fn cmd_fmt(args: FmtArgs) -> Result<()> {
+    if let Some(formatter) = &args.formatter {
+        return cmd_fmt_with(formatter, &args);
+    }
+
     let resolved = resolve_installable(&args.installable);
 
     if !resolved.is_local {
@@ -69,23 +162,53 @@ pub fn cmd_fmt(args: FmtArgs) -> Result<()> {
 
     let main_program = get_package_main_program(flake_dir, &attr)?;
     let exe_path = format!("{}/bin/{}", store_path, main_program);
+    run_formatter(&exe_path, &args.args)
+}
 
-    // Run the executable
-    let mut cmd = std::process::Command::new(&exe_path);
-    cmd.args(&args.args);
-
-    tracing::debug!("+ {} {}", exe_path, args.args.join(" "));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let status = cmd
-        .status()
-        .context(format!("Failed to run {}", exe_path))?;
+    #[test]
+    fn test_formatter_input() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            formatter_input("nixpkgs#nixfmt-rfc-style", dir.path()),
+            None
+        );
 
-    if !status.success() {
-        anyhow::bail!(
-            "Command failed with exit code: {}",
-            status.code().unwrap_or(1)
+        std::fs::write(
+            dir.path().join("flake.lock"),
+            r#"{
+              "nodes": {
+                "nixpkgs": {
+                  "locked": {
+                    "type": "github",
+                    "owner": "NixOS",
+                    "repo": "nixpkgs",
+                    "rev": "0123456789abcdef0123456789abcdef01234567"
+                  }
+                },
+                "root": { "inputs": { "nixpkgs": "nixpkgs" } }
+              },
+              "root": "root",
+              "version": 7
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            formatter_input("nixpkgs#nixfmt-rfc-style", dir.path()),
+            Some(("nixpkgs".to_string(), "nixfmt-rfc-style".to_string()))
+        );
+        assert_eq!(
+            formatter_input("nixpkgs", dir.path()),
+            Some(("nixpkgs".to_string(), "default".to_string()))
+        );
+        assert_eq!(formatter_input("home-manager#fmt", dir.path()), None);
+        assert_eq!(formatter_input("./tools#fmt", dir.path()), None);
+        assert_eq!(
+            formatter_input("github:NixOS/nixpkgs#fmt", dir.path()),
+            None
         );
     }
-
-    Ok(())
 }
@@ -0,0 +1,148 @@
+//! GitHub Actions workflow command output.
+//!
+//! When enabled with `--gha`, builds are wrapped in collapsible log groups
+//! and errors are emitted as `::error` annotations that point at the file
+//! and line nix reported, so they show up inline in pull request diffs.
+//! Workflow commands go to stderr, which the runner also reads, so they
+//! never mix with `--json` output on stdout.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Matches nix error positions like "at /path/to/flake.nix:12:5:"
+static POSITION_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"at (/[^\s:]+):(\d+):(\d+)").unwrap());
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Start a collapsible log group.
+pub fn group(title: &str) {
+    if enabled() {
+        eprintln!("::group::{}", escape_data(title));
+    }
+}
+
+/// End the current log group.
+pub fn end_group() {
+    if enabled() {
+        eprintln!("::endgroup::");
+    }
+}
+
+/// Emit an error annotation for a nix error message.
+pub fn error(message: &str) {
+    if enabled() {
+        let workspace = std::env::var("GITHUB_WORKSPACE")
+            .map(PathBuf::from)
+            .or_else(|_| std::env::current_dir())
+            .ok();
+        eprintln!("{}", format_error(message, workspace.as_deref()));
+    }
+}
+
+/// Build the `::error` workflow command for a nix error message.
+fn format_error(message: &str, workspace: Option<&Path>) -> String {
+    let summary = summarize_error(message);
+    match error_position(message) {
+        Some((file, line, col)) => format!(
+            "::error file={},line={},col={}::{}",
+            escape_property(&relative_to_workspace(&file, workspace)),
+            line,
+            col,
+            escape_data(&summary)
+        ),
+        None => format!("::error::{}", escape_data(&summary)),
+    }
+}
+
+/// Find the innermost source position in a nix error outside the store.
+fn error_position(message: &str) -> Option<(String, u32, u32)> {
+    POSITION_REGEX
+        .captures_iter(message)
+        .filter(|c| !c[1].starts_with("/nix/store/"))
+        .last()
+        .and_then(|c| Some((c[1].to_string(), c[2].parse().ok()?, c[3].parse().ok()?)))
+}
+
+/// Pick the most specific "error: ..." line, or the whole message.
+fn summarize_error(message: &str) -> String {
+    message
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("error:"))
+        .map(str::trim)
+        .rfind(|l| !l.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| message.trim().to_string())
+}
+
+/// Make a path relative to the workspace so annotations attach to the diff.
+fn relative_to_workspace(file: &str, workspace: Option<&Path>) -> String {
+    workspace
+        .and_then(|ws| Path::new(file).strip_prefix(ws).ok())
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| file.to_string())
+}
+
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NIX_ERROR: &str = "error:
+       … while evaluating the attribute 'packages.x86_64-linux.hello'
+         at /nix/store/abc-source/lib.nix:3:1:
+       … while calling a function
+         at /work/repo/flake.nix:12:5:
+           11|
+           12|     hello = foo;
+             |     ^
+       error: undefined variable 'foo'";
+
+    #[test]
+    fn test_error_position() {
+        assert_eq!(
+            error_position(NIX_ERROR),
+            Some(("/work/repo/flake.nix".to_string(), 12, 5))
+        );
+        assert_eq!(error_position("error: something broke"), None);
+    }
+
+    #[test]
+    fn test_summarize_error() {
+        assert_eq!(summarize_error(NIX_ERROR), "undefined variable 'foo'");
+        assert_eq!(summarize_error("build failed"), "build failed");
+    }
+
+    #[test]
+    fn test_format_error() {
+        let workspace = Path::new("/work/repo");
+        assert_eq!(
+            format_error(NIX_ERROR, Some(workspace)),
+            "::error file=flake.nix,line=12,col=5::undefined variable 'foo'"
+        );
+        assert_eq!(
+            format_error(NIX_ERROR, None),
+            "::error file=/work/repo/flake.nix,line=12,col=5::undefined variable 'foo'"
+        );
+        assert_eq!(format_error("a\nb", Some(workspace)), "::error::a%0Ab");
+    }
+}
@@ -1,5 +1,8 @@
 use crate::command::NixCommand;
+use crate::hash::HashAlgo;
+use anyhow::{Context, Result};
 use clap::Args;
+use std::path::Path;
 
 #[derive(Args, Clone, Debug)]
 pub struct FileArgs {
@@ -28,6 +31,17 @@ pub struct FileArgs {
     pub type_: Option<String>,
 }
 
+/// Hash each file natively and print one hash per line.
+pub fn run(args: &FileArgs, algo: HashAlgo) -> Result<()> {
+    let format = super::output_format(args.base16, args.base32, args.base64);
+    for path in &args.paths {
+        let digest = crate::hash::hash_file(Path::new(path), algo)
+            .with_context(|| format!("Failed to hash '{}'", path))?;
+        println!("{}", crate::hash::format_hash(algo, &digest, format));
+    }
+    Ok(())
+}
+
 pub fn handle(cmd: &mut NixCommand, args: &FileArgs) {
     cmd.arg("file");
     if args.base16 {
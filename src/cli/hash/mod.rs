@@ -6,6 +6,7 @@ use self::convert::{ConvertArgs, LegacyArgs};
 use self::file::FileArgs;
 use self::path::PathArgs;
 use crate::command::NixCommand;
+use crate::hash::{HashAlgo, HashFormat};
 use anyhow::Result;
 use clap::Subcommand;
 
@@ -37,7 +38,43 @@ pub enum HashCommands {
     Convert(ConvertArgs),
 }
 
+/// Output format selected by the --base16/--base32/--base64/--sri flags.
+///
+/// Like `nix hash`, SRI is the default.
+fn output_format(base16: bool, base32: bool, base64: bool) -> HashFormat {
+    if base16 {
+        HashFormat::Base16
+    } else if base32 {
+        HashFormat::Nix32
+    } else if base64 {
+        HashFormat::Base64
+    } else {
+        HashFormat::Sri
+    }
+}
+
+/// Algorithm for a --type flag, if trix can compute it without nix.
+fn native_algo(type_: &Option<String>) -> Option<HashAlgo> {
+    HashAlgo::from_name(type_.as_deref().unwrap_or("sha256"))
+}
+
 pub fn cmd_hash(cmd: HashCommands) -> Result<()> {
+    // sha1, sha256 and sha512 are computed here, so `hash file` and
+    // `hash path` work without the nix-command experimental feature
+    match &cmd {
+        HashCommands::File(args) => {
+            if let Some(algo) = native_algo(&args.type_) {
+                return file::run(args, algo);
+            }
+        }
+        HashCommands::Path(args) => {
+            if let Some(algo) = native_algo(&args.type_) {
+                return path::run(args, algo);
+            }
+        }
+        _ => {}
+    }
+
     let mut command = NixCommand::new("nix");
     command.arg("hash");
 
@@ -1,5 +1,8 @@
 use crate::command::NixCommand;
+use crate::hash::HashAlgo;
+use anyhow::{Context, Result};
 use clap::Args;
+use std::path::Path;
 
 #[derive(Args, Clone, Debug)]
 pub struct PathArgs {
@@ -28,6 +31,17 @@ pub struct PathArgs {
     pub type_: Option<String>,
 }
 
+/// Hash the NAR serialisation of each path natively and print one hash per line.
+pub fn run(args: &PathArgs, algo: HashAlgo) -> Result<()> {
+    let format = super::output_format(args.base16, args.base32, args.base64);
+    for path in &args.paths {
+        let digest = crate::hash::hash_path(Path::new(path), algo)
+            .with_context(|| format!("Failed to hash '{}'", path))?;
+        println!("{}", crate::hash::format_hash(algo, &digest, format));
+    }
+    Ok(())
+}
+
 pub fn handle(cmd: &mut NixCommand, args: &PathArgs) {
     cmd.arg("path");
     if args.base16 {
@@ -0,0 +1,20 @@
+use anyhow::Result;
+use clap::Subcommand;
+
+#[path = "switch/command.rs"]
+pub mod switch;
+
+pub use switch::cmd_switch;
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum HomeCommands {
+    /// Build a Home Manager configuration and activate it, like
+    /// `home-manager switch --flake`
+    Switch(switch::SwitchArgs),
+}
+
+pub fn cmd_home(cmd: HomeCommands) -> Result<()> {
+    match cmd {
+        HomeCommands::Switch(args) => cmd_switch(args),
+    }
+}
@@ -0,0 +1,170 @@
+use crate::flake::{ensure_lock, join_attr_path, resolve_installable};
+use crate::nix::{flake_attr_exists, run_nix_build, BuildOptions};
+use anyhow::{Context, Result};
+use clap::Args;
+use std::path::Path;
+
+#[derive(Args, Clone, Debug)]
+pub struct SwitchArgs {
+    /// Flake reference, optionally with #CONFIG (default: $USER@host, then $USER)
+    #[arg(default_value = ".")]
+    pub flake_ref: String,
+
+    /// Move existing files in the way aside with this extension
+    #[arg(short = 'b', long = "backup-extension", value_name = "EXT")]
+    pub backup_extension: Option<String>,
+
+    /// Copy the configuration to HOST over ssh and activate it there
+    #[arg(long, value_name = "HOST")]
+    pub target_host: Option<String>,
+
+    /// Print the expression trix would build and exit
+    #[arg(long)]
+    pub print_expr: bool,
+}
+
+/// Configuration names to try when none is given, most specific first.
+///
+/// `target_host` may be `user@host.domain`, whose user and short host
+/// name then take the place of the local ones.
+fn candidate_names(user: &str, local_host: Option<&str>, target_host: Option<&str>) -> Vec<String> {
+    let (user, host) = match target_host {
+        Some(target) => {
+            let (user, host) = target.rsplit_once('@').unwrap_or((user, target));
+            (
+                user,
+                Some(host.split('.').next().unwrap_or(host).to_string()),
+            )
+        }
+        None => (user, local_host.map(str::to_string)),
+    };
+    let mut names = Vec::new();
+    if let Some(host) = host.filter(|h| !h.is_empty()) {
+        names.push(format!("{}@{}", user, host));
+    }
+    names.push(user.to_string());
+    names
+}
+
+/// The `homeConfigurations` entry to switch to; `config` is what followed
+/// `#` in the flake reference, if anything.
+fn config_name(
+    flake_dir: &Path,
+    config: Option<&str>,
+    target_host: Option<&str>,
+) -> Result<String> {
+    if let Some(config) = config.filter(|c| !c.is_empty()) {
+        let name = config.strip_prefix("homeConfigurations.").unwrap_or(config);
+        return Ok(name.trim_matches('"').to_string());
+    }
+    let user = std::env::var("USER").context("USER is not set; pass flake#CONFIG")?;
+    let names = candidate_names(
+        &user,
+        crate::cli::common::short_hostname().as_deref(),
+        target_host,
+    );
+    for name in &names {
+        if flake_attr_exists(flake_dir, &join_attr_path(&["homeConfigurations", name]))? {
+            return Ok(name.clone());
+        }
+    }
+    anyhow::bail!(
+        "No homeConfigurations.{} in the flake; pass flake#CONFIG",
+        names.join(" or homeConfigurations.")
+    )
+}
+
+/// Build a Home Manager configuration and activate it
+pub fn cmd_switch(args: SwitchArgs) -> Result<()> {
+    let resolved = resolve_installable(&args.flake_ref);
+    if !resolved.is_local {
+        anyhow::bail!(
+            "trix home switch needs a local flake; use `home-manager switch --flake {}`",
+            args.flake_ref
+        );
+    }
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+    ensure_lock(flake_dir, None)?;
+
+    // Not resolved.attr_part: that is "default" when no attribute was given
+    let config = args.flake_ref.split_once('#').map(|(_, attr)| attr);
+    let name = config_name(flake_dir, config, args.target_host.as_deref())?;
+    let attr = join_attr_path(&["homeConfigurations", &name, "activationPackage"]);
+
+    if args.print_expr {
+        println!("{}", crate::nix::eval_call_expr(flake_dir, &attr)?);
+        return Ok(());
+    }
+
+    let options = BuildOptions {
+        out_link: None,
+        ..Default::default()
+    };
+    let output = run_nix_build(flake_dir, &attr, &options, true)?.context("Build failed")?;
+    let generation = output
+        .lines()
+        .last()
+        .context("nix-build printed no output path")?
+        .trim()
+        .to_string();
+    let activate = format!("{}/activate", generation);
+
+    // Not NixCommand: neither ssh nor the activation script takes nix's
+    // verbosity flags
+    let status = match &args.target_host {
+        Some(host) => {
+            eprintln!("Copying {} to {}", name, host);
+            crate::retry::with_retry("nix-copy-closure", || {
+                crate::command::NixCommand::new("nix-copy-closure")
+                    .args(["--to", host, &generation])
+                    .run_keeping_stderr()
+            })?;
+            let mut remote = String::new();
+            if let Some(ext) = &args.backup_extension {
+                remote.push_str(&format!(
+                    "HOME_MANAGER_BACKUP_EXT={} ",
+                    crate::cli::common::shell_quote(ext)
+                ));
+            }
+            remote.push_str(&crate::cli::common::shell_quote(&activate));
+            std::process::Command::new("ssh")
+                .args([host.as_str(), &remote])
+                .status()
+                .context("Failed to run ssh")?
+        }
+        None => {
+            let mut cmd = std::process::Command::new(&activate);
+            if let Some(ext) = &args.backup_extension {
+                cmd.env("HOME_MANAGER_BACKUP_EXT", ext);
+            }
+            cmd.status()
+                .with_context(|| format!("Failed to run {}", activate))?
+        }
+    };
+    if !status.success() {
+        anyhow::bail!("Activating {} failed", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_names() {
+        assert_eq!(
+            candidate_names("alice", Some("laptop"), None),
+            vec!["alice@laptop", "alice"]
+        );
+        assert_eq!(
+            candidate_names("alice", Some("laptop"), Some("deploy@server.example.com")),
+            vec!["deploy@server", "deploy"]
+        );
+        assert_eq!(
+            candidate_names("alice", Some("laptop"), Some("server")),
+            vec!["alice@server", "alice"]
+        );
+        assert_eq!(candidate_names("alice", None, None), vec!["alice"]);
+    }
+}
@@ -8,17 +8,27 @@ pub struct LogArgs {
     /// Installable reference
     #[arg(default_value = ".#default")]
     pub installable: String,
+
+    /// Show the log of the latest build trix ran
+    #[arg(long, conflicts_with = "build")]
+    pub last: bool,
+
+    /// Show the log of the Nth most recent build (as numbered by `trix builds list`)
+    #[arg(long, value_name = "N")]
+    pub build: Option<usize>,
 }
 
-/// Show build log for a package
 /// Show build log for a package
 pub fn cmd_log(args: LogArgs) -> Result<()> {
+    if let Some(n) = args.build.or(args.last.then_some(1)) {
+        return show_recorded_log(n);
+    }
+
     let resolved = resolve_installable(&args.installable);
 
     if !resolved.is_local {
         // Passthrough to nix log
-        let flake_ref = resolved.flake_ref.as_deref().unwrap_or("");
-        let full_ref = format!("{}#{}", flake_ref, resolved.attr_part);
+        let full_ref = resolved.full_ref();
 
         let mut cmd = crate::command::NixCommand::new("nix");
         cmd.args(["log", &full_ref]);
@@ -40,3 +50,42 @@ pub fn cmd_log(args: LogArgs) -> Result<()> {
 
     Ok(())
 }
+
+/// Print the log of the `n`th most recent recorded build.
+fn show_recorded_log(n: usize) -> Result<()> {
+    let records = crate::builds::load();
+    let build = crate::builds::nth_latest(&records, n).with_context(|| {
+        format!(
+            "No build #{} recorded ({} so far); see `trix builds list`",
+            n,
+            records.len()
+        )
+    })?;
+    let drv_path = build.drv_path.as_deref().with_context(|| {
+        format!(
+            "Build #{} ({}) failed before its derivation was known",
+            n, build.attr
+        )
+    })?;
+    tracing::debug!(
+        "Log of {}: {}",
+        drv_path,
+        build
+            .log_path
+            .clone()
+            .or_else(|| crate::builds::log_file(drv_path).map(|p| p.display().to_string()))
+            .unwrap_or_else(|| "not found locally".to_string())
+    );
+
+    match crate::nix::get_build_log(drv_path) {
+        Some(log) => {
+            print!("{}", log);
+            Ok(())
+        }
+        None if build.ok => anyhow::bail!(
+            "No build log available for {}; it was probably substituted rather than built",
+            drv_path
+        ),
+        None => anyhow::bail!("No build log available for {}", drv_path),
+    }
+}
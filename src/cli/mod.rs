@@ -1,6 +1,8 @@
 //! CLI module exports and shared utilities.
 
 pub mod common;
+pub mod gha;
+pub mod plugin;
 pub mod style;
 
 #[path = "build/command.rs"]
@@ -9,6 +11,9 @@ pub mod build;
 #[path = "copy/command.rs"]
 pub mod copy;
 
+#[path = "debug_build/command.rs"]
+pub mod debug_build;
+
 #[path = "develop/command.rs"]
 pub mod develop;
 
@@ -21,6 +26,15 @@ pub mod log;
 #[path = "run/command.rs"]
 pub mod run;
 
+#[path = "schema/command.rs"]
+pub mod schema;
+
+#[path = "self_test/command.rs"]
+pub mod self_test;
+
+#[path = "setup/command.rs"]
+pub mod setup;
+
 #[path = "shell/command.rs"]
 pub mod shell;
 
@@ -33,18 +47,26 @@ pub mod eval;
 #[path = "repl/command.rs"]
 pub mod repl;
 
+pub mod builds;
+pub mod darwin;
 pub mod flake;
 pub mod hash;
+pub mod home;
 pub mod profile;
 pub mod registry;
+pub mod ws;
 
 pub use build::cmd_build;
 pub use copy::cmd_copy;
+pub use debug_build::cmd_debug_build;
 pub use develop::cmd_develop;
 pub use eval::cmd_eval;
 pub use fmt::cmd_fmt;
 pub use log::cmd_log;
 pub use repl::cmd_repl;
 pub use run::cmd_run;
+pub use schema::cmd_schema;
+pub use self_test::cmd_self_test;
+pub use setup::cmd_setup;
 pub use shell::cmd_shell;
 pub use why_depends::cmd_why_depends;
@@ -0,0 +1,132 @@
+//! External subcommands.
+//!
+//! Like git, an unknown subcommand `trix foo` runs an executable named
+//! `trix-foo` from PATH with the remaining arguments, so trix can be
+//! extended without patching the dispatcher. Plugins get the path of the
+//! running trix in `$TRIX` so they can call back into it.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+const PLUGIN_PREFIX: &str = "trix-";
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Find plugins in `dirs`; the first directory providing a name wins, as with PATH lookup.
+fn find_plugins_in(dirs: &[PathBuf]) -> BTreeMap<String, PathBuf> {
+    let mut plugins = BTreeMap::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let Some(name) = file_name
+                .to_str()
+                .and_then(|n| n.strip_prefix(PLUGIN_PREFIX))
+            else {
+                continue;
+            };
+            if name.is_empty() || plugins.contains_key(name) || !is_executable(&entry.path()) {
+                continue;
+            }
+            plugins.insert(name.to_string(), entry.path());
+        }
+    }
+    plugins
+}
+
+fn path_dirs() -> Vec<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+/// Find all plugins on PATH, keyed by subcommand name.
+pub fn find_plugins() -> BTreeMap<String, PathBuf> {
+    find_plugins_in(&path_dirs())
+}
+
+/// Print the plugins on PATH (`trix --list-plugins`).
+///
+/// Plugins named like a built-in command can never run; they are marked.
+pub fn list_plugins(builtins: &[String]) {
+    let plugins = find_plugins();
+    if plugins.is_empty() {
+        println!(
+            "No plugins found on PATH (executables named {}<command>)",
+            PLUGIN_PREFIX
+        );
+        return;
+    }
+    let width = plugins.keys().map(|n| n.len()).max().unwrap_or(0);
+    for (name, path) in &plugins {
+        let shadowed = if builtins.contains(name) {
+            "  (shadowed by built-in command)"
+        } else {
+            ""
+        };
+        println!("{:<width$}  {}{}", name, path.display(), shadowed);
+    }
+}
+
+/// Replace this process with the plugin for `args[0]`, passing the rest of `args`.
+pub fn run_plugin(args: &[String]) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let Some((name, rest)) = args.split_first() else {
+        anyhow::bail!("No subcommand given");
+    };
+    let Some(path) = find_plugins().remove(name.as_str()) else {
+        anyhow::bail!(
+            "'{}' is not a trix command and no {}{} was found on PATH.\n\
+             See 'trix --help' for commands, or 'trix --list-plugins' for plugins",
+            name,
+            PLUGIN_PREFIX,
+            name
+        );
+    };
+
+    tracing::debug!("+ {} {}", path.display(), rest.join(" "));
+    let mut cmd = std::process::Command::new(&path);
+    cmd.args(rest);
+    if let Ok(exe) = std::env::current_exe() {
+        cmd.env("TRIX", exe);
+    }
+    let err = cmd.exec();
+    anyhow::bail!("Failed to exec {}: {}", path.display(), err);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(dir: &Path, name: &str, mode: u32) {
+        let path = dir.join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_find_plugins_in() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        touch(first.path(), "trix-deploy", 0o755);
+        touch(first.path(), "trix-notes", 0o644);
+        touch(first.path(), "trix-", 0o755);
+        touch(first.path(), "other", 0o755);
+        touch(second.path(), "trix-deploy", 0o755);
+        touch(second.path(), "trix-lint", 0o755);
+
+        let plugins = find_plugins_in(&[first.path().to_path_buf(), second.path().to_path_buf()]);
+        assert_eq!(plugins.keys().collect::<Vec<_>>(), vec!["deploy", "lint"]);
+        assert_eq!(plugins["deploy"], first.path().join("trix-deploy"));
+        assert_eq!(plugins["lint"], second.path().join("trix-lint"));
+    }
+}
@@ -0,0 +1,18 @@
+use anyhow::Result;
+
+/// Switch the profile to an existing generation
+pub fn cmd_activate(generation: u32) -> Result<()> {
+    let profile_dir = crate::profile::get_profile_dir()?;
+    let gen_link = profile_dir.join(format!("profile-{}-link", generation));
+
+    if std::fs::symlink_metadata(&gen_link).is_err() {
+        anyhow::bail!(
+            "Generation {} does not exist (see 'trix profile history')",
+            generation
+        );
+    }
+
+    crate::profile::activate_generation(&gen_link)?;
+    eprintln!("Switched to generation {}", generation);
+    Ok(())
+}
@@ -1,16 +1,24 @@
-use crate::profile::install;
+use crate::profile::install_all;
 use anyhow::Result;
 
 /// Add packages to the profile
-pub fn cmd_add(installables: &[String]) -> Result<()> {
-    for installable in installables {
-        tracing::debug!("Installing {}...", installable);
-
-        install(installable, None, None, None)?;
+///
+/// All packages go into one new generation. With `activate` false the
+/// generation is only staged, for `trix profile activate` to apply later.
+pub fn cmd_add(installables: &[String], activate: bool) -> Result<()> {
+    let generation = install_all(installables, activate)?;
 
+    for installable in installables {
         // Extract package name for display (matches Python behavior)
         let (_, _, pkg_name) = crate::profile::parse_installable_for_profile(installable);
-        println!("Added {}", pkg_name);
+        eprintln!("Added {}", pkg_name);
+    }
+
+    if !activate {
+        eprintln!(
+            "Staged generation {}; run 'trix profile activate {}' to switch to it",
+            generation, generation
+        );
     }
 
     Ok(())
@@ -0,0 +1,39 @@
+use crate::flake::resolve_installable;
+use crate::profile::apply;
+use anyhow::{Context, Result};
+
+/// Make the profile match a package list declared in a flake
+pub fn cmd_apply(flake_ref: &str, dry_run: bool) -> Result<()> {
+    let resolved = resolve_installable(flake_ref);
+
+    if !resolved.is_local {
+        anyhow::bail!("profile apply only supports local flakes: {}", flake_ref);
+    }
+
+    let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
+    // A dry run evaluates with the lock as it is rather than writing one
+    if !dry_run {
+        crate::flake::ensure_lock(flake_dir, None)?;
+    }
+
+    let summary = apply(flake_dir, &resolved.attr_part, dry_run)?;
+
+    if summary.is_empty() {
+        eprintln!("Profile is up to date");
+        return Ok(());
+    }
+
+    let prefix = if dry_run { "Would add" } else { "Added" };
+    for name in &summary.added {
+        eprintln!("{} {}", prefix, name);
+    }
+    let prefix = if dry_run { "Would remove" } else { "Removed" };
+    for name in &summary.removed {
+        eprintln!("{} {}", prefix, name);
+    }
+    for name in &summary.updated {
+        eprintln!("Updated {}", name);
+    }
+
+    Ok(())
+}
@@ -1,3 +1,5 @@
+use crate::cli::style::{paint, Stream};
+use crate::common::format_size;
 use anyhow::{Context, Result};
 
 /// Read manifest.json from a profile generation's store path.
@@ -89,6 +91,27 @@ pub fn parse_older_than(s: &str) -> Result<u64> {
     }
 }
 
+/// List profile generations as (number, store path), oldest first.
+pub fn list_generations() -> Result<Vec<(u32, std::path::PathBuf)>> {
+    let profile_dir = crate::profile::get_profile_dir()?;
+
+    let mut generations = Vec::new();
+    for entry in std::fs::read_dir(&profile_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name_str = name.to_string_lossy();
+
+        if let Some(num) = crate::profile::parse_generation_number(&name_str) {
+            if let Ok(target) = std::fs::read_link(entry.path()) {
+                generations.push((num, target));
+            }
+        }
+    }
+
+    generations.sort_by_key(|(num, _)| *num);
+    Ok(generations)
+}
+
 pub fn get_closure(path: &str) -> Result<Vec<String>> {
     let mut cmd = crate::command::NixCommand::new("nix-store");
     cmd.args(["--query", "--requisites", path]);
@@ -107,7 +130,8 @@ pub fn group_by_package(closure: &[String]) -> std::collections::HashMap<String,
     map
 }
 
-fn parse_store_path(path: &str) -> Option<(&str, &str)> {
+/// Split a store path into package name and version (empty if none).
+pub fn parse_store_path(path: &str) -> Option<(&str, &str)> {
     // /nix/store/hash-name-version
     let filename = path.split('/').next_back()?;
     let name_part = filename.split_once('-')?.1;
@@ -124,36 +148,54 @@ fn parse_store_path(path: &str) -> Option<(&str, &str)> {
 }
 
 pub fn get_store_path_size(path: &str) -> Result<u64> {
-    // Use nix path-info for accurate size
-    let mut cmd = crate::command::NixCommand::new("nix");
-    cmd.args(["path-info", "--json", path]);
-
-    let info: serde_json::Value = cmd.json().unwrap_or(serde_json::json!([]));
-    if let Some(arr) = info.as_array() {
-        if let Some(first) = arr.first() {
-            return Ok(first["narSize"].as_u64().unwrap_or(0));
-        }
-    }
+    // nix-store reports the NAR size and works without the nix command
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--query", "--size", path]);
 
-    Ok(0)
+    Ok(cmd
+        .output()
+        .ok()
+        .and_then(|out| out.trim().parse().ok())
+        .unwrap_or(0))
 }
 
-pub fn format_size(size: u64) -> String {
-    if size < 1024 {
-        format!("{} B", size)
-    } else if size < 1024 * 1024 {
-        format!("{:.1} KiB", size as f64 / 1024.0)
-    } else if size < 1024 * 1024 * 1024 {
-        format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0))
-    } else {
-        format!("{:.1} GiB", size as f64 / (1024.0 * 1024.0 * 1024.0))
+/// Total NAR size of `paths`.
+pub fn get_paths_size(paths: &[String]) -> Result<u64> {
+    if paths.is_empty() {
+        return Ok(0);
     }
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--query", "--size"]);
+    cmd.args(paths);
+
+    Ok(cmd
+        .output()?
+        .lines()
+        .filter_map(|line| line.trim().parse::<u64>().ok())
+        .sum())
+}
+
+/// Total NAR size of the closure of `paths`.
+pub fn get_closure_size(paths: &[String]) -> Result<u64> {
+    if paths.is_empty() {
+        return Ok(0);
+    }
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--query", "--requisites"]);
+    cmd.args(paths);
+
+    let closure: Vec<String> = cmd.output()?.lines().map(str::to_string).collect();
+    get_paths_size(&closure)
 }
 
 pub fn format_size_diff(diff: i64) -> String {
     if diff > 0 {
         // Red+bold for size increases (matches Python _red_bold)
-        format!("\x1b[31;1m+{}\x1b[0m", format_size(diff as u64))
+        paint(
+            Stream::Stdout,
+            "31;1",
+            &format!("+{}", format_size(diff as u64)),
+        )
     } else if diff < 0 {
         format!("-{}", format_size((-diff) as u64))
     } else {
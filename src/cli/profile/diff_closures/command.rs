@@ -1,32 +1,19 @@
 use super::common::{
-    format_size, format_size_diff, get_closure, get_store_path_size, group_by_package,
+    format_size_diff, get_closure, get_store_path_size, group_by_package, list_generations,
 };
+use crate::cli::style::{fit, paint, Stream};
+use crate::common::format_size;
 use anyhow::Result;
 
 /// Show closure difference between profile versions
 pub fn cmd_diff_closures() -> Result<()> {
-    let profile_dir = crate::profile::get_profile_dir()?;
-
-    let mut generations = Vec::new();
-    for entry in std::fs::read_dir(&profile_dir)? {
-        let entry = entry?;
-        let name = entry.file_name();
-        let name_str = name.to_string_lossy();
-
-        if let Some(num) = crate::profile::parse_generation_number(&name_str) {
-            if let Ok(target) = std::fs::read_link(entry.path()) {
-                generations.push((num, target));
-            }
-        }
-    }
+    let generations = list_generations()?;
 
     if generations.len() < 2 {
         println!("Need at least 2 generations to show differences.");
         return Ok(());
     }
 
-    generations.sort_by_key(|(num, _)| *num);
-
     for i in 1..generations.len() {
         let (prev_num, prev_target) = &generations[i - 1];
         let (curr_num, curr_target) = &generations[i];
@@ -70,7 +57,8 @@ pub fn cmd_diff_closures() -> Result<()> {
                 (None, Some((curr_ver, curr_path))) => {
                     let size = get_store_path_size(curr_path).unwrap_or(0);
                     // Red+bold for size of added packages (matches Python)
-                    let size_str = format!("\x1b[31;1m+{}\x1b[0m", format_size(size));
+                    let size_str =
+                        paint(Stream::Stdout, "31;1", &format!("+{}", format_size(size)));
                     changes.push(format!("  {}: ∅ → {}, {}", name, curr_ver, size_str));
                 }
                 (Some((prev_ver, prev_path)), None) => {
@@ -89,7 +77,7 @@ pub fn cmd_diff_closures() -> Result<()> {
         if !changes.is_empty() {
             println!("Version {} → {}:", prev_num, curr_num);
             for change in changes {
-                println!("{}", change);
+                println!("{}", fit(Stream::Stdout, &change));
             }
             println!();
         }
@@ -0,0 +1,96 @@
+use crate::profile::{export, ProfileExport};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::path::Path;
+
+/// File format of `profile export` and `profile import`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Toml,
+}
+
+impl ExportFormat {
+    /// The format named by `path`'s extension: TOML for `.toml`, else JSON.
+    pub fn for_path(path: &Path) -> Self {
+        if path.extension().is_some_and(|e| e == "toml") {
+            ExportFormat::Toml
+        } else {
+            ExportFormat::Json
+        }
+    }
+
+    pub fn serialize(self, export: &ProfileExport) -> Result<String> {
+        Ok(match self {
+            ExportFormat::Json => serde_json::to_string_pretty(export)?,
+            ExportFormat::Toml => toml::to_string_pretty(export)?,
+        })
+    }
+
+    pub fn parse(self, content: &str) -> Result<ProfileExport> {
+        Ok(match self {
+            ExportFormat::Json => serde_json::from_str(content)?,
+            ExportFormat::Toml => toml::from_str(content)?,
+        })
+    }
+}
+
+/// Write the installed packages to a file (or stdout)
+///
+/// Without `format`, a file ending in `.toml` gets TOML and anything else
+/// JSON.
+pub fn cmd_export(file: Option<&Path>, format: Option<ExportFormat>) -> Result<()> {
+    let export = export()?;
+    let format = format.unwrap_or_else(|| file.map_or(ExportFormat::Json, ExportFormat::for_path));
+    let content = format.serialize(&export)?;
+
+    match file {
+        Some(path) => {
+            std::fs::write(path, format!("{}\n", content.trim_end()))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!(
+                "Exported {} package(s) to {}",
+                export.packages.len(),
+                path.display()
+            );
+        }
+        None => println!("{}", content.trim_end()),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{ExportedPackage, PROFILE_EXPORT_VERSION};
+
+    #[test]
+    fn test_export_formats_roundtrip() {
+        let export = ProfileExport {
+            version: PROFILE_EXPORT_VERSION,
+            packages: vec![ExportedPackage {
+                name: "hello".to_string(),
+                url: "github:NixOS/nixpkgs/0123456789abcdef".to_string(),
+                attr_path: "legacyPackages.x86_64-linux.hello".to_string(),
+                store_path: None,
+            }],
+        };
+        for format in [ExportFormat::Json, ExportFormat::Toml] {
+            let content = format.serialize(&export).unwrap();
+            assert_eq!(format.parse(&content).unwrap(), export);
+        }
+        assert!(ExportFormat::Toml
+            .serialize(&export)
+            .unwrap()
+            .contains("[[packages]]"));
+        assert_eq!(
+            ExportFormat::for_path(Path::new("profile.toml")),
+            ExportFormat::Toml
+        );
+        assert_eq!(
+            ExportFormat::for_path(Path::new("profile.json")),
+            ExportFormat::Json
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use super::common::{get_generation_manifest, get_package_versions};
-use crate::profile::parse_generation_number;
+use crate::cli::style::{fit, paint, Stream};
+use crate::profile::{parse_generation_number, read_generation_metadata, GenerationMetadata};
 use anyhow::Result;
 use chrono::{DateTime, Local};
 use std::collections::HashMap;
@@ -57,12 +58,12 @@ pub fn cmd_history() -> Result<()> {
         // Check if this is the current generation
         let is_current = current.as_ref() == Some(target);
 
-        // Format version number with ANSI codes (green+bold for current, bold for others)
-        let version_str = if is_current {
-            format!("\x1b[32;1m{}\x1b[0m", num)
-        } else {
-            format!("\x1b[1m{}\x1b[0m", num)
-        };
+        // Green+bold for the current version, bold for others
+        let version_str = paint(
+            Stream::Stdout,
+            if is_current { "32;1" } else { "1" },
+            &num.to_string(),
+        );
 
         // Build header with parent reference
         let header = if i == 0 {
@@ -74,6 +75,10 @@ pub fn cmd_history() -> Result<()> {
 
         println!("{}", header);
 
+        if let Some(metadata) = read_generation_metadata(target) {
+            println!("  {}", format_metadata(&metadata));
+        }
+
         // Get manifest and extract package versions
         let manifest = get_generation_manifest(target);
         let curr_versions = get_package_versions(&manifest);
@@ -109,7 +114,7 @@ pub fn cmd_history() -> Result<()> {
             println!("  No changes.");
         } else {
             for change in changes {
-                println!("{}", change);
+                println!("{}", fit(Stream::Stdout, &change));
             }
         }
 
@@ -120,3 +125,18 @@ pub fn cmd_history() -> Result<()> {
 
     Ok(())
 }
+
+/// Summarize a generation's provenance on one line.
+fn format_metadata(metadata: &GenerationMetadata) -> String {
+    let mut line = metadata.action.clone();
+    if let Some(ref url) = metadata.flake_url {
+        line.push_str(&format!(" {}", url));
+    }
+    if let Some(ref rev) = metadata.rev {
+        line.push_str(&format!(" @ {}", rev));
+    }
+    format!(
+        "({}; trix {}, {})",
+        line, metadata.trix_version, metadata.date
+    )
+}
@@ -0,0 +1,23 @@
+use super::export::ExportFormat;
+use crate::profile::import;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Install the packages listed in an export file
+///
+/// Without `format`, a file ending in `.toml` is read as TOML and anything
+/// else as JSON.
+pub fn cmd_import(file: &Path, format: Option<ExportFormat>) -> Result<()> {
+    let content = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let export = format
+        .unwrap_or_else(|| ExportFormat::for_path(file))
+        .parse(&content)
+        .with_context(|| format!("Invalid profile export: {}", file.display()))?;
+
+    for name in import(&export)? {
+        eprintln!("Added {}", name);
+    }
+
+    Ok(())
+}
@@ -1,18 +1,40 @@
-use crate::profile::list_installed;
+use super::common::{get_closure_size, get_generation_manifest, parse_store_path};
+use crate::cli::common::format_table;
+use crate::common::format_size;
+use crate::profile::{list_installed, ManifestElement};
 use anyhow::Result;
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::os::unix::fs::MetadataExt;
+
+/// Order of `profile list` rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    /// Alphabetically by name (the default)
+    Name,
+    /// Largest closure first
+    Size,
+    /// Most recently installed or upgraded first
+    Date,
+}
 
 /// List installed packages
-pub fn cmd_list(output_json: bool) -> Result<()> {
+pub fn cmd_list(output_json: bool, show_size: bool, sort: ListSort) -> Result<()> {
     let mut elements = list_installed()?;
 
     // Sort alphabetically by name (matches nix profile list)
     elements.sort_by(|(a, _), (b, _)| a.cmp(b));
 
     if output_json {
-        // For JSON output, just output the elements without names
-        let elems: Vec<_> = elements.iter().map(|(_, e)| e).collect();
-        let json = serde_json::to_string_pretty(&elems)?;
-        println!("{}", json);
+        // Elements keyed by name, like manifest.json and nix profile list --json
+        let elems: std::collections::BTreeMap<_, _> =
+            elements.iter().map(|(name, e)| (name, e)).collect();
+        println!(
+            "{}",
+            crate::schema::PROFILE_LIST.to_json(&serde_json::json!({ "elements": elems }))?
+        );
         return Ok(());
     }
 
@@ -21,33 +43,157 @@ pub fn cmd_list(output_json: bool) -> Result<()> {
         return Ok(());
     }
 
-    for (i, (name, elem)) in elements.iter().enumerate() {
-        if i > 0 {
-            println!();
+    let installed = installed_times(&elements);
+    let sizes: Option<HashMap<String, u64>> = (show_size || sort == ListSort::Size).then(|| {
+        crate::progress::with_status("computing closure sizes", || {
+            elements
+                .par_iter()
+                .map(|(name, e)| {
+                    let size = get_closure_size(&e.store_paths).unwrap_or(0);
+                    (name.clone(), size)
+                })
+                .collect()
+        })
+    });
+
+    match sort {
+        ListSort::Name => {}
+        ListSort::Size => {
+            let sizes = sizes.as_ref();
+            elements.sort_by_key(|(name, _)| {
+                std::cmp::Reverse(sizes.and_then(|s| s.get(name)).copied())
+            });
         }
+        ListSort::Date => {
+            elements.sort_by_key(|(name, _)| std::cmp::Reverse(installed.get(name).copied()))
+        }
+    }
 
-        // Name in bold
-        println!("Name:               \x1b[1m{}\x1b[0m", name);
+    let rows: Vec<Vec<String>> = elements
+        .iter()
+        .map(|(name, elem)| {
+            let mut row = vec![
+                if elem.active {
+                    name.clone()
+                } else {
+                    format!("{} (inactive)", name)
+                },
+                elem.store_paths
+                    .first()
+                    .and_then(|p| parse_store_path(p))
+                    .map(|(_, version)| version)
+                    .filter(|v| !v.is_empty())
+                    .unwrap_or("-")
+                    .to_string(),
+                elem.original_url.clone().unwrap_or_else(|| "-".to_string()),
+                installed
+                    .get(name)
+                    .and_then(|t| DateTime::from_timestamp(*t, 0))
+                    .map(|dt| dt.with_timezone(&Local).format("%Y-%m-%d").to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ];
+            if let Some(sizes) = &sizes {
+                row.push(format_size(sizes.get(name).copied().unwrap_or(0)));
+            }
+            row
+        })
+        .collect();
 
-        if let Some(ref attr_path) = elem.attr_path {
-            println!("Flake attribute:    {}", attr_path);
-        }
+    let mut header = vec!["NAME", "VERSION", "ORIGIN", "INSTALLED"];
+    if sizes.is_some() {
+        header.push("SIZE");
+    }
+    print!("{}", format_table(&header, &rows));
 
-        if let Some(ref original_url) = elem.original_url {
-            println!("Original flake URL: {}", original_url);
-        }
+    Ok(())
+}
+
+/// When each element got its current store paths, from the oldest
+/// generation since which it has had them (the time its link was created).
+fn installed_times(elements: &[(String, ManifestElement)]) -> HashMap<String, i64> {
+    let Ok(profile_dir) = crate::profile::get_profile_dir() else {
+        return HashMap::new();
+    };
+    let generations: Vec<(i64, crate::profile::Manifest)> = super::common::list_generations()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(num, target)| {
+            let link = profile_dir.join(format!("profile-{}-link", num));
+            let mtime = link.symlink_metadata().ok()?.mtime();
+            Some((mtime, get_generation_manifest(&target)))
+        })
+        .collect();
+    installed_since(elements, &generations)
+}
 
-        if let Some(ref url) = elem.url {
-            println!("Locked flake URL:   {}", url);
+/// For each element, the time of the earliest generation in the unbroken
+/// run of newest generations containing it with the same store paths.
+fn installed_since(
+    elements: &[(String, ManifestElement)],
+    generations: &[(i64, crate::profile::Manifest)],
+) -> HashMap<String, i64> {
+    let mut times = HashMap::new();
+    for (name, elem) in elements {
+        let since = generations
+            .iter()
+            .rev()
+            .take_while(|(_, manifest)| {
+                manifest
+                    .elements
+                    .get(name)
+                    .is_some_and(|e| e.store_paths == elem.store_paths)
+            })
+            .last();
+        if let Some((time, _)) = since {
+            times.insert(name.clone(), *time);
         }
+    }
+    times
+}
 
-        if !elem.store_paths.is_empty() {
-            println!("Store paths:        {}", elem.store_paths[0]);
-            for path in &elem.store_paths[1..] {
-                println!("                    {}", path);
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Manifest;
+
+    fn element(path: &str) -> ManifestElement {
+        ManifestElement {
+            attr_path: None,
+            original_url: None,
+            url: None,
+            outputs: None,
+            store_paths: vec![path.to_string()],
+            active: true,
+            priority: 5,
         }
     }
 
-    Ok(())
+    #[test]
+    fn test_installed_since() {
+        let manifest = |elems: &[(&str, &str)]| Manifest {
+            version: 3,
+            elements: elems
+                .iter()
+                .map(|(n, p)| (n.to_string(), element(p)))
+                .collect(),
+        };
+        let generations = vec![
+            (100, manifest(&[("hello", "/nix/store/a-hello-2.12")])),
+            (200, manifest(&[("hello", "/nix/store/b-hello-2.12.1")])),
+            (
+                300,
+                manifest(&[
+                    ("hello", "/nix/store/b-hello-2.12.1"),
+                    ("jq", "/nix/store/c-jq-1.7"),
+                ]),
+            ),
+        ];
+        let elements = vec![
+            ("hello".to_string(), element("/nix/store/b-hello-2.12.1")),
+            ("jq".to_string(), element("/nix/store/c-jq-1.7")),
+        ];
+        let times = installed_since(&elements, &generations);
+        assert_eq!(times["hello"], 200);
+        assert_eq!(times["jq"], 300);
+    }
 }
@@ -3,15 +3,27 @@ use clap::Subcommand;
 
 pub mod common;
 
+#[path = "activate/command.rs"]
+pub mod activate;
+
 #[path = "add/command.rs"]
 pub mod add;
 
+#[path = "apply/command.rs"]
+pub mod apply;
+
 #[path = "diff_closures/command.rs"]
 pub mod diff_closures;
 
+#[path = "export/command.rs"]
+pub mod export;
+
 #[path = "history/command.rs"]
 pub mod history;
 
+#[path = "import/command.rs"]
+pub mod import;
+
 #[path = "list/command.rs"]
 pub mod list;
 
@@ -27,9 +39,13 @@ pub mod upgrade;
 #[path = "wipe_history/command.rs"]
 pub mod wipe_history;
 
+pub use activate::cmd_activate;
 pub use add::cmd_add;
+pub use apply::cmd_apply;
 pub use diff_closures::cmd_diff_closures;
+pub use export::cmd_export;
 pub use history::cmd_history;
+pub use import::cmd_import;
 pub use list::cmd_list;
 pub use remove::cmd_remove;
 pub use rollback::cmd_rollback;
@@ -43,6 +59,14 @@ pub enum ProfileCommands {
         /// Output as JSON
         #[arg(long)]
         json: bool,
+
+        /// Show each package's closure size (slow for large profiles)
+        #[arg(long)]
+        size: bool,
+
+        /// Order packages by name, closure size or installation date
+        #[arg(long, value_enum, default_value_t = list::ListSort::Name)]
+        sort: list::ListSort,
     },
 
     /// Add packages to the profile
@@ -50,6 +74,10 @@ pub enum ProfileCommands {
         /// Installable references
         #[arg(required = true)]
         installables: Vec<String>,
+
+        /// Create the new generation without switching to it
+        #[arg(long)]
+        no_activate: bool,
     },
 
     /// Alias for 'add'
@@ -57,24 +85,83 @@ pub enum ProfileCommands {
         /// Installable references
         #[arg(required = true)]
         installables: Vec<String>,
+
+        /// Create the new generation without switching to it
+        #[arg(long)]
+        no_activate: bool,
+    },
+
+    /// Make the profile match a package list declared in a flake
+    ///
+    /// Reads trixProfiles.<system>.<name> from the flake, where <name> is
+    /// the attribute of the flake reference (default: "default").
+    Apply {
+        /// Flake reference, e.g. . or ./dotfiles#work
+        #[arg(default_value = ".")]
+        flake_ref: String,
+
+        /// Show what would change without building or switching
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Remove packages from the profile
+    ///
+    /// Arguments containing '*', '?' or '[' are glob patterns matched
+    /// against package names; matches are listed and confirmed first.
     Remove {
-        /// Package names to remove
+        /// Package names or patterns to remove
         #[arg(required = true)]
         names: Vec<String>,
+
+        /// Treat the arguments as regular expressions matching whole names
+        #[arg(long)]
+        regex: bool,
+
+        /// Remove packages matched by a pattern without asking
+        #[arg(short, long)]
+        yes: bool,
     },
 
     /// Upgrade local packages in the profile
     Upgrade {
         /// Specific package to upgrade
         name: Option<String>,
+
+        /// Number of packages to build in parallel
+        #[arg(short, long, default_value_t = 1)]
+        jobs: usize,
+    },
+
+    /// Write the installed packages, pinned to exact revisions, to a file
+    Export {
+        /// Output file (default: stdout)
+        file: Option<std::path::PathBuf>,
+
+        /// File format (default: toml for a .toml file, else json)
+        #[arg(long, value_enum)]
+        format: Option<export::ExportFormat>,
+    },
+
+    /// Install the packages listed in a file written by 'export'
+    Import {
+        /// File written by 'trix profile export'
+        file: std::path::PathBuf,
+
+        /// File format (default: toml for a .toml file, else json)
+        #[arg(long, value_enum)]
+        format: Option<export::ExportFormat>,
     },
 
     /// Show profile generation history
     History,
 
+    /// Switch to an existing generation, e.g. one staged with 'add --no-activate'
+    Activate {
+        /// Generation number (see 'trix profile history')
+        generation: u32,
+    },
+
     /// Roll back to the previous profile generation
     Rollback,
 
@@ -87,6 +174,11 @@ pub enum ProfileCommands {
         /// Show what would be deleted without actually deleting
         #[arg(long)]
         dry_run: bool,
+
+        /// Also delete the store paths only the deleted versions used, and
+        /// report the space reclaimed
+        #[arg(long)]
+        gc: bool,
     },
 
     /// Show closure difference between profile versions
@@ -95,24 +187,38 @@ pub enum ProfileCommands {
 
 pub fn cmd_profile(cmd: ProfileCommands) -> Result<()> {
     match cmd {
-        ProfileCommands::List { json } => cmd_list(json),
+        ProfileCommands::List { json, size, sort } => cmd_list(json, size, sort),
 
-        ProfileCommands::Add { installables } | ProfileCommands::Install { installables } => {
-            cmd_add(&installables)
+        ProfileCommands::Add {
+            installables,
+            no_activate,
         }
+        | ProfileCommands::Install {
+            installables,
+            no_activate,
+        } => cmd_add(&installables, !no_activate),
 
-        ProfileCommands::Remove { names } => cmd_remove(&names),
+        ProfileCommands::Apply { flake_ref, dry_run } => cmd_apply(&flake_ref, dry_run),
 
-        ProfileCommands::Upgrade { name } => cmd_upgrade(name.as_deref()),
+        ProfileCommands::Remove { names, regex, yes } => cmd_remove(&names, regex, yes),
+
+        ProfileCommands::Upgrade { name, jobs } => cmd_upgrade(name.as_deref(), jobs),
+
+        ProfileCommands::Export { file, format } => cmd_export(file.as_deref(), format),
+
+        ProfileCommands::Import { file, format } => cmd_import(&file, format),
 
         ProfileCommands::History => cmd_history(),
 
+        ProfileCommands::Activate { generation } => cmd_activate(generation),
+
         ProfileCommands::Rollback => cmd_rollback(),
 
         ProfileCommands::WipeHistory {
             older_than,
             dry_run,
-        } => cmd_wipe_history(older_than.as_deref(), dry_run),
+            gc,
+        } => cmd_wipe_history(older_than.as_deref(), dry_run, gc),
 
         ProfileCommands::DiffClosures => cmd_diff_closures(),
     }
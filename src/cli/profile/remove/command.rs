@@ -1,14 +1,72 @@
-use crate::profile::remove;
+use crate::cli::profile::common::{get_closure, get_paths_size};
+use crate::common::{confirm, format_size};
+use crate::profile::{
+    get_current_manifest, get_current_profile_path, is_glob, matching_elements, remove_elements,
+};
 use anyhow::Result;
+use std::collections::BTreeSet;
+use std::io::IsTerminal;
 
 /// Remove packages from the profile
-pub fn cmd_remove(names: &[String]) -> Result<()> {
+///
+/// Arguments are names, glob patterns or, with `regex`, regular
+/// expressions. Elements matched by a pattern are listed and only removed
+/// after confirmation, unless `yes` is set.
+pub fn cmd_remove(names: &[String], regex: bool, yes: bool) -> Result<()> {
+    let manifest = get_current_manifest()?;
+
+    let mut keys = BTreeSet::new();
     for name in names {
-        if remove(name)? {
-            println!("Removed: {}", name);
-        } else {
+        let matched = matching_elements(&manifest, name, regex)?;
+        if matched.is_empty() {
             eprintln!("Package not found: {}", name);
         }
+        keys.extend(matched);
+    }
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let keys: Vec<String> = keys.into_iter().collect();
+
+    let patterns = regex || names.iter().any(|n| is_glob(n));
+    if patterns && !yes {
+        eprintln!("The following packages match:");
+        for key in &keys {
+            eprintln!("  {}", key);
+        }
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!(
+                "Not removing packages matched by a pattern without confirmation; pass --yes"
+            );
+        }
+        if !confirm(&format!("Remove {} package(s)?", keys.len())) {
+            eprintln!("Nothing removed");
+            return Ok(());
+        }
+    }
+
+    let old_profile = get_current_profile_path()?;
+    remove_elements(&keys)?;
+    for key in &keys {
+        eprintln!("Removed: {}", key);
+    }
+
+    // Paths only the removed packages needed; the garbage collector can
+    // delete them once no older generation refers to them
+    let freed = get_current_profile_path().and_then(|new_profile| {
+        let remaining: std::collections::HashSet<String> =
+            get_closure(&new_profile.to_string_lossy())?
+                .into_iter()
+                .collect();
+        let dropped: Vec<String> = get_closure(&old_profile.to_string_lossy())?
+            .into_iter()
+            .filter(|p| !remaining.contains(p))
+            .collect();
+        get_paths_size(&dropped)
+    });
+    match freed {
+        Ok(size) => eprintln!("Closure size freed: {}", format_size(size)),
+        Err(e) => tracing::debug!("Could not compute freed closure size: {}", e),
     }
 
     Ok(())
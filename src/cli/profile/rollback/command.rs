@@ -36,7 +36,7 @@ pub fn cmd_rollback() -> Result<()> {
         if let Some((prev_gen, prev_path)) = prev {
             let prev_target = std::fs::read_link(prev_path)?;
             crate::profile::switch_profile(&prev_target.display().to_string())?;
-            println!("Rolled back to generation {}", prev_gen);
+            eprintln!("Rolled back to generation {}", prev_gen);
             return Ok(());
         }
     }
@@ -1,14 +1,128 @@
-use super::common::parse_older_than;
+use super::common::{get_paths_size, list_generations, parse_older_than};
+use crate::common::format_size;
 use crate::profile::wipe_history;
 use anyhow::Result;
+use std::collections::HashSet;
+use std::path::PathBuf;
 
 /// Delete non-current versions of the profile
-pub fn cmd_wipe_history(older_than: Option<&str>, dry_run: bool) -> Result<()> {
+///
+/// With `gc`, the store paths that only the deleted versions used are
+/// deleted too.
+pub fn cmd_wipe_history(older_than: Option<&str>, dry_run: bool, gc: bool) -> Result<()> {
     let older_than_duration = if let Some(ot) = older_than {
         Some(std::time::Duration::from_secs(parse_older_than(ot)?))
     } else {
         None
     };
 
-    wipe_history(older_than_duration, dry_run)
+    let removed = wipe_history(older_than_duration, dry_run)?;
+    if gc && !removed.is_empty() {
+        collect_garbage(&removed, dry_run)?;
+    }
+    Ok(())
+}
+
+/// The closure of `paths`.
+fn closure(paths: &[String]) -> Result<HashSet<String>> {
+    if paths.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--query", "--requisites"]);
+    cmd.args(paths);
+    Ok(cmd.output()?.lines().map(str::to_string).collect())
+}
+
+/// Paths of `closure` for which `matches` holds, sorted.
+fn sorted_matching(closure: &HashSet<String>, matches: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut paths: Vec<String> = closure.iter().filter(|p| matches(p)).cloned().collect();
+    paths.sort();
+    paths
+}
+
+/// Delete the store paths that only the `removed` profile versions kept alive.
+///
+/// The collection is limited to their closures: nothing else in the store
+/// is touched, unlike `nix-store --gc`.
+fn collect_garbage(removed: &[(u32, PathBuf)], dry_run: bool) -> Result<()> {
+    let targets: Vec<String> = removed
+        .iter()
+        .map(|(_, target)| target.display().to_string())
+        .collect();
+    let candidates = crate::progress::with_status("computing closures", || closure(&targets))?;
+
+    if dry_run {
+        // The links still exist, so nix would call everything alive; the
+        // remaining versions are the best estimate of what stays
+        let removed_nums: HashSet<u32> = removed.iter().map(|(num, _)| *num).collect();
+        let kept: Vec<String> = list_generations()?
+            .into_iter()
+            .filter(|(num, _)| !removed_nums.contains(num))
+            .map(|(_, target)| target.display().to_string())
+            .collect();
+        let kept = closure(&kept)?;
+        let garbage = sorted_matching(&candidates, |p| !kept.contains(p));
+        eprintln!(
+            "would delete up to {} store paths, freeing up to {}",
+            garbage.len(),
+            format_size(get_paths_size(&garbage)?)
+        );
+        return Ok(());
+    }
+
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--gc", "--print-dead"]);
+    let dead: HashSet<String> =
+        crate::progress::with_status("finding dead store paths", || cmd.output())?
+            .lines()
+            .map(str::to_string)
+            .collect();
+
+    let garbage = sorted_matching(&candidates, |p| dead.contains(p));
+    if garbage.is_empty() {
+        eprintln!("No store paths to delete; everything is still in use");
+        return Ok(());
+    }
+    let size = get_paths_size(&garbage)?;
+
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.arg("--delete");
+    cmd.args(&garbage);
+    crate::progress::with_status("deleting store paths", || cmd.output())?;
+
+    eprintln!(
+        "Deleted {} store paths, freeing {}",
+        garbage.len(),
+        format_size(size)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sorted_matching() {
+        let closure: HashSet<String> = [
+            "/nix/store/c-glibc",
+            "/nix/store/a-hello",
+            "/nix/store/b-bash",
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+        let dead: HashSet<&str> = [
+            "/nix/store/a-hello",
+            "/nix/store/c-glibc",
+            "/nix/store/d-other",
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            sorted_matching(&closure, |p| dead.contains(p)),
+            vec!["/nix/store/a-hello", "/nix/store/c-glibc"]
+        );
+    }
 }
@@ -1,24 +1,27 @@
 use crate::registry::{add_registry_entry, registry_entry_to_flake_ref};
 use anyhow::Result;
+use std::path::Path;
 
 /// Add or update a registry entry
-pub fn cmd_add(name: &str, target: &str) -> Result<()> {
-    add_registry_entry(name, target)?;
+pub fn cmd_add(name: &str, target: &str, registry: Option<&Path>) -> Result<()> {
+    let entry = add_registry_entry(name, target, registry)?;
 
     // Show what was added
-    if let Some(entry) = crate::registry::resolve_registry_name(name, false) {
-        let flake_ref = registry_entry_to_flake_ref(&entry);
-        if entry.entry_type == "path" {
-            println!(
-                "Added: {} -> {} (local, handled natively by trix)",
-                name, flake_ref
-            );
-        } else {
-            println!(
-                "Added: {} -> {} (remote, passthrough to nix)",
-                name, flake_ref
-            );
-        }
+    let flake_ref = registry_entry_to_flake_ref(&entry);
+    let location = match registry {
+        Some(path) => format!(" to {}", path.display()),
+        None => String::new(),
+    };
+    if entry.entry_type == "path" {
+        eprintln!(
+            "Added{}: {} -> {} (local, handled natively by trix)",
+            location, name, flake_ref
+        );
+    } else {
+        eprintln!(
+            "Added{}: {} -> {} (remote, passthrough to nix)",
+            location, name, flake_ref
+        );
     }
 
     Ok(())
@@ -1,9 +1,46 @@
-use crate::registry::{list_all_registries, registry_entry_to_flake_ref};
+use crate::registry::{list_all_registries, registry_entry_to_flake_ref, RegistryEntry};
 use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// A registry entry as printed by `registry list --json`.
+#[derive(Serialize)]
+struct ListedEntry<'a> {
+    name: &'a str,
+    source: &'a str,
+    target: String,
+    to: &'a RegistryEntry,
+    pinned: bool,
+    /// False when a higher-priority registry defines the same name
+    active: bool,
+}
 
 /// List all registry entries
-pub fn cmd_list(no_global: bool) -> Result<()> {
-    let entries = list_all_registries(!no_global);
+pub fn cmd_list(no_global: bool, json: bool) -> Result<()> {
+    let cwd = std::env::current_dir().ok();
+    let entries = list_all_registries(!no_global, cwd.as_deref());
+
+    if json {
+        // Entries come in lookup order (override, project, user, system, global), so the
+        // first occurrence of a name is the one that resolves.
+        let mut seen = HashSet::new();
+        let listed: Vec<ListedEntry> = entries
+            .iter()
+            .map(|(name, source, entry)| ListedEntry {
+                name,
+                source,
+                target: registry_entry_to_flake_ref(entry),
+                to: entry,
+                pinned: entry.is_pinned(),
+                active: seen.insert(name.as_str()),
+            })
+            .collect();
+        println!(
+            "{}",
+            crate::schema::REGISTRY_LIST.to_json(&serde_json::json!({ "entries": listed }))?
+        );
+        return Ok(());
+    }
 
     if entries.is_empty() {
         println!("No registry entries found.");
@@ -23,7 +60,7 @@ pub fn cmd_list(no_global: bool) -> Result<()> {
             .push((name.as_str(), entry));
     }
 
-    for source in ["user", "system", "global"] {
+    for source in ["override", "project", "user", "system", "global"] {
         if let Some(entries) = by_source.get(source) {
             if !entries.is_empty() {
                 println!("\n{} registry:", source.to_uppercase());
@@ -33,6 +70,8 @@ pub fn cmd_list(no_global: bool) -> Result<()> {
                     let flake_ref = registry_entry_to_flake_ref(entry);
                     if entry.entry_type == "path" {
                         println!("  {} -> {} (local)", name, flake_ref);
+                    } else if entry.is_pinned() {
+                        println!("  {} -> {} (pinned)", name, flake_ref);
                     } else {
                         println!("  {} -> {}", name, flake_ref);
                     }
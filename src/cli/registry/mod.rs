@@ -1,5 +1,6 @@
 use anyhow::Result;
 use clap::Subcommand;
+use std::path::PathBuf;
 
 #[path = "add/command.rs"]
 pub mod add;
@@ -7,11 +8,15 @@ pub mod add;
 #[path = "list/command.rs"]
 pub mod list;
 
+#[path = "prune/command.rs"]
+pub mod prune;
+
 #[path = "remove/command.rs"]
 pub mod remove;
 
 pub use add::cmd_add;
 pub use list::cmd_list;
+pub use prune::cmd_prune;
 pub use remove::cmd_remove;
 
 #[derive(Subcommand, Clone, Debug)]
@@ -21,6 +26,10 @@ pub enum RegistryCommands {
         /// Don't fetch the global registry
         #[arg(long)]
         no_global: bool,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// Add or update a registry entry
@@ -30,21 +39,63 @@ pub enum RegistryCommands {
 
         /// Target flake reference
         target: String,
+
+        /// Registry file to edit instead of the user registry (e.g. .trix/registry.json)
+        #[arg(long, value_name = "FILE")]
+        registry: Option<PathBuf>,
     },
 
     /// Remove a registry entry
     Remove {
         /// Registry name to remove
-        name: String,
+        #[arg(required_unless_present = "all_matching")]
+        name: Option<String>,
+
+        /// Remove every entry pointing at this flake reference instead
+        #[arg(long, value_name = "TARGET", conflicts_with = "name")]
+        all_matching: Option<String>,
+
+        /// Registry file to edit instead of the user registry (e.g. .trix/registry.json)
+        #[arg(long, value_name = "FILE")]
+        registry: Option<PathBuf>,
+    },
+
+    /// Remove entries whose targets no longer exist
+    ///
+    /// Paths are checked on disk, GitHub repositories and refs through the
+    /// GitHub API and git refs with the remote. Entries that cannot be
+    /// checked are kept.
+    Prune {
+        /// Show what would be removed without changing the registry
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Registry file to prune instead of the user registry (e.g. .trix/registry.json)
+        #[arg(long, value_name = "FILE")]
+        registry: Option<PathBuf>,
     },
 }
 
 pub fn cmd_registry(cmd: RegistryCommands) -> Result<()> {
     match cmd {
-        RegistryCommands::List { no_global } => cmd_list(no_global),
+        RegistryCommands::List { no_global, json } => cmd_list(no_global, json),
+
+        RegistryCommands::Add {
+            name,
+            target,
+            registry,
+        } => cmd_add(&name, &target, registry.as_deref()),
 
-        RegistryCommands::Add { name, target } => cmd_add(&name, &target),
+        RegistryCommands::Remove {
+            name,
+            all_matching,
+            registry,
+        } => cmd_remove(
+            name.as_deref(),
+            all_matching.as_deref(),
+            registry.as_deref(),
+        ),
 
-        RegistryCommands::Remove { name } => cmd_remove(&name),
+        RegistryCommands::Prune { dry_run, registry } => cmd_prune(registry.as_deref(), dry_run),
     }
 }
@@ -0,0 +1,44 @@
+use crate::registry::{check_registry_entries, remove_registry_entries, TargetStatus};
+use anyhow::Result;
+use std::path::Path;
+
+/// Remove registry entries whose targets no longer exist
+pub fn cmd_prune(registry: Option<&Path>, dry_run: bool) -> Result<()> {
+    let results = crate::progress::with_status("Checking registry entries", || {
+        check_registry_entries(registry)
+    });
+
+    let mut gone = Vec::new();
+    for (name, target, status) in &results {
+        match status {
+            TargetStatus::Ok => {}
+            TargetStatus::Gone(reason) => {
+                let action = if dry_run { "Would remove" } else { "Removing" };
+                eprintln!("{} {} -> {}: {}", action, name, target, reason);
+                gone.push(name.clone());
+            }
+            TargetStatus::Unknown(reason) => {
+                crate::nix::warn(&format!(
+                    "keeping '{}', could not check {}: {}",
+                    name, target, reason
+                ));
+            }
+        }
+    }
+
+    if gone.is_empty() {
+        eprintln!("All {} entries resolve", results.len());
+        return Ok(());
+    }
+    if !dry_run {
+        remove_registry_entries(&gone, registry)?;
+    }
+    eprintln!(
+        "{} {} of {} entries",
+        if dry_run { "Would remove" } else { "Removed" },
+        gone.len(),
+        results.len()
+    );
+
+    Ok(())
+}
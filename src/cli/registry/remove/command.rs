@@ -1,12 +1,35 @@
-use crate::registry::remove_registry_entry;
+use crate::registry::{registry_entries_matching, remove_registry_entries, remove_registry_entry};
 use anyhow::Result;
+use std::path::Path;
 
-/// Remove a registry entry
-pub fn cmd_remove(name: &str) -> Result<()> {
-    if remove_registry_entry(name)? {
-        println!("Removed: {}", name);
+/// Remove a registry entry, or every entry pointing at `all_matching`
+pub fn cmd_remove(
+    name: Option<&str>,
+    all_matching: Option<&str>,
+    registry: Option<&Path>,
+) -> Result<()> {
+    let location = match registry {
+        Some(path) => path.display().to_string(),
+        None => "user registry".to_string(),
+    };
+
+    if let Some(target) = all_matching {
+        let names = registry_entries_matching(target, registry);
+        if names.is_empty() {
+            anyhow::bail!("No entries point at '{}' in {}.", target, location);
+        }
+        remove_registry_entries(&names, registry)?;
+        for name in &names {
+            eprintln!("Removed: {}", name);
+        }
+        return Ok(());
+    }
+
+    let name = name.unwrap_or_default();
+    if remove_registry_entry(name, registry)? {
+        eprintln!("Removed: {}", name);
     } else {
-        anyhow::bail!("Entry '{}' not found in user registry.", name);
+        anyhow::bail!("Entry '{}' not found in {}.", name, location);
     }
 
     Ok(())
@@ -2,7 +2,30 @@ use super::common::build_resolved_attribute;
 use crate::flake::{ensure_lock, resolve_attr_path, resolve_installable};
 use crate::nix::{get_system, BuildOptions};
 use anyhow::{Context, Result};
-use clap::Args;
+use clap::{Args, ValueEnum};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// First delay between restarts; doubled after each quick failure.
+const RESTART_DELAY_MIN: Duration = Duration::from_secs(1);
+
+/// Upper bound for the restart delay.
+const RESTART_DELAY_MAX: Duration = Duration::from_secs(30);
+
+/// A program running at least this long resets the restart delay.
+const RESTART_RESET_AFTER: Duration = Duration::from_secs(10);
+
+/// When to restart a program under `--restart`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RestartPolicy {
+    /// Run once (the default)
+    Never,
+    /// Restart when the program exits with a non-zero status
+    OnFailure,
+    /// Restart whenever the program exits
+    Always,
+}
 
 #[derive(Args, Clone, Debug)]
 pub struct RunArgs {
@@ -10,10 +33,14 @@ pub struct RunArgs {
     #[arg(default_value = ".#default")]
     pub installable: String,
 
-    /// Arguments to pass to the program
+    /// Arguments to pass to the program, verbatim, after '--'
     #[arg(last = true)]
     pub args: Vec<String>,
 
+    /// Run the program with NAME as its argv[0]
+    #[arg(long, value_name = "NAME")]
+    pub argv0: Option<String>,
+
     /// Pass --arg NAME EXPR to nix
     #[arg(long = "arg", value_names = &["NAME", "EXPR"], num_args = 2)]
     pub extra_args: Vec<String>,
@@ -25,6 +52,40 @@ pub struct RunArgs {
     /// Use specified store URL
     #[arg(long)]
     pub store: Option<String>,
+
+    /// Set an environment variable for the program
+    #[arg(long, value_names = &["NAME", "VALUE"], num_args = 2)]
+    pub set_env: Vec<String>,
+
+    /// Remove an environment variable for the program
+    #[arg(long, value_name = "NAME")]
+    pub unset_env: Vec<String>,
+
+    /// Start the program with an empty environment
+    #[arg(short = 'i', long)]
+    pub ignore_environment: bool,
+
+    /// Keep this variable when using --ignore-environment
+    #[arg(
+        short = 'k',
+        long,
+        value_name = "NAME",
+        requires = "ignore_environment"
+    )]
+    pub keep: Vec<String>,
+
+    /// Restart the program when it exits, with backoff
+    #[arg(long, value_enum, default_value = "never")]
+    pub restart: RestartPolicy,
+
+    /// Give up after this many restarts
+    #[arg(long, value_name = "N")]
+    pub max_restarts: Option<u32>,
+
+    /// Profile evaluation up to starting the program and write it to FILE
+    /// (speedscope JSON for *.json, folded stacks otherwise; Nix 2.30+)
+    #[arg(long, value_name = "FILE")]
+    pub profile_startup: Option<std::path::PathBuf>,
 }
 
 fn parse_arg_pairs(args: &[String]) -> Vec<(String, String)> {
@@ -39,14 +100,31 @@ fn parse_arg_pairs(args: &[String]) -> Vec<(String, String)> {
         .collect()
 }
 
+/// Apply --ignore-environment, --keep, --unset-env and --set-env to `cmd`.
+fn apply_env(cmd: &mut std::process::Command, args: &RunArgs) {
+    if args.ignore_environment {
+        cmd.env_clear();
+        for name in &args.keep {
+            if let Some(value) = std::env::var_os(name) {
+                cmd.env(name, value);
+            }
+        }
+    }
+    for name in &args.unset_env {
+        cmd.env_remove(name);
+    }
+    for (name, value) in parse_arg_pairs(&args.set_env) {
+        cmd.env(name, value);
+    }
+}
+
 /// Build and run a package from flake.nix
 pub fn cmd_run(args: RunArgs) -> Result<()> {
     let resolved = resolve_installable(&args.installable);
 
     if !resolved.is_local {
         // Passthrough to nix run
-        let flake_ref = resolved.flake_ref.as_deref().unwrap_or("");
-        let full_ref = format!("{}#{}", flake_ref, resolved.attr_part);
+        let full_ref = resolved.full_ref();
 
         let mut cmd = crate::command::NixCommand::new("nix");
         cmd.args(["run", &full_ref]);
@@ -55,6 +133,25 @@ pub fn cmd_run(args: RunArgs) -> Result<()> {
             cmd.args(["--store", s]);
         }
 
+        // nix itself needs PATH, HOME and friends, so the environment can
+        // only be adjusted, not replaced, when passing through
+        if args.ignore_environment {
+            anyhow::bail!("--ignore-environment is only supported for local flakes");
+        }
+        if args.restart != RestartPolicy::Never {
+            anyhow::bail!("--restart is only supported for local flakes");
+        }
+        if args.profile_startup.is_some() {
+            anyhow::bail!("--profile-startup is only supported for local flakes");
+        }
+        if args.argv0.is_some() {
+            anyhow::bail!("--argv0 is only supported for local flakes");
+        }
+        for name in &args.unset_env {
+            cmd.env_remove(name);
+        }
+        cmd.envs(parse_arg_pairs(&args.set_env));
+
         if !args.args.is_empty() {
             cmd.arg("--");
             cmd.args(&args.args);
@@ -66,6 +163,16 @@ pub fn cmd_run(args: RunArgs) -> Result<()> {
     let flake_dir = resolved.flake_dir.as_ref().context("No flake directory")?;
     let system = get_system()?;
 
+    // Profile every evaluation from locking until the program starts
+    let profile_dir = match &args.profile_startup {
+        Some(_) => {
+            let dir = crate::scratch::tempdir()?;
+            crate::eval_profile::start(dir.path())?;
+            Some(dir)
+        }
+        None => None,
+    };
+
     // Ensure lock exists
     ensure_lock(flake_dir, None)?;
 
@@ -80,7 +187,17 @@ pub fn cmd_run(args: RunArgs) -> Result<()> {
     let pkg_attr = resolve_attr_path(&resolved.attr_part, "packages", &system);
 
     // Check if it's an app
-    let exe_path = if crate::nix::flake_has_attr(flake_dir, &app_attr)? {
+    let is_app = crate::nix::flake_has_attr(flake_dir, &app_attr)?;
+    crate::flake::explain(&format!(
+        "run tries apps before packages: {} {}",
+        app_attr,
+        if is_app {
+            "matched"
+        } else {
+            "not found, falling back to packages"
+        }
+    ));
+    let exe_path = if is_app {
         // It's an app - get the program path
         let options = crate::nix::EvalOptions {
             output_json: true,
@@ -97,6 +214,7 @@ pub fn cmd_run(args: RunArgs) -> Result<()> {
             extra_args: parse_arg_pairs(&args.extra_args),
             extra_argstrs: parse_arg_pairs(&args.extra_argstrs),
             store: args.store.clone(),
+            ..Default::default()
         };
 
         let store_path = build_resolved_attribute(&resolved, &pkg_attr, &options, true)?
@@ -107,12 +225,31 @@ pub fn cmd_run(args: RunArgs) -> Result<()> {
         format!("{}/bin/{}", store_path, main_program)
     };
 
+    if let (Some(output), Some(_dir)) = (&args.profile_startup, &profile_dir) {
+        let samples =
+            crate::eval_profile::finish(output, &format!("trix run {}", args.installable))?;
+        eprintln!(
+            "Wrote evaluation profile ({} samples) to {}",
+            samples,
+            output.display()
+        );
+    }
+
     // Run the executable
     let mut cmd = std::process::Command::new(&exe_path);
     cmd.args(&args.args);
+    if let Some(argv0) = &args.argv0 {
+        use std::os::unix::process::CommandExt;
+        cmd.arg0(argv0);
+    }
+    apply_env(&mut cmd, &args);
 
     tracing::debug!("+ {} {}", exe_path, args.args.join(" "));
 
+    if args.restart != RestartPolicy::Never {
+        return supervise(cmd, &exe_path, args.restart, args.max_restarts);
+    }
+
     let status = cmd
         .status()
         .context(format!("Failed to run {}", exe_path))?;
@@ -124,3 +261,143 @@ pub fn cmd_run(args: RunArgs) -> Result<()> {
 
     Ok(())
 }
+
+/// Delay before the given restart (0-based), doubling up to a maximum.
+fn restart_delay(attempt: u32) -> Duration {
+    RESTART_DELAY_MIN
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RESTART_DELAY_MAX)
+}
+
+/// Run `cmd` under `policy`, restarting it with backoff.
+///
+/// SIGINT, SIGTERM and SIGHUP stop supervision once the running program
+/// exits, and are forwarded to it unless the kernel sent them: Ctrl-C and
+/// a terminal hangup already reach the whole foreground process group,
+/// the program included, so forwarding those would deliver them twice.
+fn supervise(
+    mut cmd: std::process::Command,
+    exe_path: &str,
+    policy: RestartPolicy,
+    max_restarts: Option<u32>,
+) -> Result<()> {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+    use signal_hook::iterator::exfiltrator::WithOrigin;
+    use signal_hook::low_level::siginfo::Cause;
+
+    let child_pid = Arc::new(AtomicU32::new(0));
+    let stopping = Arc::new(AtomicBool::new(false));
+
+    let mut signals =
+        signal_hook::iterator::SignalsInfo::<WithOrigin>::new([SIGINT, SIGTERM, SIGHUP])
+            .context("Failed to install signal handlers")?;
+    {
+        let child_pid = Arc::clone(&child_pid);
+        let stopping = Arc::clone(&stopping);
+        std::thread::spawn(move || {
+            for origin in signals.forever() {
+                stopping.store(true, Ordering::SeqCst);
+                let pid = child_pid.load(Ordering::SeqCst);
+                if pid != 0 && !matches!(origin.cause, Cause::Kernel) {
+                    // SAFETY: kill has no memory safety requirements
+                    unsafe {
+                        libc::kill(pid as libc::pid_t, origin.signal);
+                    }
+                }
+            }
+        });
+    }
+
+    let mut restarts = 0;
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        let mut child = cmd.spawn().context(format!("Failed to run {}", exe_path))?;
+        child_pid.store(child.id(), Ordering::SeqCst);
+        let status = child.wait()?;
+        child_pid.store(0, Ordering::SeqCst);
+
+        let code = status.code().unwrap_or(1);
+        if stopping.load(Ordering::SeqCst) {
+            std::process::exit(code);
+        }
+        if policy == RestartPolicy::OnFailure && status.success() {
+            return Ok(());
+        }
+        if max_restarts.is_some_and(|max| restarts >= max) {
+            eprintln!(
+                "trix: {} exited ({}), giving up after {} restarts",
+                exe_path, status, restarts
+            );
+            std::process::exit(code);
+        }
+
+        if started.elapsed() >= RESTART_RESET_AFTER {
+            attempt = 0;
+        }
+        let delay = restart_delay(attempt);
+        attempt += 1;
+        restarts += 1;
+
+        eprintln!(
+            "trix: {} exited ({}), restarting in {}s",
+            exe_path,
+            status,
+            delay.as_secs()
+        );
+        std::thread::sleep(delay);
+        if stopping.load(Ordering::SeqCst) {
+            std::process::exit(code);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        run: RunArgs,
+    }
+
+    #[test]
+    fn test_apply_env() {
+        let cli = TestCli::parse_from(["trix", "--set-env", "FOO", "bar", "--unset-env", "HOME"]);
+        let mut cmd = std::process::Command::new("true");
+        apply_env(&mut cmd, &cli.run);
+
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert!(envs.contains(&("FOO".as_ref(), Some("bar".as_ref()))));
+        assert!(envs.contains(&("HOME".as_ref(), None)));
+    }
+
+    #[test]
+    fn test_restart_delay() {
+        assert_eq!(restart_delay(0), Duration::from_secs(1));
+        assert_eq!(restart_delay(1), Duration::from_secs(2));
+        assert_eq!(restart_delay(4), Duration::from_secs(16));
+        assert_eq!(restart_delay(5), RESTART_DELAY_MAX);
+        assert_eq!(restart_delay(100), RESTART_DELAY_MAX);
+    }
+
+    #[test]
+    fn test_args_after_terminator_are_verbatim() {
+        let cli = TestCli::parse_from([
+            "trix", ".#app", "--argv0", "busybox", "--", "--store", "-i", "--", "x",
+        ]);
+        assert_eq!(cli.run.installable, ".#app");
+        assert_eq!(cli.run.argv0.as_deref(), Some("busybox"));
+        assert_eq!(cli.run.args, vec!["--store", "-i", "--", "x"]);
+        assert!(cli.run.store.is_none());
+        assert!(!cli.run.ignore_environment);
+    }
+
+    #[test]
+    fn test_keep_requires_ignore_environment() {
+        assert!(TestCli::try_parse_from(["trix", "--keep", "PATH"]).is_err());
+        assert!(TestCli::try_parse_from(["trix", "-i", "--keep", "PATH"]).is_ok());
+    }
+}
@@ -0,0 +1,28 @@
+use crate::cli::style::{display_width, pad};
+use crate::schema::{find, SCHEMAS};
+use anyhow::Result;
+
+/// Print the JSON Schema of a command's --json output
+pub fn cmd_schema(command: &[String]) -> Result<()> {
+    if command.is_empty() {
+        let width = SCHEMAS
+            .iter()
+            .map(|s| display_width(s.command))
+            .max()
+            .unwrap_or(0);
+        for schema in SCHEMAS {
+            println!("{}  schema {}", pad(schema.command, width), schema.version);
+        }
+        return Ok(());
+    }
+
+    let command = command.join(" ");
+    let Some(schema) = find(&command) else {
+        anyhow::bail!(
+            "'{}' has no JSON output schema.\nRun 'trix schema' to list commands with one",
+            command
+        );
+    };
+    println!("{}", serde_json::to_string_pretty(&schema.json_schema())?);
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use crate::cli::style::bold;
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Flake without inputs that the self-test locks and evaluates.
+const FIXTURE_FLAKE: &str = r#"{
+  description = "trix self-test fixture";
+  outputs = { self }: {
+    lib.answer = 6 * 7;
+  };
+}
+"#;
+
+#[derive(Args, Clone, Debug)]
+pub struct SelfTestArgs {
+    /// Also write the diagnostic report as JSON to FILE
+    #[arg(long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+/// Outcome of one self-test step.
+#[derive(Debug, Serialize)]
+struct Step {
+    name: &'static str,
+    ok: bool,
+    #[serde(rename = "durationMs")]
+    duration_ms: u128,
+    detail: String,
+}
+
+/// Environment report attached to bug reports.
+#[derive(Debug, Serialize)]
+struct Report {
+    #[serde(rename = "trixVersion")]
+    trix_version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    #[serde(rename = "nixVersion")]
+    nix_version: Option<String>,
+    #[serde(rename = "nixCommand")]
+    nix_command: bool,
+    daemon: bool,
+    #[serde(rename = "experimentalFeatures")]
+    experimental_features: Vec<String>,
+    #[serde(rename = "legacyOnly")]
+    legacy_only: bool,
+    steps: Vec<Step>,
+}
+
+/// Run `f` as a named step, recording its result and duration.
+fn run_step(steps: &mut Vec<Step>, name: &'static str, f: impl FnOnce() -> Result<String>) {
+    let started = Instant::now();
+    let result = f();
+    let duration_ms = started.elapsed().as_millis();
+
+    let (ok, detail) = match result {
+        Ok(detail) => (true, detail),
+        Err(e) => (false, format!("{:#}", e)),
+    };
+    let status = if ok { "ok" } else { "FAIL" };
+    eprintln!(
+        "{:<24} {:<4} {:>6} ms  {}",
+        name, status, duration_ms, detail
+    );
+    steps.push(Step {
+        name,
+        ok,
+        duration_ms,
+        detail,
+    });
+}
+
+/// Create a profile generation from an empty manifest in a scratch store.
+fn profile_in_temp_store(root: &Path) -> Result<String> {
+    // As if run with `--store`, which nix commands get through NIX_CONFIG
+    let previous = crate::command::store();
+    crate::command::set_store(Some(format!("local?root={}", root.display())));
+
+    let result = crate::profile::create_profile_store_path(
+        &crate::profile::Manifest {
+            version: 3,
+            ..Default::default()
+        },
+        &[],
+        &crate::profile::GenerationMetadata::new("self-test"),
+    );
+
+    crate::command::set_store(previous);
+    result
+}
+
+/// Exercise core flows and print a diagnostic report
+pub fn cmd_self_test(args: SelfTestArgs) -> Result<()> {
+    let caps = crate::nix::capabilities();
+    let mut steps = Vec::new();
+
+    let scratch = crate::scratch::tempdir()?;
+    let flake_dir = scratch.path().join("fixture");
+    std::fs::create_dir_all(&flake_dir)?;
+    std::fs::write(flake_dir.join("flake.nix"), FIXTURE_FLAKE)?;
+
+    run_step(&mut steps, "system", crate::nix::get_system);
+    run_step(&mut steps, "store", crate::nix::get_store_dir);
+    run_step(&mut steps, "support files", || {
+        Ok(crate::nix::get_nix_dir()?.display().to_string())
+    });
+    run_step(&mut steps, "lock generation", || {
+        crate::lock::ensure_lock(&flake_dir, None)?;
+        let lock: crate::lock::LockFile = serde_json::from_str(
+            &std::fs::read_to_string(flake_dir.join("flake.lock"))
+                .context("flake.lock was not written")?,
+        )?;
+        Ok(format!("{} node(s)", lock.nodes.len()))
+    });
+    run_step(&mut steps, "expression generation", || {
+        let preamble = crate::nix::get_eval_preamble(&flake_dir)?;
+        Ok(format!("{} bytes", preamble.len()))
+    });
+    run_step(&mut steps, "native eval", || {
+        let options = crate::nix::EvalOptions {
+            output_json: true,
+            quiet: true,
+            ..Default::default()
+        };
+        let result = crate::nix::run_nix_eval(Some(&flake_dir), "lib.answer", &options)?;
+        if result.trim() != "42" {
+            anyhow::bail!("expected 42, got {}", result.trim());
+        }
+        Ok("lib.answer = 42".to_string())
+    });
+    run_step(&mut steps, "profile creation", || {
+        profile_in_temp_store(&scratch.path().join("store"))
+    });
+
+    let report = Report {
+        trix_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        nix_version: caps
+            .version
+            .map(|(major, minor, patch)| format!("{}.{}.{}", major, minor, patch)),
+        nix_command: caps.has_nix_command,
+        daemon: caps.daemon,
+        experimental_features: caps.experimental_features.clone(),
+        legacy_only: crate::nix::legacy_only(),
+        steps,
+    };
+
+    println!();
+    println!(
+        "{} {} on {}-{}, Nix {}{}",
+        bold("trix"),
+        report.trix_version,
+        report.arch,
+        report.os,
+        report.nix_version.as_deref().unwrap_or("unknown"),
+        if report.daemon { " (daemon)" } else { "" }
+    );
+
+    if let Some(path) = &args.output {
+        std::fs::write(path, crate::schema::SELF_TEST.to_json(&report)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        eprintln!("Report written to {}", path.display());
+    }
+
+    let failed = report.steps.iter().filter(|s| !s.ok).count();
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} self-test steps failed",
+            failed,
+            report.steps.len()
+        );
+    }
+    println!("All {} self-test steps passed", report.steps.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_step() {
+        let mut steps = Vec::new();
+        run_step(&mut steps, "works", || Ok("fine".to_string()));
+        run_step(&mut steps, "breaks", || anyhow::bail!("nope"));
+
+        assert!(steps[0].ok);
+        assert_eq!(steps[0].detail, "fine");
+        assert!(!steps[1].ok);
+        assert_eq!(steps[1].detail, "nope");
+    }
+}
@@ -0,0 +1,214 @@
+use crate::cli::style::bold;
+use crate::common::confirm;
+use anyhow::{Context, Result};
+use clap::Args;
+use clap_complete::Shell;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Clone, Debug)]
+pub struct SetupArgs {
+    /// Make every offered change without asking
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Shell to install completions for (default: from $SHELL)
+    #[arg(long, value_enum)]
+    pub shell: Option<Shell>,
+}
+
+/// Where `shell` loads completions for `trix` from without further setup,
+/// and a hint to print if the file alone is not enough.
+fn completion_path(
+    shell: Shell,
+    home: &Path,
+    data_home: Option<&Path>,
+    config_home: Option<&Path>,
+) -> Option<(PathBuf, Option<&'static str>)> {
+    let data_home = data_home.map_or_else(|| home.join(".local/share"), Path::to_path_buf);
+    let config_home = config_home.map_or_else(|| home.join(".config"), Path::to_path_buf);
+    match shell {
+        Shell::Bash => Some((data_home.join("bash-completion/completions/trix"), None)),
+        Shell::Fish => Some((config_home.join("fish/completions/trix.fish"), None)),
+        Shell::Zsh => Some((
+            home.join(".zfunc/_trix"),
+            Some("add `fpath+=~/.zfunc` before `compinit` in ~/.zshrc"),
+        )),
+        _ => None,
+    }
+}
+
+/// An absolute directory from `var`, if set, as the XDG specification requires.
+fn xdg_env(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
+/// Check that Nix is installed and usable, failing if it is not.
+fn check_nix() -> Result<()> {
+    let caps = crate::nix::capabilities();
+    let (major, minor, patch) = caps
+        .version
+        .context("nix-instantiate not found; install Nix first (https://nixos.org/download)")?;
+    println!(
+        "Nix {}.{}.{}{}{}",
+        major,
+        minor,
+        patch,
+        if caps.daemon {
+            ", multi-user (daemon)"
+        } else {
+            ", single-user"
+        },
+        if caps.has_nix_command {
+            ""
+        } else {
+            ", no `nix` command"
+        }
+    );
+    println!("Store at {}", crate::nix::get_store_dir()?);
+    Ok(())
+}
+
+/// Add a small file to the store to prove it is writable by this user.
+fn check_store_write() -> Result<String> {
+    let scratch = crate::scratch::tempdir()?;
+    let file = scratch.path().join("trix-setup");
+    std::fs::write(&file, "trix setup store check\n")?;
+    let path = crate::command::NixCommand::new("nix-store")
+        .args(["--add", &file.display().to_string()])
+        .output()
+        .context("Cannot add files to the store; check the daemon or store permissions")?;
+    Ok(path.trim().to_string())
+}
+
+/// Offer to pin `nixpkgs` in the user registry to the commit it resolves to now.
+fn pin_nixpkgs(yes: bool) -> Result<()> {
+    if let Some(entry) = crate::registry::user_registry_entry("nixpkgs") {
+        println!(
+            "nixpkgs is already in the user registry: {}",
+            crate::registry::registry_entry_to_flake_ref(&entry)
+        );
+        return Ok(());
+    }
+    // Not the project registry: the pin goes in the user registry
+    let entry = crate::registry::resolve_registry_name("nixpkgs", true, None)
+        .context("nixpkgs is not in any registry")?;
+    if entry.entry_type != "github" {
+        println!(
+            "Not pinning nixpkgs: it resolves to {}",
+            crate::registry::registry_entry_to_flake_ref(&entry)
+        );
+        return Ok(());
+    }
+    let owner = entry.owner.as_deref().unwrap_or_default();
+    let repo = entry.repo.as_deref().unwrap_or_default();
+    let rev = match &entry.rev {
+        Some(rev) => rev.clone(),
+        None => {
+            let git_ref = entry.git_ref.as_deref().unwrap_or("HEAD");
+            crate::lock::github_commit(owner, repo, git_ref)?.0
+        }
+    };
+    let target = format!("github:{}/{}/{}", owner, repo, rev);
+    println!("Pinning nixpkgs makes `nixpkgs#...` use the same commit until you change it");
+    if yes || confirm(&format!("Pin nixpkgs to {} in the user registry?", target)) {
+        crate::registry::add_registry_entry("nixpkgs", &target, None)?;
+        println!("Pinned nixpkgs to {}", target);
+    }
+    Ok(())
+}
+
+/// Offer to write the completion script for `shell` where it is picked up.
+fn install_completions(
+    shell: Option<Shell>,
+    yes: bool,
+    generate: &dyn Fn(Shell) -> Vec<u8>,
+) -> Result<()> {
+    let Some(shell) = shell.or_else(Shell::from_env) else {
+        println!("Could not tell your shell from $SHELL; pass --shell to install completions");
+        return Ok(());
+    };
+    let home = dirs::home_dir().context("Could not determine the home directory")?;
+    let Some((path, hint)) = completion_path(
+        shell,
+        &home,
+        xdg_env("XDG_DATA_HOME").as_deref(),
+        xdg_env("XDG_CONFIG_HOME").as_deref(),
+    ) else {
+        println!(
+            "No standard completion directory for {}; use `trix completion {}`",
+            shell, shell
+        );
+        return Ok(());
+    };
+    if !(yes
+        || confirm(&format!(
+            "Write {} completions to {}?",
+            shell,
+            path.display()
+        )))
+    {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    std::fs::write(&path, generate(shell))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Wrote {}", path.display());
+    if let Some(hint) = hint {
+        println!("To load it, {}", hint);
+    }
+    Ok(())
+}
+
+/// Check the Nix installation and offer the usual first-run configuration
+pub fn cmd_setup(args: SetupArgs, generate: &dyn Fn(Shell) -> Vec<u8>) -> Result<()> {
+    println!("{}", bold("Nix installation"));
+    check_nix()?;
+
+    println!();
+    println!("{}", bold("Store access"));
+    println!("Added {}", check_store_write()?);
+
+    println!();
+    println!("{}", bold("Registry"));
+    if let Err(e) = pin_nixpkgs(args.yes) {
+        println!("Skipped: {:#}", e);
+    }
+
+    println!();
+    println!("{}", bold("Shell completions"));
+    install_completions(args.shell, args.yes, generate)?;
+
+    println!();
+    println!("Setup complete. Try `trix run nixpkgs#hello`.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_path() {
+        let home = Path::new("/home/alice");
+        let (bash, hint) = completion_path(Shell::Bash, home, None, None).unwrap();
+        assert_eq!(
+            bash,
+            Path::new("/home/alice/.local/share/bash-completion/completions/trix")
+        );
+        assert!(hint.is_none());
+
+        let (fish, _) = completion_path(Shell::Fish, home, None, Some(Path::new("/cfg"))).unwrap();
+        assert_eq!(fish, Path::new("/cfg/fish/completions/trix.fish"));
+
+        let (zsh, hint) = completion_path(Shell::Zsh, home, None, None).unwrap();
+        assert_eq!(zsh, Path::new("/home/alice/.zfunc/_trix"));
+        assert!(hint.is_some());
+
+        assert!(completion_path(Shell::PowerShell, home, None, None).is_none());
+    }
+}
@@ -16,6 +16,11 @@ pub struct ShellArgs {
     #[arg(short = 'i', long = "interpreter")]
     pub interpreter: Option<String>,
 
+    /// Resolve registry names with the registry even when the current
+    /// flake's lock file has an input of the same name
+    #[arg(long)]
+    pub no_lock_pin: bool,
+
     /// Script file to run with the interpreter (used in shebang mode)
     #[arg(long = "script", hide = true)]
     pub script: Option<String>,
@@ -43,6 +48,32 @@ fn build_interpreter_command(interpreter: &str, script: &str, script_args: &[Str
         .join(" ")
 }
 
+/// Pin registry names to the current flake's locked inputs.
+///
+/// `nixpkgs#hello` run inside a flake whose flake.lock locks an input named
+/// `nixpkgs` uses that revision, so ad hoc shells match the project.
+fn pin_to_lock(installable: &str) -> String {
+    let Some((ref_part, attr)) = installable.split_once('#') else {
+        return installable.to_string();
+    };
+    if !crate::registry::is_registry_name(ref_part) {
+        return installable.to_string();
+    }
+    let Ok(cwd) = std::env::current_dir() else {
+        return installable.to_string();
+    };
+    match crate::lock::locked_input_ref(&cwd, ref_part) {
+        Some(locked) => {
+            crate::flake::explain(&format!(
+                "'{}' is locked in ./flake.lock, using {}",
+                ref_part, locked
+            ));
+            format!("{}#{}", locked, attr)
+        }
+        None => installable.to_string(),
+    }
+}
+
 /// Start a shell with specified packages available
 pub fn cmd_shell(args: ShellArgs) -> Result<()> {
     // Determine the effective command to run
@@ -62,21 +93,31 @@ pub fn cmd_shell(args: ShellArgs) -> Result<()> {
         args.command.clone()
     };
 
+    let installables: Vec<String> = if args.no_lock_pin {
+        args.installables.clone()
+    } else {
+        args.installables.iter().map(|i| pin_to_lock(i)).collect()
+    };
+
     // Check if any installables are remote
-    let mut has_remote = false;
-    for installable in &args.installables {
-        let resolved = crate::flake::resolve_installable(installable);
-        if !resolved.is_local {
-            has_remote = true;
-            break;
-        }
-    }
+    let resolved_all: Vec<_> = installables
+        .iter()
+        .map(|i| crate::flake::resolve_installable(i))
+        .collect();
+    let has_remote = resolved_all.iter().any(|r| !r.is_local);
 
     if has_remote {
-        // Passthrough to nix shell
+        // Passthrough to nix shell, with remote refs normalized the same
+        // way as every other command
         let mut cmd = crate::command::NixCommand::new("nix");
         cmd.args(["shell"]);
-        cmd.args(&args.installables);
+        for (installable, resolved) in installables.iter().zip(&resolved_all) {
+            if resolved.is_local {
+                cmd.arg(installable);
+            } else {
+                cmd.arg(resolved.full_ref());
+            }
+        }
 
         if let Some(c) = &effective_command {
             cmd.args(["--command", c]);
@@ -91,7 +132,7 @@ pub fn cmd_shell(args: ShellArgs) -> Result<()> {
         ..Default::default()
     };
 
-    for installable in &args.installables {
+    for installable in &installables {
         let resolved = crate::flake::resolve_installable(installable);
         let system = crate::nix::get_system()?;
         let attr = crate::flake::resolve_attr_path(&resolved.attr_part, "packages", &system);
@@ -1,37 +1,226 @@
+//! Color and width handling shared by every renderer.
+//!
+//! Whether a stream gets ANSI colors is decided here from `--color`,
+//! `NO_COLOR` and `TERM`, so commands only say what to highlight.
+
+use clap::ValueEnum;
 use std::io::IsTerminal;
+use std::sync::Mutex;
+use unicode_width::UnicodeWidthChar;
 
-pub fn use_color() -> bool {
-    std::io::stderr().is_terminal()
+/// When to color output, as given by `--color`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color terminals, unless NO_COLOR is set or TERM is dumb
+    #[default]
+    Auto,
+    Always,
+    Never,
 }
 
-pub fn yellow(text: &str) -> String {
-    if use_color() {
-        format!("\x1b[1;33m{}\x1b[0m", text)
-    } else {
-        text.to_string()
+static COLOR: Mutex<ColorChoice> = Mutex::new(ColorChoice::Auto);
+
+pub fn set_color(choice: ColorChoice) {
+    *COLOR.lock().unwrap() = choice;
+}
+
+/// The stream a piece of styled text is written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+fn color_for(choice: ColorChoice, is_terminal: bool, no_color: bool, term: Option<&str>) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => is_terminal && !no_color && term != Some("dumb"),
     }
 }
 
-pub fn magenta(text: &str) -> String {
-    if use_color() {
-        format!("\x1b[1;35m{}\x1b[0m", text)
-    } else {
-        text.to_string()
+fn is_terminal(stream: Stream) -> bool {
+    match stream {
+        Stream::Stdout => std::io::stdout().is_terminal(),
+        Stream::Stderr => std::io::stderr().is_terminal(),
     }
 }
 
-pub fn cyan(text: &str) -> String {
-    if use_color() {
-        format!("\x1b[36m{}\x1b[0m", text)
+/// Whether text written to `stream` should carry ANSI colors.
+pub fn color_enabled(stream: Stream) -> bool {
+    let is_terminal = is_terminal(stream);
+    // NO_COLOR only counts when set to something (https://no-color.org)
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let term = std::env::var("TERM").ok();
+    color_for(
+        *COLOR.lock().unwrap(),
+        is_terminal,
+        no_color,
+        term.as_deref(),
+    )
+}
+
+/// Wrap `text` in the SGR sequence `sgr` (e.g. "1;33") if `stream` is colored.
+pub fn paint(stream: Stream, sgr: &str, text: &str) -> String {
+    if color_enabled(stream) {
+        format!("\x1b[{}m{}\x1b[0m", sgr, text)
     } else {
         text.to_string()
     }
 }
 
+pub fn yellow(text: &str) -> String {
+    paint(Stream::Stderr, "1;33", text)
+}
+
+pub fn magenta(text: &str) -> String {
+    paint(Stream::Stderr, "1;35", text)
+}
+
+pub fn cyan(text: &str) -> String {
+    paint(Stream::Stderr, "36", text)
+}
+
 pub fn bold(text: &str) -> String {
-    if use_color() {
-        format!("\x1b[1m{}\x1b[0m", text)
-    } else {
-        text.to_string()
+    paint(Stream::Stderr, "1", text)
+}
+
+/// Width of the terminal `stream` writes to, or `COLUMNS` if set.
+///
+/// `None` when `stream` is not a terminal, so piped output is never cut.
+pub fn terminal_width(stream: Stream) -> Option<usize> {
+    if !is_terminal(stream) {
+        return None;
+    }
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse::<usize>().ok())
+        .filter(|&c| c > 0)
+    {
+        return Some(columns);
+    }
+    let fd = match stream {
+        Stream::Stdout => libc::STDOUT_FILENO,
+        Stream::Stderr => libc::STDERR_FILENO,
+    };
+    // SAFETY: winsize is plain integers, for which all zeroes is valid
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ writes a winsize through the pointer, which
+    // points at `size`
+    let ret = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+    (ret == 0 && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+/// Columns `c` takes up in a terminal.
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// Columns `text` takes up in a terminal: two for wide characters such as
+/// CJK, none for escape sequences.
+pub fn display_width(text: &str) -> usize {
+    let mut count = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            count += char_width(c);
+        }
+    }
+    count
+}
+
+/// `text` followed by enough spaces to take up `width` columns.
+pub fn pad(text: &str, width: usize) -> String {
+    let fill = width.saturating_sub(display_width(text));
+    format!("{}{}", text, " ".repeat(fill))
+}
+
+/// Cut `text` to `width` terminal columns, ending it with "…".
+///
+/// Wide characters such as CJK take two columns. Escape sequences take
+/// none and are kept, so a cut never leaves a color running; a reset is
+/// added when anything was dropped.
+pub fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width || width == 0 {
+        return text.to_string();
+    }
+
+    let mut out = String::new();
+    let mut count = 0;
+    let mut colored = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            out.push(c);
+            for c in chars.by_ref() {
+                out.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            colored = true;
+        } else if count + char_width(c) < width {
+            out.push(c);
+            count += char_width(c);
+        } else {
+            // Nothing after a cut fits, even if narrower
+            count = width;
+        }
+    }
+    out.push('…');
+    if colored {
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+/// Cut `text` to the terminal width, if `stream` is a terminal.
+pub fn fit(stream: Stream, text: &str) -> String {
+    match terminal_width(stream) {
+        Some(width) => truncate(text, width),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_for() {
+        let auto = ColorChoice::Auto;
+        assert!(color_for(auto, true, false, Some("xterm-256color")));
+        assert!(color_for(auto, true, false, None));
+        assert!(!color_for(auto, false, false, Some("xterm")));
+        assert!(!color_for(auto, true, true, Some("xterm")));
+        assert!(!color_for(auto, true, false, Some("dumb")));
+        assert!(color_for(ColorChoice::Always, false, true, Some("dumb")));
+        assert!(!color_for(ColorChoice::Never, true, false, Some("xterm")));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly", 7), "exactly");
+        assert_eq!(truncate("a longer line", 8), "a longe…");
+        assert_eq!(truncate("héllo wörld", 6), "héllo…");
+        assert_eq!(truncate("日本語のテキスト", 7), "日本語…");
+        assert_eq!(truncate("日本語", 6), "日本語");
+        assert_eq!(
+            truncate("\x1b[1mbold\x1b[0m: description", 8),
+            "\x1b[1mbold\x1b[0m: d…\x1b[0m"
+        );
+        assert_eq!(truncate("\x1b[1mbold\x1b[0m", 4), "\x1b[1mbold\x1b[0m");
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("hello"), 5);
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(display_width("\x1b[1mbold\x1b[0m"), 4);
+        assert_eq!(pad("日本", 6), "日本  ");
+        assert_eq!(pad("toolong", 3), "toolong");
     }
 }
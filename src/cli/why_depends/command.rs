@@ -1,16 +1,265 @@
 use super::common::build_resolved_attribute;
+use super::profile::common::{
+    get_closure, get_generation_manifest, get_store_path_size, list_generations,
+};
+use super::style::bold;
+use crate::common::format_size;
 use anyhow::{Context, Result};
 use clap::Args;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 #[derive(Args, Clone, Debug)]
 pub struct WhyDependsArgs {
     /// Package to check
-    pub package: String,
+    #[arg(required_unless_present = "new_in_profile")]
+    pub package: Option<String>,
     /// Dependency to trace
-    pub dependency: String,
+    #[arg(required_unless_present = "new_in_profile")]
+    pub dependency: Option<String>,
+
+    /// Explain which packages brought DEPENDENCY (a store path or package
+    /// name) into the latest profile generation
+    #[arg(long, value_name = "DEPENDENCY", conflicts_with_all = ["package", "dependency"])]
+    pub new_in_profile: Option<String>,
+
+    /// Print the dependency paths as JSON, with the files making each reference
+    #[arg(long, conflicts_with = "new_in_profile")]
+    pub json: bool,
+}
+
+/// One store path on a dependency path.
+#[derive(Debug, PartialEq, Serialize)]
+struct Step {
+    path: String,
+    /// Files in `path` mentioning the next path's hash (empty for the last)
+    files: Vec<String>,
+}
+
+/// Parse `nix-store --query --graph` output into each path's references.
+///
+/// Nodes are store path base names, and every edge points from a reference
+/// to the path referring to it.
+fn parse_graph(dot: &str, store_dir: &str) -> HashMap<String, Vec<String>> {
+    let edge = regex::Regex::new(r#"^"([^"]+)" -> "([^"]+)""#).unwrap();
+    let mut references: HashMap<String, Vec<String>> = HashMap::new();
+    for caps in dot.lines().filter_map(|line| edge.captures(line.trim())) {
+        references
+            .entry(format!("{}/{}", store_dir, &caps[2]))
+            .or_default()
+            .push(format!("{}/{}", store_dir, &caps[1]));
+    }
+    for refs in references.values_mut() {
+        refs.sort();
+    }
+    references
+}
+
+/// The shortest chain from `from` to `to`, through each direct reference of
+/// `from` that leads there.
+fn dependency_chains(
+    references: &HashMap<String, Vec<String>>,
+    from: &str,
+    to: &str,
+) -> Vec<Vec<String>> {
+    if from == to {
+        return vec![vec![from.to_string()]];
+    }
+    // Breadth-first from the dependency, over referrers, gives every path's
+    // distance to it along with the next step of a shortest chain
+    let mut referrers: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (path, refs) in references {
+        for r in refs {
+            referrers.entry(r.as_str()).or_default().push(path.as_str());
+        }
+    }
+    let mut next: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::from([to]);
+    while let Some(path) = queue.pop_front() {
+        for referrer in referrers.get(path).into_iter().flatten() {
+            if *referrer != to && !next.contains_key(referrer) {
+                next.insert(referrer, path);
+                queue.push_back(referrer);
+            }
+        }
+    }
+
+    let direct = references.get(from).map(Vec::as_slice).unwrap_or_default();
+    direct
+        .iter()
+        .filter(|r| r.as_str() != from && (r.as_str() == to || next.contains_key(r.as_str())))
+        .map(|first| {
+            let mut chain = vec![from.to_string(), first.clone()];
+            let mut path = first.as_str();
+            while path != to {
+                path = next[path];
+                chain.push(path.to_string());
+            }
+            chain
+        })
+        .collect()
+}
+
+/// Files under `path`, relative to it, that mention the hash of `reference`.
+fn referring_files(path: &str, reference: &str) -> Vec<String> {
+    let hash = reference
+        .rsplit('/')
+        .next()
+        .and_then(|base| base.split_once('-'))
+        .map(|(hash, _)| hash)
+        .unwrap_or(reference);
+    let needle = regex::bytes::Regex::new(&regex::escape(hash)).unwrap();
+
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(path)
+        .sort_by_file_name()
+        .into_iter()
+        .flatten()
+    {
+        let mentions = if entry.path_is_symlink() {
+            std::fs::read_link(entry.path())
+                .is_ok_and(|target| target.to_string_lossy().contains(hash))
+        } else if entry.file_type().is_file() {
+            std::fs::read(entry.path()).is_ok_and(|data| needle.is_match(&data))
+        } else {
+            false
+        };
+        if mentions {
+            let rel = entry.path().strip_prefix(path).unwrap_or(entry.path());
+            files.push(rel.display().to_string());
+        }
+    }
+    files
+}
+
+/// Print the dependency chains from `pkg_path` to `dep_path` as JSON.
+fn print_json(pkg_path: &str, dep_path: &str) -> Result<()> {
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--query", "--graph", pkg_path]);
+    let references = parse_graph(&cmd.output()?, &crate::nix::get_store_dir()?);
+
+    let paths: Vec<Vec<Step>> = dependency_chains(&references, pkg_path, dep_path)
+        .into_iter()
+        .map(|chain| {
+            chain
+                .iter()
+                .enumerate()
+                .map(|(i, path)| Step {
+                    path: path.clone(),
+                    files: chain
+                        .get(i + 1)
+                        .map(|next| referring_files(path, next))
+                        .unwrap_or_default(),
+                })
+                .collect()
+        })
+        .collect();
+
+    println!(
+        "{}",
+        crate::schema::WHY_DEPENDS.to_json(&serde_json::json!({
+            "package": pkg_path,
+            "dependency": dep_path,
+            "paths": paths,
+        }))?
+    );
+    Ok(())
+}
+
+/// Store paths in `new_paths` matching `dependency`.
+///
+/// A store path must match exactly; a name matches `<hash>-<name>` or
+/// `<hash>-<name>-<version>`.
+fn match_new_paths<'a>(new_paths: &'a [String], dependency: &str) -> Vec<&'a String> {
+    new_paths
+        .iter()
+        .filter(|path| {
+            if dependency.starts_with('/') {
+                return path.as_str() == dependency.trim_end_matches('/');
+            }
+            let Some((_, name)) = path.rsplit('/').next().and_then(|b| b.split_once('-')) else {
+                return false;
+            };
+            name == dependency
+                || name
+                    .strip_prefix(dependency)
+                    .and_then(|rest| rest.strip_prefix('-'))
+                    .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+        })
+        .collect()
+}
+
+/// Explain why `dependency` appeared in the latest profile generation.
+fn why_depends_new_in_profile(dependency: &str) -> Result<()> {
+    let generations = list_generations()?;
+    let [.., (prev_num, prev_target), (curr_num, curr_target)] = generations.as_slice() else {
+        anyhow::bail!("Need at least 2 profile generations to compare");
+    };
+
+    let prev_closure: HashSet<String> = get_closure(&prev_target.to_string_lossy())?
+        .into_iter()
+        .collect();
+    let new_paths: Vec<String> = get_closure(&curr_target.to_string_lossy())?
+        .into_iter()
+        .filter(|p| !prev_closure.contains(p))
+        .collect();
+
+    let matches = match_new_paths(&new_paths, dependency);
+    if matches.is_empty() {
+        anyhow::bail!(
+            "'{}' is not new in generation {} (compared to generation {})",
+            dependency,
+            curr_num,
+            prev_num
+        );
+    }
+
+    let manifest = get_generation_manifest(curr_target);
+    let mut elements: Vec<_> = manifest.elements.iter().filter(|(_, e)| e.active).collect();
+    elements.sort_by_key(|(name, _)| name.as_str());
+
+    // Closures of each installed package, computed once for all matches
+    let mut closures = Vec::new();
+    for (name, element) in elements {
+        for store_path in &element.store_paths {
+            let closure: HashSet<String> = get_closure(store_path)?.into_iter().collect();
+            closures.push((name, store_path, closure));
+        }
+    }
+
+    for dep in matches {
+        let size = get_store_path_size(dep).unwrap_or(0);
+        println!(
+            "{} ({}) is new in generation {} (since {})",
+            bold(dep),
+            format_size(size),
+            curr_num,
+            prev_num
+        );
+
+        let pulled_in_by: Vec<_> = closures
+            .iter()
+            .filter(|(_, _, closure)| closure.contains(dep.as_str()))
+            .collect();
+        if pulled_in_by.is_empty() {
+            println!("  not referenced by any installed package\n");
+            continue;
+        }
+
+        for (name, store_path, _) in pulled_in_by {
+            println!("\n{} {}", bold(name), store_path);
+            if crate::nix::capabilities().supports_nix_command() {
+                let mut cmd = crate::command::NixCommand::new("nix");
+                cmd.args(["why-depends", store_path, dep]);
+                cmd.run()?;
+            }
+        }
+        println!();
+    }
+
+    Ok(())
 }
 
-/// Show why a package depends on another
 /// Show why a package depends on another
 pub fn cmd_why_depends(args: WhyDependsArgs) -> Result<()> {
     fn resolve_to_store_path(ref_str: &str) -> Result<String> {
@@ -22,11 +271,7 @@ pub fn cmd_why_depends(args: WhyDependsArgs) -> Result<()> {
         if !resolved.is_local {
             // For remote refs, we need to build first then copy the store path
             let full_ref = if resolved.attr_part != "default" {
-                format!(
-                    "{}#{}",
-                    resolved.flake_ref.as_deref().unwrap_or(""),
-                    resolved.attr_part
-                )
+                resolved.full_ref()
             } else {
                 resolved.flake_ref.as_deref().unwrap_or("").to_string()
             };
@@ -52,11 +297,104 @@ pub fn cmd_why_depends(args: WhyDependsArgs) -> Result<()> {
         Ok(store_path)
     }
 
-    let pkg_path = resolve_to_store_path(&args.package)?;
-    let dep_path = resolve_to_store_path(&args.dependency)?;
+    if let Some(dependency) = &args.new_in_profile {
+        return why_depends_new_in_profile(dependency);
+    }
+
+    let pkg_path = resolve_to_store_path(args.package.as_deref().unwrap_or_default())?;
+    let dep_path = resolve_to_store_path(args.dependency.as_deref().unwrap_or_default())?;
+
+    if args.json {
+        return print_json(&pkg_path, &dep_path);
+    }
 
     let mut cmd = crate::command::NixCommand::new("nix");
     cmd.args(["why-depends", &pkg_path, &dep_path]);
 
     cmd.run()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependency_chains() {
+        let dot = r##"digraph G {
+"aaaa-bash" [label = "bash", shape = box, style = filled, fillcolor = "#ff0000"];
+"aaaa-bash" -> "cccc-hello" [color = "black"];
+"bbbb-glibc" -> "aaaa-bash" [color = "red"];
+"bbbb-glibc" -> "cccc-hello" [color = "green"];
+"dddd-libidn" -> "bbbb-glibc" [color = "blue"];
+}"##;
+        let references = parse_graph(dot, "/nix/store");
+        assert_eq!(
+            references["/nix/store/cccc-hello"],
+            vec!["/nix/store/aaaa-bash", "/nix/store/bbbb-glibc"]
+        );
+
+        let chains = dependency_chains(
+            &references,
+            "/nix/store/cccc-hello",
+            "/nix/store/dddd-libidn",
+        );
+        assert_eq!(
+            chains,
+            vec![
+                vec![
+                    "/nix/store/cccc-hello",
+                    "/nix/store/aaaa-bash",
+                    "/nix/store/bbbb-glibc",
+                    "/nix/store/dddd-libidn",
+                ],
+                vec![
+                    "/nix/store/cccc-hello",
+                    "/nix/store/bbbb-glibc",
+                    "/nix/store/dddd-libidn",
+                ],
+            ]
+        );
+        assert!(dependency_chains(
+            &references,
+            "/nix/store/dddd-libidn",
+            "/nix/store/cccc-hello"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_referring_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("bin")).unwrap();
+        std::fs::write(root.join("bin/hello"), b"\0/nix/store/bbbb-glibc/lib\0").unwrap();
+        std::fs::write(root.join("README"), "hello").unwrap();
+        std::os::unix::fs::symlink("/nix/store/bbbb-glibc/lib", root.join("lib")).unwrap();
+        assert_eq!(
+            referring_files(&root.display().to_string(), "/nix/store/bbbb-glibc"),
+            vec!["bin/hello", "lib"]
+        );
+    }
+
+    #[test]
+    fn test_match_new_paths() {
+        let new_paths = vec![
+            "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-llvm-17.0.6".to_string(),
+            "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-llvm-17.0.6-lib".to_string(),
+            "/nix/store/cccccccccccccccccccccccccccccccc-llvm-tools-1.0".to_string(),
+            "/nix/store/dddddddddddddddddddddddddddddddd-source".to_string(),
+        ];
+
+        assert_eq!(match_new_paths(&new_paths, "llvm").len(), 2);
+        assert_eq!(match_new_paths(&new_paths, "llvm-tools").len(), 1);
+        assert_eq!(match_new_paths(&new_paths, "source").len(), 1);
+        assert_eq!(
+            match_new_paths(
+                &new_paths,
+                "/nix/store/dddddddddddddddddddddddddddddddd-source/"
+            ),
+            vec![&new_paths[3]]
+        );
+        assert!(match_new_paths(&new_paths, "gcc").is_empty());
+    }
+}
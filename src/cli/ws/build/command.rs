@@ -0,0 +1,31 @@
+use super::common::run_members;
+use crate::cli::build::BuildArgs;
+use crate::workspace::current_workspace;
+use anyhow::Result;
+
+/// Build the same attribute in every workspace member
+pub fn cmd_build(attr: &str, no_link: bool) -> Result<()> {
+    let ws = current_workspace()?;
+    run_members(&ws, |member| {
+        let out_link = member.dir.join("result");
+        crate::cli::build::cmd_build(BuildArgs {
+            installable: format!("{}#{}", member.dir.display(), attr),
+            out_link: out_link.display().to_string(),
+            no_link,
+            nix_file: None,
+            extra_args: Vec::new(),
+            extra_argstrs: Vec::new(),
+            store: None,
+            keep_failed: false,
+            rebuild: false,
+            print_out_paths: false,
+            store_dir: None,
+            check_cache_only: false,
+        })?;
+        Ok(if no_link {
+            "built".to_string()
+        } else {
+            out_link.display().to_string()
+        })
+    })
+}
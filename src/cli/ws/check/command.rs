@@ -0,0 +1,13 @@
+use super::common::run_members;
+use crate::workspace::current_workspace;
+use anyhow::Result;
+
+/// Check every workspace member
+pub fn cmd_check(strict: bool) -> Result<()> {
+    let ws = current_workspace()?;
+    run_members(&ws, |member| {
+        let flake_ref = member.dir.display().to_string();
+        crate::cli::flake::cmd_check(Some(&flake_ref), false, strict, false, 0, None, false)?;
+        Ok("passed".to_string())
+    })
+}
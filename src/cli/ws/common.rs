@@ -0,0 +1,107 @@
+use crate::cli::style::{bold, display_width, pad};
+use crate::workspace::{Member, Workspace};
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+/// Outcome of running a command in one member.
+struct MemberResult {
+    name: String,
+    ok: bool,
+    duration: Duration,
+    detail: String,
+}
+
+/// Run `f` in every member of `ws`, then print a summary table.
+///
+/// Members run one after another in this process, so the registry,
+/// system and nix capability lookups are shared. A failing member does
+/// not stop the others; the run fails at the end if any member failed.
+pub fn run_members(ws: &Workspace, f: impl Fn(&Member) -> Result<String>) -> Result<()> {
+    let mut results = Vec::new();
+    for member in &ws.members {
+        crate::cli::gha::group(&member.name);
+        eprintln!("{} {}", bold("==>"), bold(&member.name));
+        let started = Instant::now();
+        let result = f(member);
+        crate::cli::gha::end_group();
+
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(e) => {
+                crate::cli::gha::error(&format!("{}: {:#}", member.name, e));
+                (false, format!("{:#}", e))
+            }
+        };
+        results.push(MemberResult {
+            name: member.name.clone(),
+            ok,
+            duration: started.elapsed(),
+            detail,
+        });
+    }
+
+    println!();
+    print!("{}", format_summary(&results));
+
+    let failed = results.iter().filter(|r| !r.ok).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} members failed", failed, results.len());
+    }
+    Ok(())
+}
+
+fn format_summary(results: &[MemberResult]) -> String {
+    let width = results
+        .iter()
+        .map(|r| display_width(&r.name))
+        .chain([display_width("MEMBER")])
+        .max()
+        .unwrap_or(0);
+    let mut out = format!(
+        "{}  {:<6}  {:>8}  DETAIL\n",
+        pad("MEMBER", width),
+        "STATUS",
+        "TIME"
+    );
+    for r in results {
+        // Only the first line of multi-line nix errors fits in the table
+        let detail = r.detail.lines().next().unwrap_or("");
+        out.push_str(&format!(
+            "{}  {:<6}  {:>7.1}s  {}\n",
+            pad(&r.name, width),
+            if r.ok { "ok" } else { "FAILED" },
+            r.duration.as_secs_f64(),
+            detail
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_summary() {
+        let results = vec![
+            MemberResult {
+                name: "app".to_string(),
+                ok: true,
+                duration: Duration::from_millis(1500),
+                detail: "2 input(s) updated".to_string(),
+            },
+            MemberResult {
+                name: "libs/common".to_string(),
+                ok: false,
+                duration: Duration::from_millis(200),
+                detail: "evaluation failed\nerror: at line 3".to_string(),
+            },
+        ];
+        assert_eq!(
+            format_summary(&results),
+            "MEMBER       STATUS      TIME  DETAIL\n\
+             app          ok          1.5s  2 input(s) updated\n\
+             libs/common  FAILED      0.2s  evaluation failed\n"
+        );
+    }
+}
@@ -0,0 +1,17 @@
+use crate::workspace::current_workspace;
+use anyhow::Result;
+
+/// List the members of the current workspace
+pub fn cmd_list() -> Result<()> {
+    let ws = current_workspace()?;
+    eprintln!(
+        "Workspace {} ({} members)",
+        ws.root.display(),
+        ws.members.len()
+    );
+    let width = ws.members.iter().map(|m| m.name.len()).max().unwrap_or(0);
+    for member in &ws.members {
+        println!("{:<width$}  {}", member.name, member.dir.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use clap::Subcommand;
+
+pub mod common;
+
+#[path = "build/command.rs"]
+pub mod build;
+
+#[path = "check/command.rs"]
+pub mod check;
+
+#[path = "list/command.rs"]
+pub mod list;
+
+#[path = "update/command.rs"]
+pub mod update;
+
+pub use build::cmd_build;
+pub use check::cmd_check;
+pub use list::cmd_list;
+pub use update::cmd_update;
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum WsCommands {
+    /// List workspace members
+    List,
+
+    /// Update flake.lock of every member
+    Update {
+        /// Leave INPUT alone in members that have it (repeatable)
+        #[arg(long, value_name = "INPUT")]
+        exclude: Vec<String>,
+    },
+
+    /// Check every member
+    Check {
+        /// Treat output schema warnings as errors
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Build an attribute of every member, linking `result` in each member
+    Build {
+        /// Attribute to build in each member
+        #[arg(default_value = "default")]
+        attr: String,
+
+        /// Do not create result symlinks
+        #[arg(long)]
+        no_link: bool,
+    },
+}
+
+pub fn cmd_ws(cmd: WsCommands) -> Result<()> {
+    match cmd {
+        WsCommands::List => cmd_list(),
+
+        WsCommands::Update { exclude } => cmd_update(&exclude),
+
+        WsCommands::Check { strict } => cmd_check(strict),
+
+        WsCommands::Build { attr, no_link } => cmd_build(&attr, no_link),
+    }
+}
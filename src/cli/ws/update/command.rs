@@ -0,0 +1,24 @@
+use super::common::run_members;
+use crate::lock::update_lock;
+use crate::workspace::current_workspace;
+use anyhow::Result;
+
+/// Update flake.lock of every workspace member
+pub fn cmd_update(exclude: &[String]) -> Result<()> {
+    let ws = current_workspace()?;
+    run_members(&ws, |member| {
+        // Only pass on exclusions for inputs this member has
+        let inputs = crate::flake::get_flake_inputs(&member.dir)?;
+        let exclude: Vec<String> = exclude
+            .iter()
+            .filter(|name| inputs.get(name.as_str()).is_some())
+            .cloned()
+            .collect();
+
+        match update_lock(&member.dir, None, None, false, &exclude)? {
+            Some(updates) if updates.is_empty() => Ok("up to date".to_string()),
+            Some(updates) => Ok(format!("{} input(s) updated", updates.len())),
+            None => anyhow::bail!("update failed"),
+        }
+    })
+}
@@ -1,12 +1,52 @@
 use crate::common::Cache;
 use crate::nix::get_clean_env;
 use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
 use std::ffi::{OsStr, OsString};
 use std::process::Command;
+use std::sync::atomic::{AtomicI8, Ordering};
+use std::sync::Mutex;
+use tracing::Level;
 
 /// Cache for program availability checks
 static PROGRAM_AVAILABILITY: Cache<String, bool> = Cache::new();
 
+/// Verbosity passed on to nix: negative for `--quiet`, otherwise that many `-v`
+static VERBOSITY: AtomicI8 = AtomicI8::new(0);
+
+/// Store every spawned nix command uses, if not the default (`build --store-dir`)
+static STORE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Messages from captured nix stderr already logged, so repeated calls do not repeat them
+static FORWARDED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Set the verbosity of spawned nix commands (`-v`, `-vv`, `--quiet`).
+pub fn set_verbosity(level: i8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Point every nix command spawned from now on at `store`.
+///
+/// Set through `NIX_CONFIG` rather than `--store`, since not every nix
+/// program takes that flag but all of them read the setting.
+pub fn set_store(store: Option<String>) {
+    *STORE.lock().unwrap() = store;
+}
+
+/// The store set with `--store`, if any.
+pub fn store() -> Option<String> {
+    STORE.lock().unwrap().clone()
+}
+
+fn verbosity_args(level: i8) -> Vec<&'static str> {
+    if level < 0 {
+        vec!["--quiet"]
+    } else {
+        vec!["-v"; level as usize]
+    }
+}
+
 #[derive(Debug)]
 pub struct NixCommand {
     program: String,
@@ -28,8 +68,31 @@ impl NixCommand {
             envs,
         };
 
-        // Add experimental features flag unconditionally for now
-        cmd.args(["--extra-experimental-features", "flakes nix-command"]);
+        // Nix before 2.4 rejects the experimental features flag
+        if crate::nix::capabilities().supports_experimental_features() {
+            cmd.args(["--extra-experimental-features", "flakes nix-command"]);
+        }
+        cmd.args(verbosity_args(VERBOSITY.load(Ordering::Relaxed)));
+        cmd.args(crate::eval_profile::nix_args(program));
+        let mut settings = Vec::new();
+        if let Some(store) = STORE.lock().unwrap().clone() {
+            cmd.env_remove("NIX_REMOTE");
+            settings.push(("store", store));
+        }
+        if !crate::git::warn_dirty() {
+            settings.push(("warn-dirty", "false".to_string()));
+        }
+        if !settings.is_empty() {
+            let key = OsString::from("NIX_CONFIG");
+            let current = cmd
+                .envs
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string_lossy().into_owned());
+            let config = merge_nix_config(current.as_deref(), &settings);
+            cmd.env_remove(&key);
+            cmd.envs([(key, config)]);
+        }
         cmd
     }
 
@@ -62,6 +125,31 @@ impl NixCommand {
         self
     }
 
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.envs.retain(|(k, _)| k != key.as_ref());
+        self
+    }
+
+    /// Pass extra ssh `-o` options to ssh:// and ssh-ng:// stores.
+    ///
+    /// Nix reads ssh options from `NIX_SSHOPTS`, so they are appended to
+    /// whatever the user already set there.
+    pub fn ssh_options(&mut self, options: &[String]) -> &mut Self {
+        if options.is_empty() {
+            return self;
+        }
+        let key = OsString::from("NIX_SSHOPTS");
+        let current = self
+            .envs
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.to_string_lossy().into_owned());
+        let merged = merge_ssh_opts(current.as_deref(), options);
+        self.env_remove(&key);
+        self.envs([(key, merged)])
+    }
+
     fn construct_command(&self) -> Command {
         // Check for nom availability and substitutions
         let mut program = self.program.clone();
@@ -89,15 +177,17 @@ impl NixCommand {
         cmd.args(&args);
         cmd.env_clear();
         cmd.envs(self.envs.clone());
+        cmd.envs(crate::nix::path_envs());
         cmd
     }
 
     pub fn run(&mut self) -> Result<()> {
         let mut cmd = self.construct_command();
         tracing::debug!("+ {}", self.format_command());
+        self.check_available()?;
+        self.keep_expr();
 
-        let status = cmd
-            .status()
+        let status = crate::progress::suspended(|| cmd.status())
             .context(format!("Failed to run {}", self.program))?;
         if !status.success() {
             anyhow::bail!(
@@ -108,20 +198,60 @@ impl NixCommand {
         Ok(())
     }
 
+    /// Like [`NixCommand::run`], but also keep stderr for the error, so a
+    /// failure can be told apart from a transient one while the user still
+    /// sees the output as it comes.
+    pub fn run_keeping_stderr(&mut self) -> Result<()> {
+        use std::io::{BufRead, BufReader};
+        let mut cmd = self.construct_command();
+        tracing::debug!("+ {}", self.format_command());
+        self.check_available()?;
+        self.keep_expr();
+
+        cmd.stderr(std::process::Stdio::piped());
+        let (status, stderr) = crate::progress::suspended(|| -> std::io::Result<_> {
+            let mut child = cmd.spawn()?;
+            let mut stderr = String::new();
+            if let Some(pipe) = child.stderr.take() {
+                for line in BufReader::new(pipe).lines() {
+                    let line = line?;
+                    eprintln!("{}", line);
+                    stderr.push_str(&line);
+                    stderr.push('\n');
+                }
+            }
+            Ok((child.wait()?, stderr))
+        })
+        .context(format!("Failed to run {}", self.program))?;
+        if !status.success() {
+            anyhow::bail!("Command failed:\n{}", stderr);
+        }
+        Ok(())
+    }
+
     pub fn output(&mut self) -> Result<String> {
+        let (stdout, stderr) = self.output_with_stderr()?;
+        forward_stderr(&stderr);
+        Ok(stdout)
+    }
+
+    /// Like [`NixCommand::output`], but return stderr instead of logging it.
+    pub fn output_with_stderr(&mut self) -> Result<(String, String)> {
         let mut cmd = self.construct_command();
         tracing::debug!("+ {}", self.format_command());
+        self.check_available()?;
+        self.keep_expr();
 
         let output = cmd
             .output()
             .context(format!("Failed to run {}", self.program))?;
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
             anyhow::bail!("Command failed:\n{}", stderr);
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
-        Ok(stdout.trim().to_string())
+        Ok((stdout.trim().to_string(), stderr))
     }
 
     pub fn json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T> {
@@ -134,10 +264,48 @@ impl NixCommand {
         use std::os::unix::process::CommandExt;
         let mut cmd = self.construct_command();
         tracing::debug!("+ {}", self.format_command());
+        self.check_available()?;
+        self.keep_expr();
+        // The status line would otherwise stay behind the new program's output
+        crate::progress::reporter().suspend();
         let err = cmd.exec();
+        crate::progress::reporter().resume();
         anyhow::bail!("Failed to exec {}: {}", self.program, err);
     }
 
+    /// Fail early with an actionable message if `nix` itself is unusable.
+    fn check_available(&self) -> Result<()> {
+        if self.program != "nix" {
+            return Ok(());
+        }
+        let what = self
+            .args
+            .iter()
+            .map(|a| a.to_string_lossy())
+            .filter(|a| a != "flakes nix-command")
+            .find(|a| !a.starts_with('-'))
+            .map(|a| format!("`nix {}`", a))
+            .unwrap_or_else(|| "This operation".to_string());
+        crate::nix::capabilities().require_nix_command(&what)
+    }
+
+    /// Save an inline `--expr` argument for inspection (`--keep-expr`).
+    fn keep_expr(&self) {
+        let expr = self
+            .args
+            .windows(2)
+            .find(|pair| pair[0] == "--expr")
+            .map(|pair| pair[1].to_string_lossy());
+        if let Some(expr) = expr {
+            crate::scratch::keep_expr(&self.program, &expr);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn get_program(&self) -> &str {
+        &self.program
+    }
+
     pub fn format_command(&self) -> String {
         let cmd = self.construct_command();
         let program = cmd.get_program().to_string_lossy();
@@ -146,6 +314,80 @@ impl NixCommand {
     }
 }
 
+/// Split captured nix stderr into messages, with the log level their prefix implies.
+///
+/// Indented lines continue the message above them (nix indents the body
+/// of errors and warnings).
+fn parse_nix_stderr(stderr: &str) -> Vec<(Level, String)> {
+    let mut messages: Vec<(Level, String)> = Vec::new();
+    for line in stderr.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, message)) = messages.last_mut() {
+                message.push('\n');
+                message.push_str(line);
+                continue;
+            }
+        }
+        let level = if line.starts_with("error:") {
+            Level::ERROR
+        } else if line.starts_with("warning:") || line.starts_with("evaluation warning:") {
+            Level::WARN
+        } else if line.starts_with("trace:") {
+            Level::INFO
+        } else {
+            Level::DEBUG
+        };
+        messages.push((level, line.to_string()));
+    }
+    messages
+}
+
+/// Log the stderr of a successful captured command through tracing.
+fn forward_stderr(stderr: &str) {
+    for (level, message) in parse_nix_stderr(stderr) {
+        if level <= Level::WARN && !FORWARDED.lock().unwrap().insert(message.clone()) {
+            continue;
+        }
+        match level {
+            Level::ERROR => tracing::error!(target: "nix", "{}", message),
+            Level::WARN => tracing::warn!(target: "nix", "{}", message),
+            Level::INFO => tracing::info!(target: "nix", "{}", message),
+            _ => tracing::debug!(target: "nix", "{}", message),
+        }
+    }
+}
+
+/// Append `-o OPTION` for each of `options` to an existing `NIX_SSHOPTS` value.
+fn merge_ssh_opts(current: Option<&str>, options: &[String]) -> String {
+    current
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(str::to_string)
+        .into_iter()
+        .chain(options.iter().map(|o| format!("-o {}", o)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Append `name = value` lines to an existing `NIX_CONFIG` value; later lines win.
+fn merge_nix_config(current: Option<&str>, settings: &[(&str, String)]) -> String {
+    current
+        .map(str::trim_end)
+        .filter(|c| !c.is_empty())
+        .map(str::to_string)
+        .into_iter()
+        .chain(
+            settings
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value)),
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 /// Check if a program is available in PATH
 fn is_program_available(program: &str) -> bool {
     if let Some(available) = PROGRAM_AVAILABILITY.get(&program.to_string()) {
@@ -187,6 +429,76 @@ fn is_executable(_path: &std::path::Path) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_merge_ssh_opts() {
+        let options = vec!["Port=2222".to_string(), "ConnectTimeout=5".to_string()];
+        assert_eq!(
+            merge_ssh_opts(None, &options),
+            "-o Port=2222 -o ConnectTimeout=5"
+        );
+        assert_eq!(
+            merge_ssh_opts(Some("-i ~/.ssh/deploy "), &options),
+            "-i ~/.ssh/deploy -o Port=2222 -o ConnectTimeout=5"
+        );
+        assert_eq!(merge_ssh_opts(Some("-A"), &[]), "-A");
+    }
+
+    #[test]
+    fn test_merge_nix_config() {
+        let store = [("store", "local?root=/tmp/s".to_string())];
+        assert_eq!(merge_nix_config(None, &store), "store = local?root=/tmp/s");
+        assert_eq!(
+            merge_nix_config(Some("sandbox = false\n"), &store),
+            "sandbox = false\nstore = local?root=/tmp/s"
+        );
+        assert_eq!(
+            merge_nix_config(
+                Some(""),
+                &[
+                    ("store", "local?root=/tmp/s".to_string()),
+                    ("warn-dirty", "false".to_string())
+                ]
+            ),
+            "store = local?root=/tmp/s\nwarn-dirty = false"
+        );
+    }
+
+    #[test]
+    fn test_verbosity_args() {
+        assert_eq!(verbosity_args(-1), vec!["--quiet"]);
+        assert!(verbosity_args(0).is_empty());
+        assert_eq!(verbosity_args(2), vec!["-v", "-v"]);
+    }
+
+    #[test]
+    fn test_parse_nix_stderr() {
+        let stderr = "warning: Git tree '/src/proj' is dirty\n\
+                      evaluating file '/src/proj/flake.nix'\n\
+                      error: attribute 'foo' missing\n\
+                      \n\
+                      \x20      at /src/proj/flake.nix:3:5\n\
+                      trace: hello\n";
+        let messages = parse_nix_stderr(stderr);
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    Level::WARN,
+                    "warning: Git tree '/src/proj' is dirty".to_string()
+                ),
+                (
+                    Level::DEBUG,
+                    "evaluating file '/src/proj/flake.nix'".to_string()
+                ),
+                (
+                    Level::ERROR,
+                    "error: attribute 'foo' missing\n       at /src/proj/flake.nix:3:5".to_string()
+                ),
+                (Level::INFO, "trace: hello".to_string()),
+            ]
+        );
+    }
+
     #[test]
     fn test_format_command() {
         let mut cmd = NixCommand::new("nix");
@@ -1,6 +1,7 @@
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::io::{BufRead, Write};
 use std::sync::Mutex;
 
 /// A thread-safe cache for key-value pairs.
@@ -74,3 +75,92 @@ where
         *cache = Some(value);
     }
 }
+
+/// Compute the Levenshtein edit distance between two strings.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b_chars.len()).collect();
+    let mut curr = vec![0; b_chars.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b_chars.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b_chars.len()]
+}
+
+/// Find the candidates closest to `name`, within a small edit distance.
+///
+/// Results are ordered by distance, then alphabetically.
+pub fn closest_matches<'a, I>(name: &str, candidates: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    // Allow roughly one edit per three characters, capped at 3
+    let max_distance = (name.chars().count() / 3).clamp(1, 3);
+
+    let mut matches: Vec<(usize, String)> = candidates
+        .into_iter()
+        .filter(|c| *c != name)
+        .map(|c| (levenshtein(name, c), c.to_string()))
+        .filter(|(d, _)| *d <= max_distance)
+        .collect();
+
+    matches.sort();
+    matches.dedup();
+    matches.into_iter().map(|(_, c)| c).collect()
+}
+
+/// Ask a yes/no question on stderr, defaulting to no.
+pub fn confirm(question: &str) -> bool {
+    eprint!("{} (y/N) ", question);
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// A byte count in B, KiB, MiB or GiB.
+pub fn format_size(size: u64) -> String {
+    if size < 1024 {
+        format!("{} B", size)
+    } else if size < 1024 * 1024 {
+        format!("{:.1} KiB", size as f64 / 1024.0)
+    } else if size < 1024 * 1024 * 1024 {
+        format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} GiB", size as f64 / (1024.0 * 1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("abc", ""), 3);
+        assert_eq!(levenshtein("packages", "packages"), 0);
+        assert_eq!(levenshtein("packgaes", "packages"), 2);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_closest_matches() {
+        let candidates = ["packages", "checks", "apps", "legacyPackages"];
+        assert_eq!(
+            closest_matches("packgaes", candidates),
+            vec!["packages".to_string()]
+        );
+        assert_eq!(closest_matches("chekcs", candidates), vec!["checks"]);
+        assert!(closest_matches("nixosConfigurations", candidates).is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+//! Evaluation profiling (`trix run --profile-startup`).
+//!
+//! Nix 2.30 and newer can sample the evaluator and write the call stacks
+//! in collapsed ("folded") form, one `frame;frame;frame count` line per
+//! stack. While profiling is on, every nix evaluation trix starts writes
+//! its own profile; they are merged at the end into one folded file for
+//! flamegraph.pl or inferno, or into a speedscope JSON profile.
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// First Nix release with the `eval-profiler` setting.
+const EVAL_PROFILER_MIN_VERSION: (u32, u32, u32) = (2, 30, 0);
+
+/// Programs that evaluate and accept `--option`.
+const EVALUATING_PROGRAMS: &[&str] = &["nix", "nix-build", "nix-instantiate", "nix-shell"];
+
+/// Directory collecting one profile per evaluation, while profiling
+static PROFILE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Number of profiles handed out so far
+static PROFILE_SEQ: AtomicU32 = AtomicU32::new(0);
+
+/// Start profiling nix evaluations, collecting profiles in `dir`.
+pub fn start(dir: &Path) -> Result<()> {
+    let caps = crate::nix::capabilities();
+    if caps.version.is_some_and(|v| v < EVAL_PROFILER_MIN_VERSION) {
+        let (major, minor, patch) = caps.version.unwrap_or_default();
+        anyhow::bail!(
+            "Profiling evaluation needs Nix {}.{} or newer (found Nix {}.{}.{})",
+            EVAL_PROFILER_MIN_VERSION.0,
+            EVAL_PROFILER_MIN_VERSION.1,
+            major,
+            minor,
+            patch
+        );
+    }
+    *PROFILE_DIR.lock().unwrap() = Some(dir.to_path_buf());
+    Ok(())
+}
+
+/// Extra arguments for `program` so its evaluation is profiled, if profiling.
+pub fn nix_args(program: &str) -> Vec<String> {
+    let dir = PROFILE_DIR.lock().unwrap();
+    let Some(dir) = dir.as_ref() else {
+        return Vec::new();
+    };
+    if !EVALUATING_PROGRAMS.contains(&program) {
+        return Vec::new();
+    }
+    let seq = PROFILE_SEQ.fetch_add(1, Ordering::Relaxed);
+    let file = dir.join(format!("{}.folded", seq));
+    vec![
+        "--option".to_string(),
+        "eval-profiler".to_string(),
+        "flamegraph".to_string(),
+        "--option".to_string(),
+        "eval-profile-file".to_string(),
+        file.display().to_string(),
+    ]
+}
+
+/// Sum the samples of folded profiles per stack.
+fn merge_folded<'a>(profiles: impl IntoIterator<Item = &'a str>) -> BTreeMap<String, u64> {
+    let mut stacks = BTreeMap::new();
+    for line in profiles.into_iter().flat_map(str::lines) {
+        let Some((stack, count)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        let Ok(count) = count.parse::<u64>() else {
+            continue;
+        };
+        *stacks.entry(stack.to_string()).or_insert(0) += count;
+    }
+    stacks
+}
+
+fn to_folded(stacks: &BTreeMap<String, u64>) -> String {
+    stacks
+        .iter()
+        .map(|(stack, count)| format!("{} {}\n", stack, count))
+        .collect()
+}
+
+/// Convert merged stacks to a speedscope sampled profile.
+fn to_speedscope(stacks: &BTreeMap<String, u64>, name: &str) -> serde_json::Value {
+    let mut frames: Vec<&str> = Vec::new();
+    let mut frame_index: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut samples = Vec::new();
+    let mut weights = Vec::new();
+    for (stack, count) in stacks {
+        let sample: Vec<usize> = stack
+            .split(';')
+            .map(|frame| {
+                *frame_index.entry(frame).or_insert_with(|| {
+                    frames.push(frame);
+                    frames.len() - 1
+                })
+            })
+            .collect();
+        samples.push(sample);
+        weights.push(*count);
+    }
+    let total: u64 = weights.iter().sum();
+
+    json!({
+        "$schema": "https://www.speedscope.app/file-format-schema.json",
+        "name": name,
+        "exporter": format!("trix {}", env!("CARGO_PKG_VERSION")),
+        "shared": {
+            "frames": frames.iter().map(|f| json!({ "name": f })).collect::<Vec<_>>(),
+        },
+        "profiles": [{
+            "type": "sampled",
+            "name": name,
+            "unit": "none",
+            "startValue": 0,
+            "endValue": total,
+            "samples": samples,
+            "weights": weights,
+        }],
+    })
+}
+
+/// Stop profiling and write the merged profile to `output`.
+///
+/// A `.json` output gets speedscope's format, anything else folded
+/// stacks. Returns the number of samples.
+pub fn finish(output: &Path, name: &str) -> Result<u64> {
+    let Some(dir) = PROFILE_DIR.lock().unwrap().take() else {
+        return Ok(0);
+    };
+    let mut contents = Vec::new();
+    for entry in std::fs::read_dir(&dir)?.flatten() {
+        contents.push(std::fs::read_to_string(entry.path())?);
+    }
+    let stacks = merge_folded(contents.iter().map(String::as_str));
+
+    let data = if output.extension().is_some_and(|e| e == "json") {
+        serde_json::to_string(&to_speedscope(&stacks, name))?
+    } else {
+        to_folded(&stacks)
+    };
+    std::fs::write(output, data)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    Ok(stacks.values().sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_folded() {
+        let stacks = merge_folded([
+            "flake.nix:3:5:outputs;default.nix:1:1 4\nflake.nix:3:5:outputs 1\n",
+            "flake.nix:3:5:outputs;default.nix:1:1 2\nnot a stack\n",
+        ]);
+        assert_eq!(
+            to_folded(&stacks),
+            "flake.nix:3:5:outputs 1\nflake.nix:3:5:outputs;default.nix:1:1 6\n"
+        );
+    }
+
+    #[test]
+    fn test_to_speedscope() {
+        let stacks = merge_folded(["a;b 3\na;c 1\n"]);
+        let profile = to_speedscope(&stacks, "trix run");
+        assert_eq!(
+            profile["shared"]["frames"],
+            json!([{ "name": "a" }, { "name": "b" }, { "name": "c" }])
+        );
+        assert_eq!(profile["profiles"][0]["samples"], json!([[0, 1], [0, 2]]));
+        assert_eq!(profile["profiles"][0]["weights"], json!([3, 1]));
+        assert_eq!(profile["profiles"][0]["endValue"], 4);
+    }
+}
@@ -0,0 +1,583 @@
+//! Native fetching of locked tarball inputs (`--native-fetch`).
+//!
+//! By default inputs are fetched by `builtins.fetchTarball` during
+//! evaluation, which shows no progress and reports failures poorly. With
+//! `--native-fetch`, trix downloads github, gitlab, sourcehut and tarball
+//! inputs itself before evaluating, unpacks them (tarballs or zip
+//! archives, like fetchTarball), adds them with `nix-store --add`,
+//! verifies their narHash and passes the store paths to inputs.nix.
+//!
+//! Either way, locked inputs whose sources are already in the store are
+//! used from there: a fetched source's store path follows from its
+//! narHash, so inputs.nix can skip fetchTarball and fetchGit for them.
+//!
+//! Mirrors (`--mirror HOST URL`, `$TRIX_MIRRORS`) replace the
+//! `https://HOST` prefix of archive URLs, for networks that can only reach
+//! forges through a proxy. They apply to both fetch paths.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::lock::{LockFile, LockedInfo};
+
+/// Whether `--native-fetch` is in effect
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Mirror base URL by forge host
+static MIRRORS: Lazy<Mutex<BTreeMap<String, String>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Inputs already in the store or prefetched, per flake directory
+/// (narHash -> store path).
+///
+/// The mutex is held while fetching so parallel evaluations of the same
+/// flake download each input once.
+static PREFETCHED: Lazy<Mutex<HashMap<PathBuf, BTreeMap<String, String>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Fetch inputs natively (`--native-fetch`).
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Parse `HOST=URL` pairs separated by whitespace, as in `$TRIX_MIRRORS`.
+fn parse_mirrors(spec: &str) -> BTreeMap<String, String> {
+    spec.split_whitespace()
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(host, url)| (host.to_string(), url.trim_end_matches('/').to_string()))
+        .collect()
+}
+
+/// Mirrors from `env_value`, the value of `$TRIX_MIRRORS`, and
+/// `--mirror HOST URL` pairs (which win).
+fn build_mirrors(env_value: Option<&str>, pairs: &[String]) -> BTreeMap<String, String> {
+    let mut mirrors = parse_mirrors(env_value.unwrap_or_default());
+    for pair in pairs.chunks(2) {
+        if let [host, url] = pair {
+            mirrors.insert(host.clone(), url.trim_end_matches('/').to_string());
+        }
+    }
+    mirrors
+}
+
+/// Configure mirrors from `$TRIX_MIRRORS` and `--mirror HOST URL` pairs (which win).
+pub fn set_mirrors(args: &[String]) {
+    let env_value = std::env::var("TRIX_MIRRORS").ok();
+    *MIRRORS.lock().unwrap() = build_mirrors(env_value.as_deref(), args);
+}
+
+/// The configured mirrors, by forge host.
+pub fn mirrors() -> BTreeMap<String, String> {
+    MIRRORS.lock().unwrap().clone()
+}
+
+/// Rewrite `url` on `host` to go through its mirror in `mirrors`, if any.
+fn mirrored(mirrors: &BTreeMap<String, String>, host: &str, url: &str) -> String {
+    let origin = format!("https://{}", host);
+    match (mirrors.get(host), url.strip_prefix(&origin)) {
+        (Some(base), Some(rest)) => format!("{}{}", base, rest),
+        _ => url.to_string(),
+    }
+}
+
+/// Nix attrset of `mirrors`, for inputs.nix.
+pub fn mirrors_expr(mirrors: &BTreeMap<String, String>) -> String {
+    let entries: Vec<String> = mirrors
+        .iter()
+        .map(|(host, url)| {
+            format!(
+                "{} = {};",
+                crate::nix::nix_string(host),
+                crate::nix::nix_string(url)
+            )
+        })
+        .collect();
+    format!("{{ {} }}", entries.join(" "))
+}
+
+/// Get the path of the narHash -> store path cache.
+fn get_fetched_path() -> Option<PathBuf> {
+    crate::paths::cache_dir().map(|d| d.join("fetched.json"))
+}
+
+/// Load previously fetched inputs whose store paths still exist.
+fn load_fetched() -> BTreeMap<String, String> {
+    let all: BTreeMap<String, String> = get_fetched_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    all.into_iter()
+        .filter(|(_, path)| Path::new(path).exists())
+        .collect()
+}
+
+fn record_fetched(fetched: &BTreeMap<String, String>) -> Result<()> {
+    let Some(path) = get_fetched_path() else {
+        return Ok(());
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(fetched)?)?;
+    Ok(())
+}
+
+/// Forge host of a locked input, which mirrors are keyed by.
+fn archive_host(locked: &LockedInfo) -> Option<&str> {
+    match locked.lock_type.as_str() {
+        "github" => Some("github.com"),
+        "gitlab" => Some(locked.host.as_deref().unwrap_or("gitlab.com")),
+        "sourcehut" => Some(locked.host.as_deref().unwrap_or("git.sr.ht")),
+        _ => None,
+    }
+}
+
+/// URLs to try for `origin` on `host`: its configured mirror, if any, then `origin`.
+pub fn mirrored_urls(host: &str, origin: &str) -> Vec<String> {
+    urls_through(&mirrors(), host, origin)
+}
+
+/// URLs to try for `origin` on `host`: its mirror in `mirrors`, if any, then `origin`.
+fn urls_through(mirrors: &BTreeMap<String, String>, host: &str, origin: &str) -> Vec<String> {
+    let mirror = mirrored(mirrors, host, origin);
+    if mirror == origin {
+        vec![origin.to_string()]
+    } else {
+        vec![mirror, origin.to_string()]
+    }
+}
+
+/// URLs to try for a locked input: its mirror in `mirrors`, if any, then the origin.
+fn archive_urls(mirrors: &BTreeMap<String, String>, locked: &LockedInfo) -> Vec<String> {
+    let Some(origin) = archive_url(locked) else {
+        return Vec::new();
+    };
+    match archive_host(locked) {
+        Some(host) => urls_through(mirrors, host, &origin),
+        None => vec![origin],
+    }
+}
+
+/// Origin download URL for a locked input, matching the one used by inputs.nix.
+fn archive_url(locked: &LockedInfo) -> Option<String> {
+    let owner = locked.owner.as_deref();
+    let repo = locked.repo.as_deref();
+    let rev = locked.rev.as_deref();
+    match locked.lock_type.as_str() {
+        "github" => Some(format!(
+            "https://github.com/{}/{}/archive/{}.tar.gz",
+            owner?, repo?, rev?
+        )),
+        "gitlab" => {
+            let host = locked.host.as_deref().unwrap_or("gitlab.com");
+            let (repo, rev) = (repo?, rev?);
+            Some(format!(
+                "https://{}/{}/{}/-/archive/{}/{}-{}.tar.gz",
+                host, owner?, repo, rev, repo, rev
+            ))
+        }
+        "sourcehut" => {
+            let host = locked.host.as_deref().unwrap_or("git.sr.ht");
+            Some(format!(
+                "https://{}/~{}/{}/archive/{}.tar.gz",
+                host, owner?, repo?, rev?
+            ))
+        }
+        "tarball" => locked.url.clone(),
+        _ => None,
+    }
+}
+
+/// Nix attrset of inputs for `flake_dir` already in the store, keyed by
+/// narHash.
+///
+/// With `--native-fetch`, the missing tarball inputs are fetched first.
+/// Inputs that fail to fetch natively are left to `builtins.fetchTarball`.
+pub fn prefetched_expr(flake_dir: &Path) -> String {
+    let mut cache = PREFETCHED.lock().unwrap();
+    let prefetched = cache.entry(flake_dir.to_path_buf()).or_insert_with(|| {
+        let lock = read_lock(flake_dir);
+        let mut inputs = cached_inputs(&lock);
+        if ENABLED.load(Ordering::Relaxed) {
+            let fetched = prefetch_inputs(&lock, &inputs);
+            inputs.extend(fetched);
+        }
+        inputs
+    });
+
+    let entries: Vec<String> = prefetched
+        .iter()
+        .map(|(hash, path)| {
+            format!(
+                "{} = {};",
+                crate::nix::nix_string(hash),
+                crate::nix::nix_string(path)
+            )
+        })
+        .collect();
+    format!("{{ {} }}", entries.join(" "))
+}
+
+fn read_lock(flake_dir: &Path) -> LockFile {
+    fs::read_to_string(flake_dir.join("flake.lock"))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Locked inputs whose sources are already in the store (narHash -> store path).
+///
+/// Nothing is looked up when `--store` points elsewhere, since the sources
+/// would have to be in that store.
+fn cached_inputs(lock: &LockFile) -> BTreeMap<String, String> {
+    if lock.nodes.is_empty() || crate::command::store().is_some() {
+        return BTreeMap::new();
+    }
+    let Ok(store_dir) = crate::nix::get_store_dir() else {
+        return BTreeMap::new();
+    };
+    let mut candidates = source_candidates(lock, &store_dir);
+    if candidates.is_empty() {
+        return candidates;
+    }
+
+    // One query for all of them; a path that merely exists may be a
+    // partial copy the store does not consider valid
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--check-validity", "--print-invalid"]);
+    cmd.args(candidates.values());
+    match cmd.output() {
+        Ok(invalid) => {
+            let invalid: BTreeSet<&str> = invalid.lines().map(str::trim).collect();
+            candidates.retain(|_, path| !invalid.contains(path.as_str()));
+            candidates
+        }
+        Err(e) => {
+            tracing::debug!("Failed to check input sources in the store: {}", e);
+            BTreeMap::new()
+        }
+    }
+}
+
+/// The store paths locked inputs would have in `store_dir` if fetched, by
+/// narHash. Path inputs are left out, so they keep reflecting their directory.
+fn source_candidates(lock: &LockFile, store_dir: &str) -> BTreeMap<String, String> {
+    lock.nodes
+        .values()
+        .filter_map(|node| node.locked.as_ref())
+        .filter(|locked| locked.lock_type != "path")
+        .filter_map(|locked| {
+            let nar_hash = locked.nar_hash.as_ref()?;
+            let path = crate::hash::source_store_path(store_dir, nar_hash, "source")?;
+            Some((nar_hash.clone(), path))
+        })
+        .collect()
+}
+
+/// Fetch every tarball input in `lock` not in `have` yet.
+fn prefetch_inputs(lock: &LockFile, have: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let mut fetched = load_fetched();
+    let mut result = BTreeMap::new();
+
+    let mirrors = mirrors();
+    let mut names: Vec<&String> = lock.nodes.keys().collect();
+    names.sort();
+    for name in names {
+        let Some(locked) = &lock.nodes[name].locked else {
+            continue;
+        };
+        let urls = archive_urls(&mirrors, locked);
+        let (Some(nar_hash), false) = (&locked.nar_hash, urls.is_empty()) else {
+            continue;
+        };
+        if have.contains_key(nar_hash) {
+            continue;
+        }
+
+        if let Some(path) = fetched.get(nar_hash) {
+            result.insert(nar_hash.clone(), path.clone());
+            continue;
+        }
+
+        let mut errors = Vec::new();
+        for url in &urls {
+            match fetch_tarball(name, url, nar_hash) {
+                Ok(path) => {
+                    fetched.insert(nar_hash.clone(), path.clone());
+                    result.insert(nar_hash.clone(), path);
+                    break;
+                }
+                Err(e) => errors.push(format!("  {}: {:#}", url, e)),
+            }
+        }
+        if errors.len() == urls.len() {
+            crate::nix::warn(&format!(
+                "native fetch of input '{}' failed, leaving it to nix:\n{}",
+                name,
+                errors.join("\n")
+            ));
+        }
+    }
+
+    if let Err(e) = record_fetched(&fetched) {
+        tracing::debug!("Failed to record fetched inputs: {}", e);
+    }
+    result
+}
+
+/// Whether `archive` starts like a zip file rather than a tarball.
+fn is_zip(archive: &Path) -> Result<bool> {
+    let mut magic = [0u8; 4];
+    let mut file = fs::File::open(archive)?;
+    Ok(file.read_exact(&mut magic).is_ok() && magic == *b"PK\x03\x04")
+}
+
+/// Download, unpack and add a tarball or zip archive to the store,
+/// checking its narHash.
+fn fetch_tarball(name: &str, url: &str, nar_hash: &str) -> Result<String> {
+    let tmp = crate::scratch::tempdir()?;
+    let archive = tmp.path().join("archive");
+    download(name, url, &archive)?;
+
+    let unpacked = tmp.path().join("unpacked");
+    fs::create_dir(&unpacked)?;
+    // Not NixCommand: tar and unzip take none of nix's flags
+    let mut cmd = if is_zip(&archive)? {
+        let mut cmd = std::process::Command::new("unzip");
+        cmd.arg("-q").arg(&archive).arg("-d").arg(&unpacked);
+        cmd
+    } else {
+        let mut cmd = std::process::Command::new("tar");
+        cmd.arg("-xf").arg(&archive).arg("-C").arg(&unpacked);
+        cmd
+    };
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        anyhow::bail!("Failed to unpack {}", url);
+    }
+
+    // Like fetchTarball, use the single top-level directory as the source
+    let entries: Vec<PathBuf> = fs::read_dir(&unpacked)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    let top = match entries.as_slice() {
+        [single] if single.is_dir() => single.clone(),
+        _ => unpacked.clone(),
+    };
+    let source = tmp.path().join("source");
+    fs::rename(&top, &source)?;
+
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.arg("--add").arg(&source);
+    let store_path = cmd.output()?.trim().to_string();
+
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--query", "--hash", &store_path]);
+    let output = cmd.output()?;
+    let actual = crate::hash::nix_hash_to_sri(output.trim())
+        .with_context(|| format!("Unexpected nix-store output: {}", output.trim()))?;
+    if actual != nar_hash {
+        anyhow::bail!(
+            "hash mismatch for {}:\n  locked: {}\n  got:    {}",
+            url,
+            nar_hash,
+            actual
+        );
+    }
+
+    Ok(store_path)
+}
+
+/// Download `url` to `dest`, showing progress on stderr.
+///
+/// Transient failures are retried, resuming from the bytes already
+/// downloaded when the server supports range requests.
+fn download(name: &str, url: &str, dest: &Path) -> Result<()> {
+    let done = crate::retry::with_retry(&format!("download of {}", url), || {
+        download_once(name, url, dest)
+    });
+    crate::progress::reporter().clear();
+    let done = done?;
+
+    crate::progress::reporter().log(&format!(
+        "fetched input '{}' ({})",
+        name,
+        crate::common::format_size(done)
+    ));
+    Ok(())
+}
+
+/// Download `url` to `dest`, continuing a partial download already in `dest`.
+fn download_once(name: &str, url: &str, dest: &Path) -> Result<u64> {
+    let offset = fs::metadata(dest).map(|m| m.len()).unwrap_or(0);
+    let mut request = reqwest::blocking::Client::new()
+        .get(url)
+        .header("User-Agent", "trix")
+        .timeout(Duration::from_secs(600));
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+    let mut response = request
+        .send()
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to download {}", url))?;
+
+    // Servers without range support send the whole file again
+    let resumed = offset > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let (mut file, mut done) = if resumed {
+        (fs::OpenOptions::new().append(true).open(dest)?, offset)
+    } else {
+        (fs::File::create(dest)?, 0)
+    };
+    let total = response.content_length().map(|len| len + done);
+    let reporter = crate::progress::reporter();
+    let mut buf = [0u8; 64 * 1024];
+
+    loop {
+        let n = response
+            .read(&mut buf)
+            .with_context(|| format!("Failed to download {}", url))?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n])?;
+        done += n as u64;
+        reporter.transfer(name, done, total);
+    }
+
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_url() {
+        let locked = LockedInfo {
+            lock_type: "github".to_string(),
+            owner: Some("NixOS".to_string()),
+            repo: Some("nixpkgs".to_string()),
+            rev: Some("abc".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            archive_url(&locked),
+            Some("https://github.com/NixOS/nixpkgs/archive/abc.tar.gz".to_string())
+        );
+
+        let locked = LockedInfo {
+            lock_type: "gitlab".to_string(),
+            owner: Some("group".to_string()),
+            repo: Some("proj".to_string()),
+            rev: Some("abc".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            archive_url(&locked),
+            Some("https://gitlab.com/group/proj/-/archive/abc/proj-abc.tar.gz".to_string())
+        );
+
+        let locked = LockedInfo {
+            lock_type: "git".to_string(),
+            url: Some("https://example.com/repo.git".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(archive_url(&locked), None);
+    }
+
+    #[test]
+    fn test_source_candidates() {
+        let store_dir = "/nix/store";
+        let nar_hash = "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+        let lock: LockFile = serde_json::from_value(serde_json::json!({
+            "nodes": {
+                "nixpkgs": { "locked": { "type": "github", "owner": "NixOS", "repo": "nixpkgs", "rev": "abc", "narHash": nar_hash } },
+                "local": { "locked": { "type": "path", "path": "./sub", "narHash": nar_hash } },
+                "root": { "inputs": { "nixpkgs": "nixpkgs", "local": "local" } }
+            },
+            "root": "root",
+            "version": 7
+        }))
+        .unwrap();
+        let path = crate::hash::source_store_path(store_dir, nar_hash, "source").unwrap();
+        assert_eq!(
+            source_candidates(&lock, store_dir),
+            BTreeMap::from([(nar_hash.to_string(), path)])
+        );
+    }
+
+    #[test]
+    fn test_is_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip = dir.path().join("zip");
+        fs::write(&zip, b"PK\x03\x04rest").unwrap();
+        let tar = dir.path().join("tar");
+        fs::write(&tar, b"\x1f\x8b\x08\x00").unwrap();
+        let short = dir.path().join("short");
+        fs::write(&short, b"PK").unwrap();
+        assert!(is_zip(&zip).unwrap());
+        assert!(!is_zip(&tar).unwrap());
+        assert!(!is_zip(&short).unwrap());
+    }
+
+    #[test]
+    fn test_mirrors() {
+        assert_eq!(
+            parse_mirrors("github.com=https://proxy/gh/  bad gitlab.com=https://proxy/gl"),
+            BTreeMap::from([
+                ("github.com".to_string(), "https://proxy/gh".to_string()),
+                ("gitlab.com".to_string(), "https://proxy/gl".to_string()),
+            ])
+        );
+
+        let mirrors = build_mirrors(
+            Some("github.com=https://env/gh gitlab.com=https://env/gl"),
+            &["github.com".to_string(), "https://proxy/gh/".to_string()],
+        );
+        assert_eq!(
+            mirrors,
+            BTreeMap::from([
+                ("github.com".to_string(), "https://proxy/gh".to_string()),
+                ("gitlab.com".to_string(), "https://env/gl".to_string()),
+            ])
+        );
+        assert!(build_mirrors(None, &[]).is_empty());
+
+        let mirrors = build_mirrors(
+            None,
+            &["github.com".to_string(), "https://proxy/gh/".to_string()],
+        );
+        let locked = LockedInfo {
+            lock_type: "github".to_string(),
+            owner: Some("NixOS".to_string()),
+            repo: Some("nixpkgs".to_string()),
+            rev: Some("abc".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            archive_urls(&mirrors, &locked),
+            vec![
+                "https://proxy/gh/NixOS/nixpkgs/archive/abc.tar.gz".to_string(),
+                "https://github.com/NixOS/nixpkgs/archive/abc.tar.gz".to_string(),
+            ]
+        );
+        assert_eq!(
+            mirrored(&mirrors, "gitlab.com", "https://gitlab.com/a/b"),
+            "https://gitlab.com/a/b"
+        );
+        assert_eq!(
+            mirrors_expr(&mirrors),
+            r#"{ "github.com" = "https://proxy/gh"; }"#
+        );
+    }
+}
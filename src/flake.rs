@@ -1,15 +1,74 @@
 //! Flake handling - parsing, URL resolution, lock management.
 
 use anyhow::Result;
+use percent_encoding::{AsciiSet, CONTROLS};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::common::Cache;
-use crate::registry::{is_registry_name, registry_entry_to_flake_ref, resolve_registry_name};
+use crate::registry::{
+    is_registry_name, registry_entry_to_flake_ref, resolve_registry_name, RegistryEntry,
+};
 
 /// Cache for flake inputs per directory (canonical path -> inputs JSON)
 static FLAKE_INPUTS_CACHE: Cache<PathBuf, serde_json::Value> = Cache::new();
 
+/// Input URLs as flake.nix declares them, registry names resolved
+/// (canonical path -> input name -> URL)
+static FLAKE_INPUT_URLS_CACHE: Cache<PathBuf, HashMap<String, String>> = Cache::new();
+
+/// Cache for trusted nixConfig options by flake directory
+static NIX_CONFIG_OPTIONS_CACHE: Cache<PathBuf, Vec<(String, String)>> = Cache::new();
+
+/// Whether to print each step of installable resolution (`--explain-resolution`)
+static EXPLAIN_RESOLUTION: AtomicBool = AtomicBool::new(false);
+
+pub fn set_explain_resolution(enabled: bool) {
+    EXPLAIN_RESOLUTION.store(enabled, Ordering::Relaxed);
+}
+
+/// Print a resolution step to stderr when `--explain-resolution` is set.
+pub fn explain(message: &str) {
+    if EXPLAIN_RESOLUTION.load(Ordering::Relaxed) {
+        eprintln!("resolve: {}", message);
+    }
+}
+
+/// With `--explain-resolution`, print the paths evaluation tries for
+/// `attr` in the flake at `flake_dir` and the one it settles on.
+///
+/// These come from `findAttrPath` in helpers.nix, which makes the choice,
+/// so they can't disagree with what is evaluated.
+pub fn explain_attr_lookup(flake_dir: &Path, attr: &str) {
+    if !EXPLAIN_RESOLUTION.load(Ordering::Relaxed) {
+        return;
+    }
+    let candidates = match crate::nix::attr_path_candidates(flake_dir, attr) {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            explain(&format!(
+                "could not list candidates for '{}': {:#}",
+                attr, e
+            ));
+            return;
+        }
+    };
+    let tried: Vec<&str> = candidates.iter().map(|(path, _)| path.as_str()).collect();
+    explain(&format!(
+        "evaluation looks for '{}' at: {}",
+        attr,
+        tried.join(", ")
+    ));
+    match candidates.iter().find(|(_, exists)| *exists) {
+        Some((path, _)) => explain(&format!("evaluation selects {}", path)),
+        None => explain(&format!("none of the candidates for '{}' exist", attr)),
+    }
+}
+
 /// Result of resolving an installable reference.
 ///
 /// Either local (flake_dir is set) or remote (flake_ref is set).
@@ -21,6 +80,14 @@ pub struct ResolvedInstallable {
     pub flake_ref: Option<String>,  // For remote refs (e.g., "github:NixOS/nixpkgs")
 }
 
+impl ResolvedInstallable {
+    /// The full `flakeref#attr` form, for passing remote installables to nix.
+    pub fn full_ref(&self) -> String {
+        let flake_ref = self.flake_ref.as_deref().unwrap_or("");
+        format!("{}#{}", flake_ref, self.attr_part)
+    }
+}
+
 /// Structured flake source information.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -142,7 +209,7 @@ pub fn parse_flake_url(url: &str) -> FlakeSource {
 
     if let Some(rest) = url_base.strip_prefix("path:") {
         return FlakeSource::Path {
-            path: rest.to_string(),
+            path: path_from_url(rest).to_string_lossy().into_owned(),
             flake: None,
         };
     }
@@ -165,6 +232,8 @@ pub fn parse_flake_url(url: &str) -> FlakeSource {
 /// Returns a map of input names to their specs.
 /// Results are cached per canonical path.
 pub fn get_flake_inputs(flake_dir: &Path) -> Result<serde_json::Value> {
+    crate::safety::check_flake_dir(flake_dir)?;
+
     // Canonicalize path for cache key
     let canonical = flake_dir
         .canonicalize()
@@ -177,9 +246,9 @@ pub fn get_flake_inputs(flake_dir: &Path) -> Result<serde_json::Value> {
 
     let nix_dir = crate::nix::get_nix_dir()?;
     let expr = format!(
-        "import {}/flake_inputs.nix {{ flakePath = {}; }}",
-        nix_dir.display(),
-        flake_dir.display()
+        "import ({} + \"/flake_inputs.nix\") {{ flakePath = {}; }}",
+        crate::nix::nix_path(&nix_dir),
+        crate::nix::nix_path(flake_dir)
     );
 
     let mut cmd = crate::command::NixCommand::new("nix-instantiate");
@@ -196,6 +265,7 @@ pub fn get_flake_inputs(flake_dir: &Path) -> Result<serde_json::Value> {
 
     // Convert raw data to parsed format
     let mut parsed = serde_json::Map::new();
+    let mut urls = HashMap::new();
 
     for raw in raw_inputs {
         let name = raw["name"].as_str().unwrap_or("");
@@ -214,7 +284,10 @@ pub fn get_flake_inputs(flake_dir: &Path) -> Result<serde_json::Value> {
 
         // Regular input with URL
         if let Some(url) = raw["url"].as_str() {
-            let mut source = parse_flake_url(url);
+            // Registry names resolve like installables, --override-flake first
+            let url = resolve_indirect_input(flake_dir, url).unwrap_or_else(|| url.to_string());
+            let mut source = parse_flake_url(&url);
+            urls.insert(name.to_string(), url);
 
             // Check for flake = false
             let is_flake = raw["flake"].as_bool();
@@ -286,16 +359,30 @@ pub fn get_flake_inputs(flake_dir: &Path) -> Result<serde_json::Value> {
     let result = serde_json::Value::Object(parsed);
 
     // Cache the result
+    FLAKE_INPUT_URLS_CACHE.insert(canonical.clone(), urls);
     FLAKE_INPUTS_CACHE.insert(canonical, result.clone());
 
     Ok(result)
 }
 
+/// The URL flake.nix gives input `name`, with all its parameters (`dir`,
+/// `host`, `submodules`, ...) that the parsed spec leaves out.
+pub fn declared_input_url(flake_dir: &Path, name: &str) -> Result<Option<String>> {
+    get_flake_inputs(flake_dir)?;
+    let canonical = flake_dir
+        .canonicalize()
+        .unwrap_or_else(|_| flake_dir.to_path_buf());
+    Ok(FLAKE_INPUT_URLS_CACHE
+        .get(&canonical)
+        .and_then(|urls| urls.get(name).cloned()))
+}
+
 /// Extract description from flake.nix.
 pub fn get_flake_description(flake_dir: &Path) -> Option<String> {
+    crate::safety::check_flake_dir(flake_dir).ok()?;
     let expr = format!(
-        "(import {}/flake.nix).description or null",
-        flake_dir.display()
+        "(import ({} + \"/flake.nix\")).description or null",
+        crate::nix::nix_path(flake_dir)
     );
 
     let mut cmd = crate::command::NixCommand::new("nix-instantiate");
@@ -307,40 +394,19 @@ pub fn get_flake_description(flake_dir: &Path) -> Option<String> {
         .filter(|s| !s.is_empty())
 }
 
-/// Extract nixConfig from flake.nix.
-pub fn get_nix_config(flake_dir: &Path, warn_unsupported: bool) -> serde_json::Value {
-    use std::collections::HashSet;
-
-    let supported_options: HashSet<&str> =
-        ["bash-prompt", "bash-prompt-prefix", "bash-prompt-suffix"]
-            .into_iter()
-            .collect();
-
-    // First, get all nixConfig attribute names to check for unsupported options
-    let expr_all = format!(
-        "builtins.attrNames ((import {}/flake.nix).nixConfig or {{}})",
-        flake_dir.display()
-    );
-
-    if warn_unsupported {
-        let mut cmd_all = crate::command::NixCommand::new("nix-instantiate");
-        cmd_all.args(["--eval", "--json", "--expr", &expr_all]);
-
-        if let Ok(all_options) = cmd_all.json::<Vec<String>>() {
-            for opt in all_options {
-                if !supported_options.contains(opt.as_str()) {
-                    eprintln!("warning: nixConfig.{} is not supported by trix", opt);
-                }
-            }
-        }
+/// Extract the bash prompt settings from flake.nix's nixConfig.
+///
+/// These only affect the shell trix starts, so they apply without asking;
+/// other settings go through [`nix_config_options`].
+pub fn get_nix_config(flake_dir: &Path) -> serde_json::Value {
+    if crate::safety::check_flake_dir(flake_dir).is_err() {
+        return serde_json::json!({});
     }
-
-    // Now get the supported options
     let nix_dir = crate::nix::get_nix_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
     let expr = format!(
-        "import {}/flake_config.nix {{ flakePath = {}; }}",
-        nix_dir.display(),
-        flake_dir.display()
+        "import ({} + \"/flake_config.nix\") {{ flakePath = {}; }}",
+        crate::nix::nix_path(&nix_dir),
+        crate::nix::nix_path(flake_dir)
     );
 
     let mut cmd = crate::command::NixCommand::new("nix-instantiate");
@@ -361,6 +427,216 @@ pub fn get_nix_config(flake_dir: &Path, warn_unsupported: bool) -> serde_json::V
     }
 }
 
+/// nixConfig settings that only change trix's shell prompt.
+const PROMPT_SETTINGS: &[&str] = &["bash-prompt", "bash-prompt-prefix", "bash-prompt-suffix"];
+
+/// Render a nixConfig value the way nix.conf expects it.
+fn nix_config_value(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(nix_config_value)
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(" ")),
+        _ => None,
+    }
+}
+
+/// nixConfig settings (other than the prompt) that the user trusts, as
+/// `--option NAME VALUE` pairs for nix-build and nix-shell.
+pub fn nix_config_options(flake_dir: &Path) -> Vec<(String, String)> {
+    let canonical = flake_dir
+        .canonicalize()
+        .unwrap_or_else(|_| flake_dir.to_path_buf());
+    if let Some(options) = NIX_CONFIG_OPTIONS_CACHE.get(&canonical) {
+        return options;
+    }
+
+    let expr = format!(
+        "(import ({} + \"/flake.nix\")).nixConfig or {{ }}",
+        crate::nix::nix_path(flake_dir)
+    );
+    let mut cmd = crate::command::NixCommand::new("nix-instantiate");
+    cmd.args(["--eval", "--json", "--strict", "--expr", &expr]);
+    let config = cmd
+        .json::<serde_json::Map<String, serde_json::Value>>()
+        .unwrap_or_default();
+
+    let mut options = Vec::new();
+    for (name, value) in config {
+        if PROMPT_SETTINGS.contains(&name.as_str()) {
+            continue;
+        }
+        let Some(value) = nix_config_value(&value) else {
+            crate::nix::warn(&format!("ignoring nixConfig.{}: unsupported value", name));
+            continue;
+        };
+        if crate::trust::allow(&canonical, &name, &value) {
+            options.push((name, value));
+        }
+    }
+
+    NIX_CONFIG_OPTIONS_CACHE.insert(canonical, options.clone());
+    options
+}
+
+/// Split a flake reference into its base and `?key=value` query parameters.
+///
+/// Parameters keep their original order.
+pub fn split_flake_ref_query(flake_ref: &str) -> (&str, Vec<(String, String)>) {
+    match flake_ref.split_once('?') {
+        Some((base, query)) => {
+            let params = query
+                .split('&')
+                .filter_map(|part| part.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            (base, params)
+        }
+        None => (flake_ref, Vec::new()),
+    }
+}
+
+/// Split an indirect input URL (`nixpkgs`, `flake:nixpkgs/nixos-24.05`,
+/// `nixpkgs?rev=...`) into the registry name and `ref`/`rev` parameters.
+fn split_indirect_input(url: &str) -> Option<(&str, Vec<(String, String)>)> {
+    let (base, mut params) = split_flake_ref_query(url);
+    let base = base.strip_prefix("flake:").unwrap_or(base);
+    let (id, commitish) = match base.split_once('/') {
+        Some((id, commitish)) => (id, Some(commitish)),
+        None => (base, None),
+    };
+    if !is_registry_name(id) {
+        return None;
+    }
+    if let Some(commitish) = commitish {
+        let is_rev = commitish.len() == 40 && commitish.chars().all(|c| c.is_ascii_hexdigit());
+        let key = if is_rev { "rev" } else { "ref" };
+        params.insert(0, (key.to_string(), commitish.to_string()));
+    }
+    Some((id, params))
+}
+
+/// Resolve an indirect input URL of the flake in `flake_dir` to the flake
+/// reference its registry entry points at, or None if it is not a registry name.
+fn resolve_indirect_input(flake_dir: &Path, url: &str) -> Option<String> {
+    let (id, params) = split_indirect_input(url)?;
+    let entry = apply_ref_params(resolve_registry_name(id, true, Some(flake_dir))?, &params);
+    explain(&format!(
+        "input '{}' resolves to {}",
+        url,
+        registry_entry_to_flake_ref(&entry)
+    ));
+    Some(registry_entry_to_flake_ref(&entry))
+}
+
+/// Apply `ref`/`rev` query parameters to a registry entry's target.
+///
+/// A `rev` always wins over a `ref`, since it pins an exact commit. A
+/// `ref` alone replaces any rev the entry is pinned to, so asking for a
+/// branch is not silently answered with the pinned commit.
+fn apply_ref_params(mut entry: RegistryEntry, params: &[(String, String)]) -> RegistryEntry {
+    for (key, value) in params {
+        match key.as_str() {
+            "ref" => entry.git_ref = Some(value.clone()),
+            "rev" => entry.rev = Some(value.clone()),
+            _ => {}
+        }
+    }
+    let has = |name: &str| params.iter().any(|(k, _)| k == name);
+    if has("ref") && !has("rev") {
+        entry.rev = None;
+    }
+    entry
+}
+
+/// Find the flake containing `start`: the nearest ancestor with a flake.nix.
+///
+/// Like nix, the search stops at the top of a git repository. Returns the
+/// flake root and `start` relative to it.
+pub fn find_flake_root(start: &Path) -> Option<(PathBuf, PathBuf)> {
+    for dir in start.ancestors() {
+        if dir.join("flake.nix").exists() {
+            let subdir = start.strip_prefix(dir).ok()?.to_path_buf();
+            return Some((dir.to_path_buf(), subdir));
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+/// Apply a `dir` query parameter to a local flake directory.
+fn local_flake_dir(dir: PathBuf, params: &[(String, String)]) -> PathBuf {
+    match params.iter().find(|(k, _)| k == "dir") {
+        Some((_, sub)) => dir.join(sub),
+        None => dir,
+    }
+}
+
+/// Bytes percent-encoded in the path of a `path:` or `git+file://` URL,
+/// besides every byte that is not ASCII.
+const URL_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// `path` as it goes after `path:` or `git+file://` in a flake URL.
+///
+/// Works on the raw bytes, so paths that are not valid UTF-8 survive.
+pub fn url_path(path: &Path) -> String {
+    percent_encoding::percent_encode(path.as_os_str().as_bytes(), URL_PATH).to_string()
+}
+
+/// The path a `path:` or `git+file://` URL refers to; the inverse of [`url_path`].
+pub fn path_from_url(path: &str) -> PathBuf {
+    let bytes: Vec<u8> = percent_encoding::percent_decode_str(path).collect();
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+/// Turn a local flake directory plus `ref`/`rev` parameters into a git ref.
+///
+/// trix can only evaluate the working tree of a local flake, so selecting
+/// a branch or revision is handed to nix as a `git+file://` reference.
+/// nix wants the repository root there, so a flake in a subdirectory of
+/// the repository gets a `?dir=` parameter.
+fn local_ref_with_params(dir: &Path, params: &[(String, String)]) -> Option<String> {
+    if !params.iter().any(|(k, _)| k == "ref" || k == "rev") {
+        return None;
+    }
+    let mut query: Vec<String> = params
+        .iter()
+        .filter(|(k, _)| k != "dir")
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+
+    let repo = dir.ancestors().find(|d| d.join(".git").exists());
+    let (repo, subdir) = match repo.and_then(|r| Some((r, dir.strip_prefix(r).ok()?))) {
+        Some((repo, subdir)) => (repo, url_path(subdir)),
+        None => (dir, String::new()),
+    };
+    if !subdir.is_empty() {
+        query.push(format!("dir={}", subdir));
+    }
+    Some(format!("git+file://{}?{}", url_path(repo), query.join("&")))
+}
+
 /// Resolve an installable reference, handling registry lookups.
 ///
 /// This function determines whether an installable is:
@@ -369,18 +645,64 @@ pub fn get_nix_config(flake_dir: &Path, warn_unsupported: bool) -> serde_json::V
 /// 3. A registry name (nixpkgs, home-manager) - resolved via registry
 pub fn resolve_installable(installable: &str) -> ResolvedInstallable {
     // Parse the installable to separate path/ref part from attribute
+    let implicit_attr = !installable.contains('#');
     let (ref_part, attr_part) = if let Some((r, a)) = installable.split_once('#') {
         (r, a.to_string())
     } else {
+        explain("no '#' in installable, using attribute 'default'");
         (installable, "default".to_string())
     };
+    explain(&format!(
+        "installable '{}': flake ref '{}', attribute '{}'",
+        installable, ref_part, attr_part
+    ));
+
+    // Query parameters (?ref=, ?rev=) are handled once here for every command
+    let full_ref_part = ref_part;
+    let (ref_part, params) = split_flake_ref_query(ref_part);
 
     // Case 1: Empty or current directory
     if ref_part.is_empty() || ref_part == "." {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let (dir, attr_part) = match find_flake_root(&cwd) {
+            // Invoked from inside a flake: subprojects default to their own
+            // package if the flake has one by that name
+            Some((root, subdir)) if !subdir.as_os_str().is_empty() => {
+                let attr_part = match subdir.file_name() {
+                    Some(name) if implicit_attr => {
+                        let name = join_attr_path(&[name.to_string_lossy()]);
+                        if crate::nix::flake_attr_exists(&root, &name).unwrap_or(false) {
+                            name
+                        } else {
+                            explain(&format!(
+                                "the flake has no attribute '{}', using 'default'",
+                                name
+                            ));
+                            attr_part
+                        }
+                    }
+                    _ => attr_part,
+                };
+                explain(&format!(
+                    "no flake.nix in the current directory, using the flake at {} \
+                     (subdirectory '{}', attribute '{}')",
+                    root.display(),
+                    subdir.display(),
+                    attr_part
+                ));
+                (root, attr_part)
+            }
+            _ => (cwd, attr_part),
+        };
+        let dir = local_flake_dir(dir, &params);
+        if let Some(flake_ref) = local_ref_with_params(&dir, &params) {
+            return remote_with_params(attr_part, flake_ref);
+        }
+        explain("flake ref is the current directory (local)");
         return ResolvedInstallable {
             is_local: true,
             attr_part,
-            flake_dir: Some(std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."))),
+            flake_dir: Some(dir),
             flake_ref: None,
         };
     }
@@ -392,15 +714,19 @@ pub fn resolve_installable(installable: &str) -> ResolvedInstallable {
         || ref_part.starts_with('~')
         || ref_part.starts_with("path:")
     {
-        let path = if let Some(rest) = ref_part.strip_prefix("path:") {
-            rest
-        } else {
-            ref_part
+        let expanded = match ref_part.strip_prefix("path:") {
+            Some(rest) => path_from_url(&shellexpand::tilde(rest)),
+            None => PathBuf::from(shellexpand::tilde(ref_part).to_string()),
         };
-        let expanded = shellexpand::tilde(path).to_string();
-        let resolved = PathBuf::from(&expanded)
-            .canonicalize()
-            .unwrap_or_else(|_| PathBuf::from(expanded));
+        let resolved = expanded.canonicalize().unwrap_or(expanded);
+        let resolved = local_flake_dir(resolved, &params);
+        if let Some(flake_ref) = local_ref_with_params(&resolved, &params) {
+            return remote_with_params(attr_part, flake_ref);
+        }
+        explain(&format!(
+            "flake ref is an explicit path, skipping registry lookup: {} (local)",
+            resolved.display()
+        ));
 
         return ResolvedInstallable {
             is_local: true,
@@ -412,18 +738,24 @@ pub fn resolve_installable(installable: &str) -> ResolvedInstallable {
 
     // Case 3: Full flake reference (github:, git+, etc.)
     if ref_part.contains(':') {
+        explain(&format!(
+            "flake ref '{}' is a full URL, skipping registry lookup (remote, passed to nix)",
+            full_ref_part
+        ));
         return ResolvedInstallable {
             is_local: false,
             attr_part,
             flake_dir: None,
-            flake_ref: Some(ref_part.to_string()),
+            flake_ref: Some(full_ref_part.to_string()),
         };
     }
 
     // Case 4: Registry name (e.g., "nixpkgs", "home-manager")
     if is_registry_name(ref_part) {
         tracing::debug!("Looking up '{}' in flake registries...", ref_part);
-        if let Some(entry) = resolve_registry_name(ref_part, true) {
+        let cwd = std::env::current_dir().ok();
+        if let Some(entry) = resolve_registry_name(ref_part, true, cwd.as_deref()) {
+            let entry = apply_ref_params(entry, &params);
             tracing::debug!(
                 "Found '{}' in registry: type={}, path={:?}",
                 ref_part,
@@ -437,6 +769,15 @@ pub fn resolve_installable(installable: &str) -> ResolvedInstallable {
                 let resolved = PathBuf::from(&expanded)
                     .canonicalize()
                     .unwrap_or_else(|_| PathBuf::from(expanded));
+                let resolved = local_flake_dir(resolved, &params);
+                if let Some(flake_ref) = local_ref_with_params(&resolved, &params) {
+                    return remote_with_params(attr_part, flake_ref);
+                }
+                explain(&format!(
+                    "registry entry '{}' points at path {} (local)",
+                    ref_part,
+                    resolved.display()
+                ));
 
                 return ResolvedInstallable {
                     is_local: true,
@@ -452,6 +793,10 @@ pub fn resolve_installable(installable: &str) -> ResolvedInstallable {
                     ref_part,
                     flake_ref
                 );
+                explain(&format!(
+                    "registry entry '{}' resolved to {} (remote, passed to nix)",
+                    ref_part, flake_ref
+                ));
                 return ResolvedInstallable {
                     is_local: false,
                     attr_part,
@@ -462,11 +807,15 @@ pub fn resolve_installable(installable: &str) -> ResolvedInstallable {
         } else {
             // Registry name not found - still try as remote ref
             tracing::debug!("'{}' not found in any registry", ref_part);
+            explain(&format!(
+                "'{}' not found in any registry, passing it to nix as-is (remote)",
+                ref_part
+            ));
             return ResolvedInstallable {
                 is_local: false,
                 attr_part,
                 flake_dir: None,
-                flake_ref: Some(ref_part.to_string()),
+                flake_ref: Some(full_ref_part.to_string()),
             };
         }
     }
@@ -475,6 +824,15 @@ pub fn resolve_installable(installable: &str) -> ResolvedInstallable {
     let resolved = PathBuf::from(ref_part)
         .canonicalize()
         .unwrap_or_else(|_| PathBuf::from(ref_part));
+    let resolved = local_flake_dir(resolved, &params);
+    if let Some(flake_ref) = local_ref_with_params(&resolved, &params) {
+        return remote_with_params(attr_part, flake_ref);
+    }
+    explain(&format!(
+        "'{}' is not a URL or registry name, treating it as path {} (local)",
+        ref_part,
+        resolved.display()
+    ));
 
     ResolvedInstallable {
         is_local: true,
@@ -484,9 +842,108 @@ pub fn resolve_installable(installable: &str) -> ResolvedInstallable {
     }
 }
 
+/// A local flake selected at a specific ref/rev, handed to nix.
+fn remote_with_params(attr_part: String, flake_ref: String) -> ResolvedInstallable {
+    explain(&format!(
+        "local flake with ref/rev parameters, passed to nix as {} (remote)",
+        flake_ref
+    ));
+    ResolvedInstallable {
+        is_local: false,
+        attr_part,
+        flake_dir: None,
+        flake_ref: Some(flake_ref),
+    }
+}
+
 /// Check if a string looks like a Nix system identifier (e.g., x86_64-linux).
-fn looks_like_system(s: &str) -> bool {
-    s.contains('-')
+pub fn looks_like_system(s: &str) -> bool {
+    const KERNELS: &[&str] = &[
+        "linux", "darwin", "freebsd", "netbsd", "openbsd", "cygwin", "windows", "wasi", "none",
+    ];
+    match s.split_once('-') {
+        Some((arch, kernel)) => !arch.is_empty() && KERNELS.contains(&kernel),
+        None => false,
+    }
+}
+
+/// Split a dotted attribute path into its components.
+///
+/// Components may be double-quoted to contain dots, matching Nix syntax:
+///     packages.x86_64-linux."weird.name" -> ["packages", "x86_64-linux", "weird.name"]
+/// Inside quotes, a backslash escapes the next character as in a Nix
+/// string (`\"`, `\\`, `\$`, `\n`, `\t`, `\r`). Empty components are dropped.
+pub fn split_attr_path(attr: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = attr.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                // Quoted component: read up to the closing quote
+                while let Some(q) = chars.next() {
+                    match q {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => current.push('\n'),
+                            Some('t') => current.push('\t'),
+                            Some('r') => current.push('\r'),
+                            Some(e) => current.push(e),
+                            None => current.push('\\'),
+                        },
+                        _ => current.push(q),
+                    }
+                }
+            }
+            '.' => {
+                if !current.is_empty() {
+                    parts.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    parts
+}
+
+/// Whether `name` can be written unquoted in a Nix attribute path.
+fn is_nix_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-'))
+}
+
+/// Join attribute path components, quoting and escaping those that are
+/// not plain Nix identifiers.
+///
+/// This is the inverse of `split_attr_path`.
+pub fn join_attr_path<S: AsRef<str>>(parts: &[S]) -> String {
+    parts
+        .iter()
+        .map(|p| {
+            let p = p.as_ref();
+            if is_nix_identifier(p) {
+                p.to_string()
+            } else {
+                let escaped = p
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace("${", "\\${")
+                    .replace('\n', "\\n")
+                    .replace('\t', "\\t")
+                    .replace('\r', "\\r");
+                format!("\"{}\"", escaped)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
 }
 
 /// Build full attribute path with system.
@@ -515,49 +972,329 @@ pub fn resolve_attr_path(attr_part: &str, default_category: &str, system: &str)
         "self",
     ];
 
+    let parts = split_attr_path(attr_part);
+
     // Simple name like "hello" or "default" - most common case
     // Empty attr_part (from ".#") defaults to "default"
-    if !attr_part.contains('.') {
-        let name = if attr_part.is_empty() {
-            "default"
-        } else {
-            attr_part
-        };
-        return format!("{}.{}.{}", default_category, system, name);
+    if parts.len() <= 1 {
+        let name = parts.first().map(|p| p.as_str()).unwrap_or("default");
+        let full = join_attr_path(&[default_category, system, name]);
+        explain(&format!(
+            "attribute '{}' is a bare name, expanded with default category '{}': {}",
+            attr_part, default_category, full
+        ));
+        return full;
     }
 
-    let parts: Vec<&str> = attr_part.split('.').collect();
-    let first = parts[0];
+    let first = parts[0].as_str();
 
     // Top-level outputs don't need system prefix
     if top_level_categories.contains(&first) {
-        return attr_part.to_string();
+        let full = join_attr_path(&parts);
+        explain(&format!(
+            "'{}' is a top-level output, no system inserted: {}",
+            first, full
+        ));
+        return full;
     }
 
     // Per-system category (packages, devShells, etc.)
     if per_system_categories.contains(&first) {
         // Check if system is already present
-        if parts.len() >= 3 && looks_like_system(parts[1]) {
-            return attr_part.to_string();
+        if parts.len() >= 3 && looks_like_system(&parts[1]) {
+            let full = join_attr_path(&parts);
+            explain(&format!(
+                "'{}' already names system '{}': {}",
+                first, parts[1], full
+            ));
+            return full;
         }
         // Insert system: "packages.foo" -> "packages.{system}.foo"
-        return format!("{}.{}.{}", first, system, parts[1..].join("."));
+        let mut full = vec![first, system];
+        full.extend(parts[1..].iter().map(|p| p.as_str()));
+        let full = join_attr_path(&full);
+        explain(&format!(
+            "'{}' is a per-system output, inserted system '{}': {}",
+            first, system, full
+        ));
+        return full;
     }
 
     // Unknown first component with dots - pass through as-is
-    attr_part.to_string()
+    let full = join_attr_path(&parts);
+    explain(&format!(
+        "'{}' is not a known output category, using the path as-is: {}",
+        first, full
+    ));
+    full
+}
+
+/// Format the error shown when an installable's attribute cannot be found.
+///
+/// With suggestions, prints "did you mean" hints built from `attr_part`;
+/// otherwise lists the candidate attribute paths that were tried.
+pub fn format_attribute_not_found_error(
+    attr_part: &str,
+    tried: &[String],
+    suggestions: &[String],
+) -> String {
+    let name = if attr_part.is_empty() {
+        "default"
+    } else {
+        attr_part
+    };
+    let mut msg = format!("attribute '{}' not found in flake outputs", name);
+
+    if suggestions.is_empty() {
+        msg.push_str("\ntried:");
+        for path in tried {
+            msg.push_str(&format!("\n  {}", path));
+        }
+        return msg;
+    }
+
+    // Replace the last component of what the user typed with each suggestion
+    let mut parts = split_attr_path(name);
+    parts.pop();
+    let hints: Vec<String> = suggestions
+        .iter()
+        .map(|s| {
+            let mut path = parts.clone();
+            path.push(s.clone());
+            format!("`.#{}`", join_attr_path(&path))
+        })
+        .collect();
+    msg.push_str(&format!("\ndid you mean {}?", hints.join(" or ")));
+
+    msg
+}
+
+/// Output names understood by nix and the standard flake-schemas set.
+const KNOWN_FLAKE_OUTPUTS: &[&str] = &[
+    "apps",
+    "bundlers",
+    "checks",
+    "darwinConfigurations",
+    "darwinModules",
+    "defaultApp",
+    "defaultBundler",
+    "defaultPackage",
+    "defaultTemplate",
+    "devShell",
+    "devShells",
+    "flakeModules",
+    "formatter",
+    "homeConfigurations",
+    "homeManagerModules",
+    "homeModules",
+    "hydraJobs",
+    "legacyPackages",
+    "lib",
+    "nixosConfigurations",
+    "nixosModule",
+    "nixosModules",
+    "overlay",
+    "overlays",
+    "packages",
+    "schemas",
+    "templates",
+];
+
+/// Outputs whose first level of nesting must be a system name.
+const PER_SYSTEM_OUTPUTS: &[&str] = &[
+    "apps",
+    "bundlers",
+    "checks",
+    "defaultApp",
+    "defaultBundler",
+    "defaultPackage",
+    "devShell",
+    "devShells",
+    "formatter",
+    "legacyPackages",
+    "packages",
+];
+
+/// Outputs that are keyed by name and must not be nested under a system.
+const NON_SYSTEM_OUTPUTS: &[&str] = &[
+    "darwinConfigurations",
+    "darwinModules",
+    "flakeModules",
+    "homeConfigurations",
+    "homeManagerModules",
+    "homeModules",
+    "nixosConfigurations",
+    "nixosModules",
+    "overlays",
+    "templates",
+];
+
+/// Well-known system doubles, used to spot misplaced per-system nesting.
+const KNOWN_SYSTEMS: &[&str] = &[
+    "aarch64-darwin",
+    "aarch64-linux",
+    "armv6l-linux",
+    "armv7l-linux",
+    "i686-linux",
+    "powerpc64le-linux",
+    "riscv64-linux",
+    "x86_64-darwin",
+    "x86_64-freebsd",
+    "x86_64-linux",
+];
+
+/// Validate the shape of flake outputs against the known output schemas.
+///
+/// `outputs` is the structure returned by `eval_flake_outputs`, and
+/// `extra_known` holds output names declared by the flake's own `schemas`
+/// output. Returns a list of human-readable warnings.
+pub fn validate_output_schema(outputs: &serde_json::Value, extra_known: &[String]) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let Some(outputs) = outputs.as_object() else {
+        return warnings;
+    };
+
+    let mut names: Vec<&String> = outputs.keys().collect();
+    names.sort();
+
+    for name in names {
+        let is_known =
+            KNOWN_FLAKE_OUTPUTS.contains(&name.as_str()) || extra_known.iter().any(|k| k == name);
+
+        if !is_known {
+            let candidates = KNOWN_FLAKE_OUTPUTS
+                .iter()
+                .copied()
+                .chain(extra_known.iter().map(|s| s.as_str()));
+            match crate::common::closest_matches(name, candidates).first() {
+                Some(suggestion) => warnings.push(format!(
+                    "unknown flake output '{}'; did you mean '{}'?",
+                    name, suggestion
+                )),
+                None => warnings.push(format!("unknown flake output '{}'", name)),
+            }
+            continue;
+        }
+
+        // Skip markers like _unknown / _omitted from eval_category.nix
+        let Some(children) = outputs[name].as_object() else {
+            continue;
+        };
+        let mut keys: Vec<&String> = children.keys().filter(|k| !k.starts_with('_')).collect();
+        keys.sort();
+
+        if PER_SYSTEM_OUTPUTS.contains(&name.as_str()) {
+            for key in keys {
+                if !looks_like_system(key) {
+                    warnings.push(format!(
+                        "'{}.{}' is not a system; '{}' must be nested as {}.<system>.<name>",
+                        name, key, name, name
+                    ));
+                }
+            }
+        } else if NON_SYSTEM_OUTPUTS.contains(&name.as_str()) {
+            for key in keys {
+                if KNOWN_SYSTEMS.contains(&key.as_str()) {
+                    warnings.push(format!(
+                        "'{}.{}' looks like a system, but '{}' is not a per-system output",
+                        name, key, name
+                    ));
+                }
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Matches a set-pattern `outputs` function, with an optional `name@` on either side.
+static OUTPUTS_FORMALS_REGEX: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(
+    || {
+        regex::Regex::new(
+            r"(?m)^\s*outputs\s*=\s*(?:[A-Za-z_][\w'-]*\s*@\s*)?\{([^{}]*)\}\s*(?:@\s*[A-Za-z_][\w'-]*\s*)?:",
+        )
+        .unwrap()
+    },
+);
+
+/// Parse the argument names of the `outputs` function in flake.nix source,
+/// and whether it takes `...`.
+///
+/// Returns None when `outputs` is not a plain set pattern we can read
+/// (e.g. `outputs = inputs: ...`, or defaults containing braces).
+fn outputs_formals(content: &str) -> Option<(Vec<String>, bool)> {
+    let body = OUTPUTS_FORMALS_REGEX.captures(content)?.get(1)?.as_str();
+    // Drop comments so commas in them don't split formals
+    let body: String = body
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut names = Vec::new();
+    let mut ellipsis = false;
+    for formal in body.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        if formal == "..." {
+            ellipsis = true;
+        } else {
+            names.push(formal.split('?').next()?.trim().to_string());
+        }
+    }
+    Some((names, ellipsis))
+}
+
+/// Fail early, with a clear message, when flake.nix declares inputs that
+/// its `outputs` function cannot accept.
+///
+/// trix, like nix, passes every locked input to `outputs`. Without `...`
+/// an input missing from the argument list makes nix fail with a bare
+/// "called with unexpected argument". Optional arguments (`foo ? null`)
+/// that are not declared inputs are fine; their default applies.
+fn check_outputs_formals(flake_dir: &Path, inputs: &serde_json::Value) -> Result<()> {
+    let Ok(content) = std::fs::read_to_string(flake_dir.join("flake.nix")) else {
+        return Ok(());
+    };
+    let Some((formals, ellipsis)) = outputs_formals(&content) else {
+        return Ok(());
+    };
+    if ellipsis {
+        return Ok(());
+    }
+
+    let unaccepted: Vec<String> = inputs
+        .as_object()
+        .into_iter()
+        .flat_map(|m| m.keys())
+        .filter(|name| !formals.contains(name))
+        .map(|name| format!("'{}'", name))
+        .collect();
+    if !unaccepted.is_empty() {
+        anyhow::bail!(
+            "flake.nix declares input(s) {} that its outputs function does not take.\n\
+             Add them to the arguments of outputs, or add '...' to accept all inputs \
+             (e.g. 'outputs = inputs@{{ self, ... }}:')",
+            unaccepted.join(", ")
+        );
+    }
+    Ok(())
 }
 
 /// Ensure flake.lock exists with locked versions of flake inputs.
 pub fn ensure_lock(flake_dir: &Path, inputs: Option<serde_json::Value>) -> Result<()> {
     use crate::lock::ensure_lock as lock_inputs;
 
+    // Before anything imports flake.nix
+    crate::safety::check_flake_dir(flake_dir)?;
+
     // Get input names from flake.nix if not provided
     let inputs = match inputs {
         Some(i) => i,
         None => get_flake_inputs(flake_dir)?,
     };
 
+    check_outputs_formals(flake_dir, &inputs)?;
+
     if inputs.as_object().map(|m| m.is_empty()).unwrap_or(true) {
         // No inputs at all - skip entirely
         return Ok(());
@@ -575,6 +1312,80 @@ pub fn ensure_lock(flake_dir: &Path, inputs: Option<serde_json::Value>) -> Resul
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_split_indirect_input() {
+        let params = |v: &[(&str, &str)]| {
+            v.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(split_indirect_input("nixpkgs"), Some(("nixpkgs", vec![])));
+        assert_eq!(
+            split_indirect_input("flake:nixpkgs/nixos-24.05"),
+            Some(("nixpkgs", params(&[("ref", "nixos-24.05")])))
+        );
+        let rev = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(
+            split_indirect_input(&format!("nixpkgs/{}", rev)),
+            Some(("nixpkgs", params(&[("rev", rev)])))
+        );
+        assert_eq!(
+            split_indirect_input("nixpkgs?ref=main"),
+            Some(("nixpkgs", params(&[("ref", "main")])))
+        );
+        assert_eq!(split_indirect_input("github:NixOS/nixpkgs"), None);
+        assert_eq!(split_indirect_input("./vendor"), None);
+    }
+
+    #[test]
+    fn test_outputs_formals() {
+        let names = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            outputs_formals("{\n  outputs = { self, nixpkgs }: { };\n}"),
+            Some((names(&["self", "nixpkgs"]), false))
+        );
+        assert_eq!(
+            outputs_formals(
+                "{\n  outputs =\n    inputs@{\n      self,\n      foo ? null, # optional, may be absent\n      ...\n    }:\n    { };\n}"
+            ),
+            Some((names(&["self", "foo"]), true))
+        );
+        assert_eq!(
+            outputs_formals("{\n  outputs = { self, bar ? \"x\" }@inputs: { };\n}"),
+            Some((names(&["self", "bar"]), false))
+        );
+        assert_eq!(outputs_formals("{\n  outputs = inputs: { };\n}"), None);
+    }
+
+    #[test]
+    fn test_check_outputs_formals() {
+        use serde_json::json;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("flake.nix"),
+            "{\n  inputs.nixpkgs.url = \"github:NixOS/nixpkgs\";\n  outputs = { self, foo ? null }: { };\n}",
+        )
+        .unwrap();
+
+        let err = check_outputs_formals(dir.path(), &json!({ "nixpkgs": {} })).unwrap_err();
+        assert!(err.to_string().contains("'nixpkgs'"));
+        assert!(check_outputs_formals(dir.path(), &json!({ "foo": {} })).is_ok());
+        assert!(check_outputs_formals(dir.path(), &json!({})).is_ok());
+    }
+
+    #[test]
+    fn test_nix_config_value() {
+        use serde_json::json;
+        assert_eq!(
+            nix_config_value(&json!(["https://a.cachix.org", "https://b"])),
+            Some("https://a.cachix.org https://b".to_string())
+        );
+        assert_eq!(nix_config_value(&json!(true)), Some("true".to_string()));
+        assert_eq!(nix_config_value(&json!(4)), Some("4".to_string()));
+        assert_eq!(nix_config_value(&json!({ "a": 1 })), None);
+    }
+
     #[test]
     fn test_parse_flake_url_github() {
         let res = parse_flake_url("github:NixOS/nixpkgs");
@@ -700,6 +1511,82 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_split_attr_path() {
+        assert_eq!(split_attr_path(""), Vec::<String>::new());
+        assert_eq!(split_attr_path("hello"), vec!["hello"]);
+        assert_eq!(
+            split_attr_path("legacyPackages.x86_64-linux.python3Packages.requests"),
+            vec![
+                "legacyPackages",
+                "x86_64-linux",
+                "python3Packages",
+                "requests"
+            ]
+        );
+        assert_eq!(
+            split_attr_path("packages.x86_64-linux.\"weird.name\""),
+            vec!["packages", "x86_64-linux", "weird.name"]
+        );
+        assert_eq!(split_attr_path("a..b."), vec!["a", "b"]);
+        assert_eq!(
+            split_attr_path(r#"a."say \"hi\"".b"#),
+            vec!["a", "say \"hi\"", "b"]
+        );
+        assert_eq!(
+            split_attr_path(r#""back\\slash"."\${x}.y""#),
+            vec!["back\\slash", "${x}.y"]
+        );
+    }
+
+    #[test]
+    fn test_join_attr_path() {
+        assert_eq!(
+            join_attr_path(&["packages", "x86_64-linux"]),
+            "packages.x86_64-linux"
+        );
+        assert_eq!(
+            join_attr_path(&["packages", "x86_64-linux", "weird.name"]),
+            "packages.x86_64-linux.\"weird.name\""
+        );
+        assert_eq!(
+            join_attr_path(&["a", "with space", "say \"hi\"", "${x}", "0", "it's-ok"]),
+            r#"a."with space"."say \"hi\""."\${x}"."0".it's-ok"#
+        );
+        for parts in [
+            vec!["packages", "x86_64-linux", "weird.name"],
+            vec!["a b", "c\\d", "e\"f", "${g}", "h\ni", "1"],
+        ] {
+            assert_eq!(split_attr_path(&join_attr_path(&parts)), parts);
+        }
+    }
+
+    #[test]
+    fn test_resolve_attr_path_quoted() {
+        assert_eq!(
+            resolve_attr_path("\"weird.name\"", "packages", "x86_64-linux"),
+            "packages.x86_64-linux.\"weird.name\""
+        );
+        assert_eq!(
+            resolve_attr_path("packages.\"weird.name\"", "packages", "x86_64-linux"),
+            "packages.x86_64-linux.\"weird.name\""
+        );
+        // Deep legacyPackages paths keep every component
+        assert_eq!(
+            resolve_attr_path(
+                "legacyPackages.python3Packages.requests",
+                "packages",
+                "x86_64-linux"
+            ),
+            "legacyPackages.x86_64-linux.python3Packages.requests"
+        );
+        // Dashed package names are not mistaken for systems
+        assert_eq!(
+            resolve_attr_path("packages.foo-bar.baz", "packages", "x86_64-linux"),
+            "packages.x86_64-linux.foo-bar.baz"
+        );
+    }
+
     #[test]
     fn test_parse_flake_url_sourcehut() {
         let res = parse_flake_url("sourcehut:~user/repo");
@@ -780,6 +1667,225 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_split_flake_ref_query() {
+        let (base, params) = split_flake_ref_query("github:NixOS/nixpkgs?ref=nixos-24.05&rev=abc");
+        assert_eq!(base, "github:NixOS/nixpkgs");
+        assert_eq!(
+            params,
+            vec![
+                ("ref".to_string(), "nixos-24.05".to_string()),
+                ("rev".to_string(), "abc".to_string())
+            ]
+        );
+
+        let (base, params) = split_flake_ref_query("./foo");
+        assert_eq!(base, "./foo");
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_installable_remote_keeps_query() {
+        let resolved = resolve_installable("github:NixOS/nixpkgs?ref=release-24.05#hello");
+        assert!(!resolved.is_local);
+        assert_eq!(resolved.attr_part, "hello");
+        assert_eq!(
+            resolved.full_ref(),
+            "github:NixOS/nixpkgs?ref=release-24.05#hello"
+        );
+    }
+
+    #[test]
+    fn test_resolve_installable_local_with_ref() {
+        let dir = tempfile::tempdir().unwrap();
+        let canonical = dir.path().canonicalize().unwrap();
+
+        let resolved = resolve_installable(&format!("{}?ref=main#hello", dir.path().display()));
+        assert!(!resolved.is_local);
+        assert_eq!(
+            resolved.full_ref(),
+            format!("git+file://{}?ref=main#hello", canonical.display())
+        );
+
+        // Without ref/rev the flake stays local
+        let resolved = resolve_installable(&format!("{}#hello", dir.path().display()));
+        assert!(resolved.is_local);
+        assert_eq!(resolved.flake_dir, Some(canonical));
+    }
+
+    #[test]
+    fn test_find_flake_root() {
+        let repo = tempfile::tempdir().unwrap();
+        let root = repo.path().join("mono");
+        let sub = root.join("pkgs").join("tool");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::create_dir(repo.path().join(".git")).unwrap();
+        std::fs::write(root.join("flake.nix"), "{ }").unwrap();
+
+        assert_eq!(
+            find_flake_root(&sub),
+            Some((root.clone(), PathBuf::from("pkgs/tool")))
+        );
+        assert_eq!(find_flake_root(&root), Some((root.clone(), PathBuf::new())));
+
+        // The search stops at the repository root
+        std::fs::remove_file(root.join("flake.nix")).unwrap();
+        assert_eq!(find_flake_root(&sub), None);
+    }
+
+    #[test]
+    fn test_url_path() {
+        use std::os::unix::ffi::OsStrExt;
+        assert_eq!(url_path(Path::new("/src/my flake")), "/src/my%20flake");
+        assert_eq!(url_path(Path::new("/src/a#b?c%d")), "/src/a%23b%3Fc%25d");
+        assert_eq!(url_path(Path::new("/src/café")), "/src/caf%C3%A9");
+        let raw = Path::new(std::ffi::OsStr::from_bytes(b"/src/\xff dir"));
+        assert_eq!(url_path(raw), "/src/%FF%20dir");
+
+        for path in [Path::new("/src/my flake"), Path::new("/src/a#b?c%d"), raw] {
+            assert_eq!(path_from_url(&url_path(path)), path);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let spaced = dir.path().canonicalize().unwrap().join("my flake");
+        std::fs::create_dir(&spaced).unwrap();
+        let resolved = resolve_installable(&format!("path:{}#hello", url_path(&spaced)));
+        assert_eq!(resolved.flake_dir, Some(spaced.clone()));
+        let resolved = resolve_installable(&format!("{}?ref=main#hello", spaced.display()));
+        assert_eq!(
+            resolved.full_ref(),
+            format!("git+file://{}?ref=main#hello", url_path(&spaced))
+        );
+    }
+
+    #[test]
+    fn test_local_ref_with_dir() {
+        let repo = tempfile::tempdir().unwrap();
+        let repo_path = repo.path().canonicalize().unwrap();
+        std::fs::create_dir(repo_path.join(".git")).unwrap();
+        std::fs::create_dir_all(repo_path.join("sub").join("flake")).unwrap();
+
+        let resolved = resolve_installable(&format!("{}/sub?ref=main#hello", repo_path.display()));
+        assert_eq!(
+            resolved.full_ref(),
+            format!("git+file://{}?ref=main&dir=sub#hello", repo_path.display())
+        );
+
+        let resolved = resolve_installable(&format!(
+            "{}?dir=sub/flake&rev=abc#hello",
+            repo_path.display()
+        ));
+        assert_eq!(
+            resolved.full_ref(),
+            format!(
+                "git+file://{}?rev=abc&dir=sub/flake#hello",
+                repo_path.display()
+            )
+        );
+
+        // Without ref/rev, ?dir= selects the local flake directly
+        let resolved = resolve_installable(&format!("{}?dir=sub#hello", repo_path.display()));
+        assert!(resolved.is_local);
+        assert_eq!(resolved.flake_dir, Some(repo_path.join("sub")));
+    }
+
+    #[test]
+    fn test_apply_ref_params() {
+        let entry = RegistryEntry {
+            entry_type: "github".to_string(),
+            path: None,
+            owner: Some("NixOS".to_string()),
+            repo: Some("nixpkgs".to_string()),
+            git_ref: Some("nixpkgs-unstable".to_string()),
+            rev: None,
+            url: None,
+        };
+        let entry = apply_ref_params(entry, &[("ref".to_string(), "nixos-24.05".to_string())]);
+        assert_eq!(
+            registry_entry_to_flake_ref(&entry),
+            "github:NixOS/nixpkgs/nixos-24.05"
+        );
+
+        // A ref replaces the rev a pinned entry carries
+        let pinned = RegistryEntry {
+            rev: Some("0123456789abcdef0123456789abcdef01234567".to_string()),
+            ..entry.clone()
+        };
+        let entry = apply_ref_params(
+            pinned.clone(),
+            &[("ref".to_string(), "nixos-24.11".to_string())],
+        );
+        assert_eq!(entry.rev, None);
+        assert_eq!(
+            registry_entry_to_flake_ref(&entry),
+            "github:NixOS/nixpkgs/nixos-24.11"
+        );
+
+        let entry = apply_ref_params(
+            pinned,
+            &[
+                ("ref".to_string(), "nixos-24.11".to_string()),
+                ("rev".to_string(), "abc".to_string()),
+            ],
+        );
+        assert_eq!(entry.rev.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn test_format_attribute_not_found_error() {
+        let tried = vec!["packages.x86_64-linux.helo".to_string()];
+
+        let msg = format_attribute_not_found_error("helo", &tried, &["hello".to_string()]);
+        assert_eq!(
+            msg,
+            "attribute 'helo' not found in flake outputs\ndid you mean `.#hello`?"
+        );
+
+        let msg = format_attribute_not_found_error(
+            "python3Packages.reqests",
+            &tried,
+            &["requests".to_string(), "requestsx".to_string()],
+        );
+        assert!(msg.ends_with(
+            "did you mean `.#python3Packages.requests` or `.#python3Packages.requestsx`?"
+        ));
+
+        let msg = format_attribute_not_found_error("xyz", &tried, &[]);
+        assert!(msg.contains("tried:\n  packages.x86_64-linux.helo"));
+    }
+
+    #[test]
+    fn test_validate_output_schema_typo() {
+        let outputs = serde_json::json!({
+            "packgaes": { "x86_64-linux": { "hello": { "_type": "derivation" } } },
+            "myCustomThing": { "_unknown": true },
+        });
+        let warnings = validate_output_schema(&outputs, &[]);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("'myCustomThing'"));
+        assert!(warnings[1].contains("did you mean 'packages'?"));
+
+        // Outputs declared by the flake's own schemas are accepted
+        let warnings = validate_output_schema(&outputs, &["myCustomThing".to_string()]);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_validate_output_schema_nesting() {
+        let outputs = serde_json::json!({
+            "packages": {
+                "x86_64-linux": { "hello": { "_type": "derivation" } },
+                "hello": { "_type": "derivation" },
+            },
+            "nixosModules": { "x86_64-linux": { "_type": "module" }, "default": { "_type": "module" } },
+            "checks": { "_unknown": true },
+        });
+        let warnings = validate_output_schema(&outputs, &[]);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("'nixosModules.x86_64-linux'"));
+        assert!(warnings[1].contains("'packages.hello' is not a system"));
+    }
+
     pub fn parse_installable(installable: &str) -> (std::path::PathBuf, String) {
         let (path_part, attr_part) = if let Some((p, a)) = installable.split_once('#') {
             (p, a.to_string())
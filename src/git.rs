@@ -1,11 +1,20 @@
 use crate::common::Cache;
 use anyhow::{Context, Result};
 use git2::{Repository, StatusOptions};
+use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 
 /// Cache for git info per directory (canonical path -> GitInfo)
 static GIT_INFO_CACHE: Cache<PathBuf, GitInfo> = Cache::new();
 
+/// Whether to warn about dirty trees (`--no-warn-dirty` turns it off)
+static WARN_DIRTY: AtomicBool = AtomicBool::new(true);
+
+/// Trees already warned about as dirty
+static WARNED_DIRTY: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
 /// Git metadata for an input.
 ///
 /// Matches Nix's behavior:
@@ -121,6 +130,101 @@ pub fn get_git_info(path: &Path) -> Result<GitInfo> {
     Ok(info)
 }
 
+/// Set whether dirty trees are warned about, here and by nix (`--no-warn-dirty`).
+pub fn set_warn_dirty(warn: bool) {
+    WARN_DIRTY.store(warn, Ordering::Relaxed);
+}
+
+/// Whether dirty trees are warned about.
+pub fn warn_dirty() -> bool {
+    WARN_DIRTY.load(Ordering::Relaxed)
+}
+
+/// The state of the source tree a local flake lives in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeState {
+    /// Not in a git repository: a path flake, without any revision
+    NotGit,
+    /// A git repository without any commit yet
+    NoCommits(PathBuf),
+    /// Uncommitted changes to tracked files in the repository at the path
+    Dirty(PathBuf),
+    Clean(PathBuf),
+}
+
+impl TreeState {
+    /// The warning nix gives for a flake in this tree, if any.
+    pub fn warning(&self) -> Option<String> {
+        match self {
+            TreeState::NotGit => None,
+            TreeState::NoCommits(dir) => Some(format!(
+                "Git tree '{}' has no commits, so the flake has no revision",
+                dir.display()
+            )),
+            TreeState::Dirty(dir) => Some(format!("Git tree '{}' is dirty", dir.display())),
+            TreeState::Clean(_) => None,
+        }
+    }
+}
+
+/// Find out whether `path` is in a clean, dirty or empty git tree, or none.
+pub fn tree_state(path: &Path) -> TreeState {
+    let Ok(repo) = Repository::discover(path) else {
+        return TreeState::NotGit;
+    };
+    let dir = repo
+        .workdir()
+        .unwrap_or_else(|| repo.path())
+        .components()
+        .collect::<PathBuf>();
+    if repo.head().and_then(|h| h.peel_to_commit()).is_err() {
+        return TreeState::NoCommits(dir);
+    }
+    let dirty = get_git_info(path)
+        .map(|info| info.dirty_rev.is_some())
+        .unwrap_or(false);
+    if dirty {
+        TreeState::Dirty(dir)
+    } else {
+        TreeState::Clean(dir)
+    }
+}
+
+/// Warn once per tree that the flake at `path` is dirty, like nix does
+/// when fetching it, unless turned off with `--no-warn-dirty`.
+pub fn warn_if_dirty(path: &Path) {
+    if !warn_dirty() {
+        return;
+    }
+    let state = tree_state(path);
+    let TreeState::Dirty(dir) = &state else {
+        return;
+    };
+    if WARNED_DIRTY.lock().unwrap().insert(dir.clone()) {
+        crate::nix::warn(&state.warning().unwrap_or_default());
+    }
+}
+
+/// Files under `path` in its repository's index, relative to `path`.
+///
+/// These are the files a flake at `path` sees: tracked or staged, but not
+/// untracked ones. Returns None outside a git repository.
+pub fn tracked_files(path: &Path) -> Option<Vec<PathBuf>> {
+    let repo = Repository::discover(path).ok()?;
+    let workdir = repo.workdir()?.canonicalize().ok()?;
+    let prefix = path.canonicalize().ok()?;
+    let prefix = prefix.strip_prefix(&workdir).ok()?;
+    let index = repo.index().ok()?;
+    let files = index
+        .iter()
+        .filter_map(|entry| {
+            let file = PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned());
+            file.strip_prefix(prefix).ok().map(Path::to_path_buf)
+        })
+        .collect();
+    Some(files)
+}
+
 /// Check if the repository has any submodules.
 fn has_submodules(repo: &Repository) -> bool {
     repo.submodules()
@@ -178,3 +282,66 @@ fn is_repo_dirty_libgit2(repo: &Repository) -> Result<bool> {
 
     Ok(!statuses.is_empty())
 }
+
+/// Look up the commit a remote ref points at, like `git ls-remote`.
+///
+/// Without `git_ref`, the remote's HEAD is used. Nothing is fetched.
+pub fn remote_rev(url: &str, git_ref: Option<&str>) -> Result<String> {
+    let mut remote = git2::Remote::create_detached(url)?;
+    remote
+        .connect(git2::Direction::Fetch)
+        .with_context(|| format!("Failed to connect to {}", url))?;
+
+    let wanted: Vec<String> = match git_ref {
+        None | Some("HEAD") => vec!["HEAD".to_string()],
+        Some(r) if r.starts_with("refs/") => vec![r.to_string()],
+        // Prefer the peeled commit of an annotated tag
+        Some(r) => vec![
+            format!("refs/heads/{}", r),
+            format!("refs/tags/{}^{{}}", r),
+            format!("refs/tags/{}", r),
+        ],
+    };
+    let heads = remote.list()?;
+    wanted
+        .iter()
+        .find_map(|name| heads.iter().find(|h| h.name() == name))
+        .map(|h| h.oid().to_string())
+        .with_context(|| format!("{} has no ref {}", url, git_ref.unwrap_or("HEAD")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_state() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(tree_state(dir.path()), TreeState::NotGit);
+        assert_eq!(TreeState::NotGit.warning(), None);
+
+        let repo = Repository::init(dir.path()).unwrap();
+        let workdir = dir.path().components().collect::<PathBuf>();
+        let state = tree_state(dir.path());
+        assert_eq!(state, TreeState::NoCommits(workdir.clone()));
+        assert!(state.warning().unwrap().contains("has no commits"));
+
+        std::fs::write(dir.path().join("flake.nix"), "{ }").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("flake.nix")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])
+            .unwrap();
+        assert!(matches!(tree_state(dir.path()), TreeState::Clean(_)));
+        assert_eq!(
+            tracked_files(dir.path()).unwrap(),
+            vec![PathBuf::from("flake.nix")]
+        );
+        assert_eq!(
+            TreeState::Dirty(workdir).warning().unwrap(),
+            format!("Git tree '{}' is dirty", dir.path().display())
+        );
+    }
+}
@@ -0,0 +1,449 @@
+//! Hash encodings used by nix.
+//!
+//! Nix prints hashes in its own base-32 alphabet, while flake.lock stores
+//! them in SRI form (`sha256-<base64>`). These helpers convert between the
+//! two, compute store paths and hash files and NAR serialisations,
+//! without calling out to `nix hash`.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha2::Digest;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Nix's base-32 alphabet (omits e, o, u and t).
+const NIX_BASE32_CHARS: &[u8] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+/// Decode a nix base-32 string into bytes.
+pub fn nix_base32_decode(s: &str) -> Option<Vec<u8>> {
+    let chars = s.as_bytes();
+    let size = chars.len() * 5 / 8;
+    let mut bytes = vec![0u8; size];
+
+    for (n, c) in chars.iter().rev().enumerate() {
+        let digit = NIX_BASE32_CHARS.iter().position(|x| x == c)? as u16;
+        let b = n * 5;
+        let (i, j) = (b / 8, b % 8);
+        if i < size {
+            bytes[i] |= (digit << j) as u8;
+        }
+        let carry = digit >> (8 - j);
+        if i + 1 < size {
+            bytes[i + 1] |= carry as u8;
+        } else if carry != 0 {
+            return None;
+        }
+    }
+
+    Some(bytes)
+}
+
+/// Encode bytes in nix's base-32 alphabet.
+pub fn nix_base32_encode(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return String::new();
+    }
+    let len = (bytes.len() * 8 - 1) / 5 + 1;
+    let mut out = String::with_capacity(len);
+
+    for n in (0..len).rev() {
+        let b = n * 5;
+        let (i, j) = (b / 8, b % 8);
+        let low = bytes[i] as u16 >> j;
+        let high = bytes.get(i + 1).map_or(0, |&x| (x as u16) << (8 - j));
+        out.push(NIX_BASE32_CHARS[((low | high) & 0x1f) as usize] as char);
+    }
+
+    out
+}
+
+/// Hash algorithms trix can compute itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlgo {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgo {
+    /// Parse an algorithm name as accepted by `nix hash --type`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha1" => Some(Self::Sha1),
+            "sha256" => Some(Self::Sha256),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+            Self::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            Self::Sha512 => Hasher::Sha512(sha2::Sha512::new()),
+        }
+    }
+}
+
+/// An incremental hasher for any [`HashAlgo`].
+#[derive(Clone)]
+pub enum Hasher {
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha1(h) => h.update(data),
+            Self::Sha256(h) => h.update(data),
+            Self::Sha512(h) => h.update(data),
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        match self {
+            Self::Sha1(h) => h.finalize().to_vec(),
+            Self::Sha256(h) => h.finalize().to_vec(),
+            Self::Sha512(h) => h.finalize().to_vec(),
+        }
+    }
+}
+
+impl std::io::Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Ways of printing a hash, as in `nix hash --base16/--base32/--base64/--sri`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashFormat {
+    Base16,
+    Nix32,
+    Base64,
+    Sri,
+}
+
+/// Print `digest` in `format`.
+pub fn format_hash(algo: HashAlgo, digest: &[u8], format: HashFormat) -> String {
+    match format {
+        HashFormat::Base16 => to_hex(digest),
+        HashFormat::Nix32 => nix_base32_encode(digest),
+        HashFormat::Base64 => BASE64.encode(digest),
+        HashFormat::Sri => format!("{}-{}", algo.name(), BASE64.encode(digest)),
+    }
+}
+
+/// Hash the contents of a regular file.
+pub fn hash_file(path: &Path, algo: HashAlgo) -> io::Result<Vec<u8>> {
+    let mut hasher = algo.hasher();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finish())
+}
+
+/// Hash the NAR serialisation of `path`, like `nix-store --dump`.
+pub fn hash_path(path: &Path, algo: HashAlgo) -> io::Result<Vec<u8>> {
+    let mut hasher = algo.hasher();
+    dump_path(path, &mut hasher)?;
+    Ok(hasher.finish())
+}
+
+/// Write the NAR serialisation of `path` to `out`.
+pub fn dump_path(path: &Path, out: &mut impl io::Write) -> io::Result<()> {
+    nar_str(out, b"nix-archive-1")?;
+    dump_node(path, out)
+}
+
+fn dump_node(path: &Path, out: &mut impl io::Write) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    let file_type = metadata.file_type();
+    nar_str(out, b"(")?;
+
+    if file_type.is_symlink() {
+        nar_str(out, b"type")?;
+        nar_str(out, b"symlink")?;
+        nar_str(out, b"target")?;
+        nar_str(out, fs::read_link(path)?.as_os_str().as_bytes())?;
+    } else if file_type.is_dir() {
+        nar_str(out, b"type")?;
+        nar_str(out, b"directory")?;
+
+        // Entries are ordered by their raw byte names
+        let mut names: Vec<_> = fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.file_name()))
+            .collect::<io::Result<_>>()?;
+        names.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        for name in names {
+            nar_str(out, b"entry")?;
+            nar_str(out, b"(")?;
+            nar_str(out, b"name")?;
+            nar_str(out, name.as_bytes())?;
+            nar_str(out, b"node")?;
+            dump_node(&path.join(&name), out)?;
+            nar_str(out, b")")?;
+        }
+    } else if file_type.is_file() {
+        nar_str(out, b"type")?;
+        nar_str(out, b"regular")?;
+        if metadata.permissions().mode() & 0o100 != 0 {
+            nar_str(out, b"executable")?;
+            nar_str(out, b"")?;
+        }
+        nar_str(out, b"contents")?;
+
+        let size = metadata.len();
+        out.write_all(&size.to_le_bytes())?;
+        let copied = io::copy(&mut fs::File::open(path)?, out)?;
+        if copied != size {
+            return Err(io::Error::other(format!(
+                "{} changed while it was being hashed",
+                path.display()
+            )));
+        }
+        nar_pad(out, size)?;
+    } else {
+        return Err(io::Error::other(format!(
+            "{} has an unsupported file type",
+            path.display()
+        )));
+    }
+
+    nar_str(out, b")")
+}
+
+/// Write a length-prefixed, zero-padded NAR string.
+fn nar_str(out: &mut impl io::Write, s: &[u8]) -> io::Result<()> {
+    out.write_all(&(s.len() as u64).to_le_bytes())?;
+    out.write_all(s)?;
+    nar_pad(out, s.len() as u64)
+}
+
+fn nar_pad(out: &mut impl io::Write, len: u64) -> io::Result<()> {
+    let padding = (8 - len % 8) % 8;
+    out.write_all(&[0u8; 8][..padding as usize])
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Store path of a fetched source with the given SRI sha256 narHash.
+///
+/// This is where `builtins.fetchTarball`, `builtins.fetchGit` and
+/// `nix-store --add` put a source called `name`, so a locked input can be
+/// found in the store without fetching it.
+pub fn source_store_path(store_dir: &str, nar_hash: &str, name: &str) -> Option<String> {
+    let digest = BASE64.decode(nar_hash.strip_prefix("sha256-")?).ok()?;
+    if digest.len() != 32 {
+        return None;
+    }
+
+    let fingerprint = format!("source:sha256:{}:{}:{}", to_hex(&digest), store_dir, name);
+    let hash = sha2::Sha256::digest(fingerprint.as_bytes());
+
+    // Store path hashes are the full hash folded to 160 bits
+    let mut compressed = [0u8; 20];
+    for (i, byte) in hash.iter().enumerate() {
+        compressed[i % 20] ^= byte;
+    }
+
+    Some(format!(
+        "{}/{}-{}",
+        store_dir,
+        nix_base32_encode(&compressed),
+        name
+    ))
+}
+
+/// Convert a nix-style `algo:base32` hash (as printed by `nix-store
+/// --query --hash`) or a bare base-32 sha256 to SRI form.
+pub fn nix_hash_to_sri(hash: &str) -> Option<String> {
+    let (algo, digest) = hash.split_once(':').unwrap_or(("sha256", hash));
+    let bytes = nix_base32_decode(digest.trim())?;
+    Some(format!("{}-{}", algo, BASE64.encode(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nix_base32_roundtrip() {
+        let bytes: Vec<u8> = (0u8..32).map(|i| i.wrapping_mul(37)).collect();
+        let encoded = nix_base32_encode(&bytes);
+        assert_eq!(encoded.len(), 52);
+        assert_eq!(nix_base32_decode(&encoded), Some(bytes));
+
+        assert_eq!(
+            nix_base32_encode(&sha2::Sha256::digest(b"")),
+            "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"
+        );
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            to_hex(&HashAlgo::Sha256.hasher().finish()),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        // Multi-block input fed in uneven pieces
+        let data = vec![b'a'; 1000];
+        let mut hasher = HashAlgo::Sha256.hasher();
+        for chunk in data.chunks(7) {
+            hasher.update(chunk);
+        }
+        assert_eq!(
+            to_hex(&hasher.finish()),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn test_sha1_sha512() {
+        assert_eq!(
+            to_hex(&HashAlgo::Sha1.hasher().finish()),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+        let mut hasher = HashAlgo::Sha1.hasher();
+        hasher.update(b"abc");
+        assert_eq!(
+            to_hex(&hasher.finish()),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+
+        let mut hasher = HashAlgo::Sha512.hasher();
+        hasher.update(b"abc");
+        assert_eq!(
+            to_hex(&hasher.finish()),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+
+        // Crosses the 112-byte padding boundary
+        let mut hasher = HashAlgo::Sha512.hasher();
+        hasher.update(&[b'a'; 1000]);
+        assert_eq!(
+            to_hex(&hasher.finish()),
+            "67ba5535a46e3f86dbfbed8cbbaf0125c76ed549ff8b0b9e03e0c88cf90fa634\
+             fa7b12b47d77b694de488ace8d9a65967dc96df599727d3292a8d9d447709c97"
+        );
+    }
+
+    #[test]
+    fn test_format_hash() {
+        let digest = sha2::Sha256::digest(b"");
+        assert_eq!(
+            format_hash(HashAlgo::Sha256, &digest, HashFormat::Sri),
+            "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+        assert_eq!(
+            format_hash(HashAlgo::Sha256, &digest, HashFormat::Nix32),
+            "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"
+        );
+        assert_eq!(
+            format_hash(HashAlgo::Sha256, &digest, HashFormat::Base64),
+            "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+    }
+
+    #[test]
+    fn test_dump_path() {
+        fn s(out: &mut Vec<u8>, value: &[u8]) {
+            nar_str(out, value).unwrap();
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("b"), "hi").unwrap();
+        std::os::unix::fs::symlink("b", dir.path().join("a")).unwrap();
+
+        let mut expected = Vec::new();
+        for token in [
+            &b"nix-archive-1"[..],
+            b"(",
+            b"type",
+            b"directory",
+            b"entry",
+            b"(",
+            b"name",
+            b"a",
+            b"node",
+            b"(",
+            b"type",
+            b"symlink",
+            b"target",
+            b"b",
+            b")",
+            b")",
+            b"entry",
+            b"(",
+            b"name",
+            b"b",
+            b"node",
+            b"(",
+            b"type",
+            b"regular",
+            b"contents",
+            b"hi",
+            b")",
+            b")",
+            b")",
+        ] {
+            s(&mut expected, token);
+        }
+        assert_eq!(expected.len() % 8, 0);
+
+        let mut nar = Vec::new();
+        dump_path(dir.path(), &mut nar).unwrap();
+        assert_eq!(nar, expected);
+    }
+
+    #[test]
+    fn test_source_store_path() {
+        assert_eq!(
+            source_store_path(
+                "/nix/store",
+                "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+                "source"
+            ),
+            Some("/nix/store/f6jkrn77ysv9dhs8hh6s2faflhjc930i-source".to_string())
+        );
+        assert_eq!(
+            source_store_path("/nix/store", "sha512-AAAA", "source"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_nix_hash_to_sri() {
+        // sha256 of the empty string
+        assert_eq!(
+            nix_hash_to_sri("sha256:0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"),
+            Some("sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string())
+        );
+        assert_eq!(
+            nix_hash_to_sri("0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"),
+            Some("sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string())
+        );
+        // 'e' is not in the nix alphabet
+        assert_eq!(nix_hash_to_sri("sha256:eeee"), None);
+    }
+}
@@ -1,13 +1,27 @@
 //! trix - Impure flakes wrapper using legacy nix-* commands.
 
+pub mod builds;
 pub mod cli;
 pub mod command;
 pub mod common;
+pub mod eval_profile;
+pub mod fetch;
 pub mod flake;
 pub mod git;
+pub mod hash;
 pub mod lock;
 pub mod nix;
+pub mod oci;
+pub mod paths;
+pub mod plan;
 pub mod profile;
+pub mod progress;
 pub mod registry;
+pub mod retry;
+pub mod safety;
+pub mod schema;
+pub mod scratch;
+pub mod trust;
+pub mod workspace;
 
 pub use flake::ResolvedInstallable;
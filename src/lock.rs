@@ -1,14 +1,22 @@
 //! Version locking using nix flake prefetch.
 //!
 //! Produces flake.lock files in the native nix format (version 7).
+//!
+//! Commands that rewrite flake.lock hold an advisory lock on the flake
+//! directory from reading the lock file to writing it, so two trix
+//! processes in the same flake (say direnv and a manual build) take turns
+//! instead of overwriting each other's changes. Writes go through a
+//! temporary file and a rename, so readers never see a partial file.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 use std::collections::{HashMap, HashSet};
 use std::fs;
-
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
 
 use crate::cli::style::*;
@@ -100,11 +108,214 @@ pub struct LockedInfo {
     pub host: Option<String>,
 }
 
+/// Resolve a follows path (e.g. ["nixpkgs"] or ["foo", "nixpkgs"]) from the
+/// root to the name of the node it points at.
+pub fn resolve_follows(lock: &LockFile, path: &[Value]) -> Option<String> {
+    resolve_follows_at(lock, path, 0)
+}
+
+/// `resolve_follows` at nesting `depth`, giving up on follows cycles.
+fn resolve_follows_at(lock: &LockFile, path: &[Value], depth: usize) -> Option<String> {
+    if depth > lock.nodes.len() {
+        return None;
+    }
+    let mut node = lock.root.clone();
+    for elem in path {
+        let target = lock
+            .nodes
+            .get(&node)?
+            .inputs
+            .as_ref()?
+            .get(elem.as_str()?)?;
+        node = match target {
+            Value::String(name) => name.clone(),
+            Value::Array(nested) => resolve_follows_at(lock, nested, depth + 1)?,
+            _ => return None,
+        };
+    }
+    lock.nodes.contains_key(&node).then_some(node)
+}
+
+/// Prefetch an input, returning output shaped like `nix flake prefetch --json`.
+///
+/// Without a usable `nix` command (or with `--legacy-only`), github and git
+/// inputs are fetched with nix-prefetch-url and builtins.fetchGit instead.
+fn prefetch_input(flake_ref: &str, input_type: &str, spec: &Value) -> Result<Option<Value>> {
+    let caps = crate::nix::capabilities();
+    if caps.supports_nix_command() {
+        return prefetch_flake(flake_ref);
+    }
+    match input_type {
+        "github" => legacy_prefetch_github(spec).map(Some),
+        "git" => legacy_prefetch_git(spec).map(Some),
+        _ => caps
+            .require_nix_command(&format!("Locking {} inputs", input_type))
+            .map(|_| None),
+    }
+}
+
 fn prefetch_flake(flake_ref: &str) -> Result<Option<Value>> {
+    crate::nix::capabilities().require_nix_command("Locking remote flake inputs")?;
+
     let mut cmd = crate::command::NixCommand::new("nix");
     cmd.args(["flake", "prefetch", "--json", flake_ref]);
 
-    Ok(cmd.json().ok())
+    let what = format!("prefetch of {}", flake_ref);
+    Ok(crate::retry::with_retry(&what, || cmd.json()).ok())
+}
+
+/// GET a GitHub API endpoint, authenticating with `$GITHUB_TOKEN` if set.
+fn github_api(path: &str) -> Result<Value> {
+    let url = format!("https://api.github.com/{}", path);
+    crate::retry::with_retry(&format!("GitHub API request to {}", url), || {
+        let mut request = reqwest::blocking::Client::new()
+            .get(&url)
+            .header("User-Agent", "trix")
+            .header("Accept", "application/vnd.github+json")
+            .timeout(std::time::Duration::from_secs(30));
+        if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+            request = request.bearer_auth(token);
+        }
+        request
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.json())
+            .with_context(|| format!("GitHub API request to {} failed", url))
+    })
+}
+
+/// Resolve a commit-ish in a GitHub repository to its rev and commit time.
+pub fn github_commit(owner: &str, repo: &str, commitish: &str) -> Result<(String, Option<i64>)> {
+    let commit = github_api(&format!("repos/{}/{}/commits/{}", owner, repo, commitish))
+        .with_context(|| format!("Failed to resolve github:{}/{}/{}", owner, repo, commitish))?;
+
+    let rev = commit["sha"]
+        .as_str()
+        .context("No commit hash in GitHub API response")?;
+    let last_modified = commit["commit"]["committer"]["date"]
+        .as_str()
+        .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+        .map(|d| d.timestamp());
+    Ok((rev.to_string(), last_modified))
+}
+
+/// Count the commits `head` has on top of `base` in a GitHub repository.
+pub fn github_commits_between(owner: &str, repo: &str, base: &str, head: &str) -> Result<u64> {
+    let comparison = github_api(&format!(
+        "repos/{}/{}/compare/{}...{}",
+        owner, repo, base, head
+    ))?;
+    comparison["ahead_by"]
+        .as_u64()
+        .context("No ahead_by in GitHub API response")
+}
+
+/// Whether `rev` is reachable from `git_ref` in a GitHub repository.
+pub fn github_rev_on_ref(owner: &str, repo: &str, rev: &str, git_ref: &str) -> Result<bool> {
+    let comparison = github_api(&format!(
+        "repos/{}/{}/compare/{}...{}",
+        owner, repo, rev, git_ref
+    ))?;
+    Ok(matches!(
+        comparison["status"].as_str(),
+        Some("ahead" | "identical")
+    ))
+}
+
+/// Lock a github input through the GitHub API and nix-prefetch-url.
+fn legacy_prefetch_github(spec: &Value) -> Result<Value> {
+    let owner = spec["owner"].as_str().unwrap_or("");
+    let repo = spec["repo"].as_str().unwrap_or("");
+    let commitish = spec["rev"]
+        .as_str()
+        .or_else(|| spec["ref"].as_str())
+        .unwrap_or("HEAD");
+
+    let (rev, last_modified) = github_commit(owner, repo, commitish)?;
+    let rev = rev.as_str();
+
+    let origin = format!(
+        "https://github.com/{}/{}/archive/{}.tar.gz",
+        owner, repo, rev
+    );
+    let mut errors = Vec::new();
+    let mut output = None;
+    for url in crate::fetch::mirrored_urls("github.com", &origin) {
+        let mut cmd = crate::command::NixCommand::new("nix-prefetch-url");
+        cmd.args(["--unpack", "--name", "source", &url]);
+        match crate::retry::with_retry(&format!("download of {}", url), || cmd.output()) {
+            Ok(out) => {
+                output = Some(out);
+                break;
+            }
+            Err(e) => errors.push(format!("  {}: {:#}", url, e)),
+        }
+    }
+    let Some(output) = output else {
+        anyhow::bail!(
+            "Failed to fetch {}/{} at {} from any of:\n{}",
+            owner,
+            repo,
+            rev,
+            errors.join("\n")
+        );
+    };
+    let hash = output
+        .lines()
+        .next()
+        .and_then(crate::hash::nix_hash_to_sri)
+        .with_context(|| format!("Unexpected nix-prefetch-url output: {}", output.trim()))?;
+
+    Ok(json!({
+        "hash": hash,
+        "locked": {
+            "rev": rev,
+            "lastModified": last_modified,
+        },
+    }))
+}
+
+/// Lock a git input through builtins.fetchGit and nix-store.
+fn legacy_prefetch_git(spec: &Value) -> Result<Value> {
+    use crate::nix::nix_string;
+
+    let mut args = vec![format!(
+        "url = {};",
+        nix_string(spec["url"].as_str().unwrap_or(""))
+    )];
+    if let Some(git_ref) = spec["ref"].as_str() {
+        args.push(format!("ref = {};", nix_string(git_ref)));
+    }
+    if let Some(rev) = spec["rev"].as_str() {
+        args.push(format!("rev = {};", nix_string(rev)));
+    }
+    let expr = format!(
+        "let r = builtins.fetchGit {{ {} }}; in {{ inherit (r) rev revCount outPath; lastModified = r.lastModified or null; }}",
+        args.join(" ")
+    );
+
+    let mut cmd = crate::command::NixCommand::new("nix-instantiate");
+    cmd.args(["--eval", "--json", "--strict", "--expr", &expr]);
+    let what = format!("fetch of {}", spec["url"].as_str().unwrap_or(""));
+    let fetched: Value = crate::retry::with_retry(&what, || cmd.json())?;
+
+    let out_path = fetched["outPath"]
+        .as_str()
+        .context("builtins.fetchGit returned no outPath")?;
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--query", "--hash", out_path]);
+    let output = cmd.output()?;
+    let hash = crate::hash::nix_hash_to_sri(output.trim())
+        .with_context(|| format!("Unexpected nix-store output: {}", output.trim()))?;
+
+    Ok(json!({
+        "hash": hash,
+        "locked": {
+            "rev": fetched["rev"],
+            "revCount": fetched["revCount"],
+            "lastModified": fetched["lastModified"],
+        },
+    }))
 }
 
 /// Lock a single input, returning a node in native flake.lock format.
@@ -192,7 +403,7 @@ fn lock_input(name: &str, spec: &Value) -> Result<Option<LockNode>> {
     };
 
     // Prefetch to get hash and revision
-    let prefetch_result = prefetch_flake(&flake_ref)?;
+    let prefetch_result = prefetch_input(&flake_ref, input_type, spec)?;
 
     if let Some(result) = prefetch_result {
         let mut locked = LockedInfo {
@@ -591,6 +802,51 @@ fn collect_transitive_deps(
     }
 }
 
+/// Advisory lock on a flake directory, released when dropped.
+struct FlakeDirLock {
+    _dir: Option<fs::File>,
+}
+
+/// Take the exclusive lock on `flake_dir`, waiting for other trix processes.
+///
+/// The directory itself is locked, so no lock file is left behind. Where
+/// locking is not supported (e.g. some network filesystems), trix carries
+/// on without it.
+fn lock_flake_dir(flake_dir: &Path) -> FlakeDirLock {
+    let dir = match fs::File::open(flake_dir) {
+        Ok(dir) => dir,
+        Err(e) => {
+            tracing::debug!("Could not open {} to lock it: {}", flake_dir.display(), e);
+            return FlakeDirLock { _dir: None };
+        }
+    };
+    let flock = |operation| {
+        // SAFETY: flock only operates on the descriptor, which `dir` keeps open
+        if unsafe { libc::flock(dir.as_raw_fd(), operation) } == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    };
+
+    let locked = flock(libc::LOCK_EX | libc::LOCK_NB).or_else(|e| {
+        if e.kind() != std::io::ErrorKind::WouldBlock {
+            return Err(e);
+        }
+        crate::progress::with_status(
+            "Waiting for another trix process to finish updating flake.lock",
+            || flock(libc::LOCK_EX),
+        )
+    });
+    match locked {
+        Ok(()) => FlakeDirLock { _dir: Some(dir) },
+        Err(e) => {
+            tracing::debug!("Could not lock {}: {}", flake_dir.display(), e);
+            FlakeDirLock { _dir: None }
+        }
+    }
+}
+
 /// Read existing lock file or return empty structure.
 fn read_lock(flake_lock: &Path) -> LockFile {
     let default_lock = || {
@@ -619,6 +875,56 @@ fn read_lock(flake_lock: &Path) -> LockFile {
     }
 }
 
+/// Flake reference for a locked node, pinned to its locked revision.
+fn locked_flake_ref(locked: &LockedInfo) -> Option<String> {
+    let rev = locked.rev.as_deref();
+    match locked.lock_type.as_str() {
+        "github" | "gitlab" | "sourcehut" => {
+            let mut flake_ref = format!(
+                "{}:{}/{}/{}",
+                locked.lock_type,
+                locked.owner.as_deref()?,
+                locked.repo.as_deref()?,
+                rev?
+            );
+            if let Some(host) = &locked.host {
+                flake_ref.push_str(&format!("?host={}", host));
+            }
+            Some(flake_ref)
+        }
+        "git" => {
+            let mut flake_ref = format!("git+{}?rev={}", locked.url.as_deref()?, rev?);
+            if let Some(git_ref) = &locked.git_ref {
+                flake_ref.push_str(&format!("&ref={}", git_ref));
+            }
+            Some(flake_ref)
+        }
+        "path" => Some(format!("path:{}", locked.path.as_deref()?)),
+        "tarball" | "file" => locked.url.clone(),
+        _ => None,
+    }
+}
+
+/// Flake reference pinning root input `input` to the revision in
+/// `flake_dir`'s flake.lock, if it is locked there.
+pub fn locked_input_ref(flake_dir: &Path, input: &str) -> Option<String> {
+    let flake_lock = flake_dir.join("flake.lock");
+    if !flake_lock.exists() {
+        return None;
+    }
+    let lock_data = read_lock(&flake_lock);
+
+    // Inputs that follow another input are lists; only direct ones are pinned
+    let node_name = lock_data
+        .nodes
+        .get(&lock_data.root)?
+        .inputs
+        .as_ref()?
+        .get(input)?
+        .as_str()?;
+    locked_flake_ref(lock_data.nodes.get(node_name)?.locked.as_ref()?)
+}
+
 /// Recursively remove null values from JSON (nix doesn't accept them).
 fn remove_nulls(value: Value) -> Value {
     match value {
@@ -656,10 +962,73 @@ fn write_lock(flake_lock: &Path, lock_data: &LockFile) -> Result<()> {
     let sanitized = remove_nulls(value);
     let sorted = sort_json(sanitized);
     let content = serde_json::to_string_pretty(&sorted)?;
-    fs::write(flake_lock, format!("{}\n", content))?;
+
+    // Write a temporary file next to the lock file and rename it over
+    let dir = flake_lock
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".flake.lock.")
+        .tempfile_in(dir)
+        .with_context(|| format!("Failed to write {}", flake_lock.display()))?;
+    tmp.write_all(format!("{}\n", content).as_bytes())?;
+    let permissions = fs::metadata(flake_lock)
+        .map(|m| m.permissions())
+        .unwrap_or_else(|_| fs::Permissions::from_mode(0o644));
+    fs::set_permissions(tmp.path(), permissions)?;
+    tmp.persist(flake_lock)
+        .with_context(|| format!("Failed to write {}", flake_lock.display()))?;
     Ok(())
 }
 
+/// Remove nodes no longer reachable from the root node.
+///
+/// Removing an input or changing what it follows leaves the nodes it
+/// brought in behind. Follows (list) references need no walking of their
+/// own, since the node they resolve to is also referenced by name from
+/// another reachable node. Returns the removed node names, sorted.
+fn prune_unreachable(lock_data: &mut LockFile) -> Vec<String> {
+    let root = if lock_data.root.is_empty() {
+        "root"
+    } else {
+        lock_data.root.as_str()
+    };
+    if !lock_data.nodes.contains_key(root) {
+        return Vec::new();
+    }
+
+    let mut reachable: HashSet<String> = HashSet::new();
+    let mut queue = vec![root.to_string()];
+    while let Some(name) = queue.pop() {
+        if !reachable.insert(name.clone()) {
+            continue;
+        }
+        let Some(inputs) = lock_data.nodes.get(&name).and_then(|n| n.inputs.as_ref()) else {
+            continue;
+        };
+        queue.extend(
+            inputs
+                .values()
+                .filter_map(|v| v.as_str())
+                .filter(|n| !reachable.contains(*n))
+                .map(str::to_string),
+        );
+    }
+
+    let mut pruned: Vec<String> = lock_data
+        .nodes
+        .keys()
+        .filter(|name| !reachable.contains(*name))
+        .cloned()
+        .collect();
+    pruned.sort();
+    for name in &pruned {
+        lock_data.nodes.remove(name);
+    }
+    pruned
+}
+
 /// Print lock file changes in nix's format.
 fn print_lock_changes(
     flake_lock: &Path,
@@ -668,11 +1037,13 @@ fn print_lock_changes(
     updated_inputs: &[(String, LockNode, LockNode)],
     removed_inputs: &[String],
     added_follows: &[(String, Vec<String>)],
+    pruned_nodes: &[String],
 ) {
     if added_inputs.is_empty()
         && updated_inputs.is_empty()
         && removed_inputs.is_empty()
         && added_follows.is_empty()
+        && pruned_nodes.is_empty()
     {
         return;
     }
@@ -693,7 +1064,13 @@ fn print_lock_changes(
             magenta("Added input"),
             bold(&format!("'{}'", name))
         );
-        eprintln!("    {}", cyan(&format!("'{}'", url)));
+        eprintln!(
+            "{}",
+            fit(
+                Stream::Stderr,
+                &format!("    {}", cyan(&format!("'{}'", url)))
+            )
+        );
     }
 
     for (name, follows_path) in added_follows {
@@ -719,8 +1096,20 @@ fn print_lock_changes(
             magenta("Updated input"),
             bold(&format!("'{}'", name))
         );
-        eprintln!("    {}", cyan(&format!("'{}'", old_url)));
-        eprintln!("  → {}", cyan(&format!("'{}'", new_url)));
+        eprintln!(
+            "{}",
+            fit(
+                Stream::Stderr,
+                &format!("    {}", cyan(&format!("'{}'", old_url)))
+            )
+        );
+        eprintln!(
+            "{}",
+            fit(
+                Stream::Stderr,
+                &format!("  → {}", cyan(&format!("'{}'", new_url)))
+            )
+        );
     }
 
     for name in removed_inputs {
@@ -731,6 +1120,15 @@ fn print_lock_changes(
             bold(&format!("'{}'", name))
         );
     }
+
+    for name in pruned_nodes {
+        eprintln!(
+            "{} {} {}",
+            magenta("•"),
+            magenta("Removed unused node"),
+            bold(&format!("'{}'", name))
+        );
+    }
 }
 
 /// Sync flake.nix inputs to lock file.
@@ -743,7 +1141,6 @@ fn print_lock_changes(
 /// Produces native flake.lock format (version 7).
 pub fn sync_inputs(flake_dir: &Path, inputs: Option<serde_json::Value>) -> Result<bool> {
     let flake_lock = flake_dir.join("flake.lock");
-    let lock_existed = flake_lock.exists();
     let inputs = match inputs {
         Some(i) => i,
         None => get_flake_inputs(flake_dir)?,
@@ -754,6 +1151,9 @@ pub fn sync_inputs(flake_dir: &Path, inputs: Option<serde_json::Value>) -> Resul
         _ => return Ok(true), // No inputs to lock
     };
 
+    let _guard = lock_flake_dir(flake_dir);
+    let lock_existed = flake_lock.exists();
+
     // Read existing lock
     let mut lock_data = read_lock(&flake_lock);
 
@@ -842,7 +1242,11 @@ pub fn sync_inputs(flake_dir: &Path, inputs: Option<serde_json::Value>) -> Resul
         }
 
         // Lock the input
-        if let Some(mut node) = lock_input(name, spec)? {
+        if let Some(mut node) =
+            crate::progress::with_status(&format!("locking input '{}'", name), || {
+                lock_input(name, spec)
+            })?
+        {
             // Add transitive follows if specified
             if let Some(follows_map) = spec.get("follows").and_then(|f| f.as_object()) {
                 let mut node_inputs = node.inputs.clone().unwrap_or_default();
@@ -886,13 +1290,18 @@ pub fn sync_inputs(flake_dir: &Path, inputs: Option<serde_json::Value>) -> Resul
                 root_inputs.remove(name);
             }
         }
-        lock_data.nodes.remove(name);
         removed_inputs.push(name.clone());
     }
 
+    // The removed inputs' nodes go with everything else nothing uses
+    let mut pruned = prune_unreachable(&mut lock_data);
+    pruned.retain(|name| !removed_inputs.contains(name));
+
     // Write if changed
-    let changed =
-        !added_inputs.is_empty() || !added_follows.is_empty() || !removed_inputs.is_empty();
+    let changed = !added_inputs.is_empty()
+        || !added_follows.is_empty()
+        || !removed_inputs.is_empty()
+        || !pruned.is_empty();
     if changed {
         write_lock(&flake_lock, &lock_data)?;
         print_lock_changes(
@@ -902,6 +1311,7 @@ pub fn sync_inputs(flake_dir: &Path, inputs: Option<serde_json::Value>) -> Resul
             &[], // No updates in sync_inputs
             &removed_inputs,
             &added_follows,
+            &pruned,
         );
     }
 
@@ -1016,19 +1426,125 @@ fn lock_flake_ref(
     }
 }
 
+/// Matches `# trix: follow-branch <input> <branch>` in flake.nix
+static FOLLOW_BRANCH_REGEX: once_cell::sync::Lazy<regex::Regex> =
+    once_cell::sync::Lazy::new(|| {
+        regex::Regex::new(r"#\s*trix:\s*follow-branch\s+(\S+)\s+(\S+)").unwrap()
+    });
+
+/// Read branch constraints declared in flake.nix comments.
+///
+/// A line like `# trix: follow-branch nixpkgs nixos-24.05` keeps
+/// `trix flake update` from moving `nixpkgs` to any other branch. These are
+/// comments because nix rejects unknown attributes in input specs.
+pub fn branch_constraints(flake_dir: &Path) -> HashMap<String, String> {
+    let content = fs::read_to_string(flake_dir.join("flake.nix")).unwrap_or_default();
+    FOLLOW_BRANCH_REGEX
+        .captures_iter(&content)
+        .map(|c| (c[1].to_string(), c[2].to_string()))
+        .collect()
+}
+
+static NO_UPDATE_REGEX: once_cell::sync::Lazy<regex::Regex> =
+    once_cell::sync::Lazy::new(|| regex::Regex::new(r"#\s*trix:\s*no-update\s+(.+)").unwrap());
+
+/// Read inputs that blanket updates must leave alone, declared in flake.nix comments.
+///
+/// A line like `# trix: no-update rust-overlay gcc` pins those inputs
+/// until they are updated by name (`trix flake update rust-overlay`).
+pub fn pinned_inputs(flake_dir: &Path) -> HashSet<String> {
+    let content = fs::read_to_string(flake_dir.join("flake.nix")).unwrap_or_default();
+    NO_UPDATE_REGEX
+        .captures_iter(&content)
+        .flat_map(|c| {
+            c[1].split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Whether `rev` is a full commit hash rather than a tag or branch.
+pub fn is_commit_hash(rev: &str) -> bool {
+    rev.len() == 40 && rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Whether `git_ref` satisfies the constraint `pattern`: a branch name, or a
+/// glob like `v2.*` that holds an input to one major release.
+fn ref_matches(pattern: &str, git_ref: &str) -> bool {
+    let parts: Vec<String> = pattern.split('*').map(regex::escape).collect();
+    regex::Regex::new(&format!("^{}$", parts.join(".*"))).is_ok_and(|re| re.is_match(git_ref))
+}
+
+/// The ref the lock node of `name` was locked from, if it has one.
+fn locked_ref<'a>(lock: &'a LockFile, name: &str) -> Option<&'a str> {
+    lock.nodes.get(name)?.original.as_ref()?["ref"].as_str()
+}
+
+/// The ref overriding `name` with `flake_ref` locks it from: the one
+/// `flake_ref` names, or when it only pins a commit, the one flake.nix
+/// declares in `spec`, which the lock keeps as its original.
+fn override_ref(flake_ref: &str, spec: Option<&Value>) -> Option<String> {
+    let parsed = serde_json::to_value(crate::flake::parse_flake_url(flake_ref)).ok()?;
+    match parsed["ref"].as_str() {
+        Some(git_ref) if !is_commit_hash(git_ref) => Some(git_ref.to_string()),
+        _ => spec?["ref"].as_str().map(str::to_string),
+    }
+}
+
+/// Refuse to lock `name` from `requested`, the ref `source` asks for, when
+/// that jumps off its constraint.
+///
+/// `locked` is the ref its lock node was locked from. Staying on it isn't a
+/// jump, so an input moved once with `--allow-branch-change` keeps updating
+/// on its new branch.
+fn check_branch_constraint(
+    name: &str,
+    requested: Option<&str>,
+    locked: Option<&str>,
+    source: &str,
+    constraints: &HashMap<String, String>,
+) -> Result<()> {
+    let Some(branch) = constraints.get(name) else {
+        return Ok(());
+    };
+    if requested.is_some_and(|r| ref_matches(branch, r))
+        || requested.is_some() && requested == locked
+    {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "input '{}' follows branch '{}'{}, but {} asks for {}\n\
+         Pass --allow-branch-change to update it anyway",
+        name,
+        branch,
+        locked
+            .map(|r| format!(" and is locked from '{}'", r))
+            .unwrap_or_default(),
+        source,
+        requested
+            .map(|r| format!("'{}'", r))
+            .unwrap_or_else(|| "the default branch".to_string())
+    )
+}
+
 /// Update locked inputs to latest versions.
 ///
 /// Args:
 ///   flake_dir: Directory containing flake.nix
 ///   input_name: Specific input to update, or None for all
 ///   override_inputs: Dict mapping input names to flake refs to pin to
+///   allow_branch_change: Ignore `# trix: follow-branch` constraints
+///   exclude: Inputs to leave alone when updating all inputs, on top of
+///     those pinned with `# trix: no-update`
 pub fn update_lock(
     flake_dir: &Path,
     input_name: Option<&str>,
     override_inputs: Option<&HashMap<String, String>>,
+    allow_branch_change: bool,
+    exclude: &[String],
 ) -> Result<Option<HashMap<String, (Value, Value)>>> {
     let flake_lock = flake_dir.join("flake.lock");
-    let lock_existed = flake_lock.exists();
     let inputs = get_flake_inputs(flake_dir)?;
     let override_inputs = override_inputs.cloned().unwrap_or_default();
 
@@ -1037,8 +1553,8 @@ pub fn update_lock(
         _ => return Ok(Some(HashMap::new())),
     };
 
-    // Validate override inputs exist in flake.nix
-    for name in override_inputs.keys() {
+    // Validate override and excluded inputs exist in flake.nix
+    for name in override_inputs.keys().chain(exclude) {
         if !input_map.contains_key(name) {
             eprintln!("Error: input '{}' not found in flake.nix", name);
             return Ok(None);
@@ -1046,6 +1562,8 @@ pub fn update_lock(
     }
 
     // Read existing lock or create new
+    let _guard = lock_flake_dir(flake_dir);
+    let lock_existed = flake_lock.exists();
     let mut lock_data = read_lock(&flake_lock);
     let mut updates: HashMap<String, (Value, Value)> = HashMap::new();
     let mut added_inputs: Vec<(String, LockNode)> = Vec::new();
@@ -1074,6 +1592,23 @@ pub fn update_lock(
         }
     }
 
+    // Check constraints before fetching anything so a violation leaves the lock untouched
+    let constraints = if allow_branch_change {
+        HashMap::new()
+    } else {
+        branch_constraints(flake_dir)
+    };
+    for (name, flake_ref) in &override_inputs {
+        let requested = override_ref(flake_ref, input_map.get(name));
+        check_branch_constraint(
+            name,
+            requested.as_deref(),
+            locked_ref(&lock_data, name),
+            "--override-input",
+            &constraints,
+        )?;
+    }
+
     // Apply override inputs first
     for (name, flake_ref) in &override_inputs {
         let old_node = lock_data.nodes.get(name).cloned();
@@ -1122,6 +1657,7 @@ pub fn update_lock(
 
     // If we only have overrides and no input_name, we're done
     if !override_inputs.is_empty() && input_name.is_none() {
+        let pruned = prune_unreachable(&mut lock_data);
         write_lock(&flake_lock, &lock_data)?;
         print_lock_changes(
             &flake_lock,
@@ -1130,6 +1666,7 @@ pub fn update_lock(
             &updated_inputs,
             &[],
             &[],
+            &pruned,
         );
 
         // Inform user if nothing changed
@@ -1166,13 +1703,45 @@ pub fn update_lock(
             return Ok(None);
         }
     } else {
+        // Inputs that are not locked yet are always locked, even if excluded
+        let pinned = pinned_inputs(flake_dir);
+        let mut skipped: Vec<&String> = input_map
+            .keys()
+            .filter(|k| exclude.contains(k) || pinned.contains(*k))
+            .filter(|k| lock_data.nodes.contains_key(*k))
+            .collect();
+        skipped.sort();
+        for name in &skipped {
+            let reason = if exclude.contains(name) {
+                "--exclude"
+            } else {
+                "# trix: no-update"
+            };
+            eprintln!(
+                "Not updating input {} ({})",
+                bold(&format!("'{}'", name)),
+                reason
+            );
+        }
         input_map
             .keys()
-            .filter(|k| !override_inputs.contains_key(*k))
+            .filter(|k| !override_inputs.contains_key(*k) && !skipped.contains(k))
             .cloned()
             .collect()
     };
 
+    for name in &inputs_to_update {
+        if let Some(spec) = input_map.get(name) {
+            check_branch_constraint(
+                name,
+                spec["ref"].as_str(),
+                locked_ref(&lock_data, name),
+                "flake.nix",
+                &constraints,
+            )?;
+        }
+    }
+
     for name in inputs_to_update {
         let spec = match input_map.get(&name) {
             Some(s) => s,
@@ -1189,7 +1758,11 @@ pub fn update_lock(
         let old_node = lock_data.nodes.get(&name).cloned();
 
         // Re-lock the input
-        if let Some(mut new_node) = lock_input(&name, spec)? {
+        if let Some(mut new_node) =
+            crate::progress::with_status(&format!("locking input '{}'", name), || {
+                lock_input(&name, spec)
+            })?
+        {
             let old_rev = old_node
                 .as_ref()
                 .and_then(|n| n.locked.as_ref())
@@ -1250,8 +1823,9 @@ pub fn update_lock(
         }
     }
 
-    // Write if changed
-    if !updates.is_empty() || !added_inputs.is_empty() {
+    // Write if changed; updated inputs may no longer use their old dependencies
+    let pruned = prune_unreachable(&mut lock_data);
+    if !updates.is_empty() || !added_inputs.is_empty() || !pruned.is_empty() {
         write_lock(&flake_lock, &lock_data)?;
         print_lock_changes(
             &flake_lock,
@@ -1260,6 +1834,7 @@ pub fn update_lock(
             &updated_inputs,
             &[],
             &[],
+            &pruned,
         );
     }
 
@@ -1271,6 +1846,140 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_branch_constraints() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("flake.nix"),
+            r#"{
+  # trix: follow-branch nixpkgs nixos-24.05
+  inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-24.11";
+  inputs.utils.url = "github:numtide/flake-utils";
+  outputs = _: { };
+}"#,
+        )
+        .unwrap();
+
+        let constraints = branch_constraints(dir.path());
+        assert_eq!(constraints.len(), 1);
+        assert_eq!(constraints["nixpkgs"], "nixos-24.05");
+
+        let check = |name, requested, locked| {
+            check_branch_constraint(name, requested, locked, "flake.nix", &constraints)
+        };
+        let err = check("nixpkgs", Some("nixos-24.11"), Some("nixos-24.05")).unwrap_err();
+        assert!(err.to_string().contains("locked from 'nixos-24.05'"));
+        assert!(err.to_string().contains("flake.nix asks for 'nixos-24.11'"));
+        assert!(check("nixpkgs", Some("nixos-24.05"), Some("nixos-24.05")).is_ok());
+        assert!(check("nixpkgs", Some("nixos-24.05"), None).is_ok());
+        assert!(check("nixpkgs", None, None).is_err());
+        assert!(check("utils", None, None).is_ok());
+
+        // Already moved with --allow-branch-change: staying put isn't a jump
+        assert!(check("nixpkgs", Some("nixos-24.11"), Some("nixos-24.11")).is_ok());
+        assert!(check("nixpkgs", Some("nixos-unstable"), Some("nixos-24.11")).is_err());
+    }
+
+    #[test]
+    fn test_branch_constraint_major_release() {
+        let constraints = HashMap::from([("lib".to_string(), "v2.*".to_string())]);
+        let check = |requested| {
+            check_branch_constraint("lib", Some(requested), None, "flake.nix", &constraints)
+        };
+        assert!(check("v2.3.1").is_ok());
+        assert!(check("v2.10").is_ok());
+        assert!(check("v3.0.0").is_err());
+        assert!(check("v20.1").is_err());
+        assert!(ref_matches("release-1.x", "release-1.x"));
+        assert!(!ref_matches("release-1.x", "release-1.y"));
+    }
+
+    #[test]
+    fn test_override_ref() {
+        let spec =
+            json!({ "type": "github", "owner": "NixOS", "repo": "nixpkgs", "ref": "nixos-24.05" });
+        let commit = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(
+            override_ref("github:NixOS/nixpkgs/nixos-unstable", Some(&spec)).as_deref(),
+            Some("nixos-unstable")
+        );
+        assert_eq!(
+            override_ref("git+https://example.com/repo?ref=main", Some(&spec)).as_deref(),
+            Some("main")
+        );
+        // Pinning a commit keeps the declared ref
+        assert_eq!(
+            override_ref(&format!("github:NixOS/nixpkgs/{}", commit), Some(&spec)).as_deref(),
+            Some("nixos-24.05")
+        );
+        assert_eq!(override_ref("github:NixOS/nixpkgs", None), None);
+    }
+
+    #[test]
+    fn test_update_lock_override_jumps_branch() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("flake.nix"),
+            r#"{
+  # trix: follow-branch nixpkgs nixos-24.05
+  inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-24.05";
+  outputs = _: { };
+}"#,
+        )
+        .unwrap();
+        let lock = r#"{
+  "nodes": {
+    "nixpkgs": {
+      "locked": { "type": "github", "owner": "NixOS", "repo": "nixpkgs", "rev": "abc" },
+      "original": { "type": "github", "owner": "NixOS", "repo": "nixpkgs", "ref": "nixos-24.05" }
+    },
+    "root": { "inputs": { "nixpkgs": "nixpkgs" } }
+  },
+  "root": "root",
+  "version": 7
+}"#;
+        fs::write(dir.path().join("flake.lock"), lock).unwrap();
+
+        let overrides = HashMap::from([(
+            "nixpkgs".to_string(),
+            "github:NixOS/nixpkgs/nixos-unstable".to_string(),
+        )]);
+        let err = update_lock(dir.path(), None, Some(&overrides), false, &[]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("--override-input asks for 'nixos-unstable'"));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("flake.lock")).unwrap(),
+            lock
+        );
+    }
+
+    #[test]
+    fn test_pinned_inputs() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("flake.nix"),
+            r#"{
+  # trix: no-update rust-overlay gcc
+  inputs.rust-overlay.url = "github:oxalica/rust-overlay";
+  # trix: no-update crane
+  inputs.crane.url = "github:ipetkov/crane";
+  outputs = _: { };
+}"#,
+        )
+        .unwrap();
+
+        let pinned = pinned_inputs(dir.path());
+        assert_eq!(
+            pinned,
+            HashSet::from([
+                "rust-overlay".to_string(),
+                "gcc".to_string(),
+                "crane".to_string()
+            ])
+        );
+    }
+
     #[test]
     fn test_read_lock_nonexistent() {
         let dir = tempdir().unwrap();
@@ -1301,6 +2010,66 @@ mod tests {
         assert_eq!(read.version, 7);
         assert_eq!(read.root, "root");
         assert!(read.nodes.contains_key("root"));
+
+        // Rewrites keep the file's permissions and leave no temporary files
+        fs::set_permissions(&lock_file, fs::Permissions::from_mode(0o664)).unwrap();
+        write_lock(&lock_file, &lock).expect("Failed to write lock");
+        let mode = fs::metadata(&lock_file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o664);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_prune_unreachable() {
+        let node = |inputs: &[(&str, Value)]| LockNode {
+            inputs: Some(
+                inputs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.clone()))
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let mut lock = LockFile {
+            version: 7,
+            root: "root".to_string(),
+            ..Default::default()
+        };
+        lock.nodes.insert(
+            "root".to_string(),
+            node(&[
+                ("app", json!("app")),
+                ("nixpkgs", json!(["app", "nixpkgs"])),
+            ]),
+        );
+        lock.nodes
+            .insert("app".to_string(), node(&[("nixpkgs", json!("nixpkgs_2"))]));
+        lock.nodes
+            .insert("nixpkgs_2".to_string(), LockNode::default());
+        // Left behind by a removed input
+        lock.nodes
+            .insert("old".to_string(), node(&[("utils", json!("utils"))]));
+        lock.nodes.insert("utils".to_string(), LockNode::default());
+
+        assert_eq!(prune_unreachable(&mut lock), vec!["old", "utils"]);
+        let mut names: Vec<_> = lock.nodes.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["app", "nixpkgs_2", "root"]);
+        assert!(prune_unreachable(&mut lock).is_empty());
+    }
+
+    #[test]
+    fn test_lock_flake_dir() {
+        let dir = tempdir().unwrap();
+        let guard = lock_flake_dir(dir.path());
+        assert!(guard._dir.is_some());
+
+        // A second lock on the directory is refused while the first is held
+        let other = fs::File::open(dir.path()).unwrap();
+        let try_lock = || unsafe { libc::flock(other.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        assert_ne!(try_lock(), 0);
+        drop(guard);
+        assert_eq!(try_lock(), 0);
     }
 
     #[test]
@@ -1364,4 +2133,53 @@ mod tests {
         // even if it might fail network ops in some envs.
         // We skip actual execution here to avoid network dependency in unit tests.
     }
+
+    #[test]
+    fn test_locked_input_ref() {
+        let dir = tempdir().unwrap();
+        let lock_file = dir.path().join("flake.lock");
+        let mut lock = read_lock(&lock_file);
+
+        let root = lock.nodes.get_mut("root").unwrap();
+        let inputs = root.inputs.as_mut().unwrap();
+        inputs.insert("nixpkgs".to_string(), json!("nixpkgs"));
+        inputs.insert("utils".to_string(), json!(["other", "utils"]));
+        lock.nodes.insert(
+            "nixpkgs".to_string(),
+            LockNode {
+                locked: Some(LockedInfo {
+                    lock_type: "github".to_string(),
+                    owner: Some("NixOS".to_string()),
+                    repo: Some("nixpkgs".to_string()),
+                    rev: Some("abc".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(locked_input_ref(dir.path(), "nixpkgs"), None);
+        write_lock(&lock_file, &lock).unwrap();
+        assert_eq!(
+            locked_input_ref(dir.path(), "nixpkgs"),
+            Some("github:NixOS/nixpkgs/abc".to_string())
+        );
+        assert_eq!(locked_input_ref(dir.path(), "utils"), None);
+        assert_eq!(locked_input_ref(dir.path(), "missing"), None);
+    }
+
+    #[test]
+    fn test_locked_flake_ref_git() {
+        let locked = LockedInfo {
+            lock_type: "git".to_string(),
+            url: Some("https://example.com/repo.git".to_string()),
+            rev: Some("abc".to_string()),
+            git_ref: Some("main".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            locked_flake_ref(&locked),
+            Some("git+https://example.com/repo.git?rev=abc&ref=main".to_string())
+        );
+    }
 }
@@ -7,28 +7,104 @@ use anyhow::Result;
 use clap::{CommandFactory, Parser, Subcommand};
 use clap_complete::{generate, Shell};
 
+mod builds;
 mod cli;
 mod command;
 mod common;
+mod eval_profile;
+mod fetch;
 mod flake;
 mod git;
+mod hash;
 mod lock;
 mod nix;
+mod oci;
+mod paths;
+mod plan;
 mod profile;
+mod progress;
 mod registry;
+mod retry;
+mod safety;
+mod schema;
+mod scratch;
 mod shebang;
+mod trust;
+mod workspace;
 
 /// trix - trick yourself into flakes
 #[derive(Parser)]
 #[command(name = "trix")]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// Enable verbose output
-    #[arg(short, long, global = true)]
-    verbose: bool,
+    /// Enable verbose output; repeat for more (also passed on to nix)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only show warnings and errors, with no progress (also passed on to nix)
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Emit GitHub Actions workflow commands (log groups, error annotations)
+    #[arg(long, global = true)]
+    gha: bool,
+
+    /// Print each step of installable resolution to stderr
+    #[arg(long, global = true)]
+    explain_resolution: bool,
+
+    /// Write each generated Nix expression to DIR for inspection
+    #[arg(long, global = true, value_name = "DIR")]
+    keep_expr: Option<std::path::PathBuf>,
+
+    /// Download tarball inputs with trix, showing progress, instead of
+    /// builtins.fetchTarball
+    #[arg(long, global = true)]
+    native_fetch: bool,
+
+    /// Never use the `nix` command; only nix-build, nix-instantiate and nix-store
+    #[arg(long, global = true)]
+    legacy_only: bool,
+
+    /// Apply flake nixConfig and project registries without asking
+    #[arg(long, global = true)]
+    accept_flake_config: bool,
+
+    /// Resolve the registry name ORIGINAL to RESOLVED for this command
+    #[arg(long, global = true, num_args = 2, value_names = ["ORIGINAL", "RESOLVED"])]
+    override_flake: Vec<String>,
+
+    /// Fetch archives from HOST through the mirror URL (repeatable; also $TRIX_MIRRORS)
+    #[arg(long, global = true, num_args = 2, value_names = ["HOST", "URL"])]
+    mirror: Vec<String>,
+
+    /// Evaluate flakes in world-writable directories and shebang scripts
+    /// owned by other users, with a warning, instead of refusing
+    #[arg(long, global = true)]
+    no_trust_check: bool,
+
+    /// Don't warn about uncommitted changes in the flake's git tree
+    #[arg(long, global = true)]
+    no_warn_dirty: bool,
+
+    /// When to color output (NO_COLOR is honored in auto mode)
+    #[arg(long, global = true, value_name = "WHEN", default_value = "auto")]
+    color: cli::style::ColorChoice,
+
+    /// Don't color output; same as --color=never
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Retry failed downloads, prefetches and copies this many times
+    #[arg(long, global = true, value_name = "N", default_value_t = 3)]
+    retries: u32,
+
+    /// List plugins (`trix-<command>` executables on PATH) and exit
+    #[arg(long)]
+    list_plugins: bool,
 
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
 }
 
 #[derive(Subcommand)]
@@ -51,6 +127,13 @@ enum Commands {
     /// Show build log for a package
     Log(cli::log::LogArgs),
 
+    /// Show the builds trix has run
+    #[command(subcommand)]
+    Builds(cli::builds::BuildsCommands),
+
+    /// Enter the kept build directory of a failed build
+    DebugBuild(cli::debug_build::DebugBuildArgs),
+
     /// Start an interactive Nix REPL
     Repl(cli::repl::ReplArgs),
 
@@ -72,6 +155,18 @@ enum Commands {
     #[command(subcommand)]
     Registry(cli::registry::RegistryCommands),
 
+    /// Run commands across the flakes listed in trix-workspace.toml
+    #[command(subcommand)]
+    Ws(cli::ws::WsCommands),
+
+    /// Manage Home Manager configurations from the flake
+    #[command(subcommand)]
+    Home(cli::home::HomeCommands),
+
+    /// Manage nix-darwin configurations from the flake
+    #[command(subcommand)]
+    Darwin(cli::darwin::DarwinCommands),
+
     /// Compute and convert cryptographic hashes
     #[command(subcommand)]
     Hash(cli::hash::HashCommands),
@@ -80,12 +175,30 @@ enum Commands {
     #[command(name = "fmt")]
     Fmt(cli::fmt::FmtArgs),
 
+    /// Print the JSON Schema of a command's --json output, or list them
+    Schema {
+        /// Command, e.g. `flake check` or `flake show --compare`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+
+    /// Check the Nix installation and set up trix for first use
+    Setup(cli::setup::SetupArgs),
+
+    /// Exercise core flows and print a diagnostic report for bug reports
+    #[command(hide = true)]
+    SelfTest(cli::self_test::SelfTestArgs),
+
     /// Generate shell completion script
     Completion {
         /// Shell to generate completions for
         #[arg(value_enum)]
         shell: Shell,
     },
+
+    /// Run a plugin: `trix foo ARGS` runs `trix-foo ARGS` from PATH
+    #[command(external_subcommand)]
+    External(Vec<String>),
 }
 
 fn main() {
@@ -120,13 +233,21 @@ fn main() {
     };
 
     // Initialize tracing
-    // Default to INFO unless verbose is set (then DEBUG), or RUST_LOG overrides it.
-    let default_level = if cli.verbose {
-        tracing::Level::DEBUG
-    } else {
-        tracing::Level::INFO
+    // Default to INFO; -q lowers it to WARN, -v raises it to DEBUG and
+    // -vv to TRACE. RUST_LOG overrides it.
+    let default_level = match (cli.quiet, cli.verbose) {
+        (true, _) => tracing::Level::WARN,
+        (false, 0) => tracing::Level::INFO,
+        (false, 1) => tracing::Level::DEBUG,
+        (false, _) => tracing::Level::TRACE,
     };
 
+    cli::style::set_color(if cli.no_color {
+        cli::style::ColorChoice::Never
+    } else {
+        cli.color
+    });
+
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::builder()
@@ -134,21 +255,60 @@ fn main() {
                 .from_env_lossy(),
         )
         .with_target(false) // cleaner output for simple CLI tools
-        .with_writer(std::io::stderr)
+        .with_writer(progress::LogWriter::new)
+        .with_ansi(cli::style::color_enabled(cli::style::Stream::Stderr))
         .init();
 
     if shebang_info.is_some() {
         tracing::debug!("Running in shebang mode");
     }
 
-    if let Err(e) = run(cli) {
+    cli::gha::set_enabled(cli.gha);
+    progress::init(cli.quiet);
+    command::set_verbosity(if cli.quiet {
+        -1
+    } else {
+        cli.verbose.min(i8::MAX as u8) as i8
+    });
+    flake::set_explain_resolution(cli.explain_resolution);
+    scratch::set_keep_expr_dir(cli.keep_expr.clone());
+    nix::set_legacy_only(cli.legacy_only);
+    fetch::set_enabled(cli.native_fetch);
+    trust::set_accept_flake_config(cli.accept_flake_config);
+    registry::set_flake_overrides(&cli.override_flake);
+    retry::set_retries(cli.retries);
+    git::set_warn_dirty(!cli.no_warn_dirty);
+    safety::set_enabled(!cli.no_trust_check);
+    fetch::set_mirrors(&cli.mirror);
+    scratch::cleanup_stale();
+
+    let script_check = match &shebang_info {
+        Some(script) => safety::check_script(std::path::Path::new(&script.script_path)),
+        None => Ok(()),
+    };
+    if let Err(e) = script_check.and_then(|()| run(cli)) {
+        cli::gha::error(&format!("{:#}", e));
         tracing::error!("Error: {:#}", e); // Use {:#} for alternate view (causal chain)
         std::process::exit(1);
     }
 }
 
 fn run(cli: Cli) -> Result<()> {
-    match cli.command {
+    if cli.list_plugins {
+        let builtins: Vec<String> = Cli::command()
+            .get_subcommands()
+            .map(|c| c.get_name().to_string())
+            .collect();
+        cli::plugin::list_plugins(&builtins);
+        return Ok(());
+    }
+
+    let Some(command) = cli.command else {
+        Cli::command().print_help()?;
+        std::process::exit(2);
+    };
+
+    match command {
         Commands::Build(args) => cli::cmd_build(args),
 
         Commands::Develop(args) => cli::cmd_develop(args),
@@ -161,6 +321,10 @@ fn run(cli: Cli) -> Result<()> {
 
         Commands::Log(args) => cli::cmd_log(args),
 
+        Commands::Builds(builds_cmd) => cli::builds::cmd_builds(builds_cmd),
+
+        Commands::DebugBuild(args) => cli::cmd_debug_build(args),
+
         Commands::Repl(args) => cli::cmd_repl(args),
 
         Commands::WhyDepends(args) => cli::cmd_why_depends(args),
@@ -173,14 +337,29 @@ fn run(cli: Cli) -> Result<()> {
 
         Commands::Registry(registry_cmd) => cli::registry::cmd_registry(registry_cmd),
 
+        Commands::Ws(ws_cmd) => cli::ws::cmd_ws(ws_cmd),
+
         Commands::Hash(hash_cmd) => cli::hash::cmd_hash(hash_cmd),
+        Commands::Home(home_cmd) => cli::home::cmd_home(home_cmd),
+        Commands::Darwin(darwin_cmd) => cli::darwin::cmd_darwin(darwin_cmd),
 
         Commands::Fmt(args) => cli::cmd_fmt(args),
 
+        Commands::Schema { command } => cli::cmd_schema(&command),
+
+        Commands::SelfTest(args) => cli::cmd_self_test(args),
+        Commands::Setup(args) => cli::cmd_setup(args, &|shell| {
+            let mut script = Vec::new();
+            generate(shell, &mut Cli::command(), "trix", &mut script);
+            script
+        }),
+
         Commands::Completion { shell } => {
             let mut cmd = Cli::command();
             generate(shell, &mut cmd, "trix", &mut std::io::stdout());
             Ok(())
         }
+
+        Commands::External(args) => cli::plugin::run_plugin(&args),
     }
 }
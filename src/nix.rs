@@ -6,9 +6,10 @@
 use crate::common::Memoized;
 use anyhow::{Context, Result};
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// Empty lock expression for flakes without a lock file
 pub const EMPTY_LOCK_EXPR: &str =
@@ -23,6 +24,24 @@ static SYSTEM_CACHE: Memoized<String> = Memoized::new();
 /// Cached store dir value
 static STORE_DIR_CACHE: Memoized<String> = Memoized::new();
 
+/// Cached host nix capabilities
+static CAPABILITIES_CACHE: Memoized<NixCapabilities> = Memoized::new();
+
+/// Never use the `nix` command, even if it is installed (`--legacy-only`)
+static LEGACY_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Paths that are not valid UTF-8, by the environment variable nix reads them from
+static ENV_PATHS: once_cell::sync::Lazy<std::sync::Mutex<Vec<(String, std::ffi::OsString)>>> =
+    once_cell::sync::Lazy::new(|| std::sync::Mutex::new(Vec::new()));
+
+/// Matches paths that can be written as a Nix path literal.
+static PLAIN_PATH_REGEX: once_cell::sync::Lazy<regex::Regex> =
+    once_cell::sync::Lazy::new(|| regex::Regex::new(r"^(/[A-Za-z0-9._+-]+)+$").unwrap());
+
+/// First Nix release with the unified `nix` CLI, flakes and the
+/// experimental-features setting.
+const NIX_COMMAND_MIN_VERSION: (u32, u32, u32) = (2, 4, 0);
+
 /// Get environment suitable for spawning nix commands.
 ///
 /// Removes TMPDIR to let nix/bash use the system default (/tmp).
@@ -34,6 +53,159 @@ pub fn get_clean_env() -> HashMap<String, String> {
     env_map
 }
 
+/// Features of the host nix installation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NixCapabilities {
+    /// Version reported by nix-instantiate, if it could be determined
+    pub version: Option<(u32, u32, u32)>,
+    /// Whether the unified `nix` command is on PATH
+    pub has_nix_command: bool,
+    /// Whether builds go through a nix daemon (multi-user install)
+    pub daemon: bool,
+    /// Experimental features enabled in nix.conf or NIX_CONFIG
+    pub experimental_features: Vec<String>,
+}
+
+impl NixCapabilities {
+    /// Whether nix understands `--extra-experimental-features`.
+    ///
+    /// An unknown version is assumed to be recent.
+    pub fn supports_experimental_features(&self) -> bool {
+        self.version.is_none_or(|v| v >= NIX_COMMAND_MIN_VERSION)
+    }
+
+    /// Whether `nix flake ...`, `nix hash ...` and friends can be used.
+    pub fn supports_nix_command(&self) -> bool {
+        !legacy_only() && self.has_nix_command && self.supports_experimental_features()
+    }
+
+    /// Fail with an actionable message if the `nix` command can't be used.
+    ///
+    /// `what` describes the operation that needs it.
+    pub fn require_nix_command(&self, what: &str) -> Result<()> {
+        if self.supports_nix_command() {
+            return Ok(());
+        }
+        let (min_major, min_minor, _) = NIX_COMMAND_MIN_VERSION;
+        if legacy_only() {
+            anyhow::bail!(
+                "{} needs the `nix` command, which is disabled by --legacy-only.",
+                what
+            );
+        }
+        let found = match self.version {
+            Some((major, minor, patch)) if self.has_nix_command => {
+                format!("found Nix {}.{}.{}", major, minor, patch)
+            }
+            _ => "the `nix` command is not installed".to_string(),
+        };
+        anyhow::bail!(
+            "{} needs the `nix` command from Nix {}.{} or newer ({}).\n\
+             Upgrade Nix, or use a local flake so trix can work with nix-build and nix-instantiate alone.",
+            what,
+            min_major,
+            min_minor,
+            found
+        )
+    }
+}
+
+/// Restrict trix to nix-build, nix-instantiate and nix-store (`--legacy-only`).
+pub fn set_legacy_only(enabled: bool) {
+    LEGACY_ONLY.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether `--legacy-only` is in effect.
+pub fn legacy_only() -> bool {
+    LEGACY_ONLY.load(Ordering::Relaxed)
+}
+
+/// Parse the version out of `nix --version` style output.
+///
+/// Accepts e.g. "nix-instantiate (Nix) 2.18.1" or "nix (Nix) 2.25.0pre20241101_dirty".
+pub fn parse_nix_version(output: &str) -> Option<(u32, u32, u32)> {
+    let version = output.split_whitespace().last()?;
+    let mut parts = version.split('.').map(|p| {
+        p.chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>()
+    });
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// Collect experimental features from nix.conf-style content.
+fn parse_experimental_features(conf: &str, features: &mut Vec<String>) {
+    for line in conf.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        if key == "experimental-features" {
+            features.clear();
+        } else if key != "extra-experimental-features" {
+            continue;
+        }
+        for feature in value.split_whitespace() {
+            if !features.iter().any(|f| f == feature) {
+                features.push(feature.to_string());
+            }
+        }
+    }
+}
+
+/// Probe the host nix installation. Result is cached.
+///
+/// Runs `nix-instantiate --version` once; everything else is read from
+/// PATH, the environment and nix.conf without spawning processes.
+pub fn capabilities() -> NixCapabilities {
+    if let Some(caps) = CAPABILITIES_CACHE.get() {
+        return caps;
+    }
+
+    // Not NixCommand: it consults capabilities() itself
+    let version = std::process::Command::new("nix-instantiate")
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| parse_nix_version(&String::from_utf8_lossy(&o.stdout)));
+
+    let has_nix_command = env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|p| p.join("nix").is_file()))
+        .unwrap_or(false);
+
+    let daemon = env::var("NIX_REMOTE").is_ok_and(|r| r == "daemon")
+        || Path::new("/nix/var/nix/daemon-socket/socket").exists();
+
+    let mut experimental_features = Vec::new();
+    let mut conf_files = vec![PathBuf::from("/etc/nix/nix.conf")];
+    if let Some(config) = crate::paths::nix_config_dir() {
+        conf_files.push(config.join("nix.conf"));
+    }
+    for file in conf_files {
+        if let Ok(conf) = std::fs::read_to_string(file) {
+            parse_experimental_features(&conf, &mut experimental_features);
+        }
+    }
+    if let Ok(conf) = env::var("NIX_CONFIG") {
+        parse_experimental_features(&conf, &mut experimental_features);
+    }
+
+    let caps = NixCapabilities {
+        version,
+        has_nix_command,
+        daemon,
+        experimental_features,
+    };
+    tracing::debug!("nix capabilities: {:?}", caps);
+
+    CAPABILITIES_CACHE.set(caps.clone());
+    caps
+}
+
 /// Print a warning message to stderr in nix style.
 pub fn warn(msg: &str) {
     tracing::warn!("{}", msg);
@@ -87,8 +259,8 @@ pub fn get_lock_expr(flake_dir: &Path) -> String {
     let lock_file = flake_dir.join("flake.lock");
     if lock_file.exists() {
         format!(
-            "builtins.fromJSON (builtins.readFile {}/flake.lock)",
-            flake_dir.display()
+            "builtins.fromJSON (builtins.readFile ({} + \"/flake.lock\"))",
+            nix_path(flake_dir)
         )
     } else {
         EMPTY_LOCK_EXPR.to_string()
@@ -121,39 +293,132 @@ pub fn get_self_info_expr(flake_dir: &Path) -> String {
 ///     "packages.x86_64-linux.hello" -> '["packages" "x86_64-linux" "hello"]'
 ///     "" -> "[]"
 pub fn attr_to_nix_list(attr: &str) -> String {
-    let parts: Vec<&str> = attr.split('.').filter(|p| !p.is_empty()).collect();
+    let parts = crate::flake::split_attr_path(attr);
     if parts.is_empty() {
         return "[]".to_string();
     }
-    let quoted: Vec<String> = parts.iter().map(|p| format!("\"{}\"", p)).collect();
+    let quoted: Vec<String> = parts.iter().map(|p| nix_string(p)).collect();
     format!("[{}]", quoted.join(" "))
 }
 
+/// Quote a string as a Nix string literal.
+pub fn nix_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+/// Render a filesystem path as a Nix expression for that path.
+///
+/// Paths made of the characters Nix path literals allow are written as
+/// literals. Others (spaces, quotes, `${`, non-ASCII) are built from an
+/// escaped string as `(/. + "...")`. Paths that are not valid UTF-8
+/// cannot be written in a Nix expression at all, so nix reads them from
+/// an environment variable that [`path_envs`] provides.
+pub fn nix_path(path: &Path) -> String {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()
+            .map(|cwd| cwd.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+    match path.to_str() {
+        Some("/") => "/.".to_string(),
+        Some(s) if PLAIN_PATH_REGEX.is_match(s) => s.to_string(),
+        Some(s) => format!("(/. + {})", nix_string(s.trim_end_matches('/'))),
+        None => {
+            let mut env_paths = ENV_PATHS.lock().unwrap();
+            let os = path.as_os_str().to_os_string();
+            let name = match env_paths.iter().find(|(_, p)| *p == os) {
+                Some((name, _)) => name.clone(),
+                None => {
+                    let name = format!("TRIX_PATH_{}", env_paths.len());
+                    env_paths.push((name.clone(), os));
+                    name
+                }
+            };
+            format!("(/. + builtins.getEnv {})", nix_string(&name))
+        }
+    }
+}
+
+/// Environment variables holding the non-UTF-8 paths used by [`nix_path`].
+pub fn path_envs() -> Vec<(String, std::ffi::OsString)> {
+    ENV_PATHS.lock().unwrap().clone()
+}
+
 /// Prepare common flake arguments (is_flake, self_info, lock).
-fn prepare_flake_args(flake_dir: &Path) -> (bool, String, String) {
+fn prepare_flake_args(flake_dir: &Path) -> Result<(bool, String, String)> {
+    crate::safety::check_flake_dir(flake_dir)?;
     if check_is_flake(flake_dir) {
-        (
+        crate::git::warn_if_dirty(flake_dir);
+        Ok((
             true,
             get_self_info_expr(flake_dir),
             get_lock_expr(flake_dir),
-        )
+        ))
     } else {
-        (false, "{}".to_string(), "{}".to_string())
+        Ok((false, "{}".to_string(), "{}".to_string()))
     }
 }
 
+/// The eval.nix call that building `attr` of the flake at `flake_dir` makes,
+/// as a standalone expression.
+pub fn eval_call_expr(flake_dir: &Path, attr: &str) -> Result<String> {
+    let (_, self_info_expr, _) = prepare_flake_args(flake_dir)?;
+    Ok(eval_call(&get_nix_dir()?, flake_dir, &self_info_expr, attr))
+}
+
+fn eval_call(nix_dir: &Path, flake_dir: &Path, self_info_expr: &str, attr: &str) -> String {
+    format!(
+        "import {} {{ flakeDir = {}; selfInfo = {}; prefetched = {}; mirrors = {}; attr = {}; }}",
+        nix_path(&nix_dir.join("eval.nix")),
+        nix_path(flake_dir),
+        self_info_expr,
+        crate::fetch::prefetched_expr(flake_dir),
+        crate::fetch::mirrors_expr(&crate::fetch::mirrors()),
+        nix_string(attr)
+    )
+}
+
 /// Setup common arguments for eval.nix wrapper commands.
 fn setup_eval_command(
     cmd: &mut crate::command::NixCommand,
     nix_dir: &Path,
     flake_dir: &Path,
     attr: &str,
-) {
-    let (_, self_info_expr, _) = prepare_flake_args(flake_dir);
+) -> Result<()> {
+    let (_, self_info_expr, _) = prepare_flake_args(flake_dir)?;
+    crate::flake::explain_attr_lookup(flake_dir, attr);
+    crate::scratch::keep_expr(
+        "equivalent of the eval.nix call made by trix",
+        &eval_call(nix_dir, flake_dir, &self_info_expr, attr),
+    );
     cmd.arg(nix_dir.join("eval.nix"));
-    cmd.args(["--arg", "flakeDir", &flake_dir.display().to_string()]);
+    cmd.args(["--arg", "flakeDir", &nix_path(flake_dir)]);
     cmd.args(["--arg", "selfInfo", &self_info_expr]);
+    cmd.args([
+        "--arg",
+        "prefetched",
+        &crate::fetch::prefetched_expr(flake_dir),
+    ]);
+    cmd.args([
+        "--arg",
+        "mirrors",
+        &crate::fetch::mirrors_expr(&crate::fetch::mirrors()),
+    ]);
     cmd.args(["--argstr", "attr", attr]);
+
+    for (name, value) in crate::flake::nix_config_options(flake_dir) {
+        cmd.args(["--option", &name, &value]);
+    }
+    Ok(())
 }
 
 /// Generate the common Nix let-bindings for evaluation.
@@ -162,27 +427,119 @@ fn setup_eval_command(
 /// either a flake (via flake.nix) or a legacy project (via default.nix).
 pub fn get_eval_preamble(flake_dir: &Path) -> Result<String> {
     let nix_dir = get_nix_dir()?;
-    let (is_flake, self_info_expr, lock_expr) = prepare_flake_args(flake_dir);
+    let (is_flake, self_info_expr, lock_expr) = prepare_flake_args(flake_dir)?;
 
     Ok(format!(
         r#"
-      context = import {nix_dir}/get_eval_preamble.nix {{
+      context = import ({nix_dir} + "/get_eval_preamble.nix") {{
         flakeDir = {flake_dir};
         isFlake = {is_flake};
         lock = {lock_expr};
         selfInfo = {self_info_expr};
         nixDir = {nix_dir};
+        prefetched = {prefetched_expr};
+        mirrors = {mirrors_expr};
       }};
       inherit (context) helpers hasPath getPath resolveAttrPath outputs;
     "#,
-        nix_dir = nix_dir.display(),
-        flake_dir = flake_dir.display(),
+        nix_dir = nix_path(&nix_dir),
+        flake_dir = nix_path(flake_dir),
         is_flake = is_flake,
         lock_expr = lock_expr,
         self_info_expr = self_info_expr,
+        prefetched_expr = crate::fetch::prefetched_expr(flake_dir),
+        mirrors_expr = crate::fetch::mirrors_expr(&crate::fetch::mirrors()),
     ))
 }
 
+/// An impure access seen while evaluating a flake with traced builtins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImpureAccess {
+    /// Output attribute whose evaluation made the access; empty for the
+    /// outputs function itself
+    pub attr: String,
+    /// File whose code made the access
+    pub file: String,
+    pub message: String,
+}
+
+/// Evaluate `attrs` of the flake at `flake_dir` through impure_trace.nix and
+/// return the impure accesses evaluation made, each once, in the order seen.
+///
+/// Failing to evaluate an attribute doesn't fail the trace, but anything
+/// `tryEval` can't catch, such as a missing attribute, does.
+pub fn trace_impurities(flake_dir: &Path, attrs: &[String]) -> Result<Vec<ImpureAccess>> {
+    let nix_dir = get_nix_dir()?;
+    let (_, self_info_expr, lock_expr) = prepare_flake_args(flake_dir)?;
+    let attrs_expr = attrs
+        .iter()
+        .map(|attr| nix_string(attr))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let expr = format!(
+        "import {} {{ flakeDir = {}; lock = {}; selfInfo = {}; nixDir = {}; prefetched = {}; mirrors = {}; attrs = [ {} ]; }}",
+        nix_path(&nix_dir.join("impure_trace.nix")),
+        nix_path(flake_dir),
+        lock_expr,
+        self_info_expr,
+        nix_path(&nix_dir),
+        crate::fetch::prefetched_expr(flake_dir),
+        crate::fetch::mirrors_expr(&crate::fetch::mirrors()),
+        attrs_expr
+    );
+
+    let mut cmd = crate::command::NixCommand::new("nix-instantiate");
+    // drvPath needs a writable store
+    cmd.args([
+        "--eval",
+        "--strict",
+        "--json",
+        "--read-write-mode",
+        "--expr",
+        &expr,
+    ]);
+    for (name, value) in crate::flake::nix_config_options(flake_dir) {
+        cmd.args(["--option", &name, &value]);
+    }
+    let (_, stderr) = cmd.output_with_stderr()?;
+    Ok(parse_impure_trace(&stderr))
+}
+
+/// The accesses in the `trix-attr:` and `trix-impure:` traces impure_trace.nix
+/// printed to `stderr`. An access made again, by the same file, is only
+/// kept the first time.
+fn parse_impure_trace(stderr: &str) -> Vec<ImpureAccess> {
+    #[derive(serde::Deserialize)]
+    struct Marker {
+        file: String,
+        message: String,
+    }
+
+    let mut attr = String::new();
+    let mut seen = HashSet::new();
+    let mut accesses = Vec::new();
+    for line in stderr.lines() {
+        let Some(trace) = line.trim_start().strip_prefix("trace: ") else {
+            continue;
+        };
+        if let Some(name) = trace.strip_prefix("trix-attr:") {
+            attr = name.to_string();
+        } else if let Some(json) = trace.strip_prefix("trix-impure:") {
+            let Ok(marker) = serde_json::from_str::<Marker>(json) else {
+                continue;
+            };
+            if seen.insert((marker.file.clone(), marker.message.clone())) {
+                accesses.push(ImpureAccess {
+                    attr: attr.clone(),
+                    file: marker.file,
+                    message: marker.message,
+                });
+            }
+        }
+    }
+    accesses
+}
+
 /// Get the current Nix system (e.g., x86_64-linux). Result is cached.
 pub fn get_system() -> Result<String> {
     // Check cache first
@@ -261,6 +618,9 @@ pub struct BuildOptions {
     pub extra_args: Vec<(String, String)>,
     pub extra_argstrs: Vec<(String, String)>,
     pub store: Option<String>,
+    pub keep_failed: bool,
+    /// Symlink to the derivation being built, made before building starts
+    pub drv_link: Option<PathBuf>,
 }
 
 impl CommonNixOptions for BuildOptions {
@@ -288,7 +648,7 @@ pub fn run_nix_build(
 
     if check_is_flake(flake_dir) {
         let nix_dir = get_nix_dir()?;
-        setup_eval_command(&mut cmd, &nix_dir, flake_dir, attr);
+        setup_eval_command(&mut cmd, &nix_dir, flake_dir, attr)?;
     } else {
         // Legacy mode: use standard nix-build with attribute path.
         cmd.arg(flake_dir);
@@ -297,6 +657,14 @@ pub fn run_nix_build(
 
     apply_common_args(&mut cmd, options);
 
+    if options.keep_failed {
+        cmd.arg("--keep-failed");
+    }
+
+    if let Some(link) = &options.drv_link {
+        cmd.arg("--drv-link").arg(link);
+    }
+
     match &options.out_link {
         Some(link) => {
             cmd.args(["-o", link]);
@@ -307,15 +675,53 @@ pub fn run_nix_build(
     }
 
     if capture_output {
-        Ok(Some(cmd.output()?))
+        let output = crate::progress::with_status(&format!("building {}", attr), || cmd.output())?;
+        Ok(Some(output))
     } else {
         cmd.run()?;
         Ok(None)
     }
 }
 
+/// Build `attr` of the root input `input` of the flake in `flake_dir`,
+/// resolved like an installable of that input, and return its store path.
+///
+/// The input is the one the flake's lock pins, fetched the way evaluating
+/// the flake would fetch it.
+pub fn build_input_attr(
+    flake_dir: &Path,
+    input: &str,
+    attr: &str,
+    options: &BuildOptions,
+) -> Result<String> {
+    let preamble = get_eval_preamble(flake_dir)?;
+    let nix_expr = format!(
+        r#"
+    let
+      {preamble}
+    in resolveAttrPath {attr} {outputs}
+    "#,
+        preamble = preamble,
+        attr = nix_string(attr),
+        outputs = input_outputs_expr(input),
+    );
+
+    let mut cmd = crate::command::NixCommand::new("nix-build");
+    cmd.args(["--expr", &nix_expr]);
+    apply_common_args(&mut cmd, options);
+    cmd.arg("--no-link");
+
+    let what = format!("{}#{}", input, attr);
+    let output = crate::progress::with_status(&format!("building {}", what), || cmd.output())?;
+    output
+        .lines()
+        .last()
+        .map(|line| line.trim().to_string())
+        .with_context(|| format!("nix-build printed no output path for {}", what))
+}
+
 /// Options for nix-shell
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct ShellOptions {
     pub command: Option<String>,
     pub extra_args: Vec<(String, String)>,
@@ -340,10 +746,71 @@ impl CommonNixOptions for ShellOptions {
 
 /// Run nix-shell with eval.nix wrapper. Replaces current process.
 pub fn run_nix_shell(flake_dir: &Path, attr: &str, options: &ShellOptions) -> Result<()> {
+    nix_shell_command(flake_dir, attr, options)?.exec()
+}
+
+/// Marks where the environment starts in the output of a captured shell,
+/// after anything the shellHook printed.
+const SHELL_ENV_MARKER: &str = "__TRIX_SHELL_ENV__";
+
+/// Variables of a captured shell that only make sense inside it.
+const SHELL_ENV_SKIP: &[&str] = &[
+    "_",
+    "OLDPWD",
+    "PWD",
+    "SHLVL",
+    "NIX_BUILD_SHELL",
+    "NIX_BUILD_TOP",
+    "TMP",
+    "TMPDIR",
+    "TEMP",
+    "TEMPDIR",
+];
+
+/// Enter a dev shell non-interactively and return the variables it sets or changes.
+///
+/// `options.command` is ignored. The environment is read after the
+/// shellHook has run; shell functions and unexported variables are lost.
+pub fn capture_nix_shell_env(
+    flake_dir: &Path,
+    attr: &str,
+    options: &ShellOptions,
+) -> Result<BTreeMap<String, String>> {
+    let options = ShellOptions {
+        command: Some(format!("printf '\\0{}\\0'; env -0", SHELL_ENV_MARKER)),
+        ..options.clone()
+    };
+    let output = nix_shell_command(flake_dir, attr, &options)?.output()?;
+    let marker = format!("\0{}\0", SHELL_ENV_MARKER);
+    let (_, env) = output
+        .split_once(&marker)
+        .context("Failed to read the shell environment")?;
+    Ok(parse_shell_env(env, |name| env::var(name).ok()))
+}
+
+/// Parse `env -0` output, keeping variables that differ from the current environment.
+fn parse_shell_env(
+    output: &str,
+    current: impl Fn(&str) -> Option<String>,
+) -> BTreeMap<String, String> {
+    output
+        .split('\0')
+        .filter_map(|entry| entry.split_once('='))
+        .filter(|(name, _)| !SHELL_ENV_SKIP.contains(name))
+        .filter(|(name, value)| current(name).as_deref() != Some(*value))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+fn nix_shell_command(
+    flake_dir: &Path,
+    attr: &str,
+    options: &ShellOptions,
+) -> Result<crate::command::NixCommand> {
     let nix_dir = get_nix_dir()?;
 
     let mut cmd = crate::command::NixCommand::new("nix-shell");
-    setup_eval_command(&mut cmd, &nix_dir, flake_dir, attr);
+    setup_eval_command(&mut cmd, &nix_dir, flake_dir, attr)?;
 
     apply_common_args(&mut cmd, options);
 
@@ -382,7 +849,7 @@ pub fn run_nix_shell(flake_dir: &Path, attr: &str, options: &ShellOptions) -> Re
         cmd.envs(env_overrides);
     }
 
-    cmd.exec()
+    Ok(cmd)
 }
 
 /// Options for nix eval
@@ -396,6 +863,7 @@ pub struct EvalOptions {
     pub expr: Option<String>,
     pub store: Option<String>,
     pub quiet: bool,
+    pub show_trace: bool,
 }
 
 impl CommonNixOptions for EvalOptions {
@@ -431,23 +899,25 @@ pub fn run_nix_eval(flake_dir: Option<&Path>, attr: &str, options: &EvalOptions)
         // But wait, run_nix_eval builds the expression string.
         // It uses `nix-instantiate --expr`.
         // If I want to use `eval_attr.nix`, I do:
-        // import {nix_dir}/eval_attr.nix { inherit outputs resolveAttrPath; attr = "{attr}"; applyFn = {apply_fn_or_null}; }
+        // import ({nix_dir} + "/eval_attr.nix") { inherit outputs resolveAttrPath; attr = "{attr}"; applyFn = {apply_fn_or_null}; }
 
         let apply_fn_arg = options.apply_fn.as_deref().unwrap_or("id: id");
 
         format!(
             r#"
         let
+          # trix: flake inputs and outputs
           {preamble}
-        in import {nix_dir}/eval_attr.nix {{
+        # trix: attribute selection
+        in import ({nix_dir} + "/eval_attr.nix") {{
           inherit outputs resolveAttrPath;
-          attr = "{attr}";
+          attr = {attr};
           applyFn = {apply_fn};
         }}
         "#,
             preamble = preamble,
-            nix_dir = get_nix_dir()?.display(),
-            attr = effective_attr,
+            nix_dir = nix_path(&get_nix_dir()?),
+            attr = nix_string(effective_attr),
             apply_fn = apply_fn_arg,
         )
     };
@@ -467,7 +937,18 @@ pub fn run_nix_eval(flake_dir: Option<&Path>, attr: &str, options: &EvalOptions)
         cmd.arg("--json");
     }
 
-    match cmd.output() {
+    if options.show_trace {
+        cmd.arg("--show-trace");
+    }
+
+    let status = match options.expr {
+        Some(_) => "evaluating expression".to_string(),
+        None => format!(
+            "evaluating {}",
+            if attr.is_empty() { "default" } else { attr }
+        ),
+    };
+    match crate::progress::with_status(&status, || cmd.output()) {
         Ok(stdout) => {
             let mut result = stdout;
             // Handle --raw: strip quotes from string output
@@ -478,6 +959,11 @@ pub fn run_nix_eval(flake_dir: Option<&Path>, attr: &str, options: &EvalOptions)
             Ok(result)
         }
         Err(e) => {
+            let is_user_expr = options.expr.is_some();
+            let e = match explain_eval_error(&e.to_string(), &nix_expr, is_user_expr) {
+                Some(note) => anyhow::anyhow!("{}\n{}", e, note),
+                None => e,
+            };
             if !options.quiet {
                 tracing::error!("{}", e);
             }
@@ -486,6 +972,144 @@ pub fn run_nix_eval(flake_dir: Option<&Path>, attr: &str, options: &EvalOptions)
     }
 }
 
+/// Matches nix error positions: "at «string»:3:5", "at (string):3:5"
+/// (nix 2.3) and "at /path/to/file.nix:12:5"
+static ERROR_POSITION_REGEX: once_cell::sync::Lazy<regex::Regex> =
+    once_cell::sync::Lazy::new(|| {
+        regex::Regex::new(r"at («string»|\(string\)|/[^\s:]+):(\d+):(\d+)").unwrap()
+    });
+
+/// Number of lines shown on either side of an error position.
+const ERROR_CONTEXT_LINES: usize = 2;
+
+/// Point an evaluation error at something the user recognizes.
+///
+/// Positions in the user's own files get context lines from that file.
+/// Positions in the expression trix generated (or in trix's own nix files)
+/// are named by the part of the pipeline they belong to. Returns None if
+/// the error has no position.
+pub fn explain_eval_error(message: &str, expr: &str, is_user_expr: bool) -> Option<String> {
+    let positions: Vec<(String, usize)> = ERROR_POSITION_REGEX
+        .captures_iter(message)
+        .filter_map(|c| Some((c[1].to_string(), c[2].parse().ok()?)))
+        .collect();
+
+    // Prefer the user's own files over anything trix generated
+    let nix_dir = get_nix_dir().ok();
+    let is_trix_file = |f: &str| {
+        nix_dir
+            .as_ref()
+            .is_some_and(|d| Path::new(f).starts_with(d))
+    };
+    let user_file = positions
+        .iter()
+        .find(|(f, _)| f.starts_with('/') && !f.starts_with("/nix/store/") && !is_trix_file(f));
+
+    if let Some((file, line)) = user_file {
+        let content = std::fs::read_to_string(file).ok()?;
+        return Some(format!(
+            "note: error in {} line {}:\n{}",
+            file,
+            line,
+            context_lines(&content, *line)
+        ));
+    }
+
+    let (file, line) = positions.first()?;
+    if file.starts_with('/') {
+        let name = Path::new(file).file_name()?.to_string_lossy();
+        let section = match name.as_ref() {
+            "inputs.nix" => "inputs construction from flake.lock",
+            "eval.nix" | "get_eval_preamble.nix" => "call to the flake's outputs function",
+            "helpers.nix" | "eval_attr.nix" => "attribute selection",
+            "smoke_check.nix" => "overlay and module smoke test",
+            "impure_trace.nix" => "impurity trace",
+            _ => return None,
+        };
+        return Some(format!(
+            "note: error raised by trix's {} ({}:{})",
+            section, name, line
+        ));
+    }
+
+    if is_user_expr {
+        return Some(format!(
+            "note: error in --expr line {}:\n{}",
+            line,
+            context_lines(expr, *line)
+        ));
+    }
+
+    // Name the section of the generated expression from its marker comment
+    let section = expr
+        .lines()
+        .take(*line)
+        .filter_map(|l| l.trim().strip_prefix("# trix: "))
+        .last()
+        .unwrap_or("generated expression");
+    Some(format!(
+        "note: error in trix's generated expression ({}), line {}:\n{}",
+        section,
+        line,
+        context_lines(expr, *line)
+    ))
+}
+
+/// Format the lines around `line` (1-based), marking `line` itself.
+fn context_lines(content: &str, line: usize) -> String {
+    let first = line.saturating_sub(ERROR_CONTEXT_LINES).max(1);
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, text)| (i + 1, text))
+        .filter(|(n, _)| *n >= first && *n <= line + ERROR_CONTEXT_LINES)
+        .map(|(n, text)| {
+            let marker = if n == line { ">" } else { " " };
+            format!("  {} {:4} | {}", marker, n, text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Evaluate a Nix file like `nix-instantiate --eval FILE -A ATTR`.
+///
+/// A file containing a function is called with `--arg`/`--argstr` and
+/// defaults for the rest. Unlike flake evaluation, the result is only
+/// forced deeply with `strict` (or JSON output), as with nix-instantiate.
+/// trix has no in-process evaluator, so this runs nix-instantiate itself.
+pub fn run_nix_eval_file(
+    file: &str,
+    attr: Option<&str>,
+    strict: bool,
+    options: &EvalOptions,
+) -> Result<String> {
+    let mut cmd = crate::command::NixCommand::new("nix-instantiate");
+    cmd.args(["--eval", file]);
+    if let Some(attr) = attr {
+        cmd.args(["-A", attr]);
+    }
+    if strict {
+        cmd.arg("--strict");
+    }
+
+    apply_common_args(&mut cmd, options);
+
+    if options.output_json {
+        cmd.arg("--json");
+    }
+
+    if options.show_trace {
+        cmd.arg("--show-trace");
+    }
+
+    let status = format!("evaluating {}", attr.unwrap_or(file));
+    let mut result = crate::progress::with_status(&status, || cmd.output())?;
+    if options.raw && result.starts_with('"') && result.ends_with('"') {
+        result = unescape_nix_string(&result[1..result.len() - 1]);
+    }
+    Ok(result)
+}
+
 /// Unescape a Nix string literal (handles standard escape sequences).
 fn unescape_nix_string(s: &str) -> String {
     let mut result = String::with_capacity(s.len());
@@ -540,11 +1164,135 @@ pub fn flake_has_attr(flake_dir: &Path, attr: &str) -> Result<bool> {
     }
 }
 
+/// List the attribute names at an attribute path in the flake outputs.
+///
+/// The path is resolved like any installable attribute (with the usual
+/// packages/legacyPackages fallbacks); an empty path lists the top-level
+/// outputs. Returns an empty list if the path does not exist or is not an
+/// attrset. Values are not evaluated beyond their names.
+pub fn eval_flake_attr_names(flake_dir: &Path, attr: &str) -> Result<Vec<String>> {
+    let preamble = get_eval_preamble(flake_dir)?;
+
+    let nix_expr = format!(
+        r#"
+    let
+      {preamble}
+      attr = {attr};
+      result =
+        if attr == "" then {{ success = true; value = outputs; }}
+        else builtins.tryEval (resolveAttrPath attr outputs);
+    in
+      if result.success && builtins.isAttrs result.value
+      then builtins.attrNames result.value
+      else [ ]
+    "#,
+        preamble = preamble,
+        attr = nix_string(attr),
+    );
+
+    let mut cmd = crate::command::NixCommand::new("nix-instantiate");
+    cmd.args(["--eval", "--json", "--read-write-mode", "--expr", &nix_expr]);
+
+    cmd.json()
+}
+
+/// Check whether a flake attribute exists, with the same fallbacks as
+/// evaluating it, but without evaluating the attribute's value.
+pub fn flake_attr_exists(flake_dir: &Path, attr: &str) -> Result<bool> {
+    let preamble = get_eval_preamble(flake_dir)?;
+    let effective_attr = if attr.is_empty() { "default" } else { attr };
+
+    let nix_expr = format!(
+        r#"
+    let
+      {preamble}
+    in helpers.findAttrPath {attr} outputs != null
+    "#,
+        preamble = preamble,
+        attr = nix_string(effective_attr),
+    );
+
+    let mut cmd = crate::command::NixCommand::new("nix-instantiate");
+    cmd.args(["--eval", "--json", "--read-write-mode", "--expr", &nix_expr]);
+
+    cmd.json()
+}
+
+/// The `helpers.explainAttrPath` expression for `attr`, given let-bindings
+/// for `helpers` and `outputs`.
+fn attr_path_candidates_expr(preamble: &str, attr: &str) -> String {
+    format!(
+        r#"
+    let
+      {preamble}
+    in helpers.explainAttrPath {attr} outputs
+    "#,
+        preamble = preamble,
+        attr = nix_string(attr),
+    )
+}
+
+/// Parse the result of `helpers.explainAttrPath` into (path, exists) pairs.
+fn parse_attr_path_candidates(value: &serde_json::Value) -> Result<Vec<(String, bool)>> {
+    value
+        .as_array()
+        .context("Expected a list of candidates")?
+        .iter()
+        .map(|c| {
+            let parts: Vec<&str> = c["path"]
+                .as_array()
+                .context("Expected a candidate path")?
+                .iter()
+                .filter_map(|p| p.as_str())
+                .collect();
+            Ok((
+                crate::flake::join_attr_path(&parts),
+                c["exists"].as_bool().unwrap_or(false),
+            ))
+        })
+        .collect()
+}
+
+/// The paths evaluation tries for `attr`, in the order `findAttrPath` in
+/// helpers.nix tries them, each with whether it exists in the flake.
+pub fn attr_path_candidates(flake_dir: &Path, attr: &str) -> Result<Vec<(String, bool)>> {
+    let preamble = get_eval_preamble(flake_dir)?;
+    let nix_expr = attr_path_candidates_expr(&preamble, attr);
+
+    let mut cmd = crate::command::NixCommand::new("nix-instantiate");
+    cmd.args([
+        "--eval",
+        "--json",
+        "--strict",
+        "--read-write-mode",
+        "--expr",
+        &nix_expr,
+    ]);
+    parse_attr_path_candidates(&cmd.json()?)
+}
+
 /// Get the main program name for a package.
 ///
 /// Determines the executable name by inspecting the package's metadata
 /// (meta.mainProgram, pname, or name).
 pub fn get_package_main_program(flake_dir: &Path, attr: &str) -> Result<String> {
+    main_program(flake_dir, "outputs", attr)
+}
+
+/// Like [`get_package_main_program`], for a package of the flake's root
+/// input `input`.
+pub fn get_input_main_program(flake_dir: &Path, input: &str, attr: &str) -> Result<String> {
+    main_program(flake_dir, &input_outputs_expr(input), attr)
+}
+
+/// The outputs of root input `input`, given the eval preamble's bindings.
+fn input_outputs_expr(input: &str) -> String {
+    format!("context.inputs.{}", nix_string(input))
+}
+
+/// Main program of `attr` in `outputs`, a Nix expression over the eval
+/// preamble's bindings.
+fn main_program(flake_dir: &Path, outputs: &str, attr: &str) -> Result<String> {
     let nix_dir = get_nix_dir()?;
     let preamble = get_eval_preamble(flake_dir)?;
 
@@ -554,14 +1302,16 @@ pub fn get_package_main_program(flake_dir: &Path, attr: &str) -> Result<String>
         r#"
     let
       {preamble}
-    in import {nix_dir}/get_package_main_program.nix {{
-      inherit outputs resolveAttrPath;
-      attr = "{attr}";
+    in import ({nix_dir} + "/get_package_main_program.nix") {{
+      inherit resolveAttrPath;
+      outputs = {outputs};
+      attr = {attr};
     }}
     "#,
         preamble = preamble,
-        nix_dir = nix_dir.display(),
-        attr = attr,
+        nix_dir = nix_path(&nix_dir),
+        outputs = outputs,
+        attr = nix_string(attr),
     );
 
     let mut cmd = crate::command::NixCommand::new("nix-instantiate");
@@ -575,15 +1325,25 @@ pub fn get_package_main_program(flake_dir: &Path, attr: &str) -> Result<String>
 /// Run nix repl with flake context loaded. Replaces current process.
 pub fn run_nix_repl(flake_dir: &Path) -> Result<()> {
     let nix_dir = get_nix_dir()?;
-    let (is_flake, self_info_expr, lock_expr) = prepare_flake_args(flake_dir);
+    let (is_flake, self_info_expr, lock_expr) = prepare_flake_args(flake_dir)?;
 
     let mut cmd = crate::command::NixCommand::new("nix");
     cmd.args(["repl", "--file"]);
     cmd.arg(nix_dir.join("repl.nix"));
-    cmd.args(["--arg", "flakeDir", &flake_dir.display().to_string()]);
+    cmd.args(["--arg", "flakeDir", &nix_path(flake_dir)]);
     cmd.args(["--arg", "isFlake", if is_flake { "true" } else { "false" }]);
     cmd.args(["--arg", "selfInfo", &self_info_expr]);
     cmd.args(["--arg", "lock", &lock_expr]);
+    cmd.args([
+        "--arg",
+        "prefetched",
+        &crate::fetch::prefetched_expr(flake_dir),
+    ]);
+    cmd.args([
+        "--arg",
+        "mirrors",
+        &crate::fetch::mirrors_expr(&crate::fetch::mirrors()),
+    ]);
 
     cmd.exec()
 }
@@ -593,11 +1353,48 @@ pub fn get_derivation_path(flake_dir: &Path, attr: &str) -> Result<String> {
     let nix_dir = get_nix_dir()?;
 
     let mut cmd = crate::command::NixCommand::new("nix-instantiate");
-    setup_eval_command(&mut cmd, &nix_dir, flake_dir, attr);
+    setup_eval_command(&mut cmd, &nix_dir, flake_dir, attr)?;
 
     cmd.output()
 }
 
+/// What realising some store paths would take, as `nix-store --dry-run` reports it.
+#[derive(Debug, Default, PartialEq)]
+pub struct MissingPaths {
+    /// Derivations that would be built
+    pub will_build: Vec<String>,
+    /// Paths that would be downloaded from a substituter
+    pub will_fetch: Vec<String>,
+}
+
+/// Parse the report of `nix-store --realise --dry-run` (on stderr).
+fn parse_dry_run(stderr: &str) -> MissingPaths {
+    let mut missing = MissingPaths::default();
+    let mut section: Option<&mut Vec<String>> = None;
+    for line in stderr.lines() {
+        if line.contains(" will be built") {
+            section = Some(&mut missing.will_build);
+        } else if line.contains(" will be fetched") {
+            section = Some(&mut missing.will_fetch);
+        } else if let (Some(paths), Some(path)) = (section.as_mut(), line.strip_prefix("  ")) {
+            paths.push(path.trim().to_string());
+        } else {
+            section = None;
+        }
+    }
+    missing
+}
+
+/// Find out which of `paths` (derivations or outputs) would have to be
+/// built or fetched, without realising anything.
+pub fn query_missing(paths: &[String]) -> Result<MissingPaths> {
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--realise", "--dry-run"]);
+    cmd.args(paths);
+    let (_, stderr) = cmd.output_with_stderr()?;
+    Ok(parse_dry_run(&stderr))
+}
+
 /// Get the output store path from a derivation path.
 pub fn get_store_path_from_drv(drv_path: &str) -> Result<String> {
     let mut cmd = crate::command::NixCommand::new("nix-store");
@@ -614,6 +1411,64 @@ pub fn get_build_log(store_path: &str) -> Option<String> {
     cmd.output().ok()
 }
 
+/// Get the prefix of the build directory nix uses for a derivation.
+///
+/// Nix names build directories `nix-build-<name>.drv-<n>`, where `<name>`
+/// is the derivation file name without its store hash.
+fn kept_build_dir_prefix(drv_path: &str) -> Option<String> {
+    let file_name = Path::new(drv_path).file_name()?.to_str()?;
+    let (_hash, name) = file_name.split_once('-')?;
+    if !name.ends_with(".drv") {
+        return None;
+    }
+    Some(format!("nix-build-{}-", name))
+}
+
+/// Find the build directory kept by `--keep-failed` for a derivation.
+///
+/// Returns the most recently modified match in the temp directories.
+pub fn find_kept_build_dir(drv_path: &str) -> Option<PathBuf> {
+    let prefix = kept_build_dir_prefix(drv_path)?;
+
+    let mut search_dirs = vec![PathBuf::from("/tmp"), env::temp_dir()];
+    search_dirs.dedup();
+
+    search_dirs
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((modified, entry.path()))
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+/// Get the build environment of a derivation.
+pub fn get_derivation_env(drv_path: &str) -> Result<HashMap<String, String>> {
+    let mut cmd = crate::command::NixCommand::new("nix");
+    cmd.args(["derivation", "show", drv_path]);
+
+    let json: serde_json::Value = cmd.json()?;
+    let drv = json
+        .as_object()
+        .and_then(|m| m.values().next())
+        .context("Unexpected output from nix derivation show")?;
+
+    let env = drv
+        .get("env")
+        .and_then(|e| e.as_object())
+        .context("Derivation has no environment")?;
+
+    Ok(env
+        .iter()
+        .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+        .collect())
+}
+
 /// Get the structure of flake outputs.
 pub fn eval_flake_outputs(
     flake_dir: &Path,
@@ -674,7 +1529,7 @@ pub fn eval_flake_output_category(
       {preamble}
       allSystemsFlag = {all_systems_nix};
       showLegacyFlag = {show_legacy_nix};
-    in import {nix_dir}/eval_category.nix {{
+    in import ({nix_dir} + "/eval_category.nix") {{
       inherit outputs allSystemsFlag showLegacyFlag;
       category = "{category}";
     }}
@@ -682,7 +1537,7 @@ pub fn eval_flake_output_category(
         preamble = preamble,
         all_systems_nix = all_systems_nix,
         show_legacy_nix = show_legacy_nix,
-        nix_dir = nix_dir.display(),
+        nix_dir = nix_path(&nix_dir),
         category = category
     );
 
@@ -711,12 +1566,12 @@ pub fn get_flake_output_categories(flake_dir: &Path) -> Result<Option<Vec<String
         r#"
     let
       {preamble}
-    in import {nix_dir}/get_categories.nix {{
+    in import ({nix_dir} + "/get_categories.nix") {{
       inherit outputs;
     }}
     "#,
         preamble = preamble,
-        nix_dir = nix_dir.display(),
+        nix_dir = nix_path(&nix_dir),
     );
 
     let mut cmd = crate::command::NixCommand::new("nix-instantiate");
@@ -771,6 +1626,28 @@ mod tests {
         assert!(!env.contains_key("TMPDIR"));
     }
 
+    #[test]
+    fn test_parse_shell_env() {
+        let output = "PATH=/nix/store/abc-cargo/bin:/usr/bin\0HOME=/home/me\0\
+                      SHLVL=2\0shellHook=echo hi\nexport A=1\0";
+        let current = |name: &str| match name {
+            "PATH" => Some("/usr/bin".to_string()),
+            "HOME" => Some("/home/me".to_string()),
+            _ => None,
+        };
+        let env = parse_shell_env(output, current);
+        assert_eq!(
+            env.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "PATH".to_string(),
+                    "/nix/store/abc-cargo/bin:/usr/bin".to_string()
+                ),
+                ("shellHook".to_string(), "echo hi\nexport A=1".to_string()),
+            ]
+        );
+    }
+
     #[test]
     fn test_get_nix_dir() {
         let nix_dir = get_nix_dir().expect("Failed to get nix dir");
@@ -801,6 +1678,48 @@ mod tests {
             attr_to_nix_list("packages.x86_64-linux.hello"),
             "[\"packages\" \"x86_64-linux\" \"hello\"]"
         );
+        assert_eq!(
+            attr_to_nix_list("packages.x86_64-linux.\"weird.name\""),
+            "[\"packages\" \"x86_64-linux\" \"weird.name\"]"
+        );
+    }
+
+    #[test]
+    fn test_nix_string() {
+        assert_eq!(nix_string("hello"), "\"hello\"");
+        assert_eq!(nix_string("a\"b"), "\"a\\\"b\"");
+        assert_eq!(nix_string("${x}"), "\"\\${x}\"");
+    }
+
+    #[test]
+    fn test_nix_path() {
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(nix_path(Path::new("/")), "/.");
+        assert_eq!(
+            nix_path(Path::new("/home/user/my-flake_2.0+x")),
+            "/home/user/my-flake_2.0+x"
+        );
+        assert_eq!(
+            nix_path(Path::new("/home/user/My Projects/flake")),
+            r#"(/. + "/home/user/My Projects/flake")"#
+        );
+        assert_eq!(
+            nix_path(Path::new(r#"/tmp/it's "${here}"/"#)),
+            r#"(/. + "/tmp/it's \"\${here}\"")"#
+        );
+        assert_eq!(nix_path(Path::new("/srv/café")), r#"(/. + "/srv/café")"#);
+
+        let latin1 = Path::new(std::ffi::OsStr::from_bytes(b"/srv/caf\xe9"));
+        let expr = nix_path(latin1);
+        let (name, value) = path_envs()
+            .into_iter()
+            .find(|(_, p)| p == latin1.as_os_str())
+            .unwrap();
+        assert_eq!(expr, format!(r#"(/. + builtins.getEnv "{}")"#, name));
+        assert_eq!(value, latin1.as_os_str());
+        // The same path reuses its variable
+        assert_eq!(nix_path(latin1), expr);
     }
 
     #[test]
@@ -813,6 +1732,137 @@ mod tests {
         assert!(opts.command.is_none());
     }
 
+    #[test]
+    fn test_parse_impure_trace() {
+        let stderr = r#"trace: trix-attr:
+trace: trix-impure:{"file":"/src/flake.nix","message":"reads environment variable HOME"}
+warning: Git tree '/src' is dirty
+trace: trix-attr:packages.x86_64-linux.hello
+trace: trix-impure:{"file":"/src/flake.nix","message":"reads environment variable HOME"}
+trace: trix-impure:{"file":"/src/pkgs/hello.nix","message":"uses builtins.currentSystem"}
+trace: trix-impure:not json
+trace: something the flake printed
+"#;
+        let access = |attr: &str, file: &str, message: &str| ImpureAccess {
+            attr: attr.to_string(),
+            file: file.to_string(),
+            message: message.to_string(),
+        };
+        assert_eq!(
+            parse_impure_trace(stderr),
+            vec![
+                access("", "/src/flake.nix", "reads environment variable HOME"),
+                access(
+                    "packages.x86_64-linux.hello",
+                    "/src/pkgs/hello.nix",
+                    "uses builtins.currentSystem"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_attr_path_in_nix() {
+        let helpers = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/resources/helpers.nix");
+        let parts = [
+            "a b",
+            "c\\d",
+            "e\"f",
+            "${g}",
+            "h\ni",
+            "weird.name",
+            "0",
+            "plain",
+        ];
+        let attr = crate::flake::join_attr_path(&parts);
+        let value = eval_expr(&format!(
+            "(import {}).splitAttrPath {}",
+            nix_path(&helpers),
+            nix_string(&attr)
+        ))
+        .unwrap();
+        assert_eq!(value, serde_json::json!(parts));
+    }
+
+    #[test]
+    fn test_attr_path_candidates() {
+        let helpers = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/resources/helpers.nix");
+        let system = get_system().unwrap();
+        let candidates = |outputs: &str, attr: &str| {
+            let preamble = format!(
+                "helpers = import {}; outputs = {};",
+                nix_path(&helpers),
+                outputs.replace("SYS", &system)
+            );
+            let value = eval_expr(&attr_path_candidates_expr(&preamble, attr)).unwrap();
+            parse_attr_path_candidates(&value)
+                .unwrap()
+                .into_iter()
+                .map(|(path, exists)| (path.replace(&system, "SYS"), exists))
+                .collect::<Vec<_>>()
+        };
+        let tried = |paths: &[(&str, bool)]| {
+            paths
+                .iter()
+                .map(|(p, e)| (p.to_string(), *e))
+                .collect::<Vec<_>>()
+        };
+
+        // packages, then legacyPackages, then the bare path
+        assert_eq!(
+            candidates("{ packages.SYS.hello = 1; }", "hello"),
+            tried(&[
+                ("packages.SYS.hello", true),
+                ("legacyPackages.SYS.hello", false),
+                ("hello", false),
+            ])
+        );
+        assert_eq!(
+            candidates("{ legacyPackages.SYS.hello = 1; }", "hello"),
+            tried(&[
+                ("packages.SYS.hello", false),
+                ("legacyPackages.SYS.hello", true),
+                ("hello", false),
+            ])
+        );
+        assert_eq!(
+            candidates("{ hello = 1; }", "hello"),
+            tried(&[
+                ("packages.SYS.hello", false),
+                ("legacyPackages.SYS.hello", false),
+                ("hello", true),
+            ])
+        );
+        // A full packages path falls back to legacyPackages when the flake
+        // has no packages output
+        assert_eq!(
+            candidates(
+                "{ legacyPackages.SYS.hello = 1; }",
+                &format!("packages.{}.hello", system)
+            ),
+            tried(&[("legacyPackages.SYS.hello", true)])
+        );
+    }
+
+    #[test]
+    fn test_smoke_check_overlay() {
+        let smoke = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/resources/smoke_check.nix");
+        // An overlay that needs prev.lib and prev.callPackage to apply
+        let expr = format!(
+            r#"
+            let
+              lib = {{ optionalAttrs = cond: attrs: if cond then attrs else {{ }}; }};
+              smoke = import {} {{ inputs.nixpkgs.lib = lib; }};
+            in
+            smoke.overlay (final: prev: prev.lib.optionalAttrs true {{
+              hello = prev.callPackage ({{ lib, system }}: system) {{ }};
+            }})
+            "#,
+            nix_path(&smoke)
+        );
+        assert_eq!(eval_expr(&expr).unwrap(), serde_json::json!(["hello"]));
+    }
+
     #[test]
     fn test_eval_expr_simple() {
         let result = eval_expr("1 + 1").expect("Failed to eval expr");
@@ -865,6 +1915,138 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_explain_eval_error_generated_expr() {
+        let expr = "let\n  # trix: flake inputs and outputs\n  x = 1;\n# trix: attribute selection\nin x.y";
+        let msg = "error: value is an integer while a set was expected\n       at «string»:5:4:";
+        let note = explain_eval_error(msg, expr, false).unwrap();
+        assert!(note.contains("generated expression (attribute selection), line 5"));
+        assert!(note.contains(">    5 | in x.y"));
+
+        // No position: nothing to add
+        assert!(explain_eval_error("error: boom", expr, false).is_none());
+    }
+
+    #[test]
+    fn test_explain_eval_error_user_file() {
+        let dir = tempdir().unwrap();
+        let flake = dir.path().join("flake.nix");
+        std::fs::write(
+            &flake,
+            "{\n  outputs = _: {\n    x = throw \"boom\";\n  };\n}\n",
+        )
+        .unwrap();
+
+        let msg = format!(
+            "error: boom\n       at «string»:2:1:\n       at {}:3:9:",
+            flake.display()
+        );
+        let note = explain_eval_error(&msg, "", false).unwrap();
+        assert!(note.contains(&format!("error in {} line 3", flake.display())));
+        assert!(note.contains(">    3 |     x = throw \"boom\";"));
+        assert!(note.contains("     1 | {"));
+    }
+
+    #[test]
+    fn test_explain_eval_error_user_expr() {
+        let note = explain_eval_error("error: boom\n at (string):1:1", "1 + x", true).unwrap();
+        assert!(note.contains("error in --expr line 1"));
+    }
+
+    #[test]
+    fn test_parse_dry_run() {
+        let stderr = "these 2 derivations will be built:\n  \
+                      /nix/store/aaa-hello-2.12.1.drv\n  \
+                      /nix/store/bbb-hello-wrapper.drv\n\
+                      this path will be fetched (0.05 MiB download, 0.21 MiB unpacked):\n  \
+                      /nix/store/ccc-glibc-2.39\n\
+                      warning: something else\n";
+        assert_eq!(
+            parse_dry_run(stderr),
+            MissingPaths {
+                will_build: vec![
+                    "/nix/store/aaa-hello-2.12.1.drv".to_string(),
+                    "/nix/store/bbb-hello-wrapper.drv".to_string(),
+                ],
+                will_fetch: vec!["/nix/store/ccc-glibc-2.39".to_string()],
+            }
+        );
+        assert_eq!(parse_dry_run(""), MissingPaths::default());
+    }
+
+    #[test]
+    fn test_parse_nix_version() {
+        assert_eq!(
+            parse_nix_version("nix-instantiate (Nix) 2.18.1\n"),
+            Some((2, 18, 1))
+        );
+        assert_eq!(
+            parse_nix_version("nix (Nix) 2.25.0pre20241101_dirty"),
+            Some((2, 25, 0))
+        );
+        assert_eq!(
+            parse_nix_version("nix-instantiate (Nix) 2.3"),
+            Some((2, 3, 0))
+        );
+        assert_eq!(parse_nix_version("garbage"), None);
+    }
+
+    #[test]
+    fn test_nix_capabilities_gating() {
+        let old = NixCapabilities {
+            version: Some((2, 3, 16)),
+            has_nix_command: true,
+            ..Default::default()
+        };
+        assert!(!old.supports_experimental_features());
+        let err = old.require_nix_command("Locking inputs").unwrap_err();
+        assert!(err.to_string().contains("found Nix 2.3.16"));
+
+        let missing = NixCapabilities {
+            version: Some((2, 18, 1)),
+            ..Default::default()
+        };
+        assert!(missing.supports_experimental_features());
+        assert!(missing.require_nix_command("Locking inputs").is_err());
+
+        let modern = NixCapabilities {
+            version: Some((2, 18, 1)),
+            has_nix_command: true,
+            ..Default::default()
+        };
+        assert!(modern.require_nix_command("Locking inputs").is_ok());
+
+        // Unknown version is assumed recent
+        assert!(NixCapabilities::default().supports_experimental_features());
+    }
+
+    #[test]
+    fn test_parse_experimental_features() {
+        let mut features = Vec::new();
+        parse_experimental_features(
+            "# comment\nexperimental-features = nix-command\nextra-experimental-features = flakes nix-command\n",
+            &mut features,
+        );
+        assert_eq!(features, vec!["nix-command", "flakes"]);
+
+        parse_experimental_features("experimental-features = ca-derivations", &mut features);
+        assert_eq!(features, vec!["ca-derivations"]);
+    }
+
+    #[test]
+    fn test_kept_build_dir_prefix() {
+        assert_eq!(
+            kept_build_dir_prefix("/nix/store/0123456789abcdfghijklmnpqrsvwxyz-hello-2.12.drv"),
+            Some("nix-build-hello-2.12.drv-".to_string())
+        );
+        assert_eq!(kept_build_dir_prefix("/nix/store/abc-hello"), None);
+    }
+
+    #[test]
+    fn test_find_kept_build_dir_missing() {
+        assert!(find_kept_build_dir("/nix/store/0000-trix-test-no-such-build.drv").is_none());
+    }
+
     #[test]
     fn test_get_lock_expr() {
         let dir = tempdir().expect("Failed to create temp dir");
@@ -0,0 +1,209 @@
+//! Writing store closures as OCI images (`trix develop --containerize`).
+//!
+//! The image is a single uncompressed layer holding the closure under
+//! `/nix/store` plus a world-writable `/tmp`, written as an OCI image
+//! layout directory that `skopeo`, `podman` and friends can read. The
+//! layer is made with GNU tar, normalised so the same closure always
+//! gives the same image.
+
+use crate::hash::{format_hash, hash_file, HashAlgo, HashFormat};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+/// What goes into an image besides the closure.
+pub struct ImageConfig {
+    /// `NAME=value` environment
+    pub env: Vec<String>,
+    /// Command run by default
+    pub cmd: Vec<String>,
+    /// Name recorded as the image's ref in index.json (e.g. "latest")
+    pub tag: String,
+}
+
+/// The OCI name of the architecture trix was built for.
+fn oci_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        "powerpc64" => "ppc64le",
+        other => other,
+    }
+}
+
+/// Store `path` as a blob, returning its digest and size.
+fn add_blob(layout: &Path, path: &Path) -> Result<(String, u64)> {
+    let digest = format_hash(
+        HashAlgo::Sha256,
+        &hash_file(path, HashAlgo::Sha256)?,
+        HashFormat::Base16,
+    );
+    let size = fs::metadata(path)?.len();
+    fs::rename(path, layout.join("blobs/sha256").join(&digest))
+        .with_context(|| format!("Failed to store blob {}", digest))?;
+    Ok((format!("sha256:{}", digest), size))
+}
+
+/// Store JSON as a blob, returning its digest and size.
+fn add_json_blob(layout: &Path, value: &serde_json::Value) -> Result<(String, u64)> {
+    let path = layout.join("blobs/sha256/.tmp");
+    fs::write(&path, serde_json::to_vec(value)?)?;
+    add_blob(layout, &path)
+}
+
+/// Write the layer tarball: `/tmp` and every store path in `closure`.
+fn write_layer(out: &Path, closure: &[String]) -> Result<()> {
+    let staging = crate::scratch::tempdir()?;
+    let tmp = staging.path().join("tmp");
+    fs::create_dir(&tmp)?;
+    fs::set_permissions(&tmp, std::os::unix::fs::PermissionsExt::from_mode(0o1777))?;
+
+    let mut cmd = std::process::Command::new("tar");
+    cmd.arg("--create")
+        .arg("--file")
+        .arg(out)
+        .args([
+            "--sort=name",
+            "--numeric-owner",
+            "--owner=0",
+            "--group=0",
+            "--mtime=@1",
+            "--format=posix",
+            "--pax-option=exthdr.name=%d/PaxHeaders/%f,delete=atime,delete=ctime",
+        ])
+        .arg("-C")
+        .arg(staging.path())
+        .arg("tmp");
+    if !closure.is_empty() {
+        cmd.args(["-C", "/"]);
+        cmd.args(closure.iter().map(|p| p.trim_start_matches('/')));
+    }
+
+    let status = cmd
+        .status()
+        .context("Failed to run tar (GNU tar is needed)")?;
+    if !status.success() {
+        anyhow::bail!("tar failed to write the image layer");
+    }
+    Ok(())
+}
+
+/// Write an OCI image layout with `closure` and `config` to `layout`,
+/// which must not exist yet. Returns the manifest digest.
+pub fn write_image(layout: &Path, closure: &[String], config: &ImageConfig) -> Result<String> {
+    if layout.exists() {
+        anyhow::bail!("{} already exists", layout.display());
+    }
+    fs::create_dir_all(layout.join("blobs/sha256"))
+        .with_context(|| format!("Failed to create {}", layout.display()))?;
+
+    let layer_path = layout.join("blobs/sha256/.layer");
+    write_layer(&layer_path, closure)?;
+    let (layer_digest, layer_size) = add_blob(layout, &layer_path)?;
+
+    let image_config = json!({
+        "architecture": oci_arch(),
+        "os": "linux",
+        "config": {
+            "Env": config.env,
+            "Cmd": config.cmd,
+            "WorkingDir": "/",
+        },
+        "rootfs": {
+            // An uncompressed layer's diff ID is its digest
+            "type": "layers",
+            "diff_ids": [layer_digest],
+        },
+        "history": [{ "created_by": format!("trix {}", env!("CARGO_PKG_VERSION")) }],
+    });
+    let (config_digest, config_size) = add_json_blob(layout, &image_config)?;
+
+    let manifest = json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "config": {
+            "mediaType": CONFIG_MEDIA_TYPE,
+            "digest": config_digest,
+            "size": config_size,
+        },
+        "layers": [{
+            "mediaType": LAYER_MEDIA_TYPE,
+            "digest": layer_digest,
+            "size": layer_size,
+        }],
+    });
+    let (manifest_digest, manifest_size) = add_json_blob(layout, &manifest)?;
+
+    fs::write(
+        layout.join("oci-layout"),
+        serde_json::to_vec(&json!({ "imageLayoutVersion": "1.0.0" }))?,
+    )?;
+    fs::write(
+        layout.join("index.json"),
+        serde_json::to_vec_pretty(&json!({
+            "schemaVersion": 2,
+            "manifests": [{
+                "mediaType": MANIFEST_MEDIA_TYPE,
+                "digest": manifest_digest,
+                "size": manifest_size,
+                "annotations": { "org.opencontainers.image.ref.name": config.tag },
+            }],
+        }))?,
+    )?;
+    Ok(manifest_digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = dir.path().join("image");
+        let config = ImageConfig {
+            env: vec!["PATH=/nix/store/x-hello/bin".to_string()],
+            cmd: vec!["/bin/sh".to_string()],
+            tag: "latest".to_string(),
+        };
+        let digest = write_image(&layout, &[], &config).unwrap();
+
+        let index: serde_json::Value =
+            serde_json::from_slice(&fs::read(layout.join("index.json")).unwrap()).unwrap();
+        assert_eq!(index["manifests"][0]["digest"], digest);
+        let blob = |digest: &str| {
+            let file = digest.strip_prefix("sha256:").unwrap();
+            let bytes = fs::read(layout.join("blobs/sha256").join(file)).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes)
+        };
+        let manifest = blob(&digest).unwrap();
+        let image_config = blob(manifest["config"]["digest"].as_str().unwrap()).unwrap();
+        assert_eq!(
+            image_config["config"]["Env"][0],
+            "PATH=/nix/store/x-hello/bin"
+        );
+        assert_eq!(
+            image_config["rootfs"]["diff_ids"][0],
+            manifest["layers"][0]["digest"]
+        );
+
+        // Every blob is stored under its own digest
+        for entry in fs::read_dir(layout.join("blobs/sha256")).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            let hash = hash_file(&path, HashAlgo::Sha256).unwrap();
+            assert_eq!(
+                format_hash(HashAlgo::Sha256, &hash, HashFormat::Base16),
+                name
+            );
+        }
+
+        assert!(write_image(&layout, &[], &config).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! Where trix keeps its files.
+//!
+//! Locations follow the XDG base directory specification on every
+//! platform, like nix itself, and each can be moved with an environment
+//! variable:
+//!
+//! | Files   | Override          | Default                                        |
+//! |---------|-------------------|------------------------------------------------|
+//! | cache   | `TRIX_CACHE_DIR`  | `$XDG_CACHE_HOME/trix` (`~/.cache/trix`)       |
+//! | state   | `TRIX_STATE_DIR`  | `$XDG_STATE_HOME/trix` (`~/.local/state/trix`) |
+//! | scratch | `TRIX_TEMP_DIR`   | `$XDG_RUNTIME_DIR/trix`, else `/tmp/trix-$USER` |
+//!
+//! Relative values are ignored, as the XDG specification requires.
+//!
+//! Earlier versions used the platform directories instead, which on macOS
+//! are under `~/Library`. A cache or state directory found only there is
+//! moved to the new location on first use, so trust decisions survive.
+
+use once_cell::sync::OnceCell;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Reads an environment variable; a parameter so tests need not touch the real environment.
+type Env<'a> = &'a dyn Fn(&str) -> Option<OsString>;
+
+fn real_env(var: &str) -> Option<OsString> {
+    std::env::var_os(var)
+}
+
+/// An absolute directory from `var`, if set.
+fn env_dir(env: Env, var: &str) -> Option<PathBuf> {
+    env(var)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
+/// `$var`, or `fallback` under the home directory.
+fn xdg_dir(env: Env, home: Option<&Path>, var: &str, fallback: &str) -> Option<PathBuf> {
+    env_dir(env, var).or_else(|| home.map(|h| h.join(fallback)))
+}
+
+fn resolve_cache_dir(env: Env, home: Option<&Path>) -> Option<PathBuf> {
+    env_dir(env, "TRIX_CACHE_DIR")
+        .or_else(|| xdg_dir(env, home, "XDG_CACHE_HOME", ".cache").map(|d| d.join("trix")))
+}
+
+fn resolve_state_dir(env: Env, home: Option<&Path>) -> Option<PathBuf> {
+    env_dir(env, "TRIX_STATE_DIR")
+        .or_else(|| xdg_dir(env, home, "XDG_STATE_HOME", ".local/state").map(|d| d.join("trix")))
+}
+
+fn resolve_temp_dir(env: Env) -> PathBuf {
+    // Use /tmp rather than TMPDIR as the fallback, since TMPDIR may point
+    // into a nix-shell temp dir that disappears
+    env_dir(env, "TRIX_TEMP_DIR")
+        .or_else(|| env_dir(env, "XDG_RUNTIME_DIR").map(|d| d.join("trix")))
+        .unwrap_or_else(|| {
+            let user = env("USER")
+                .map(|u| u.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "default".to_string());
+            PathBuf::from("/tmp").join(format!("trix-{}", user))
+        })
+}
+
+/// Move `legacy` to `current` if only `legacy` exists, and return the
+/// directory to use: `current`, or `legacy` if it could not be moved.
+fn migrate(legacy: Option<PathBuf>, current: PathBuf) -> PathBuf {
+    let Some(legacy) = legacy.filter(|l| *l != current && l.is_dir()) else {
+        return current;
+    };
+    if current.exists() {
+        return current;
+    }
+    let moved = current
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::rename(&legacy, &current));
+    match moved {
+        Ok(()) => {
+            tracing::info!("Moved {} to {}", legacy.display(), current.display());
+            current
+        }
+        Err(e) => {
+            tracing::debug!(
+                "Failed to move {} to {}: {}",
+                legacy.display(),
+                current.display(),
+                e
+            );
+            legacy
+        }
+    }
+}
+
+/// Directory for caches that can be deleted at any time.
+pub fn cache_dir() -> Option<PathBuf> {
+    static DIR: OnceCell<Option<PathBuf>> = OnceCell::new();
+    DIR.get_or_init(|| {
+        let dir = resolve_cache_dir(&real_env, dirs::home_dir().as_deref())?;
+        if env_dir(&real_env, "TRIX_CACHE_DIR").is_some() {
+            return Some(dir);
+        }
+        Some(migrate(dirs::cache_dir().map(|d| d.join("trix")), dir))
+    })
+    .clone()
+}
+
+/// Directory for state worth keeping, like trust decisions.
+pub fn state_dir() -> Option<PathBuf> {
+    static DIR: OnceCell<Option<PathBuf>> = OnceCell::new();
+    DIR.get_or_init(|| {
+        let dir = resolve_state_dir(&real_env, dirs::home_dir().as_deref())?;
+        if env_dir(&real_env, "TRIX_STATE_DIR").is_some() {
+            return Some(dir);
+        }
+        let legacy = dirs::state_dir().or_else(dirs::data_local_dir);
+        Some(migrate(legacy.map(|d| d.join("trix")), dir))
+    })
+    .clone()
+}
+
+/// Directory for temporary files (see [`crate::scratch`]).
+pub fn temp_dir() -> PathBuf {
+    resolve_temp_dir(&real_env)
+}
+
+/// Nix's per-user configuration directory (`$XDG_CONFIG_HOME/nix`).
+pub fn nix_config_dir() -> Option<PathBuf> {
+    xdg_dir(
+        &real_env,
+        dirs::home_dir().as_deref(),
+        "XDG_CONFIG_HOME",
+        ".config",
+    )
+    .map(|d| d.join("nix"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars: HashMap<String, OsString> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), OsString::from(v)))
+            .collect();
+        move |var| vars.get(var).cloned()
+    }
+
+    #[test]
+    fn test_resolve_dirs() {
+        let home = Some(Path::new("/home/alice"));
+
+        let env = env_of(&[("USER", "alice")]);
+        assert_eq!(
+            resolve_cache_dir(&env, home),
+            Some(PathBuf::from("/home/alice/.cache/trix"))
+        );
+        assert_eq!(
+            resolve_state_dir(&env, home),
+            Some(PathBuf::from("/home/alice/.local/state/trix"))
+        );
+        assert_eq!(resolve_temp_dir(&env), PathBuf::from("/tmp/trix-alice"));
+        assert_eq!(resolve_cache_dir(&env, None), None);
+
+        let env = env_of(&[
+            ("XDG_CACHE_HOME", "/var/cache/alice"),
+            ("XDG_STATE_HOME", "relative/state"),
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+        ]);
+        assert_eq!(
+            resolve_cache_dir(&env, home),
+            Some(PathBuf::from("/var/cache/alice/trix"))
+        );
+        assert_eq!(
+            resolve_state_dir(&env, home),
+            Some(PathBuf::from("/home/alice/.local/state/trix"))
+        );
+        assert_eq!(resolve_temp_dir(&env), PathBuf::from("/run/user/1000/trix"));
+
+        let env = env_of(&[
+            ("XDG_CACHE_HOME", "/var/cache/alice"),
+            ("TRIX_CACHE_DIR", "/ci/cache"),
+            ("TRIX_STATE_DIR", "/ci/state"),
+            ("TRIX_TEMP_DIR", "/ci/tmp"),
+        ]);
+        assert_eq!(
+            resolve_cache_dir(&env, home),
+            Some(PathBuf::from("/ci/cache"))
+        );
+        assert_eq!(
+            resolve_state_dir(&env, home),
+            Some(PathBuf::from("/ci/state"))
+        );
+        assert_eq!(resolve_temp_dir(&env), PathBuf::from("/ci/tmp"));
+    }
+
+    #[test]
+    fn test_migrate() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("Library/trix");
+        let current = dir.path().join(".local/state/trix");
+
+        // Nothing to move
+        assert_eq!(migrate(Some(legacy.clone()), current.clone()), current);
+        assert_eq!(migrate(None, current.clone()), current);
+
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::write(legacy.join("trust.json"), "{}").unwrap();
+        assert_eq!(migrate(Some(legacy.clone()), current.clone()), current);
+        assert!(current.join("trust.json").is_file());
+        assert!(!legacy.exists());
+
+        // An existing new directory wins over a leftover old one
+        std::fs::create_dir_all(&legacy).unwrap();
+        assert_eq!(migrate(Some(legacy.clone()), current.clone()), current);
+        assert!(legacy.exists());
+    }
+}
@@ -0,0 +1,229 @@
+//! CI shard planning.
+//!
+//! Enumerates buildable flake outputs and splits them into balanced shards,
+//! weighted by build durations recorded locally by previous trix builds.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Weight used for outputs without a recorded build duration.
+const DEFAULT_WEIGHT: f64 = 1.0;
+
+/// A buildable flake output and its estimated cost.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PlanItem {
+    pub attr: String,
+    pub weight: f64,
+}
+
+/// A group of outputs to build together on one CI runner.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Shard {
+    pub index: usize,
+    pub weight: f64,
+    pub attrs: Vec<String>,
+}
+
+/// Get the path of the local build duration cache.
+fn get_build_times_path() -> Option<PathBuf> {
+    crate::paths::cache_dir().map(|d| d.join("build-times.json"))
+}
+
+/// Load recorded build durations (attr path -> seconds).
+pub fn load_build_times() -> HashMap<String, f64> {
+    get_build_times_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Record build durations for attribute paths, merging with previous entries.
+pub fn record_build_times(times: &[(String, f64)]) -> Result<()> {
+    if times.is_empty() {
+        return Ok(());
+    }
+    let Some(path) = get_build_times_path() else {
+        return Ok(());
+    };
+
+    let mut all = load_build_times();
+    for (attr, secs) in times {
+        all.insert(attr.clone(), *secs);
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&all)?)?;
+
+    Ok(())
+}
+
+/// Collect buildable attribute paths from the structure returned by
+/// `eval_flake_outputs`.
+///
+/// Includes packages and checks for every listed system, and the
+/// toplevel of each nixosConfiguration.
+pub fn collect_buildable_attrs(outputs: &serde_json::Value) -> Vec<String> {
+    let mut attrs = Vec::new();
+
+    for category in ["packages", "checks"] {
+        let Some(systems) = outputs.get(category).and_then(|c| c.as_object()) else {
+            continue;
+        };
+        for (system, items) in systems {
+            let Some(items) = items.as_object() else {
+                continue;
+            };
+            for (name, info) in items {
+                // Skip markers and known non-derivations
+                if name.starts_with('_') {
+                    continue;
+                }
+                let kind = info.get("_type").and_then(|t| t.as_str());
+                if kind.is_none_or(|k| k == "derivation") {
+                    attrs.push(crate::flake::join_attr_path(&[category, system, name]));
+                }
+            }
+        }
+    }
+
+    if let Some(configs) = outputs
+        .get("nixosConfigurations")
+        .and_then(|c| c.as_object())
+    {
+        for name in configs.keys().filter(|k| !k.starts_with('_')) {
+            attrs.push(format!(
+                "{}.config.system.build.toplevel",
+                crate::flake::join_attr_path(&["nixosConfigurations", name])
+            ));
+        }
+    }
+
+    attrs.sort();
+    attrs
+}
+
+/// Attach weights to attribute paths from recorded build durations.
+///
+/// Outputs without a recorded duration get the median of the known ones,
+/// or a default weight if nothing has been recorded.
+pub fn weigh_attrs(attrs: &[String], build_times: &HashMap<String, f64>) -> Vec<PlanItem> {
+    let mut known: Vec<f64> = attrs
+        .iter()
+        .filter_map(|a| build_times.get(a).copied())
+        .collect();
+    known.sort_by(|a, b| a.total_cmp(b));
+    let fallback = known
+        .get(known.len() / 2)
+        .copied()
+        .unwrap_or(DEFAULT_WEIGHT);
+
+    attrs
+        .iter()
+        .map(|attr| PlanItem {
+            attr: attr.clone(),
+            weight: build_times.get(attr).copied().unwrap_or(fallback),
+        })
+        .collect()
+}
+
+/// Split items into `count` shards with roughly equal total weight.
+///
+/// Uses the longest-processing-time heuristic: heaviest items first, each
+/// assigned to the currently lightest shard.
+pub fn plan_shards(items: &[PlanItem], count: usize) -> Vec<Shard> {
+    let count = count.max(1);
+    let mut shards: Vec<Shard> = (0..count)
+        .map(|index| Shard {
+            index,
+            weight: 0.0,
+            attrs: Vec::new(),
+        })
+        .collect();
+
+    let mut sorted: Vec<&PlanItem> = items.iter().collect();
+    sorted.sort_by(|a, b| b.weight.total_cmp(&a.weight).then(a.attr.cmp(&b.attr)));
+
+    for item in sorted {
+        let lightest = shards
+            .iter_mut()
+            .min_by(|a, b| a.weight.total_cmp(&b.weight).then(a.index.cmp(&b.index)))
+            .expect("at least one shard");
+        lightest.weight += item.weight;
+        lightest.attrs.push(item.attr.clone());
+    }
+
+    shards
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_buildable_attrs() {
+        let outputs = serde_json::json!({
+            "packages": {
+                "x86_64-linux": {
+                    "hello": { "_type": "derivation", "_name": "hello-2.12" },
+                    "notADrv": { "_type": "unknown" },
+                },
+                "aarch64-linux": { "hello": { "_omitted": true } },
+            },
+            "checks": { "x86_64-linux": { "fmt": { "_type": "derivation" } } },
+            "nixosConfigurations": { "server": { "_type": "configuration" } },
+            "lib": { "_unknown": true },
+        });
+
+        assert_eq!(
+            collect_buildable_attrs(&outputs),
+            vec![
+                "checks.x86_64-linux.fmt",
+                "nixosConfigurations.server.config.system.build.toplevel",
+                "packages.aarch64-linux.hello",
+                "packages.x86_64-linux.hello",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_weigh_attrs() {
+        let attrs = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let times = HashMap::from([("a".to_string(), 10.0), ("b".to_string(), 30.0)]);
+
+        let items = weigh_attrs(&attrs, &times);
+        assert_eq!(items[0].weight, 10.0);
+        assert_eq!(items[1].weight, 30.0);
+        // Unknown gets the median of known durations
+        assert_eq!(items[2].weight, 30.0);
+
+        let items = weigh_attrs(&attrs, &HashMap::new());
+        assert!(items.iter().all(|i| i.weight == DEFAULT_WEIGHT));
+    }
+
+    #[test]
+    fn test_plan_shards() {
+        let items: Vec<PlanItem> = [("a", 8.0), ("b", 5.0), ("c", 4.0), ("d", 3.0)]
+            .iter()
+            .map(|(attr, weight)| PlanItem {
+                attr: attr.to_string(),
+                weight: *weight,
+            })
+            .collect();
+
+        let shards = plan_shards(&items, 2);
+        assert_eq!(shards.len(), 2);
+        assert_eq!(shards[0].attrs, vec!["a", "d"]);
+        assert_eq!(shards[1].attrs, vec!["b", "c"]);
+        assert_eq!(shards[0].weight, 11.0);
+        assert_eq!(shards[1].weight, 9.0);
+
+        // More shards than items leaves some empty
+        let shards = plan_shards(&items[..1], 3);
+        assert_eq!(shards.iter().filter(|s| s.attrs.is_empty()).count(), 2);
+    }
+}
@@ -3,9 +3,11 @@
 //! Compatible with nix profile's manifest.json format (version 3).
 //! Supports both local flake packages (via flake-compat) and remote packages.
 
+use crate::flake::split_attr_path;
 use crate::nix::{get_store_dir, get_system, run_nix_build, BuildOptions};
 use anyhow::{Context, Result};
 use once_cell::sync::Lazy;
+use rayon::prelude::*;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -44,6 +46,53 @@ pub struct ManifestElement {
     pub priority: i32,
 }
 
+/// Name of the provenance sidecar written into each profile generation.
+pub const GENERATION_METADATA_FILE: &str = "trix-generation.json";
+
+/// Provenance of a profile generation created by trix.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct GenerationMetadata {
+    /// Operation that created the generation (add, remove, upgrade, apply)
+    pub action: String,
+    #[serde(rename = "flakeUrl", skip_serializing_if = "Option::is_none")]
+    pub flake_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    #[serde(rename = "trixVersion")]
+    pub trix_version: String,
+    /// RFC 3339 creation time
+    pub date: String,
+}
+
+impl GenerationMetadata {
+    pub fn new(action: &str) -> Self {
+        Self {
+            action: action.to_string(),
+            flake_url: None,
+            rev: None,
+            trix_version: env!("CARGO_PKG_VERSION").to_string(),
+            date: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Record the flake the generation was built from.
+    ///
+    /// For local flakes, the git revision (or dirty revision) is recorded too.
+    pub fn with_flake(mut self, flake_url: &str, flake_dir: Option<&Path>) -> Self {
+        self.flake_url = Some(flake_url.to_string());
+        if let Some(info) = flake_dir.and_then(|d| crate::git::get_git_info(d).ok()) {
+            self.rev = info.rev.or(info.dirty_rev);
+        }
+        self
+    }
+}
+
+/// Read the provenance sidecar of a profile generation, if it has one.
+pub fn read_generation_metadata(target: &Path) -> Option<GenerationMetadata> {
+    let content = fs::read_to_string(target.join(GENERATION_METADATA_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
 /// Get the profile directory (where profile-N-link symlinks live).
 pub fn get_profile_dir() -> Result<PathBuf> {
     let profile_link = dirs::home_dir()
@@ -137,7 +186,8 @@ pub fn collect_package_paths(store_paths: &[String]) -> Result<HashMap<String, V
             let name = entry.file_name().to_string_lossy().to_string();
 
             // Skip manifest.json and similar
-            if name == "manifest.json" || name == "nix-support" {
+            if name == "manifest.json" || name == "nix-support" || name == GENERATION_METADATA_FILE
+            {
                 continue;
             }
 
@@ -149,10 +199,122 @@ pub fn collect_package_paths(store_paths: &[String]) -> Result<HashMap<String, V
 }
 
 /// Create a new profile store path with the given manifest and packages.
-pub fn create_profile_store_path(manifest: &Manifest, store_paths: &[String]) -> Result<String> {
+///
+/// The package tree is built by nix's `builtin:buildenv`, the same builder
+/// nix-env and `nix profile` use, so priorities and collisions behave as
+/// they do there. If that builder fails for a reason other than a
+/// collision, the packages are symlinked by hand instead.
+pub fn create_profile_store_path(
+    manifest: &Manifest,
+    store_paths: &[String],
+    metadata: &GenerationMetadata,
+) -> Result<String> {
+    let env = match build_profile_env(manifest) {
+        Ok(env) => env,
+        Err(e) if format!("{:#}", e).contains("collision") => return Err(e),
+        Err(e) => {
+            tracing::debug!("buildenv failed, linking profile manually: {:#}", e);
+            return create_profile_store_path_manual(manifest, store_paths, metadata);
+        }
+    };
+
+    let temp_parent = crate::scratch::tempdir()?;
+    let profile_dir = temp_parent.path().join("profile");
+    copy_symlink_tree(Path::new(&env), &profile_dir)?;
+
+    // buildenv links its manifest argument here; profiles use manifest.json
+    let _ = fs::remove_file(profile_dir.join("manifest.nix"));
+
+    fs::write(
+        profile_dir.join("manifest.json"),
+        serde_json::to_string_pretty(manifest)?,
+    )?;
+    fs::write(
+        profile_dir.join(GENERATION_METADATA_FILE),
+        serde_json::to_string_pretty(metadata)?,
+    )?;
+
+    let mut cmd = crate::command::NixCommand::new("nix-store");
+    cmd.args(["--add", &profile_dir.display().to_string()]);
+
+    cmd.output()
+}
+
+/// Nix expression for a `builtin:buildenv` derivation of the manifest's packages.
+fn buildenv_expr(manifest: &Manifest) -> String {
+    let mut names: Vec<&String> = manifest.elements.keys().collect();
+    names.sort();
+
+    let entries: Vec<String> = names
+        .into_iter()
+        .map(|name| &manifest.elements[name])
+        .filter(|element| !element.store_paths.is_empty())
+        .map(|element| {
+            let paths: Vec<String> = element
+                .store_paths
+                .iter()
+                .map(|p| format!("(builtins.storePath {})", crate::nix::nix_string(p)))
+                .collect();
+            format!(
+                "[ \"{}\" {} {} {} ]",
+                element.active,
+                element.priority,
+                paths.len(),
+                paths.join(" ")
+            )
+        })
+        .collect();
+
+    format!(
+        r#"derivation {{
+  name = "profile";
+  system = "builtin";
+  builder = "builtin:buildenv";
+  manifest = "/dev/null";
+  derivations = [ {} ];
+  preferLocalBuild = true;
+  allowSubstitutes = false;
+}}"#,
+        entries.join(" ")
+    )
+}
+
+/// Build the profile's package tree with `builtin:buildenv`.
+fn build_profile_env(manifest: &Manifest) -> Result<String> {
+    let mut cmd = crate::command::NixCommand::new("nix-build");
+    cmd.args(["--no-out-link", "--expr", &buildenv_expr(manifest)]);
+    Ok(cmd.output()?.trim().to_string())
+}
+
+/// Recreate a tree of directories and symlinks (as made by buildenv) at `dest`.
+fn copy_symlink_tree(src: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            symlink(fs::read_link(entry.path())?, &target)?;
+        } else if file_type.is_dir() {
+            copy_symlink_tree(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a profile store path by symlinking package contents directly.
+///
+/// Fallback for when the buildenv builder is unavailable; later packages
+/// silently lose file collisions and priorities are ignored.
+fn create_profile_store_path_manual(
+    manifest: &Manifest,
+    store_paths: &[String],
+    metadata: &GenerationMetadata,
+) -> Result<String> {
     // Create a temporary directory for the profile
-    // Use /tmp explicitly to avoid issues with TMPDIR pointing to a nix-shell temp dir
-    let temp_parent = tempfile::tempdir_in("/tmp")?;
+    let temp_parent = crate::scratch::tempdir()?;
     let profile_dir = temp_parent.path().join("user-environment");
     fs::create_dir_all(&profile_dir)?;
 
@@ -160,6 +322,10 @@ pub fn create_profile_store_path(manifest: &Manifest, store_paths: &[String]) ->
     let manifest_content = serde_json::to_string_pretty(manifest)?;
     fs::write(profile_dir.join("manifest.json"), manifest_content)?;
 
+    // Write provenance sidecar
+    let metadata_content = serde_json::to_string_pretty(metadata)?;
+    fs::write(profile_dir.join(GENERATION_METADATA_FILE), metadata_content)?;
+
     // Collect and symlink package contents
     let package_paths = collect_package_paths(store_paths)?;
 
@@ -196,15 +362,24 @@ pub fn create_profile_store_path(manifest: &Manifest, store_paths: &[String]) ->
 
 /// Switch to a new profile generation atomically.
 pub fn switch_profile(new_store_path: &str) -> Result<()> {
+    let (_, gen_link) = add_generation(new_store_path)?;
+    activate_generation(&gen_link)
+}
+
+/// Create the next profile-N-link pointing at `new_store_path`, without switching to it.
+pub fn add_generation(new_store_path: &str) -> Result<(u32, PathBuf)> {
     let profile_dir = get_profile_dir()?;
     let next_gen = get_next_profile_number()?;
 
     fs::create_dir_all(&profile_dir)?;
 
-    // Create profile-N-link
     let gen_link = profile_dir.join(format!("profile-{}-link", next_gen));
     symlink(new_store_path, &gen_link)?;
+    Ok((next_gen, gen_link))
+}
 
+/// Point `~/.nix-profile` at an existing profile-N-link.
+pub fn activate_generation(gen_link: &Path) -> Result<()> {
     // Atomically update the profile symlink
     let home = dirs::home_dir().context("Could not find home directory")?;
     let profile_link = home.join(".nix-profile");
@@ -213,7 +388,7 @@ pub fn switch_profile(new_store_path: &str) -> Result<()> {
     // (rename fails across filesystems with EXDEV)
     let temp_link = home.join(".nix-profile.tmp");
     let _ = fs::remove_file(&temp_link);
-    symlink(&gen_link, &temp_link)?;
+    symlink(gen_link, &temp_link)?;
     fs::rename(&temp_link, &profile_link)?;
 
     Ok(())
@@ -254,30 +429,61 @@ pub fn parse_installable_for_profile(installable: &str) -> (String, String, Stri
             .unwrap_or_else(|| "default".to_string())
     } else {
         // Use attribute name
-        attr.split('.').next_back().unwrap_or(&attr).to_string()
+        split_attr_path(&attr).pop().unwrap_or(attr.clone())
     };
 
     (ref_part, attr, pkg_name)
 }
 
-/// Install a package to the profile.
-pub fn install(
+/// Flake URL recorded in the manifest for a local flake directory.
+///
+/// Uses git+file:// for git repos, path: otherwise (matches nix behavior).
+fn local_flake_url(dir: &Path) -> String {
+    let canonical = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let is_git = dir.join(".git").exists()
+        || std::process::Command::new("git")
+            .args(["-C", &dir.display().to_string(), "rev-parse", "--git-dir"])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+    if is_git {
+        format!("git+file://{}", crate::flake::url_path(&canonical))
+    } else {
+        format!("path:{}", crate::flake::url_path(&canonical))
+    }
+}
+
+/// A package built (or found) for the profile, not yet in a generation.
+struct PreparedPackage {
+    name: String,
+    element: ManifestElement,
+    flake_url: String,
+    source_dir: Option<PathBuf>,
+}
+
+/// Build an installable and describe its manifest element.
+fn prepare_install(
     installable: &str,
     flake_dir: Option<&Path>,
     attr: Option<&str>,
     store_path: Option<&str>,
-) -> Result<bool> {
+) -> Result<PreparedPackage> {
     let system = get_system()?;
     let store_dir = get_store_dir()?;
 
     // Build the package if needed
-    let (final_store_path, final_attr, flake_ref) = if let Some(path) = store_path {
+    let (final_store_path, final_attr, flake_ref, source_dir) = if let Some(path) = store_path {
         // Pre-built package
         let a = attr.unwrap_or("default");
         let ref_str = flake_dir
-            .map(|d| format!("path:{}", d.display()))
+            .map(|d| format!("path:{}", crate::flake::url_path(d)))
             .unwrap_or_else(|| ".".to_string());
-        (path.to_string(), a.to_string(), ref_str)
+        (
+            path.to_string(),
+            a.to_string(),
+            ref_str,
+            flake_dir.map(Path::to_path_buf),
+        )
     } else {
         // Need to build
         let resolved = crate::flake::resolve_installable(installable);
@@ -289,23 +495,8 @@ pub fn install(
             // But only if it's not a flake source (doesn't have flake.nix)
             if dir.starts_with(&store_dir) && !crate::nix::check_is_flake(dir) {
                 let store_path_str = dir.display().to_string();
-                let store_name = dir
-                    .file_name()
-                    .map(|n| n.to_string_lossy().to_string())
-                    .unwrap_or_default();
-
-                let pkg_name = if store_name.len() > 33 && store_name.as_bytes()[32] == b'-' {
-                    let name_version = &store_name[33..];
-                    if let Some(caps) = PKG_NAME_REGEX.captures(name_version) {
-                        caps.get(1).unwrap().as_str().to_string()
-                    } else {
-                        name_version.to_string()
-                    }
-                } else {
-                    store_name
-                };
-
-                return install_store_path(&store_path_str, &pkg_name);
+                let pkg_name = store_path_package_name(&store_path_str);
+                return Ok(prepare_store_path(&store_path_str, &pkg_name));
             }
 
             let full_attr =
@@ -318,58 +509,127 @@ pub fn install(
 
             let path = run_nix_build(dir, &full_attr, &options, true)?.context("Build failed")?;
 
-            // Use git+file:// for git repos, path: otherwise (matches nix behavior)
-            let canonical = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
-            let is_git = dir.join(".git").exists()
-                || std::process::Command::new("git")
-                    .args(["-C", &dir.display().to_string(), "rev-parse", "--git-dir"])
-                    .output()
-                    .map(|o| o.status.success())
-                    .unwrap_or(false);
-            let flake_url = if is_git {
-                format!("git+file://{}", canonical.display())
-            } else {
-                format!("path:{}", canonical.display())
-            };
-
-            (path, full_attr, flake_url)
+            (path, full_attr, local_flake_url(dir), Some(dir.clone()))
         } else {
             // Remote package - need to use nix profile install
             let flake_ref = resolved.flake_ref.as_ref().context("No flake reference")?;
-            let full_ref = format!("{}#{}", flake_ref, resolved.attr_part);
+            let full_ref = resolved.full_ref();
 
             let mut cmd = crate::command::NixCommand::new("nix");
             cmd.args(["build", "--no-link", "--print-out-paths", &full_ref]);
 
             let path = cmd.output().context("nix build failed")?;
-            (path, resolved.attr_part.clone(), flake_ref.clone())
+            (path, resolved.attr_part.clone(), flake_ref.clone(), None)
         }
     };
 
-    // Update manifest
-    let mut manifest = get_current_manifest()?;
-
     // Use package name as the key
-    let pkg_name = final_attr
-        .split('.')
-        .next_back()
-        .unwrap_or(&final_attr)
-        .to_string();
-
-    // Add/replace element (match nix profile format)
-    manifest.elements.insert(
-        pkg_name,
-        ManifestElement {
+    let pkg_name = split_attr_path(&final_attr)
+        .pop()
+        .unwrap_or_else(|| final_attr.clone());
+
+    // Match nix profile format
+    Ok(PreparedPackage {
+        name: pkg_name,
+        element: ManifestElement {
             attr_path: Some(final_attr),
             original_url: Some(flake_ref.clone()),
-            url: Some(flake_ref),
+            url: Some(flake_ref.clone()),
             outputs: None,
-            store_paths: vec![final_store_path.clone()],
+            store_paths: vec![final_store_path],
             active: true,
             priority: 5,
         },
+        flake_url: flake_ref,
+        source_dir,
+    })
+}
+
+/// The package name in a store path, without hash or version: "hello" for
+/// `/nix/store/<hash>-hello-2.12`.
+fn store_path_package_name(store_path: &str) -> String {
+    let store_name = Path::new(store_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if store_name.len() > 33 && store_name.as_bytes()[32] == b'-' {
+        let name_version = &store_name[33..];
+        match PKG_NAME_REGEX.captures(name_version) {
+            Some(caps) => caps.get(1).unwrap().as_str().to_string(),
+            None => name_version.to_string(),
+        }
+    } else {
+        store_name
+    }
+}
+
+/// Describe a direct store path install.
+fn prepare_store_path(store_path: &str, pkg_name: &str) -> PreparedPackage {
+    PreparedPackage {
+        name: pkg_name.to_string(),
+        element: ManifestElement {
+            attr_path: Some(pkg_name.to_string()),
+            original_url: Some(format!("path:{}", store_path)),
+            store_paths: vec![store_path.to_string()],
+            active: true,
+            priority: 5,
+            ..Default::default()
+        },
+        flake_url: format!("path:{}", store_path),
+        source_dir: None,
+    }
+}
+
+/// The action recorded for adding `packages` to `manifest`.
+///
+/// "upgrade" when every package replaces an installed element with other
+/// store paths; "add" otherwise, which includes reinstalling the same paths.
+fn add_action<'a>(
+    manifest: &Manifest,
+    packages: impl IntoIterator<Item = (&'a str, &'a [String])>,
+) -> &'static str {
+    let mut packages = packages.into_iter().peekable();
+    let upgrades = packages.peek().is_some()
+        && packages.all(|(name, paths)| {
+            manifest
+                .elements
+                .get(name)
+                .is_some_and(|old| old.store_paths != paths)
+        });
+    if upgrades {
+        "upgrade"
+    } else {
+        "add"
+    }
+}
+
+/// Add prepared packages to the current manifest as one new generation.
+///
+/// Returns the generation number. With `activate` false the generation is
+/// created but `~/.nix-profile` keeps pointing at the current one.
+fn add_to_profile(packages: Vec<PreparedPackage>, activate: bool) -> Result<u32> {
+    let mut manifest = get_current_manifest()?;
+
+    let action = add_action(
+        &manifest,
+        packages
+            .iter()
+            .map(|p| (p.name.as_str(), p.element.store_paths.as_slice())),
     );
 
+    // Record the source flake when every package came from the same one
+    let mut metadata = GenerationMetadata::new(action);
+    if let Some(first) = packages.first() {
+        if packages.iter().all(|p| p.flake_url == first.flake_url) {
+            metadata = metadata.with_flake(&first.flake_url, first.source_dir.as_deref());
+        }
+    }
+
+    // Add/replace elements
+    for package in packages {
+        manifest.elements.insert(package.name, package.element);
+    }
+
     // Get all store paths
     let all_paths: Vec<String> = manifest
         .elements
@@ -378,42 +638,113 @@ pub fn install(
         .collect();
 
     // Create new profile
-    let new_profile = create_profile_store_path(&manifest, &all_paths)?;
-    switch_profile(&new_profile)?;
+    let new_profile = create_profile_store_path(&manifest, &all_paths, &metadata)?;
+    let (generation, gen_link) = add_generation(&new_profile)?;
+    if activate {
+        activate_generation(&gen_link)?;
+    }
+    Ok(generation)
+}
 
-    Ok(true)
+/// Install several packages in a single new generation.
+///
+/// Every package is built before the profile is touched, so one failing
+/// build leaves the profile unchanged. Returns the new generation number.
+pub fn install_all(installables: &[String], activate: bool) -> Result<u32> {
+    let packages = installables
+        .iter()
+        .map(|installable| {
+            tracing::debug!("Installing {}...", installable);
+            prepare_install(installable, None, None, None)
+                .with_context(|| format!("Failed to install {}", installable))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    add_to_profile(packages, activate)
 }
 
-/// Remove a package from the profile.
-pub fn remove(name: &str) -> Result<bool> {
-    let mut manifest = get_current_manifest()?;
+/// Whether a `profile remove` argument is a glob pattern rather than a name.
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
 
-    // Try to remove by key directly, or by matching attr_path
-    let removed = manifest.elements.remove(name).is_some() || {
-        let keys_to_remove: Vec<_> = manifest
+/// Translate a shell glob (`*`, `?`, `[...]`) to an anchored regex.
+fn glob_regex(pattern: &str) -> Result<Regex> {
+    let mut re = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            '[' => {
+                re.push('[');
+                if chars.next_if_eq(&'!').is_some() {
+                    re.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        re.push('\\');
+                    }
+                    re.push(c);
+                }
+                re.push(']');
+            }
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).with_context(|| format!("Invalid pattern '{}'", pattern))
+}
+
+/// Keys of the elements matched by a `profile remove` argument, sorted.
+///
+/// With `regex`, the argument is a regular expression that must match the
+/// whole element name, as with `nix profile remove --regex`. Otherwise it
+/// is a glob pattern if it contains `*`, `?` or `[`, and else an element
+/// name or the last component of an element's attribute path.
+pub fn matching_elements(manifest: &Manifest, pattern: &str, regex: bool) -> Result<Vec<String>> {
+    let mut keys: Vec<String> = if regex || is_glob(pattern) {
+        let re = if regex {
+            Regex::new(&format!("^(?:{})$", pattern))
+                .with_context(|| format!("Invalid regex '{}'", pattern))?
+        } else {
+            glob_regex(pattern)?
+        };
+        manifest
+            .elements
+            .keys()
+            .filter(|k| re.is_match(k))
+            .cloned()
+            .collect()
+    } else if manifest.elements.contains_key(pattern) {
+        vec![pattern.to_string()]
+    } else {
+        manifest
             .elements
             .iter()
             .filter(|(_, e)| {
                 e.attr_path
                     .as_ref()
-                    .map(|p| p.split('.').next_back() == Some(name))
+                    .map(|p| split_attr_path(p).last().map(|s| s.as_str()) == Some(pattern))
                     .unwrap_or(false)
             })
             .map(|(k, _)| k.clone())
-            .collect();
-        let did_remove = !keys_to_remove.is_empty();
-        for key in keys_to_remove {
-            manifest.elements.remove(&key);
-        }
-        did_remove
+            .collect()
     };
+    keys.sort();
+    Ok(keys)
+}
 
-    if !removed {
-        return Ok(false);
+/// Remove elements from the profile, creating a single new generation.
+pub fn remove_elements(keys: &[String]) -> Result<()> {
+    let mut manifest = get_current_manifest()?;
+    for key in keys {
+        tracing::debug!("Removing package: {}", key);
+        manifest.elements.remove(key);
     }
 
-    tracing::debug!("Removing package: {}", name);
-
     // Get all remaining store paths
     let all_paths: Vec<String> = manifest
         .elements
@@ -422,31 +753,32 @@ pub fn remove(name: &str) -> Result<bool> {
         .collect();
 
     // Create new profile
-    let new_profile = create_profile_store_path(&manifest, &all_paths)?;
+    let metadata = GenerationMetadata::new("remove");
+    let new_profile = create_profile_store_path(&manifest, &all_paths, &metadata)?;
     switch_profile(&new_profile)?;
 
-    Ok(true)
+    Ok(())
 }
 
 /// Extract local path from a flake URL (path: or git+file://)
-fn extract_local_path(url: &str) -> Option<&str> {
-    if let Some(path) = url.strip_prefix("path:") {
-        Some(path)
-    } else if let Some(path) = url.strip_prefix("git+file://") {
-        Some(path)
-    } else {
-        None
-    }
+fn extract_local_path(url: &str) -> Option<PathBuf> {
+    let path = url
+        .strip_prefix("path:")
+        .or_else(|| url.strip_prefix("git+file://"))?;
+    Some(crate::flake::path_from_url(path))
 }
 
 /// Upgrade local packages in profile.
-pub fn upgrade(name: Option<&str>) -> Result<(u32, u32)> {
-    let manifest = get_current_manifest()?;
+///
+/// All candidates are rebuilt first, up to `jobs` at a time, and the
+/// profile is then switched once to a generation with every new path.
+pub fn upgrade(name: Option<&str>, jobs: usize) -> Result<(u32, u32)> {
+    let mut manifest = get_current_manifest()?;
     let system = get_system()?;
     let store_dir = crate::nix::get_store_dir()?;
 
-    let mut upgraded = 0u32;
     let mut skipped = 0u32;
+    let mut candidates = Vec::new();
 
     for (elem_name, element) in &manifest.elements {
         let attr = match &element.attr_path {
@@ -454,7 +786,7 @@ pub fn upgrade(name: Option<&str>) -> Result<(u32, u32)> {
             None => continue,
         };
 
-        let pkg_name = attr.split('.').next_back().unwrap_or(attr);
+        let pkg_name = split_attr_path(attr).pop().unwrap_or_else(|| attr.clone());
 
         // If a specific name is provided, only process that package
         if let Some(target_name) = name {
@@ -469,7 +801,7 @@ pub fn upgrade(name: Option<&str>) -> Result<(u32, u32)> {
             None => None,
         };
 
-        let path = match local_path {
+        let flake_dir = match local_path {
             Some(p) if !p.starts_with(&store_dir) => p,
             _ => {
                 // Not a local path or is a store path - can't upgrade
@@ -481,87 +813,380 @@ pub fn upgrade(name: Option<&str>) -> Result<(u32, u32)> {
             }
         };
 
-        let flake_dir = PathBuf::from(path);
-
         if !flake_dir.exists() {
-            eprintln!("warning: flake directory not found: {}", path);
+            eprintln!(
+                "warning: flake directory not found: {}",
+                flake_dir.display()
+            );
             skipped += 1;
             continue;
         }
 
         let full_attr = crate::flake::resolve_attr_path(attr, "packages", &system);
+        candidates.push((elem_name.clone(), flake_dir, full_attr));
+    }
 
-        let options = BuildOptions {
-            out_link: None,
-            ..Default::default()
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.max(1))
+        .build()
+        .context("Failed to start build threads")?;
+    let built: Vec<(String, PathBuf, Option<String>)> = pool.install(|| {
+        candidates
+            .into_par_iter()
+            .map(|(elem_name, flake_dir, full_attr)| {
+                let options = BuildOptions {
+                    out_link: None,
+                    ..Default::default()
+                };
+                let new_path = match run_nix_build(&flake_dir, &full_attr, &options, true) {
+                    Ok(path) => path,
+                    Err(e) => {
+                        eprintln!("warning: failed to build {}: {:#}", elem_name, e);
+                        None
+                    }
+                };
+                (elem_name, flake_dir, new_path)
+            })
+            .collect()
+    });
+
+    let mut upgraded_dirs = Vec::new();
+    for (elem_name, flake_dir, new_path) in built {
+        let Some(new_path) = new_path else {
+            skipped += 1;
+            continue;
+        };
+        let Some(element) = manifest.elements.get_mut(&elem_name) else {
+            continue;
         };
+        if element.store_paths.first() == Some(&new_path) {
+            skipped += 1;
+            continue;
+        }
 
-        match run_nix_build(&flake_dir, &full_attr, &options, true) {
-            Ok(Some(new_path)) => {
-                let old_path = element
-                    .store_paths
-                    .first()
-                    .map(|s| s.as_str())
-                    .unwrap_or("");
-                if new_path != old_path {
-                    tracing::debug!("Upgrading {}: {} -> {}", pkg_name, old_path, new_path);
-
-                    // Re-install with new store path
-                    install(
-                        &format!("{}#{}", path, attr),
-                        Some(&flake_dir),
-                        Some(attr),
-                        Some(&new_path),
-                    )?;
-
-                    upgraded += 1;
-                } else {
-                    skipped += 1;
+        tracing::debug!(
+            "Upgrading {}: {} -> {}",
+            elem_name,
+            element
+                .store_paths
+                .first()
+                .map(|s| s.as_str())
+                .unwrap_or(""),
+            new_path
+        );
+        element.store_paths = vec![new_path];
+        upgraded_dirs.push(flake_dir);
+    }
+
+    let upgraded = upgraded_dirs.len() as u32;
+    if upgraded == 0 {
+        return Ok((0, skipped));
+    }
+
+    // Record the source flake when every upgrade came from the same one
+    let mut metadata = GenerationMetadata::new("upgrade");
+    upgraded_dirs.sort();
+    upgraded_dirs.dedup();
+    if let [flake_dir] = upgraded_dirs.as_slice() {
+        metadata = metadata.with_flake(&local_flake_url(flake_dir), Some(flake_dir));
+    }
+
+    let all_paths: Vec<String> = manifest
+        .elements
+        .values()
+        .flat_map(|e| e.store_paths.clone())
+        .collect();
+    let new_profile = create_profile_store_path(&manifest, &all_paths, &metadata)?;
+    switch_profile(&new_profile)?;
+
+    Ok((upgraded, skipped))
+}
+
+/// Changes made (or planned) by `apply`.
+#[derive(Debug, Default, PartialEq)]
+pub struct ApplySummary {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+impl ApplySummary {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+}
+
+/// The manifest key for package `pname` listed in a `trixProfiles` list.
+///
+/// `install` keys elements by the last component of their attribute path,
+/// which a list entry doesn't have. So an element already providing the
+/// package keeps its key, whatever `install` called it, and only a new
+/// package is keyed by `pname`.
+fn apply_key(current: &Manifest, pname: &str) -> String {
+    if current.elements.contains_key(pname) {
+        return pname.to_string();
+    }
+    let mut keys: Vec<&String> = current
+        .elements
+        .iter()
+        .filter(|(_, e)| {
+            e.store_paths
+                .iter()
+                .any(|p| store_path_package_name(p) == pname)
+        })
+        .map(|(k, _)| k)
+        .collect();
+    keys.sort();
+    keys.first()
+        .map_or_else(|| pname.to_string(), |k| k.to_string())
+}
+
+/// Compare the current manifest with the desired one.
+///
+/// Elements without store paths (not built yet) are only checked for
+/// presence, never reported as updated.
+fn diff_manifests(current: &Manifest, desired: &Manifest) -> ApplySummary {
+    let mut summary = ApplySummary::default();
+
+    for (name, element) in &desired.elements {
+        match current.elements.get(name) {
+            None => summary.added.push(name.clone()),
+            Some(existing) => {
+                if !element.store_paths.is_empty() && existing.store_paths != element.store_paths {
+                    summary.updated.push(name.clone());
                 }
             }
-            Ok(None) | Err(_) => {
-                skipped += 1;
-            }
+        }
+    }
+    for name in current.elements.keys() {
+        if !desired.elements.contains_key(name) {
+            summary.removed.push(name.clone());
         }
     }
 
-    Ok((upgraded, skipped))
+    summary.added.sort();
+    summary.removed.sort();
+    summary.updated.sort();
+    summary
 }
-/// Install a direct store path to the profile.
-fn install_store_path(store_path: &str, pkg_name: &str) -> Result<bool> {
-    let mut manifest = get_current_manifest()?;
 
-    // Add/replace element
-    manifest.elements.insert(
-        pkg_name.to_string(),
-        ManifestElement {
-            attr_path: Some(pkg_name.to_string()),
-            original_url: Some(format!("path:{}", store_path)),
-            store_paths: vec![store_path.to_string()],
+/// Converge the profile to the package list declared in a flake.
+///
+/// Reads `trixProfiles.<system>.<profile_name>`, a list of packages, builds
+/// each one and replaces the profile contents with exactly that set in a
+/// single new generation. With `dry_run`, nothing is built and only
+/// additions and removals are reported.
+pub fn apply(flake_dir: &Path, profile_name: &str, dry_run: bool) -> Result<ApplySummary> {
+    let system = get_system()?;
+    let list_attr = crate::flake::join_attr_path(&["trixProfiles", &system, profile_name]);
+
+    let options = crate::nix::EvalOptions {
+        output_json: true,
+        apply_fn: Some("map (p: p.pname or (builtins.parseDrvName p.name).name)".to_string()),
+        ..Default::default()
+    };
+    let output = crate::nix::run_nix_eval(Some(flake_dir), &list_attr, &options)
+        .with_context(|| format!("Failed to evaluate {}", list_attr))?;
+    let names: Vec<String> =
+        serde_json::from_str(&output).context("Expected a list of packages")?;
+
+    let flake_url = local_flake_url(flake_dir);
+    let current = get_current_manifest()?;
+    let mut desired = Manifest {
+        version: 3,
+        elements: HashMap::new(),
+    };
+
+    for (index, pname) in names.iter().enumerate() {
+        let name = apply_key(&current, pname);
+        let attr = format!("{}.{}", list_attr, index);
+        let store_paths = if dry_run {
+            Vec::new()
+        } else {
+            let build_options = BuildOptions {
+                out_link: None,
+                ..Default::default()
+            };
+            let path = run_nix_build(flake_dir, &attr, &build_options, true)?
+                .with_context(|| format!("Failed to build {}", name))?;
+            vec![path]
+        };
+
+        let element = ManifestElement {
+            attr_path: Some(attr),
+            original_url: Some(flake_url.clone()),
+            url: Some(flake_url.clone()),
+            outputs: None,
+            store_paths,
             active: true,
             priority: 5,
-            ..Default::default()
-        },
-    );
+        };
+        if desired.elements.insert(name.clone(), element).is_some() {
+            eprintln!(
+                "warning: '{}' is listed more than once in {}, using the last entry",
+                name, list_attr
+            );
+        }
+    }
 
-    // Get all store paths
-    let all_paths: Vec<String> = manifest
+    let summary = diff_manifests(&current, &desired);
+    if dry_run || summary.is_empty() {
+        return Ok(summary);
+    }
+
+    let all_paths: Vec<String> = desired
         .elements
         .values()
         .flat_map(|e| e.store_paths.clone())
         .collect();
 
-    // Create new profile
-    let new_profile = create_profile_store_path(&manifest, &all_paths)?;
+    let metadata = GenerationMetadata::new("apply").with_flake(&flake_url, Some(flake_dir));
+    let new_profile = create_profile_store_path(&desired, &all_paths, &metadata)?;
     switch_profile(&new_profile)?;
 
-    tracing::info!("Added {} (direct store path)", pkg_name);
+    Ok(summary)
+}
+
+/// Portable description of a profile's packages, written by `profile export`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProfileExport {
+    pub version: u32,
+    pub packages: Vec<ExportedPackage>,
+}
 
-    Ok(true)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExportedPackage {
+    pub name: String,
+    /// Flake URL, locked to a revision when one is known
+    pub url: String,
+    #[serde(rename = "attrPath")]
+    pub attr_path: String,
+    /// Store path on the exporting machine, for reference only
+    #[serde(rename = "storePath", skip_serializing_if = "Option::is_none")]
+    pub store_path: Option<String>,
+}
+
+/// Current version of the export file format.
+pub const PROFILE_EXPORT_VERSION: u32 = 1;
+
+/// Pin a flake URL to an exact revision where possible.
+///
+/// Local git flakes get the current commit as `?rev=`; remote refs are
+/// locked through `nix flake metadata`. Anything else is returned as-is.
+fn lock_flake_url(url: &str) -> String {
+    if let Some(path) = extract_local_path(url) {
+        if !url.starts_with("git+file://") {
+            return url.to_string();
+        }
+        let info = crate::git::get_git_info(&path).unwrap_or_default();
+        return match info.rev {
+            Some(rev) => format!("git+file://{}?rev={}", crate::flake::url_path(&path), rev),
+            None => {
+                eprintln!(
+                    "warning: {} has uncommitted changes, exporting it unpinned",
+                    path.display()
+                );
+                url.to_string()
+            }
+        };
+    }
+
+    let mut cmd = crate::command::NixCommand::new("nix");
+    cmd.args(["flake", "metadata", "--json", url]);
+    cmd.json::<serde_json::Value>()
+        .ok()
+        .and_then(|m| m["url"].as_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Describe the current profile in a form that can be imported elsewhere.
+pub fn export() -> Result<ProfileExport> {
+    let mut elements = list_installed()?;
+    elements.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let packages = elements
+        .into_iter()
+        .filter_map(|(name, element)| {
+            let url = element.original_url.as_deref().or(element.url.as_deref())?;
+            let attr_path = element.attr_path.clone()?;
+            Some(ExportedPackage {
+                name,
+                url: lock_flake_url(url),
+                attr_path,
+                store_path: element.store_paths.first().cloned(),
+            })
+        })
+        .collect();
+
+    Ok(ProfileExport {
+        version: PROFILE_EXPORT_VERSION,
+        packages,
+    })
+}
+
+/// Replace the system in a per-system attribute path with `system`.
+///
+/// Lets an export taken on one platform be imported on another:
+///     packages.x86_64-linux.hello -> packages.aarch64-darwin.hello
+fn retarget_system(attr: &str, system: &str) -> String {
+    let mut parts = split_attr_path(attr);
+    if parts.len() >= 3 && crate::flake::looks_like_system(&parts[1]) {
+        parts[1] = system.to_string();
+    }
+    crate::flake::join_attr_path(&parts)
+}
+
+/// Install every package of an export into the current profile, as one
+/// new generation.
+///
+/// Returns the names of the packages that were installed.
+pub fn import(export: &ProfileExport) -> Result<Vec<String>> {
+    if export.version > PROFILE_EXPORT_VERSION {
+        anyhow::bail!(
+            "Unsupported profile export version {} (this trix supports up to {})",
+            export.version,
+            PROFILE_EXPORT_VERSION
+        );
+    }
+
+    let system = get_system()?;
+    let store_dir = get_store_dir()?;
+    let mut installables = Vec::new();
+    let mut installed = Vec::new();
+
+    for package in &export.packages {
+        // Store paths of direct installs can't be rebuilt from a flake
+        let is_store_path = package
+            .url
+            .strip_prefix("path:")
+            .is_some_and(|p| p.starts_with(&store_dir));
+        if is_store_path {
+            eprintln!(
+                "warning: skipping {}: installed from a store path, not a flake",
+                package.name
+            );
+            continue;
+        }
+
+        let attr = retarget_system(&package.attr_path, &system);
+        installables.push(format!("{}#{}", package.url, attr));
+        installed.push(package.name.clone());
+    }
+
+    if !installables.is_empty() {
+        install_all(&installables, true)?;
+    }
+    Ok(installed)
 }
 
 /// Delete non-current versions of the profile.
-pub fn wipe_history(older_than: Option<std::time::Duration>, dry_run: bool) -> Result<()> {
+///
+/// Returns the versions deleted (or that would be, with `dry_run`) and
+/// the store paths their links pointed at.
+pub fn wipe_history(
+    older_than: Option<std::time::Duration>,
+    dry_run: bool,
+) -> Result<Vec<(u32, PathBuf)>> {
     let profile_dir = get_profile_dir()?;
     let current_path = get_current_profile_path().ok();
 
@@ -569,7 +1194,7 @@ pub fn wipe_history(older_than: Option<std::time::Duration>, dry_run: bool) -> R
     let mut to_delete = Vec::new();
 
     if !profile_dir.exists() {
-        return Ok(());
+        return Ok(Vec::new());
     }
 
     for entry in fs::read_dir(&profile_dir)? {
@@ -598,27 +1223,31 @@ pub fn wipe_history(older_than: Option<std::time::Duration>, dry_run: bool) -> R
                 }
             }
 
-            to_delete.push((num, path));
+            to_delete.push((num, path, target));
         }
     }
 
     if to_delete.is_empty() {
         tracing::debug!("No profile versions to delete.");
-        return Ok(());
+        return Ok(Vec::new());
     }
 
-    to_delete.sort_by_key(|(num, _)| *num);
+    to_delete.sort_by_key(|(num, _, _)| *num);
 
-    for (num, path) in to_delete {
+    let mut removed = Vec::new();
+    for (num, path, target) in to_delete {
         if dry_run {
-            println!("would remove profile version {}", num);
+            eprintln!("would remove profile version {}", num);
         } else {
             tracing::debug!("removing profile version {}", num);
             fs::remove_file(path)?;
         }
+        if let Some(target) = target {
+            removed.push((num, target));
+        }
     }
 
-    Ok(())
+    Ok(removed)
 }
 
 #[cfg(test)]
@@ -639,6 +1268,212 @@ mod tests {
         assert!(!is_local_path("nixpkgs"));
     }
 
+    #[test]
+    fn test_buildenv_expr() {
+        let mut manifest = Manifest {
+            version: 3,
+            elements: HashMap::new(),
+        };
+        manifest.elements.insert(
+            "hello".to_string(),
+            ManifestElement {
+                store_paths: vec!["/nix/store/abc-hello".to_string()],
+                active: true,
+                priority: 5,
+                ..Default::default()
+            },
+        );
+        manifest
+            .elements
+            .insert("unbuilt".to_string(), ManifestElement::default());
+
+        let expr = buildenv_expr(&manifest);
+        assert!(expr.contains(r#"builder = "builtin:buildenv";"#));
+        assert!(expr.contains(
+            r#"derivations = [ [ "true" 5 1 (builtins.storePath "/nix/store/abc-hello") ] ];"#
+        ));
+    }
+
+    #[test]
+    fn test_copy_symlink_tree() {
+        let src = tempdir().unwrap();
+        fs::create_dir(src.path().join("share")).unwrap();
+        symlink("/nix/store/abc-hello/bin", src.path().join("bin")).unwrap();
+        symlink(
+            "/nix/store/abc-hello/share/man",
+            src.path().join("share/man"),
+        )
+        .unwrap();
+
+        let dest = tempdir().unwrap();
+        let copy = dest.path().join("profile");
+        copy_symlink_tree(src.path(), &copy).unwrap();
+
+        assert_eq!(
+            fs::read_link(copy.join("bin")).unwrap(),
+            PathBuf::from("/nix/store/abc-hello/bin")
+        );
+        assert!(copy.join("share").is_dir());
+        assert_eq!(
+            fs::read_link(copy.join("share/man")).unwrap(),
+            PathBuf::from("/nix/store/abc-hello/share/man")
+        );
+    }
+
+    #[test]
+    fn test_diff_manifests() {
+        fn element(store_path: &str) -> ManifestElement {
+            ManifestElement {
+                store_paths: if store_path.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![store_path.to_string()]
+                },
+                active: true,
+                ..Default::default()
+            }
+        }
+        fn manifest(entries: &[(&str, &str)]) -> Manifest {
+            Manifest {
+                version: 3,
+                elements: entries
+                    .iter()
+                    .map(|(n, p)| (n.to_string(), element(p)))
+                    .collect(),
+            }
+        }
+
+        let current = manifest(&[("hello", "/nix/store/a-hello"), ("jq", "/nix/store/b-jq")]);
+        let desired = manifest(&[
+            ("hello", "/nix/store/c-hello"),
+            ("ripgrep", "/nix/store/d-rg"),
+        ]);
+        let summary = diff_manifests(&current, &desired);
+        assert_eq!(summary.added, vec!["ripgrep"]);
+        assert_eq!(summary.removed, vec!["jq"]);
+        assert_eq!(summary.updated, vec!["hello"]);
+
+        // Unbuilt elements (dry run) are never reported as updated
+        let desired = manifest(&[("hello", ""), ("jq", "")]);
+        assert!(diff_manifests(&current, &desired).is_empty());
+    }
+
+    #[test]
+    fn test_apply_key() {
+        let element = |path: &str| ManifestElement {
+            store_paths: vec![path.to_string()],
+            ..Default::default()
+        };
+        let current = Manifest {
+            version: 3,
+            elements: HashMap::from([
+                (
+                    "nodejs_20".to_string(),
+                    element("/nix/store/00000000000000000000000000000000-nodejs-20.11.1"),
+                ),
+                (
+                    "hello".to_string(),
+                    element("/nix/store/11111111111111111111111111111111-hello-2.12"),
+                ),
+            ]),
+        };
+        // Installed as nodejs_20, listed by its pname
+        assert_eq!(apply_key(&current, "nodejs"), "nodejs_20");
+        assert_eq!(apply_key(&current, "hello"), "hello");
+        assert_eq!(apply_key(&current, "ripgrep"), "ripgrep");
+        assert_eq!(
+            store_path_package_name("/nix/store/00000000000000000000000000000000-nodejs-20.11.1"),
+            "nodejs"
+        );
+    }
+
+    #[test]
+    fn test_add_action() {
+        let paths = |path: &str| vec![path.to_string()];
+        let hello = paths("/nix/store/11111111111111111111111111111111-hello-2.12");
+        let newer = paths("/nix/store/22222222222222222222222222222222-hello-2.12.1");
+        let manifest = Manifest {
+            version: 3,
+            elements: HashMap::from([(
+                "hello".to_string(),
+                ManifestElement {
+                    store_paths: hello.clone(),
+                    ..Default::default()
+                },
+            )]),
+        };
+        assert_eq!(
+            add_action(&manifest, [("hello", newer.as_slice())]),
+            "upgrade"
+        );
+        // Reinstalling the same path is not an upgrade
+        assert_eq!(add_action(&manifest, [("hello", hello.as_slice())]), "add");
+        assert_eq!(
+            add_action(&manifest, [("ripgrep", newer.as_slice())]),
+            "add"
+        );
+        assert_eq!(
+            add_action(
+                &manifest,
+                [("hello", newer.as_slice()), ("ripgrep", newer.as_slice())]
+            ),
+            "add"
+        );
+        assert_eq!(add_action(&manifest, []), "add");
+    }
+
+    #[test]
+    fn test_retarget_system() {
+        assert_eq!(
+            retarget_system("packages.x86_64-linux.hello", "aarch64-darwin"),
+            "packages.aarch64-darwin.hello"
+        );
+        assert_eq!(
+            retarget_system("legacyPackages.x86_64-linux.\"foo.bar\"", "aarch64-linux"),
+            "legacyPackages.aarch64-linux.\"foo.bar\""
+        );
+        // No system component: unchanged
+        assert_eq!(retarget_system("hello", "aarch64-linux"), "hello");
+    }
+
+    #[test]
+    fn test_profile_export_roundtrip() {
+        let export = ProfileExport {
+            version: PROFILE_EXPORT_VERSION,
+            packages: vec![ExportedPackage {
+                name: "hello".to_string(),
+                url: "github:NixOS/nixpkgs/0123456789abcdef".to_string(),
+                attr_path: "legacyPackages.x86_64-linux.hello".to_string(),
+                store_path: None,
+            }],
+        };
+        let json = serde_json::to_string(&export).unwrap();
+        assert!(json.contains("\"attrPath\""));
+        assert!(!json.contains("storePath"));
+        assert_eq!(
+            serde_json::from_str::<ProfileExport>(&json).unwrap(),
+            export
+        );
+    }
+
+    #[test]
+    fn test_generation_metadata_roundtrip() {
+        let dir = tempdir().unwrap();
+        assert!(read_generation_metadata(dir.path()).is_none());
+
+        let metadata = GenerationMetadata::new("add").with_flake("github:NixOS/nixpkgs", None);
+        assert_eq!(metadata.trix_version, env!("CARGO_PKG_VERSION"));
+        assert!(metadata.rev.is_none());
+
+        fs::write(
+            dir.path().join(GENERATION_METADATA_FILE),
+            serde_json::to_string(&metadata).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(read_generation_metadata(dir.path()), Some(metadata));
+    }
+
     #[test]
     fn test_collect_package_paths() {
         let dir = tempdir().unwrap();
@@ -713,6 +1548,39 @@ mod tests {
         assert_eq!(p, "hello");
     }
 
+    #[test]
+    fn test_matching_elements() {
+        let mut manifest = Manifest::default();
+        for (name, attr) in [
+            ("hello", "hello"),
+            ("python3", "python3"),
+            ("python3Packages.black", "python3Packages.black"),
+            ("my-tool", "packages.x86_64-linux.default"),
+        ] {
+            manifest.elements.insert(
+                name.to_string(),
+                ManifestElement {
+                    attr_path: Some(attr.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let matches = |pattern, regex| matching_elements(&manifest, pattern, regex).unwrap();
+        assert_eq!(matches("hello", false), vec!["hello"]);
+        assert_eq!(matches("default", false), vec!["my-tool"]);
+        assert_eq!(
+            matches("python*", false),
+            vec!["python3", "python3Packages.black"]
+        );
+        assert_eq!(matches("python?", false), vec!["python3"]);
+        assert_eq!(matches("[!p]*", false), vec!["hello", "my-tool"]);
+        assert_eq!(matches("python3.black", false), Vec::<String>::new());
+        assert_eq!(matches("python3|hello", true), vec!["hello", "python3"]);
+        assert_eq!(matches("pyth", true), Vec::<String>::new());
+        assert!(matching_elements(&manifest, "(", true).is_err());
+    }
+
     #[test]
     fn test_get_current_manifest_empty() {
         let _dir = tempdir().unwrap();
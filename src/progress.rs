@@ -0,0 +1,375 @@
+//! Progress reporting on stderr.
+//!
+//! Interactive terminals get a status line that is redrawn in place by a
+//! ticker thread, which is the only thing that draws it. CI logs and pipes
+//! get plain lines only, since carriage returns garble them. `--quiet`
+//! silences progress entirely. Errors and warnings do not go through here,
+//! but the line is taken down while they, or a child's output, are shown.
+
+use crate::common::format_size;
+use once_cell::sync::OnceCell;
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Width of the transfer progress bar, in characters
+const PROGRESS_WIDTH: usize = 30;
+
+const SPINNER: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// How often the spinner advances
+const TICK: Duration = Duration::from_millis(100);
+
+static REPORTER: OnceCell<Box<dyn ProgressReporter>> = OnceCell::new();
+
+/// Receives progress from long-running operations.
+pub trait ProgressReporter: Send + Sync {
+    /// Show `message` until `end` is called with the returned id. Of
+    /// several statuses at once (from parallel work), the newest is shown.
+    fn begin(&self, message: &str) -> usize;
+
+    /// Stop showing the status `begin` returned `id` for.
+    fn end(&self, id: usize);
+
+    /// Show how much of a transfer is done, until `clear`.
+    fn transfer(&self, name: &str, done: u64, total: Option<u64>);
+
+    /// Stop showing the transfer, if any.
+    fn clear(&self);
+
+    /// Take the status line down while something else writes to stderr;
+    /// it comes back at `resume`. Calls nest.
+    fn suspend(&self);
+
+    fn resume(&self);
+
+    /// Print a line that stays in the output.
+    fn log(&self, message: &str);
+}
+
+#[derive(Default)]
+struct TtyState {
+    /// Spinner frame
+    frame: usize,
+    /// Whether a status line is currently on screen
+    drawn: bool,
+    /// Active statuses by id, oldest first
+    statuses: Vec<(usize, String)>,
+    next_id: usize,
+    transfer: Option<String>,
+    /// Nesting depth of `suspend`
+    suspended: usize,
+    ticking: bool,
+}
+
+impl TtyState {
+    /// The line to show, if anything should be shown now.
+    fn line(&self) -> Option<String> {
+        if self.suspended > 0 {
+            return None;
+        }
+        if let Some(transfer) = &self.transfer {
+            return Some(transfer.clone());
+        }
+        let (_, newest) = self.statuses.last()?;
+        Some(match self.statuses.len() {
+            1 => newest.clone(),
+            n => format!("{} (+{} more)", newest, n - 1),
+        })
+    }
+
+    /// Bring the terminal in line with the state.
+    fn redraw(&mut self) {
+        let mut stderr = std::io::stderr().lock();
+        match self.line() {
+            Some(line) => {
+                let line = crate::cli::style::fit(
+                    crate::cli::style::Stream::Stderr,
+                    &format!("{} {}", SPINNER[self.frame], line),
+                );
+                let _ = write!(stderr, "\r\x1b[K{}", line);
+                self.drawn = true;
+            }
+            None if self.drawn => {
+                let _ = write!(stderr, "\r\x1b[K");
+                self.drawn = false;
+            }
+            None => {}
+        }
+        let _ = stderr.flush();
+    }
+}
+
+/// Redraws a single status line with a spinner.
+struct TtyReporter {
+    state: Arc<Mutex<TtyState>>,
+}
+
+impl TtyReporter {
+    /// Update the state and redraw, starting the ticker on first use.
+    fn update(&self, change: impl FnOnce(&mut TtyState)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state);
+        state.redraw();
+        if !state.ticking {
+            state.ticking = true;
+            let shared = Arc::clone(&self.state);
+            std::thread::spawn(move || loop {
+                std::thread::sleep(TICK);
+                let mut state = shared.lock().unwrap();
+                if state.line().is_some() {
+                    state.frame = (state.frame + 1) % SPINNER.len();
+                    state.redraw();
+                }
+            });
+        }
+    }
+}
+
+impl ProgressReporter for TtyReporter {
+    fn begin(&self, message: &str) -> usize {
+        let mut id = 0;
+        self.update(|state| {
+            id = state.next_id;
+            state.next_id += 1;
+            state.statuses.push((id, message.to_string()));
+        });
+        id
+    }
+
+    fn end(&self, id: usize) {
+        self.update(|state| state.statuses.retain(|(i, _)| *i != id));
+    }
+
+    fn transfer(&self, name: &str, done: u64, total: Option<u64>) {
+        self.update(|state| state.transfer = Some(progress_line(name, done, total)));
+    }
+
+    fn clear(&self) {
+        self.update(|state| state.transfer = None);
+    }
+
+    fn suspend(&self) {
+        self.update(|state| state.suspended += 1);
+    }
+
+    fn resume(&self) {
+        self.update(|state| state.suspended = state.suspended.saturating_sub(1));
+    }
+
+    fn log(&self, message: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.suspended += 1;
+        state.redraw();
+        eprintln!("{}", message);
+        state.suspended -= 1;
+        state.redraw();
+    }
+}
+
+/// Prints only lasting messages, for CI logs and pipes.
+struct PlainReporter;
+
+impl ProgressReporter for PlainReporter {
+    fn begin(&self, _message: &str) -> usize {
+        0
+    }
+
+    fn end(&self, _id: usize) {}
+
+    fn transfer(&self, _name: &str, _done: u64, _total: Option<u64>) {}
+
+    fn clear(&self) {}
+
+    fn suspend(&self) {}
+
+    fn resume(&self) {}
+
+    fn log(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// Reports nothing (`--quiet`).
+struct SilentReporter;
+
+impl ProgressReporter for SilentReporter {
+    fn begin(&self, _message: &str) -> usize {
+        0
+    }
+
+    fn end(&self, _id: usize) {}
+
+    fn transfer(&self, _name: &str, _done: u64, _total: Option<u64>) {}
+
+    fn clear(&self) {}
+
+    fn suspend(&self) {}
+
+    fn resume(&self) {}
+
+    fn log(&self, _message: &str) {}
+}
+
+#[derive(Debug, PartialEq)]
+enum Kind {
+    Tty,
+    Plain,
+    Silent,
+}
+
+fn select(quiet: bool, terminal: bool, ci: bool) -> Kind {
+    if quiet {
+        Kind::Silent
+    } else if terminal && !ci {
+        Kind::Tty
+    } else {
+        Kind::Plain
+    }
+}
+
+/// Whether we are running under a CI system, where stderr may still look like a terminal.
+fn in_ci() -> bool {
+    crate::cli::gha::enabled()
+        || ["CI", "GITHUB_ACTIONS", "BUILDKITE", "GITLAB_CI"]
+            .iter()
+            .any(|var| std::env::var(var).is_ok_and(|v| !v.is_empty() && v != "false"))
+}
+
+fn create(quiet: bool) -> Box<dyn ProgressReporter> {
+    match select(quiet, std::io::stderr().is_terminal(), in_ci()) {
+        Kind::Tty => Box::new(TtyReporter {
+            state: Arc::new(Mutex::new(TtyState::default())),
+        }),
+        Kind::Plain => Box::new(PlainReporter),
+        Kind::Silent => Box::new(SilentReporter),
+    }
+}
+
+/// Pick the reporter for this run (`--quiet` selects the silent one).
+pub fn init(quiet: bool) {
+    let _ = REPORTER.set(create(quiet));
+}
+
+/// The reporter for this run.
+pub fn reporter() -> &'static dyn ProgressReporter {
+    REPORTER.get_or_init(|| create(false)).as_ref()
+}
+
+/// Run `f` while showing `message` as the status.
+pub fn with_status<T>(message: &str, f: impl FnOnce() -> T) -> T {
+    let id = reporter().begin(message);
+    let result = f();
+    reporter().end(id);
+    result
+}
+
+/// Run `f`, which lets a child process write to the terminal, with the
+/// status line taken down.
+pub fn suspended<T>(f: impl FnOnce() -> T) -> T {
+    reporter().suspend();
+    let result = f();
+    reporter().resume();
+    result
+}
+
+/// Stderr for log output, with the status line taken down while it is
+/// written. Before `init`, there is no status line to take down.
+pub struct LogWriter {
+    suspended: bool,
+}
+
+impl LogWriter {
+    pub fn new() -> Self {
+        let reporter = REPORTER.get();
+        if let Some(reporter) = reporter {
+            reporter.suspend();
+        }
+        LogWriter {
+            suspended: reporter.is_some(),
+        }
+    }
+}
+
+impl Default for LogWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::io::stderr().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        if self.suspended {
+            reporter().resume();
+        }
+    }
+}
+
+/// Render a progress line like `nixpkgs [#####     ] 12.0/40.0 MiB`.
+fn progress_line(name: &str, done: u64, total: Option<u64>) -> String {
+    match total {
+        Some(total) if total > 0 => {
+            let filled = ((done.min(total) as f64 / total as f64) * PROGRESS_WIDTH as f64) as usize;
+            format!(
+                "{} [{}{}] {} / {}",
+                name,
+                "#".repeat(filled),
+                " ".repeat(PROGRESS_WIDTH - filled),
+                format_size(done),
+                format_size(total)
+            )
+        }
+        _ => format!("{} {}", name, format_size(done)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        assert_eq!(select(true, true, false), Kind::Silent);
+        assert_eq!(select(false, true, false), Kind::Tty);
+        assert_eq!(select(false, true, true), Kind::Plain);
+        assert_eq!(select(false, false, false), Kind::Plain);
+    }
+
+    #[test]
+    fn test_tty_state_line() {
+        let mut state = TtyState::default();
+        assert_eq!(state.line(), None);
+        state.statuses.push((0, "Parsing".to_string()));
+        assert_eq!(state.line().as_deref(), Some("Parsing"));
+        state.statuses.push((1, "Evaluating hello".to_string()));
+        assert_eq!(state.line().as_deref(), Some("Evaluating hello (+1 more)"));
+        state.suspended = 1;
+        assert_eq!(state.line(), None);
+        state.suspended = 0;
+        state.transfer = Some("nixpkgs 512 B".to_string());
+        assert_eq!(state.line().as_deref(), Some("nixpkgs 512 B"));
+    }
+
+    #[test]
+    fn test_progress_line() {
+        assert_eq!(
+            progress_line("nixpkgs", 512, Some(1024)),
+            format!(
+                "nixpkgs [{}{}] 512 B / 1.0 KiB",
+                "#".repeat(15),
+                " ".repeat(15)
+            )
+        );
+        assert_eq!(progress_line("nixpkgs", 512, None), "nixpkgs 512 B");
+    }
+}
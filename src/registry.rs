@@ -2,6 +2,8 @@
 //!
 //! Reads nix flake registries to resolve short names like 'nixpkgs' to their
 //! full flake references. Supports:
+//! - Command-line overrides: `--override-flake ORIGINAL RESOLVED`
+//! - Project registry: .trix/registry.json at the flake root
 //! - User registry: ~/.config/nix/registry.json
 //! - System registry: /etc/nix/registry.json
 //! - Global registry: https://channels.nixos.org/flake-registry.json (cached)
@@ -10,19 +12,25 @@ use anyhow::Result;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
 const GLOBAL_REGISTRY_URL: &str = "https://channels.nixos.org/flake-registry.json";
 const CACHE_TTL: Duration = Duration::from_secs(3600); // 1 hour
 
+/// Project registry location, relative to the flake root.
+pub const PROJECT_REGISTRY_PATH: &str = ".trix/registry.json";
+
 /// Cache for global registry
 static GLOBAL_REGISTRY_CACHE: Lazy<Mutex<Option<(RegistryFile, Instant)>>> =
     Lazy::new(|| Mutex::new(None));
 
+/// Entries from `--override-flake`, consulted before any registry file
+static FLAKE_OVERRIDES: Lazy<Mutex<RegistryFile>> =
+    Lazy::new(|| Mutex::new(RegistryFile::default()));
+
 /// A resolved registry entry.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegistryEntry {
@@ -42,6 +50,13 @@ pub struct RegistryEntry {
     pub url: Option<String>,
 }
 
+impl RegistryEntry {
+    /// Whether the entry is pinned to an exact revision.
+    pub fn is_pinned(&self) -> bool {
+        self.rev.is_some()
+    }
+}
+
 /// Registry file structure
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct RegistryFile {
@@ -85,12 +100,9 @@ struct RegistryTo {
 
 /// Get the user registry path.
 fn get_user_registry_path() -> PathBuf {
-    let config_home = env::var("XDG_CONFIG_HOME").unwrap_or_else(|_| {
-        dirs::home_dir()
-            .map(|h| h.join(".config").display().to_string())
-            .unwrap_or_else(|| "~/.config".to_string())
-    });
-    PathBuf::from(config_home).join("nix").join("registry.json")
+    crate::paths::nix_config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config/nix"))
+        .join("registry.json")
 }
 
 /// Get the system registry path.
@@ -98,8 +110,67 @@ fn get_system_registry_path() -> PathBuf {
     PathBuf::from("/etc/nix/registry.json")
 }
 
+/// Find the project registry of the flake containing `dir`.
+pub fn find_project_registry(dir: &Path) -> Option<PathBuf> {
+    let (root, _) = crate::flake::find_flake_root(dir)?;
+    let path = root.join(PROJECT_REGISTRY_PATH);
+    path.exists().then_some(path)
+}
+
+/// Directory that relative paths in a shared registry file are relative to.
+///
+/// For `<root>/.trix/registry.json` this is `<root>`, otherwise the
+/// directory containing the file.
+fn registry_base_dir(path: &Path) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    let base = if parent.file_name().is_some_and(|n| n == ".trix") {
+        parent.parent().unwrap_or(parent)
+    } else {
+        parent
+    };
+    if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base.to_path_buf()
+    }
+}
+
+/// Load the project registry of the flake containing `dir`, resolving
+/// relative paths against the flake root.
+///
+/// The registry comes from the flake, so it is only used once trusted.
+fn load_project_registry(dir: Option<&Path>) -> RegistryFile {
+    let Some(path) = dir.and_then(find_project_registry) else {
+        return RegistryFile::default();
+    };
+    let base = registry_base_dir(&path);
+    let mut registry = load_registry_file(&path);
+
+    let mut summary: Vec<String> = registry
+        .flakes
+        .iter()
+        .filter_map(|e| {
+            let target = registry_entry_to_flake_ref(&parse_registry_entry(e)?);
+            Some(format!("{} -> {}", e.from.id, target))
+        })
+        .collect();
+    summary.sort();
+    if summary.is_empty() || !crate::trust::allow(&base, PROJECT_REGISTRY_PATH, &summary.join(", "))
+    {
+        return RegistryFile::default();
+    }
+    for entry in &mut registry.flakes {
+        if let Some(p) = &entry.to.path {
+            if p == "." || p.starts_with("./") || p.starts_with("../") {
+                entry.to.path = Some(base.join(p).display().to_string());
+            }
+        }
+    }
+    registry
+}
+
 /// Load a registry file, returning empty registry if not found.
-fn load_registry_file(path: &PathBuf) -> RegistryFile {
+fn load_registry_file(path: &Path) -> RegistryFile {
     if !path.exists() {
         return RegistryFile::default();
     }
@@ -186,6 +257,44 @@ fn parse_registry_entry(entry: &RegistryFlakeEntry) -> Option<RegistryEntry> {
     }
 }
 
+/// Install `--override-flake ORIGINAL RESOLVED` pairs for this run.
+///
+/// `values` holds the flattened pairs as clap collects them. Like nix,
+/// ORIGINAL may be written as `nixpkgs` or `flake:nixpkgs`. Overrides
+/// apply to installables and to flake.nix inputs given by registry name.
+pub fn set_flake_overrides(values: &[String]) {
+    let flakes = values
+        .chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| RegistryFlakeEntry {
+            from: RegistryFrom {
+                from_type: "indirect".to_string(),
+                id: pair[0]
+                    .strip_prefix("flake:")
+                    .unwrap_or(&pair[0])
+                    .to_string(),
+            },
+            to: parse_flake_ref_to_entry(&pair[1]),
+        })
+        .collect();
+    *FLAKE_OVERRIDES.lock().unwrap() = RegistryFile { version: 2, flakes };
+}
+
+/// `--override-flake` arguments to forward when passing a command through to nix.
+pub fn override_flake_args() -> Vec<String> {
+    FLAKE_OVERRIDES
+        .lock()
+        .unwrap()
+        .flakes
+        .iter()
+        .filter_map(|e| {
+            let target = registry_entry_to_flake_ref(&parse_registry_entry(e)?);
+            Some(["--override-flake".to_string(), e.from.id.clone(), target])
+        })
+        .flatten()
+        .collect()
+}
+
 /// Search a registry for a name, return the resolved entry.
 fn search_registry(registry: &RegistryFile, name: &str) -> Option<RegistryEntry> {
     for entry in &registry.flakes {
@@ -196,14 +305,36 @@ fn search_registry(registry: &RegistryFile, name: &str) -> Option<RegistryEntry>
     None
 }
 
+/// Look a name up in the user registry only.
+pub fn user_registry_entry(name: &str) -> Option<RegistryEntry> {
+    search_registry(&load_registry_file(&get_user_registry_path()), name)
+}
+
 /// Resolve a registry name to its target.
 ///
 /// Searches in order:
-/// 1. User registry (~/.config/nix/registry.json)
-/// 2. System registry (/etc/nix/registry.json)
-/// 3. Global registry (https://channels.nixos.org/flake-registry.json)
-pub fn resolve_registry_name(name: &str, use_global: bool) -> Option<RegistryEntry> {
-    // Check user registry first
+/// 1. Command-line overrides (--override-flake)
+/// 2. Project registry (.trix/registry.json at the root of the flake
+///    containing `project`, if given)
+/// 3. User registry (~/.config/nix/registry.json)
+/// 4. System registry (/etc/nix/registry.json)
+/// 5. Global registry (https://channels.nixos.org/flake-registry.json)
+pub fn resolve_registry_name(
+    name: &str,
+    use_global: bool,
+    project: Option<&Path>,
+) -> Option<RegistryEntry> {
+    if let Some(result) = search_registry(&FLAKE_OVERRIDES.lock().unwrap(), name) {
+        return Some(result);
+    }
+
+    // Check the project registry first, so teams can share short names
+    let project_registry = load_project_registry(project);
+    if let Some(result) = search_registry(&project_registry, name) {
+        return Some(result);
+    }
+
+    // Then the user registry
     let user_registry = load_registry_file(&get_user_registry_path());
     if let Some(result) = search_registry(&user_registry, name) {
         return Some(result);
@@ -284,8 +415,8 @@ pub fn is_registry_name(ref_str: &str) -> bool {
         return false;
     }
 
-    // Contains # (has attribute part) - get base
-    let base = ref_str.split('#').next().unwrap_or("");
+    // Contains # (has attribute part) or ? (query parameters) - get base
+    let base = ref_str.split(['#', '?']).next().unwrap_or("");
 
     // Check if base is a simple identifier (alphanumeric + hyphen + underscore)
     !base.is_empty()
@@ -407,23 +538,44 @@ fn parse_query_params(s: &str) -> (&str, HashMap<String, String>) {
     }
 }
 
-/// Save the user registry file.
-fn save_user_registry(registry: &RegistryFile) -> Result<()> {
-    let path = get_user_registry_path();
+/// Save a registry file, creating its directory if needed.
+fn save_registry_file(path: &Path, registry: &RegistryFile) -> Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
     }
     let content = serde_json::to_string_pretty(registry)?;
-    fs::write(&path, format!("{}\n", content))?;
+    fs::write(path, format!("{}\n", content))?;
     Ok(())
 }
 
 /// List all registry entries from all sources.
 ///
-/// Returns a list of (name, source, entry) tuples where source is "user", "system", or "global".
-pub fn list_all_registries(use_global: bool) -> Vec<(String, String, RegistryEntry)> {
+/// Returns a list of (name, source, entry) tuples where source is "project",
+/// "user", "system", or "global". The project registry is that of the flake
+/// containing `project`, if given.
+pub fn list_all_registries(
+    use_global: bool,
+    project: Option<&Path>,
+) -> Vec<(String, String, RegistryEntry)> {
     let mut results = Vec::new();
 
+    // Command-line overrides
+    for entry in &FLAKE_OVERRIDES.lock().unwrap().flakes {
+        if let Some(parsed) = parse_registry_entry(entry) {
+            results.push((entry.from.id.clone(), "override".to_string(), parsed));
+        }
+    }
+
+    // Project registry
+    let project_registry = load_project_registry(project);
+    for entry in &project_registry.flakes {
+        if entry.from.from_type == "indirect" {
+            if let Some(parsed) = parse_registry_entry(entry) {
+                results.push((entry.from.id.clone(), "project".to_string(), parsed));
+            }
+        }
+    }
+
     // User registry
     let user_registry = load_registry_file(&get_user_registry_path());
     for entry in &user_registry.flakes {
@@ -459,51 +611,256 @@ pub fn list_all_registries(use_global: bool) -> Vec<(String, String, RegistryEnt
     results
 }
 
-/// Add an entry to the user registry.
-pub fn add_registry_entry(name: &str, target: &str) -> Result<()> {
-    let mut user_registry = load_registry_file(&get_user_registry_path());
+/// Make a path target relative to a shared registry's base directory.
+///
+/// Entries in project registries are committed alongside the flake, so
+/// paths inside the project are stored as `./...` rather than absolute.
+fn relativize_path_target(to: &mut RegistryTo, registry: &Path) {
+    let Some(path) = &to.path else {
+        return;
+    };
+    let base = registry_base_dir(registry);
+    let base = base.canonicalize().unwrap_or(base);
+    if let Ok(rel) = Path::new(path).strip_prefix(&base) {
+        let rel = rel.display().to_string();
+        to.path = Some(if rel.is_empty() {
+            ".".to_string()
+        } else {
+            format!("./{}", rel)
+        });
+    }
+}
+
+/// Add an entry to the user registry, or to `registry` if given.
+///
+/// Returns the added entry.
+pub fn add_registry_entry(
+    name: &str,
+    target: &str,
+    registry: Option<&Path>,
+) -> Result<RegistryEntry> {
+    let path = registry
+        .map(Path::to_path_buf)
+        .unwrap_or_else(get_user_registry_path);
+    let mut registry_file = load_registry_file(&path);
 
     // Ensure structure
-    if user_registry.version == 0 {
-        user_registry.version = 2;
+    if registry_file.version == 0 {
+        registry_file.version = 2;
     }
 
     // Remove existing entry with same name
-    user_registry
+    registry_file
         .flakes
         .retain(|e| !(e.from.from_type == "indirect" && e.from.id == name));
 
-    // Add new entry
-    user_registry.flakes.push(RegistryFlakeEntry {
+    let mut to = parse_flake_ref_to_entry(target);
+    if registry.is_some() {
+        relativize_path_target(&mut to, &path);
+    }
+    let entry = RegistryFlakeEntry {
         from: RegistryFrom {
             from_type: "indirect".to_string(),
             id: name.to_string(),
         },
-        to: parse_flake_ref_to_entry(target),
-    });
+        to,
+    };
+    let parsed = parse_registry_entry(&entry);
 
-    save_user_registry(&user_registry)
+    // Add new entry
+    registry_file.flakes.push(entry);
+    save_registry_file(&path, &registry_file)?;
+
+    parsed.ok_or_else(|| anyhow::anyhow!("Unsupported registry target '{}'", target))
 }
 
-/// Remove an entry from the user registry.
+/// Remove an entry from the user registry, or from `registry` if given.
 ///
 /// Returns true if entry was found and removed, false otherwise.
-pub fn remove_registry_entry(name: &str) -> Result<bool> {
-    let mut user_registry = load_registry_file(&get_user_registry_path());
+pub fn remove_registry_entry(name: &str, registry: Option<&Path>) -> Result<bool> {
+    Ok(remove_registry_entries(&[name.to_string()], registry)? > 0)
+}
 
-    let original_count = user_registry.flakes.len();
+/// Remove entries by name from the user registry, or from `registry` if given.
+///
+/// Returns the number of entries removed.
+pub fn remove_registry_entries(names: &[String], registry: Option<&Path>) -> Result<usize> {
+    let path = registry
+        .map(Path::to_path_buf)
+        .unwrap_or_else(get_user_registry_path);
+    let mut registry_file = load_registry_file(&path);
+
+    let original_count = registry_file.flakes.len();
 
-    // Filter out the entry
-    user_registry
+    // Filter out the entries
+    registry_file
         .flakes
-        .retain(|e| !(e.from.from_type == "indirect" && e.from.id == name));
+        .retain(|e| !(e.from.from_type == "indirect" && names.contains(&e.from.id)));
 
-    if user_registry.flakes.len() < original_count {
-        save_user_registry(&user_registry)?;
-        Ok(true)
-    } else {
-        Ok(false)
+    let removed = original_count - registry_file.flakes.len();
+    if removed > 0 {
+        save_registry_file(&path, &registry_file)?;
+    }
+    Ok(removed)
+}
+
+/// Names of the entries in the user registry, or in `registry` if given,
+/// that point at `target`.
+pub fn registry_entries_matching(target: &str, registry: Option<&Path>) -> Vec<String> {
+    let path = registry
+        .map(Path::to_path_buf)
+        .unwrap_or_else(get_user_registry_path);
+    let mut to = parse_flake_ref_to_entry(target);
+    if registry.is_some() {
+        relativize_path_target(&mut to, &path);
     }
+    let wanted = parse_registry_entry(&RegistryFlakeEntry {
+        from: RegistryFrom {
+            from_type: "indirect".to_string(),
+            id: String::new(),
+        },
+        to,
+    })
+    .map(|e| registry_entry_to_flake_ref(&e));
+
+    load_registry_file(&path)
+        .flakes
+        .iter()
+        .filter(|e| e.from.from_type == "indirect")
+        .filter(|e| {
+            wanted.is_some()
+                && parse_registry_entry(e).map(|p| registry_entry_to_flake_ref(&p)) == wanted
+        })
+        .map(|e| e.from.id.clone())
+        .collect()
+}
+
+/// Whether a registry entry's target still resolves.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetStatus {
+    Ok,
+    /// The target is gone, for the given reason
+    Gone(String),
+    /// The target could not be checked, e.g. because the network is down
+    Unknown(String),
+}
+
+/// Whether an error is an HTTP 404 or 422 (GitHub's answer for unknown refs).
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .filter_map(|e| e.status())
+        .any(|status| {
+            status == reqwest::StatusCode::NOT_FOUND
+                || status == reqwest::StatusCode::UNPROCESSABLE_ENTITY
+        })
+}
+
+/// Decide what a GitHub API 404 for `commitish` means.
+///
+/// Without `$GITHUB_TOKEN`, GitHub answers 404 for private repositories
+/// too, so a ref is confirmed with `git ls-remote` (exit code 2 means the
+/// repository answered but has no such ref). A bare rev cannot be listed,
+/// so it stays unknown.
+fn confirm_github_gone(owner: &str, repo: &str, commitish: &str, is_rev: bool) -> TargetStatus {
+    let gone = format!("github:{}/{} has no {}", owner, repo, commitish);
+    if std::env::var_os("GITHUB_TOKEN").is_some() {
+        return TargetStatus::Gone(gone);
+    }
+    if is_rev {
+        return TargetStatus::Unknown(format!(
+            "GitHub reports no commit {} in {}/{}; set GITHUB_TOKEN to confirm",
+            commitish, owner, repo
+        ));
+    }
+    // Not NixCommand: git takes none of nix's verbosity flags
+    let url = format!("https://github.com/{}/{}.git", owner, repo);
+    let output = std::process::Command::new("git")
+        .args(["ls-remote", "--exit-code", &url, commitish])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output();
+    match output {
+        Ok(o) if o.status.success() => TargetStatus::Ok,
+        Ok(o) if o.status.code() == Some(2) => TargetStatus::Gone(gone),
+        Ok(o) => TargetStatus::Unknown(format!(
+            "git ls-remote {} failed: {}",
+            url,
+            String::from_utf8_lossy(&o.stderr).trim()
+        )),
+        Err(e) => TargetStatus::Unknown(format!("Failed to run git: {}", e)),
+    }
+}
+
+/// Check whether an entry's target resolves, without fetching it.
+///
+/// Only definite answers (a missing path, a confirmed 404, a missing git
+/// ref) count as gone; anything else is unknown, so pruning never drops an entry
+/// because of a flaky network.
+fn check_target(entry: &RegistryEntry, base: &Path) -> TargetStatus {
+    match entry.entry_type.as_str() {
+        "path" => {
+            let path = entry.path.as_deref().unwrap_or("");
+            if base.join(shellexpand::tilde(path).as_ref()).exists() {
+                TargetStatus::Ok
+            } else {
+                TargetStatus::Gone("path does not exist".to_string())
+            }
+        }
+        "github" => {
+            let owner = entry.owner.as_deref().unwrap_or("");
+            let repo = entry.repo.as_deref().unwrap_or("");
+            let commitish = entry
+                .rev
+                .as_deref()
+                .or(entry.git_ref.as_deref())
+                .unwrap_or("HEAD");
+            match crate::lock::github_commit(owner, repo, commitish) {
+                Ok(_) => TargetStatus::Ok,
+                Err(e) if is_not_found(&e) => {
+                    confirm_github_gone(owner, repo, commitish, entry.rev.is_some())
+                }
+                Err(e) => TargetStatus::Unknown(format!("{:#}", e)),
+            }
+        }
+        "git" => {
+            let url = entry.url.as_deref().unwrap_or("");
+            match crate::git::remote_rev(url, entry.git_ref.as_deref()) {
+                Ok(_) => TargetStatus::Ok,
+                // Reachable, but the ref is gone
+                Err(e) if e.to_string().contains(" has no ref ") => {
+                    TargetStatus::Gone(e.to_string())
+                }
+                Err(e) => TargetStatus::Unknown(format!("{:#}", e)),
+            }
+        }
+        other => TargetStatus::Unknown(format!("cannot check {} targets", other)),
+    }
+}
+
+/// Check every entry of the user registry, or of `registry` if given.
+///
+/// Returns (name, target, status) in file order.
+pub fn check_registry_entries(registry: Option<&Path>) -> Vec<(String, String, TargetStatus)> {
+    use rayon::prelude::*;
+
+    let path = registry
+        .map(Path::to_path_buf)
+        .unwrap_or_else(get_user_registry_path);
+    let base = registry_base_dir(&path);
+    let entries: Vec<(String, RegistryEntry)> = load_registry_file(&path)
+        .flakes
+        .iter()
+        .filter(|e| e.from.from_type == "indirect")
+        .filter_map(|e| Some((e.from.id.clone(), parse_registry_entry(e)?)))
+        .collect();
+
+    entries
+        .into_par_iter()
+        .map(|(name, entry)| {
+            let status = check_target(&entry, &base);
+            (name, registry_entry_to_flake_ref(&entry), status)
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -514,6 +871,7 @@ mod tests {
     fn test_is_registry_name() {
         assert!(is_registry_name("nixpkgs"));
         assert!(is_registry_name("home-manager"));
+        assert!(is_registry_name("nixpkgs?ref=nixos-24.05"));
         assert!(!is_registry_name("."));
         assert!(!is_registry_name("./foo"));
         assert!(!is_registry_name("/foo"));
@@ -522,6 +880,39 @@ mod tests {
         assert!(!is_registry_name("path:/foo"));
     }
 
+    #[test]
+    fn test_flake_overrides() {
+        let saved = FLAKE_OVERRIDES.lock().unwrap().flakes.clone();
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().canonicalize().unwrap().display().to_string();
+        set_flake_overrides(&[
+            "flake:trix-override-test".to_string(),
+            target.clone(),
+            "trix-override-remote".to_string(),
+            "github:acme/flakes/stable".to_string(),
+        ]);
+
+        let entry = resolve_registry_name("trix-override-test", false, None).unwrap();
+        assert_eq!(entry.entry_type, "path");
+        assert_eq!(entry.path.as_deref(), Some(target.as_str()));
+        assert_eq!(
+            override_flake_args(),
+            vec![
+                "--override-flake",
+                "trix-override-test",
+                &target,
+                "--override-flake",
+                "trix-override-remote",
+                "github:acme/flakes/stable",
+            ]
+        );
+
+        set_flake_overrides(&[]);
+        assert!(override_flake_args().is_empty());
+
+        FLAKE_OVERRIDES.lock().unwrap().flakes = saved;
+    }
+
     #[test]
     fn test_parse_query_params() {
         let (base, params) = parse_query_params("foo?ref=master&rev=123");
@@ -551,6 +942,121 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_registry_entry_is_pinned() {
+        let mut entry = RegistryEntry {
+            entry_type: "github".to_string(),
+            path: None,
+            owner: Some("NixOS".to_string()),
+            repo: Some("nixpkgs".to_string()),
+            git_ref: Some("nixos-unstable".to_string()),
+            rev: None,
+            url: None,
+        };
+        assert!(!entry.is_pinned());
+
+        entry.rev = Some("0123456789abcdef".to_string());
+        assert!(entry.is_pinned());
+    }
+
+    #[test]
+    fn test_find_project_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("flake.nix"), "{ }").unwrap();
+        fs::create_dir_all(root.join("sub/dir")).unwrap();
+        assert_eq!(find_project_registry(&root.join("sub/dir")), None);
+
+        fs::create_dir(root.join(".trix")).unwrap();
+        fs::write(root.join(PROJECT_REGISTRY_PATH), "{}").unwrap();
+        assert_eq!(
+            find_project_registry(&root.join("sub/dir")),
+            Some(root.join(PROJECT_REGISTRY_PATH))
+        );
+        assert_eq!(
+            find_project_registry(root),
+            Some(root.join(PROJECT_REGISTRY_PATH))
+        );
+    }
+
+    #[test]
+    fn test_registry_base_dir() {
+        assert_eq!(
+            registry_base_dir(Path::new("/src/proj/.trix/registry.json")),
+            PathBuf::from("/src/proj")
+        );
+        assert_eq!(
+            registry_base_dir(Path::new("/etc/team/registry.json")),
+            PathBuf::from("/etc/team")
+        );
+        assert_eq!(
+            registry_base_dir(Path::new("registry.json")),
+            PathBuf::from(".")
+        );
+    }
+
+    #[test]
+    fn test_add_to_registry_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("vendor")).unwrap();
+        let registry = root.join(".trix/registry.json");
+
+        let entry = add_registry_entry("company", "github:acme/flakes", Some(&registry)).unwrap();
+        assert_eq!(registry_entry_to_flake_ref(&entry), "github:acme/flakes");
+
+        let vendor = root.join("vendor").display().to_string();
+        let entry = add_registry_entry("vendored", &vendor, Some(&registry)).unwrap();
+        assert_eq!(entry.path.as_deref(), Some("./vendor"));
+
+        let file = load_registry_file(&registry);
+        assert_eq!(file.version, 2);
+        assert_eq!(file.flakes.len(), 2);
+
+        assert!(remove_registry_entry("company", Some(&registry)).unwrap());
+        assert!(!remove_registry_entry("company", Some(&registry)).unwrap());
+        assert_eq!(load_registry_file(&registry).flakes.len(), 1);
+    }
+
+    #[test]
+    fn test_entries_matching_and_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("vendor")).unwrap();
+        let registry = root.join("registry.json");
+        let vendor = root.join("vendor").display().to_string();
+
+        add_registry_entry("a", "github:acme/flakes", Some(&registry)).unwrap();
+        add_registry_entry("b", "github:acme/flakes/main", Some(&registry)).unwrap();
+        add_registry_entry("c", "github:acme/flakes", Some(&registry)).unwrap();
+        add_registry_entry("vendored", &vendor, Some(&registry)).unwrap();
+        add_registry_entry("gone", &format!("{}/old", root.display()), Some(&registry)).unwrap();
+
+        assert_eq!(
+            registry_entries_matching("github:acme/flakes", Some(&registry)),
+            vec!["a", "c"]
+        );
+        assert_eq!(
+            registry_entries_matching(&vendor, Some(&registry)),
+            vec!["vendored"]
+        );
+        assert!(registry_entries_matching("github:acme/other", Some(&registry)).is_empty());
+
+        let base = registry_base_dir(&registry);
+        let check = |name: &str| {
+            let file = load_registry_file(&registry);
+            let entry = file.flakes.iter().find(|e| e.from.id == name).unwrap();
+            check_target(&parse_registry_entry(entry).unwrap(), &base)
+        };
+        assert_eq!(check("vendored"), TargetStatus::Ok);
+        assert!(matches!(check("gone"), TargetStatus::Gone(_)));
+
+        let removed =
+            remove_registry_entries(&["a".to_string(), "c".to_string()], Some(&registry)).unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(load_registry_file(&registry).flakes.len(), 3);
+    }
+
     #[test]
     fn test_parse_flake_ref_to_entry() {
         let entry = parse_flake_ref_to_entry("github:owner/repo?ref=main");
@@ -8,6 +8,8 @@
   flakeDir, # Path to directory containing flake.nix (as string or path)
   attr, # Attribute path to select, e.g., "packages.x86_64-linux.default"
   selfInfo ? { }, # Git metadata for self input
+  prefetched ? { }, # narHash -> store path of inputs already in the store
+  mirrors ? { }, # Forge host -> archive base URL
 }:
 
 let
@@ -44,7 +46,7 @@ let
   inputs =
     let
       baseInputs = import ./inputs.nix {
-        inherit lock flakeDirPath selfInfo;
+        inherit lock flakeDirPath selfInfo prefetched mirrors;
       };
     in
     baseInputs
@@ -25,6 +25,13 @@ let
     "flakeModules"
   ];
 
+  # System configuration categories (enumerate names only)
+  configurationAttrs = [
+    "nixosConfigurations"
+    "darwinConfigurations"
+    "homeConfigurations"
+  ];
+
   # Template categories
   templateAttrs = [ "templates" ];
 
@@ -279,18 +286,27 @@ let
         }) (builtins.attrNames val)
       )
 
-    else if
-      (name == "nixosConfigurations" || name == "darwinConfigurations" || name == "homeConfigurations")
-      && builtins.isAttrs val
-    then
-      builtins.listToAttrs (
-        map (n: {
-          name = n;
-          value = {
-            _type = "configuration";
-          };
-        }) (builtins.attrNames val)
-      )
+    else if builtins.elem name configurationAttrs then
+      # Only list names: forcing a configuration evaluates its whole module
+      # system. The attrset itself may be built lazily (flake-parts, haumea),
+      # so guard against it failing or not being an attrset at all.
+      let
+        namesResult = builtins.tryEval (
+          if builtins.isAttrs val then builtins.attrNames val else null
+        );
+      in
+      if !namesResult.success || namesResult.value == null then
+        { _unknown = true; }
+      else
+        builtins.listToAttrs (
+          map (n: {
+            name = n;
+            value = {
+              _type = "configuration";
+              _category = name;
+            };
+          }) namesResult.value
+        )
 
     # For categories known to not contain derivations (lib, htmlDocs, etc.), mark as unknown
     else if builtins.elem name nonDerivationAttrs then
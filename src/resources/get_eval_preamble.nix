@@ -4,21 +4,34 @@
   lock,
   selfInfo,
   nixDir,
+  prefetched ? { },
+  mirrors ? { },
+  importFlake ? import, # How flake.nix files are loaded; impure_trace.nix wraps it
 }:
 
 let
   helpers = import (nixDir + "/helpers.nix");
   inherit (helpers) hasPath getPath resolveAttrPath;
 
+  inputs =
+    if isFlake then
+      import (nixDir + "/inputs.nix") {
+        inherit lock;
+        flakeDirPath = flakeDir;
+        inherit
+          selfInfo
+          prefetched
+          mirrors
+          importFlake
+          ;
+      }
+    else
+      { };
+
   outputs =
     if isFlake then
       let
-        flake = import (flakeDir + "/flake.nix");
-        inputs = import (nixDir + "/inputs.nix") {
-          inherit lock;
-          flakeDirPath = flakeDir;
-          inherit selfInfo;
-        };
+        flake = importFlake (flakeDir + "/flake.nix");
       in
       flake.outputs (inputs // { self = inputs.self // outputs; })
     else
@@ -34,6 +47,7 @@ in
     hasPath
     getPath
     resolveAttrPath
+    inputs
     outputs
     ;
 }
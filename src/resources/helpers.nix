@@ -3,15 +3,23 @@
 # These are used by eval.nix and inline expressions in nix.rs.
 
 rec {
+  # Parse a path component as a list index, or null if it isn't one
+  listIndex = k: if builtins.match "[0-9]+" k != null then builtins.fromJSON k else null;
+
   # Check if a nested path exists in an attrset
+  # Numeric components index into lists, e.g. trixProfiles.x86_64-linux.default.0
   hasPath =
     path: obj:
     let
+      k = builtins.head path;
+      idx = listIndex k;
       attempt = builtins.tryEval (
         if path == [ ] then
           true
-        else if builtins.isAttrs obj && (obj ? ${builtins.head path}) then
-          hasPath (builtins.tail path) obj.${builtins.head path}
+        else if builtins.isAttrs obj && (obj ? ${k}) then
+          hasPath (builtins.tail path) obj.${k}
+        else if builtins.isList obj && idx != null && idx < builtins.length obj then
+          hasPath (builtins.tail path) (builtins.elemAt obj idx)
         else
           false
       );
@@ -19,7 +27,35 @@ rec {
     attempt.success && attempt.value;
 
   # Get a value at a nested path
-  getPath = path: obj: builtins.foldl' (o: k: o.${k}) obj path;
+  getPath =
+    path: obj:
+    builtins.foldl' (
+      o: k: if builtins.isList o then builtins.elemAt o (listIndex k) else o.${k}
+    ) obj path;
+
+  # Split a dotted attribute path into its components.
+  # Components may be double-quoted to contain dots, e.g.
+  # packages.x86_64-linux."weird.name", with backslash escapes inside the
+  # quotes as in a Nix string. Empty components are dropped.
+  # Matches split_attr_path in flake.rs.
+  splitAttrPath =
+    path:
+    let
+      matches = builtins.filter builtins.isList (
+        builtins.split "(\"([^\"\\\\]|\\\\.)*\"|[^.\"]+)" path
+      );
+      unescape =
+        builtins.replaceStrings
+          [ "\\\\" "\\\"" "\\$" "\\n" "\\t" "\\r" ]
+          [ "\\" "\"" "$" "\n" "\t" "\r" ];
+      unquote =
+        s:
+        if builtins.substring 0 1 s == "\"" then
+          unescape (builtins.substring 1 (builtins.stringLength s - 2) s)
+        else
+          s;
+    in
+    builtins.filter (x: x != "") (map (m: unquote (builtins.head m)) matches);
 
   # Resolve an attribute path with fallbacks.
   # Mirrors nix's behavior for .#attr references:
@@ -35,15 +71,53 @@ rec {
   resolveAttrPath =
     path: outputs:
     let
-      raw = builtins.split "\\." path;
-      parts = builtins.filter (x: builtins.isString x && x != "") raw;
+      resultPath = findAttrPath path outputs;
+    in
+    if resultPath == null then
+      throw "attribute '${path}' not found in flake outputs"
+    else
+      getPath resultPath outputs;
+
+  # The full path resolveAttrPath would select, or null if none exists.
+  # Only the attrsets along the way are evaluated, not the value itself.
+  findAttrPath =
+    path: outputs:
+    let
+      findFirstValid =
+        paths:
+        if paths == [ ] then
+          null
+        else if hasPath (builtins.head paths) outputs then
+          builtins.head paths
+        else
+          findFirstValid (builtins.tail paths);
+    in
+    findFirstValid (attrPathCandidates path outputs);
+
+  # Every path findAttrPath tries, in order, with whether it exists.
+  # Used by --explain-resolution.
+  explainAttrPath =
+    path: outputs:
+    map (p: {
+      path = p;
+      exists = hasPath p outputs;
+    }) (attrPathCandidates path outputs);
+
+  # The full paths findAttrPath tries for path, most likely first.
+  attrPathCandidates =
+    path: outputs:
+    let
+      parts = splitAttrPath path;
       firstPart = builtins.head parts;
       restParts = builtins.tail parts;
       system = builtins.currentSystem;
 
       # Check if this looks like a per-system category (has system in second position)
       looksLikePerSystem =
-        builtins.length parts >= 2 && builtins.match ".*-.*" (builtins.elemAt parts 1) != null;
+        builtins.length parts >= 2
+        && builtins.match "[^-]+-(linux|darwin|freebsd|netbsd|openbsd|cygwin|windows|wasi|none)" (
+          builtins.elemAt parts 1
+        ) != null;
 
       # Known per-system categories
       startsWithKnownCategory =
@@ -93,21 +167,6 @@ rec {
             )
             parts
           ];
-
-      # Find the first valid path
-      findFirstValid =
-        paths:
-        if paths == [ ] then
-          null
-        else if hasPath (builtins.head paths) outputs then
-          builtins.head paths
-        else
-          findFirstValid (builtins.tail paths);
-
-      resultPath = findFirstValid pathsToTry;
     in
-    if resultPath == null then
-      throw "attribute '${path}' not found in flake outputs"
-    else
-      getPath resultPath outputs;
+    pathsToTry;
 }
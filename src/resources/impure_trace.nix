@@ -0,0 +1,128 @@
+# Evaluate a flake with its impure builtins traced
+#
+# Used by `trix flake check --impure-warnings`. flake.nix, the flake.nix of
+# every flake input, and every file any of them imports are loaded with
+# scopedImport and a `builtins` whose impure parts print a `trix-impure:`
+# trace naming the file whose code used them. Each of `attrs` is forced
+# after a `trix-attr:` trace, so trix can tell which output reached each
+# access.
+
+{
+  flakeDir,
+  lock,
+  selfInfo,
+  nixDir,
+  prefetched ? { },
+  mirrors ? { },
+  attrs, # Output attribute paths to evaluate
+}:
+
+let
+  within =
+    dir: p:
+    let
+      s = toString p;
+    in
+    s == dir || builtins.substring 0 (builtins.stringLength dir + 1) s == dir + "/";
+
+  # Paths pure evaluation can't read: neither in the flake nor in the store
+  outside = p: !(within (toString flakeDir) p) && !(within builtins.storeDir p);
+
+  report =
+    file: message: value:
+    builtins.trace ("trix-impure:" + builtins.toJSON { inherit file message; }) value;
+
+  reportIf =
+    cond: file: message: value:
+    if cond then report file message value else value;
+
+  hashed = args: builtins.isAttrs args && (args ? sha256 || args ? hash || args ? narHash);
+  pinned = args: builtins.isAttrs args && (args ? rev || args ? narHash);
+
+  # The file `import p` loads
+  fileOf =
+    p:
+    let
+      s = toString p;
+    in
+    if builtins.pathExists (s + "/default.nix") then s + "/default.nix" else s;
+
+  # `builtins` as code in `file` sees it
+  tracedBuiltins =
+    file:
+    let
+      onPath =
+        verb: f: p:
+        reportIf (outside p) file "${verb} path ${toString p} outside the flake" (f p);
+    in
+    builtins
+    // {
+      getEnv = name: report file "reads environment variable ${name}" (builtins.getEnv name);
+      currentSystem = report file "uses builtins.currentSystem" builtins.currentSystem;
+      currentTime = report file "uses builtins.currentTime" builtins.currentTime;
+      nixPath = report file "uses builtins.nixPath" builtins.nixPath;
+      storePath = p: report file "uses builtins.storePath ${toString p}" (builtins.storePath p);
+      findFile =
+        searchPath: name: report file "looks up <${name}> in NIX_PATH" (builtins.findFile searchPath name);
+      fetchTarball =
+        args: reportIf (!hashed args) file "calls fetchTarball without a hash" (builtins.fetchTarball args);
+      fetchurl = args: reportIf (!hashed args) file "calls fetchurl without a hash" (builtins.fetchurl args);
+      fetchGit = args: reportIf (!pinned args) file "calls fetchGit without a rev" (builtins.fetchGit args);
+      readFile = onPath "reads" builtins.readFile;
+      readDir = onPath "reads" builtins.readDir;
+      pathExists = onPath "checks" builtins.pathExists;
+      import =
+        p: reportIf (outside p) file "imports path ${toString p} outside the flake" (tracedImport p);
+      scopedImport = scope: p: builtins.scopedImport (scopeFor (fileOf p) // scope) p;
+    }
+    # Only where this Nix has it, so `builtins ? fetchTree` still tells
+    // (
+      if builtins ? fetchTree then
+        {
+          fetchTree =
+            args:
+            reportIf (!pinned args) file "calls fetchTree without a rev or narHash" (builtins.fetchTree args);
+        }
+      else
+        { }
+    );
+
+  # What scopedImport puts in scope for code in `file`: `builtins` and the
+  # builtins also reachable without it. `<name>` is sugar for __findFile.
+  scopeFor =
+    file:
+    let
+      traced = tracedBuiltins file;
+    in
+    {
+      builtins = traced;
+      inherit (traced) import fetchTarball fetchGit;
+      __findFile = traced.findFile;
+    };
+
+  tracedImport = p: builtins.scopedImport (scopeFor (fileOf p)) p;
+
+  context = import (nixDir + "/get_eval_preamble.nix") {
+    inherit
+      flakeDir
+      lock
+      selfInfo
+      nixDir
+      prefetched
+      mirrors
+      ;
+    isFlake = true;
+    importFlake = tracedImport;
+  };
+  inherit (context) helpers outputs;
+
+  force =
+    attr:
+    let
+      value = helpers.getPath (helpers.splitAttrPath attr) outputs;
+      result = builtins.tryEval (if value ? drvPath then value.drvPath else builtins.seq value null);
+    in
+    builtins.trace "trix-attr:${attr}" result.success;
+in
+# The outputs function itself runs before any attribute is selected
+[ (builtins.trace "trix-attr:" (builtins.attrNames outputs != [ ])) ] ++ map force attrs
@@ -7,6 +7,9 @@
   lock, # Parsed flake.lock content
   flakeDirPath, # Path to the flake directory
   selfInfo ? { }, # Git info for self (rev, dirty, etc)
+  prefetched ? { }, # narHash -> store path, for inputs already in the store or from `trix --native-fetch`
+  mirrors ? { }, # forge host -> base URL replacing https://<host>, from `trix --mirror`
+  importFlake ? import, # How input flake.nix files are loaded
 }:
 
 let
@@ -55,6 +58,9 @@ let
   # Default resolver for the main lock file
   resolveFollows = resolveFollowsInContext nodes flakeDirPath [ ];
 
+  # Base URL for archives on a forge host
+  mirrorFor = host: mirrors.${host} or "https://${host}";
+
   # Fetch a source based on the native flake.lock format
   # basePath is used for resolving relative path inputs
   fetchSource =
@@ -62,10 +68,14 @@ let
     let
       locked = node.locked or { };
       type = locked.type or "unknown";
+      narHash = locked.narHash or "";
     in
-    if type == "github" then
+    # Checked again here, since a path may have been collected since trix looked
+    if prefetched ? ${narHash} && builtins.pathExists prefetched.${narHash} then
+      builtins.storePath prefetched.${narHash}
+    else if type == "github" then
       builtins.fetchTarball {
-        url = "https://github.com/${locked.owner}/${locked.repo}/archive/${locked.rev}.tar.gz";
+        url = "${mirrorFor "github.com"}/${locked.owner}/${locked.repo}/archive/${locked.rev}.tar.gz";
         sha256 = locked.narHash;
       }
     else if type == "gitlab" then
@@ -73,7 +83,7 @@ let
         host = locked.host or "gitlab.com";
       in
       builtins.fetchTarball {
-        url = "https://${host}/${locked.owner}/${locked.repo}/-/archive/${locked.rev}/${locked.repo}-${locked.rev}.tar.gz";
+        url = "${mirrorFor host}/${locked.owner}/${locked.repo}/-/archive/${locked.rev}/${locked.repo}-${locked.rev}.tar.gz";
         sha256 = locked.narHash;
       }
     else if type == "sourcehut" then
@@ -81,7 +91,7 @@ let
         host = locked.host or "git.sr.ht";
       in
       builtins.fetchTarball {
-        url = "https://${host}/~${locked.owner}/${locked.repo}/archive/${locked.rev}.tar.gz";
+        url = "${mirrorFor host}/~${locked.owner}/${locked.repo}/archive/${locked.rev}.tar.gz";
         sha256 = locked.narHash;
       }
     else if type == "git" then
@@ -139,7 +149,7 @@ let
     # For flake inputs, import their flake.nix and call outputs
     else
       let
-        inputFlake = import (src + "/flake.nix");
+        inputFlake = importFlake (src + "/flake.nix");
 
         # Our lock file may only have follows overrides, not all of the input's deps.
         # Read the input's own flake.lock to get its other dependencies.
@@ -172,12 +182,17 @@ let
           buildInput inputLockNodes iname refNode src;
 
         # What inputs does this flake actually need?
-        # Some flakes declare inputs explicitly, others infer them from outputs args
-        inputFlakeInputNames =
+        # Some flakes declare inputs explicitly, others infer them from outputs args.
+        # Optional arguments (`foo ? null`) that are locked nowhere are left out,
+        # so their default applies instead of a missing-input error.
+        outputsArgs = builtins.functionArgs inputFlake.outputs;
+        isLocked = iname: nodeInputs ? ${iname} || inputLockRootInputs ? ${iname};
+        inputFlakeInputNames = builtins.filter (iname: !(outputsArgs.${iname} or false) || isLocked iname) (
           if inputFlake ? inputs then
             builtins.attrNames inputFlake.inputs
           else
-            builtins.attrNames (builtins.functionArgs inputFlake.outputs);
+            builtins.attrNames outputsArgs
+        );
 
         # Our overrides from the main lock file (follows, etc.)
         nodeInputs = node.inputs or { };
@@ -16,6 +16,8 @@
   selfInfo ? { }, # Git metadata for self input (rev, shortRev, etc.)
   isFlake ? true, # Whether to treat this as a flake
   lock ? { }, # Flake lock file content
+  prefetched ? { }, # narHash -> store path of inputs already in the store
+  mirrors ? { }, # Forge host -> archive base URL
 }:
 
 let
@@ -32,7 +34,7 @@ let
 
         # Build inputs using shared inputs.nix
         baseInputs = import ./inputs.nix {
-          inherit lock flakeDirPath selfInfo;
+          inherit lock flakeDirPath selfInfo prefetched mirrors;
         };
 
         inputs = baseInputs // {
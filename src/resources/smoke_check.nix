@@ -0,0 +1,53 @@
+# Shallow checks for flake outputs that are not derivations
+#
+# Used by `trix flake check` to catch overlays and modules that fail to
+# even apply, before consumers of the flake hit them.
+
+{ inputs }:
+
+let
+  # lib from the flake's own nixpkgs input, if it has one
+  lib = if inputs ? nixpkgs && inputs.nixpkgs ? lib then inputs.nixpkgs.lib else null;
+
+  # The least of a package set that overlays commonly reach for at the top
+  # level. Values are never forced, so callPackage need not build anything.
+  fixture =
+    {
+      system = builtins.currentSystem;
+      callPackage =
+        fn: args:
+        let
+          f = if builtins.isFunction fn then fn else import fn;
+        in
+        f (builtins.intersectAttrs (builtins.functionArgs f) fixture // args);
+    }
+    // (if lib == null then { } else { inherit lib; });
+in
+{
+  # Apply an overlay to a minimal package set and list the names it defines
+  overlay =
+    overlay:
+    let
+      final = fixture // overlay final fixture;
+    in
+    builtins.attrNames (overlay final fixture);
+
+  # Evaluate a NixOS module on its own in a trivial evalModules call and
+  # list the options it declares. Definitions of options the module does
+  # not declare itself are ignored. Returns null without a nixpkgs input.
+  nixosModule =
+    module:
+    if lib == null then
+      null
+    else
+      let
+        evaluated = lib.evalModules {
+          modules = [
+            module
+            { _module.check = false; }
+          ];
+          specialArgs.modulesPath = inputs.nixpkgs.outPath + "/nixos/modules";
+        };
+      in
+      builtins.attrNames evaluated.options;
+}
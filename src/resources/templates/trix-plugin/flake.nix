@@ -0,0 +1,27 @@
+{
+  description = "A trix plugin";
+
+  inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";
+
+  outputs =
+    { self, nixpkgs }:
+    let
+      systems = [
+        "x86_64-linux"
+        "aarch64-linux"
+        "x86_64-darwin"
+        "aarch64-darwin"
+      ];
+      forAllSystems = f: nixpkgs.lib.genAttrs systems (system: f nixpkgs.legacyPackages.${system});
+    in
+    {
+      # Installing this package puts `trix-hello` on PATH, which makes
+      # `trix hello` available.
+      packages = forAllSystems (pkgs: {
+        default = pkgs.writeShellApplication {
+          name = "trix-hello";
+          text = builtins.readFile ./trix-hello.sh;
+        };
+      });
+    };
+}
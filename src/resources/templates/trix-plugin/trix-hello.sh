@@ -0,0 +1,4 @@
+# Runs as `trix hello [ARGS...]`. trix passes the remaining arguments
+# through and sets $TRIX to its own executable for calling back into it.
+echo "Hello from a trix plugin! Arguments: $*"
+"${TRIX:-trix}" --version
@@ -0,0 +1,162 @@
+//! Retrying flaky network operations.
+//!
+//! Downloads, prefetches and copies that trix starts itself are retried
+//! with exponential backoff. Only failures that look transient (timeouts,
+//! dropped connections, 5xx and 429 responses) are retried.
+//! `--retries` sets how many times to retry; 0 disables retrying.
+
+use anyhow::Result;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Retries after the first attempt
+static RETRIES: AtomicU32 = AtomicU32::new(3);
+
+/// Delay before the first retry; doubled for each further retry.
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound for the delay between attempts.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Messages from nix and curl that indicate a failure worth retrying.
+const TRANSIENT_MESSAGES: &[&str] = &[
+    "timed out",
+    "timeout was reached",
+    "connection reset",
+    "connection refused",
+    "connection closed",
+    "could not resolve host",
+    "couldn't resolve host",
+    "temporary failure in name resolution",
+    "network is unreachable",
+    "http error 429",
+    "http error 500",
+    "http error 502",
+    "http error 503",
+    "http error 504",
+];
+
+/// Set how many times failed operations are retried (`--retries`).
+pub fn set_retries(retries: u32) {
+    RETRIES.store(retries, Ordering::Relaxed);
+}
+
+/// Delay before retry number `retry` (starting at 1).
+fn backoff_delay(retry: u32) -> Duration {
+    INITIAL_DELAY
+        .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+        .min(MAX_DELAY)
+}
+
+/// Whether an error looks like a temporary network problem.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() || e.is_connect() || e.is_body() {
+                return true;
+            }
+            if let Some(status) = e.status() {
+                return status.is_server_error()
+                    || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+            }
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            use std::io::ErrorKind;
+            let dropped = matches!(
+                e.kind(),
+                ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::TimedOut
+                    | ErrorKind::UnexpectedEof
+            );
+            // reqwest reports errors while streaming a body as io::Error
+            let body = e
+                .get_ref()
+                .is_some_and(|inner| inner.downcast_ref::<reqwest::Error>().is_some());
+            if dropped || body {
+                return true;
+            }
+        }
+    }
+    let message = format!("{:#}", err).to_lowercase();
+    TRANSIENT_MESSAGES.iter().any(|m| message.contains(m))
+}
+
+fn retry_if<T>(
+    what: &str,
+    should_retry: impl Fn(&anyhow::Error) -> bool,
+    mut f: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let mut retry = 0;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) if retry < retries && should_retry(&e) => {
+                retry += 1;
+                let delay = backoff_delay(retry);
+                crate::progress::reporter().log(&format!(
+                    "{} failed, retrying in {}s ({}/{}): {:#}",
+                    what,
+                    delay.as_secs(),
+                    retry,
+                    retries,
+                    e
+                ));
+                std::thread::sleep(delay);
+            }
+            Err(e) if retry > 0 => {
+                return Err(e.context(format!("{} failed after {} attempts", what, retry + 1)))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Run `f`, retrying transient failures with exponential backoff.
+pub fn with_retry<T>(what: &str, f: impl FnMut() -> Result<T>) -> Result<T> {
+    retry_if(what, is_transient, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay(1), Duration::from_secs(1));
+        assert_eq!(backoff_delay(2), Duration::from_secs(2));
+        assert_eq!(backoff_delay(4), Duration::from_secs(8));
+        assert_eq!(backoff_delay(10), MAX_DELAY);
+        assert_eq!(backoff_delay(100), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&anyhow::anyhow!(
+            "Command failed:\nerror: unable to download 'https://x': HTTP error 503"
+        )));
+        assert!(is_transient(&anyhow::Error::new(std::io::Error::from(
+            std::io::ErrorKind::ConnectionReset
+        ))));
+        assert!(!is_transient(&anyhow::anyhow!(
+            "Command failed:\nerror: unable to download 'https://x': HTTP error 404"
+        )));
+        assert!(!is_transient(&anyhow::anyhow!("hash mismatch")));
+    }
+
+    #[test]
+    fn test_retry_if() {
+        let mut calls = 0;
+        let result: Result<()> = retry_if(
+            "op",
+            |_| false,
+            || {
+                calls += 1;
+                anyhow::bail!("permanent")
+            },
+        );
+        assert_eq!(calls, 1);
+        assert_eq!(format!("{:#}", result.unwrap_err()), "permanent");
+    }
+}
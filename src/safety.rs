@@ -0,0 +1,113 @@
+//! Ownership and permission checks before evaluating local code.
+//!
+//! trix evaluates local flakes impurely and in place, so anyone who can
+//! change the files can run code as the user evaluating them. Flakes in
+//! world-writable directories and shebang scripts that other users own or
+//! can write are refused; `--no-trust-check` turns the refusals into
+//! warnings.
+
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Whether failed checks refuse (`--no-trust-check` turns it off)
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Flake directories already checked
+static CHECKED: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Refuse to evaluate code others can change (off with `--no-trust-check`).
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Why a file with `mode` owned by `owner` is unsafe for user `me` to
+/// evaluate, if it is. Only scripts need to be owned by the user (or root).
+fn problem(mode: u32, owner: u32, me: u32, script: bool) -> Option<String> {
+    if mode & 0o002 != 0 {
+        return Some("it is world-writable".to_string());
+    }
+    if script && owner != me && owner != 0 {
+        return Some(format!("it is owned by another user (uid {})", owner));
+    }
+    if script && mode & 0o020 != 0 && owner != me {
+        return Some("it is group-writable and not owned by you".to_string());
+    }
+    None
+}
+
+fn check(path: &Path, what: &str, script: bool) -> Result<()> {
+    let Ok(metadata) = path.metadata() else {
+        return Ok(());
+    };
+    // SAFETY: getuid takes no arguments and cannot fail
+    let me = unsafe { libc::getuid() };
+    let Some(problem) = problem(metadata.mode(), metadata.uid(), me, script) else {
+        return Ok(());
+    };
+    if ENABLED.load(Ordering::Relaxed) {
+        anyhow::bail!(
+            "Refusing to evaluate {} '{}': {}, so others could run code as you.\n\
+             Fix its permissions, or pass --no-trust-check to evaluate it anyway",
+            what,
+            path.display(),
+            problem
+        );
+    }
+    crate::nix::warn(&format!(
+        "evaluating {} '{}' although {}",
+        what,
+        path.display(),
+        problem
+    ));
+    Ok(())
+}
+
+/// Check that no other user can change a local flake before evaluating it.
+///
+/// Called before anything imports flake.nix (reading its inputs, locking,
+/// evaluating outputs); each directory is only checked once.
+pub fn check_flake_dir(flake_dir: &Path) -> Result<()> {
+    let dir = flake_dir
+        .canonicalize()
+        .unwrap_or_else(|_| flake_dir.to_path_buf());
+    if !CHECKED.lock().unwrap().insert(dir.clone()) {
+        return Ok(());
+    }
+    check(&dir, "the flake in", false)?;
+    for file in ["flake.nix", "flake.lock", "default.nix"] {
+        check(&dir.join(file), "flake file", false)?;
+    }
+    Ok(())
+}
+
+/// Check a shebang script before running its `#!trix` directives.
+pub fn check_script(script: &Path) -> Result<()> {
+    check(script, "script", true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem() {
+        let me = 1000;
+        assert_eq!(problem(0o40755, me, me, false), None);
+        assert_eq!(problem(0o40755, 1001, me, false), None);
+        assert!(problem(0o40777, me, me, false)
+            .unwrap()
+            .contains("world-writable"));
+        assert!(problem(0o41777, 0, me, false).is_some());
+
+        assert_eq!(problem(0o100755, me, me, true), None);
+        assert_eq!(problem(0o100755, 0, me, true), None);
+        assert!(problem(0o100755, 1001, me, true)
+            .unwrap()
+            .contains("uid 1001"));
+        assert!(problem(0o100757, me, me, true).is_some());
+    }
+}
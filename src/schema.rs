@@ -0,0 +1,437 @@
+//! Versioned JSON output.
+//!
+//! Every `--json` report trix prints is an object whose first field is
+//! `"schema"`, the version of that command's output format. Adding fields
+//! does not change the version; removing, renaming or changing the meaning
+//! of a field does. `trix schema <command>` prints the JSON Schema.
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// A report with its schema version in front.
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+    schema: u32,
+    #[serde(flatten)]
+    report: &'a T,
+}
+
+/// A machine-readable output format.
+pub struct OutputSchema {
+    /// Command printing it, e.g. "flake check"
+    pub command: &'static str,
+    pub version: u32,
+    /// JSON Schema of the fields besides `schema`
+    properties: fn() -> Value,
+}
+
+pub const BUILDS_LIST: OutputSchema = OutputSchema {
+    command: "builds list",
+    version: 1,
+    properties: builds_list,
+};
+
+pub const FLAKE_CHECK: OutputSchema = OutputSchema {
+    command: "flake check",
+    version: 2,
+    properties: flake_check,
+};
+
+pub const FLAKE_GRAPH: OutputSchema = OutputSchema {
+    command: "flake graph",
+    version: 1,
+    properties: flake_graph,
+};
+
+pub const FLAKE_OUTDATED: OutputSchema = OutputSchema {
+    command: "flake outdated",
+    version: 1,
+    properties: flake_outdated,
+};
+
+pub const FLAKE_PLAN: OutputSchema = OutputSchema {
+    command: "flake plan",
+    version: 1,
+    properties: flake_plan,
+};
+
+pub const FLAKE_SHOW: OutputSchema = OutputSchema {
+    command: "flake show",
+    version: 1,
+    properties: flake_show,
+};
+
+pub const FLAKE_SHOW_COMPARE: OutputSchema = OutputSchema {
+    command: "flake show --compare",
+    version: 1,
+    properties: flake_show_compare,
+};
+
+pub const PROFILE_LIST: OutputSchema = OutputSchema {
+    command: "profile list",
+    version: 1,
+    properties: profile_list,
+};
+
+pub const REGISTRY_LIST: OutputSchema = OutputSchema {
+    command: "registry list",
+    version: 1,
+    properties: registry_list,
+};
+
+pub const SELF_TEST: OutputSchema = OutputSchema {
+    command: "self-test",
+    version: 1,
+    properties: self_test,
+};
+
+pub const WHY_DEPENDS: OutputSchema = OutputSchema {
+    command: "why-depends",
+    version: 1,
+    properties: why_depends,
+};
+
+pub const SCHEMAS: &[OutputSchema] = &[
+    BUILDS_LIST,
+    FLAKE_CHECK,
+    FLAKE_GRAPH,
+    FLAKE_OUTDATED,
+    FLAKE_PLAN,
+    FLAKE_SHOW,
+    FLAKE_SHOW_COMPARE,
+    PROFILE_LIST,
+    REGISTRY_LIST,
+    SELF_TEST,
+    WHY_DEPENDS,
+];
+
+impl OutputSchema {
+    /// Serialize `report`, which must serialize to an object, with the schema version.
+    pub fn to_json<T: Serialize>(&self, report: &T) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&Versioned {
+            schema: self.version,
+            report,
+        })?)
+    }
+
+    /// The JSON Schema document for this output.
+    pub fn json_schema(&self) -> Value {
+        let mut properties = (self.properties)();
+        let required: Vec<String> = std::iter::once("schema".to_string())
+            .chain(
+                properties
+                    .as_object()
+                    .into_iter()
+                    .flat_map(|p| p.keys().cloned()),
+            )
+            .collect();
+        if let Some(map) = properties.as_object_mut() {
+            map.insert("schema".to_string(), json!({ "const": self.version }));
+        }
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": format!("trix {} --json", self.command),
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+}
+
+/// Find the schema for `command` (e.g. "flake check").
+pub fn find(command: &str) -> Option<&'static OutputSchema> {
+    SCHEMAS.iter().find(|s| s.command == command)
+}
+
+fn string_set() -> Value {
+    json!({ "type": "array", "items": { "type": "string" }, "uniqueItems": true })
+}
+
+fn builds_list() -> Value {
+    json!({
+        "builds": {
+            "description": "Recorded builds, newest first",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "attr": { "type": "string" },
+                    "flakeDir": { "type": "string" },
+                    "drvPath": { "type": ["string", "null"] },
+                    "logPath": { "type": ["string", "null"] },
+                    "started": { "type": "integer" },
+                    "seconds": { "type": "number" },
+                    "ok": { "type": "boolean" }
+                },
+                "required": ["attr", "flakeDir", "drvPath", "logPath", "started", "seconds", "ok"]
+            }
+        }
+    })
+}
+
+fn flake_check() -> Value {
+    json!({
+        "passed": { "type": "integer" },
+        "failed": { "type": "integer" },
+        "failures": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "attr": { "type": "string" },
+                    "kind": { "enum": ["parse", "schema", "eval", "smoke", "build", "budget"] },
+                    "message": { "type": "string" }
+                },
+                "required": ["attr", "kind", "message"]
+            }
+        },
+        "timings": {
+            "description": "Time taken per evaluated, smoke tested or built output, slowest first",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "attr": { "type": "string" },
+                    "kind": { "enum": ["eval", "smoke", "build"] },
+                    "seconds": { "type": "number" }
+                },
+                "required": ["attr", "kind", "seconds"]
+            }
+        },
+        "warnings": {
+            "description": "Impure accesses found with --impure-warnings: \"impure\" ones made while evaluating attr (empty for the outputs function) by the file at location, \"impure-scan\" ones found at location, as file:line:col, by scanning the sources when evaluation couldn't be traced",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "attr": { "type": "string" },
+                    "location": { "type": "string" },
+                    "kind": { "enum": ["impure", "impure-scan"] },
+                    "message": { "type": "string" }
+                },
+                "required": ["attr", "location", "kind", "message"]
+            }
+        }
+    })
+}
+
+fn flake_graph() -> Value {
+    json!({
+        "outputs": {
+            "description": "Output attribute -> lock nodes whose source it references",
+            "type": "object",
+            "additionalProperties": string_set()
+        },
+        "inputs": {
+            "description": "Lock node -> lock nodes it takes as inputs",
+            "type": "object",
+            "additionalProperties": string_set()
+        }
+    })
+}
+
+fn flake_outdated() -> Value {
+    json!({
+        "inputs": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "input": { "type": "string" },
+                    "type": { "type": "string" },
+                    "ref": { "type": "string" },
+                    "lockedRev": { "type": "string" },
+                    "latestRev": { "type": "string" },
+                    "commitsBehind": { "type": "integer" },
+                    "daysBehind": { "type": "integer" },
+                    "error": { "type": "string" }
+                },
+                "required": ["input", "type"]
+            }
+        }
+    })
+}
+
+fn flake_plan() -> Value {
+    json!({
+        "shards": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "index": { "type": "integer" },
+                    "weight": { "type": "number" },
+                    "attrs": { "type": "array", "items": { "type": "string" } }
+                },
+                "required": ["index", "weight", "attrs"]
+            }
+        }
+    })
+}
+
+fn flake_show() -> Value {
+    json!({
+        "outputs": {
+            "description": "Output tree in the layout of `nix flake show --json`: nested attrsets ending in leaves with a `type`",
+            "type": "object",
+            "additionalProperties": { "type": "object" }
+        }
+    })
+}
+
+fn flake_show_compare() -> Value {
+    let leaves = |description: &str| {
+        json!({
+            "description": description,
+            "type": "object",
+            "additionalProperties": { "type": "object" }
+        })
+    };
+    json!({
+        "added": leaves("Attribute path -> leaf only in the current outputs"),
+        "removed": leaves("Attribute path -> leaf only in the snapshot"),
+        "changed": {
+            "description": "Attribute path -> leaf in the snapshot and now",
+            "type": "object",
+            "additionalProperties": {
+                "type": "object",
+                "properties": {
+                    "old": { "type": "object" },
+                    "new": { "type": "object" }
+                },
+                "required": ["old", "new"]
+            }
+        }
+    })
+}
+
+fn profile_list() -> Value {
+    json!({
+        "elements": {
+            "description": "Element name -> element, as in the profile's manifest.json",
+            "type": "object",
+            "additionalProperties": {
+                "type": "object",
+                "properties": {
+                    "attrPath": { "type": "string" },
+                    "originalUrl": { "type": "string" },
+                    "url": { "type": "string" },
+                    "outputs": {},
+                    "storePaths": { "type": "array", "items": { "type": "string" } },
+                    "active": { "type": "boolean" },
+                    "priority": { "type": "integer" }
+                },
+                "required": ["storePaths", "active", "priority"]
+            }
+        }
+    })
+}
+
+fn registry_list() -> Value {
+    json!({
+        "entries": {
+            "description": "Entries in lookup order; the first active one for a name resolves",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                    "source": { "type": "string" },
+                    "target": { "type": "string" },
+                    "to": { "type": "object" },
+                    "pinned": { "type": "boolean" },
+                    "active": { "type": "boolean" }
+                },
+                "required": ["name", "source", "target", "to", "pinned", "active"]
+            }
+        }
+    })
+}
+
+fn self_test() -> Value {
+    json!({
+        "trixVersion": { "type": "string" },
+        "os": { "type": "string" },
+        "arch": { "type": "string" },
+        "nixVersion": { "type": ["string", "null"] },
+        "nixCommand": { "type": "boolean" },
+        "daemon": { "type": "boolean" },
+        "experimentalFeatures": { "type": "array", "items": { "type": "string" } },
+        "legacyOnly": { "type": "boolean" },
+        "steps": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                    "ok": { "type": "boolean" },
+                    "durationMs": { "type": "integer" },
+                    "detail": { "type": "string" }
+                },
+                "required": ["name", "ok", "durationMs", "detail"]
+            }
+        }
+    })
+}
+
+fn why_depends() -> Value {
+    json!({
+        "package": { "type": "string" },
+        "dependency": { "type": "string" },
+        "paths": {
+            "description": "A shortest chain from package to dependency through each of the package's references that leads there",
+            "type": "array",
+            "items": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string" },
+                        "files": {
+                            "description": "Files in path mentioning the next path's hash",
+                            "type": "array",
+                            "items": { "type": "string" }
+                        }
+                    },
+                    "required": ["path", "files"]
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        #[derive(Serialize)]
+        struct Report {
+            passed: usize,
+            failed: usize,
+        }
+        let out = FLAKE_CHECK
+            .to_json(&Report {
+                passed: 3,
+                failed: 0,
+            })
+            .unwrap();
+        assert_eq!(
+            out,
+            "{\n  \"schema\": 2,\n  \"passed\": 3,\n  \"failed\": 0\n}"
+        );
+    }
+
+    #[test]
+    fn test_json_schema() {
+        for output in SCHEMAS {
+            let schema = output.json_schema();
+            assert_eq!(schema["properties"]["schema"]["const"], output.version);
+            assert_eq!(schema["required"][0], "schema");
+            assert!(find(output.command).is_some());
+        }
+        assert!(find("flake frobnicate").is_none());
+    }
+}
@@ -0,0 +1,172 @@
+//! Scratch space for temporary files and generated Nix expressions.
+//!
+//! Temporary files live under `$XDG_RUNTIME_DIR/trix` (or `/tmp/trix-$USER`,
+//! or `$TRIX_TEMP_DIR`) instead of loose in the temp directory, so anything
+//! left behind by a crashed run can be found and removed on the next startup.
+//!
+//! With `--keep-expr DIR`, every expression trix generates for nix is also
+//! written to DIR as a numbered `.nix` file for inspection.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Scratch entries older than this are considered leaked and removed.
+const STALE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Directory to keep generated expressions in (`--keep-expr`)
+static KEEP_EXPR_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+/// Number of expressions kept so far, used to order the files
+static KEPT_EXPRS: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the scratch directory, creating it if needed.
+pub fn scratch_dir() -> Result<PathBuf> {
+    let dir = crate::paths::temp_dir();
+
+    if !dir.exists() {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create scratch directory {}", dir.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        }
+    }
+
+    Ok(dir)
+}
+
+/// Create a temporary directory in the scratch directory.
+pub fn tempdir() -> Result<tempfile::TempDir> {
+    tempfile::Builder::new()
+        .prefix("trix-")
+        .tempdir_in(scratch_dir()?)
+        .context("Failed to create temporary directory")
+}
+
+/// Create a temporary file in the scratch directory.
+pub fn named_tempfile() -> Result<tempfile::NamedTempFile> {
+    tempfile::Builder::new()
+        .prefix("trix-")
+        .tempfile_in(scratch_dir()?)
+        .context("Failed to create temporary file")
+}
+
+/// Remove scratch entries left behind by earlier runs.
+///
+/// Only entries trix created (named `trix-*`) are touched, since
+/// `$TRIX_TEMP_DIR` may be shared. Errors are ignored; this is best-effort
+/// housekeeping.
+pub fn cleanup_stale() {
+    if let Ok(dir) = scratch_dir() {
+        remove_older_than(&dir, STALE_AGE, SystemTime::now());
+    }
+}
+
+fn remove_older_than(dir: &Path, max_age: Duration, now: SystemTime) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with("trix-") {
+            continue;
+        }
+        let modified = entry.metadata().and_then(|m| m.modified());
+        let is_stale = modified
+            .ok()
+            .and_then(|m| now.duration_since(m).ok())
+            .is_some_and(|age| age > max_age);
+        if !is_stale {
+            continue;
+        }
+
+        let path = entry.path();
+        tracing::debug!("Removing stale scratch entry {}", path.display());
+        let _ = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+    }
+}
+
+/// Keep generated expressions in `dir` (`--keep-expr`).
+pub fn set_keep_expr_dir(dir: Option<PathBuf>) {
+    *KEEP_EXPR_DIR.lock().unwrap() = dir;
+}
+
+/// Write a generated expression to the `--keep-expr` directory, if set.
+///
+/// `origin` describes where the expression came from and is written as a
+/// comment at the top of the file. Returns the path written.
+pub fn keep_expr(origin: &str, expr: &str) -> Option<PathBuf> {
+    let dir = KEEP_EXPR_DIR.lock().unwrap().clone()?;
+    keep_expr_in(&dir, origin, expr)
+}
+
+/// Write a generated expression to `dir`, as [`keep_expr`] does.
+fn keep_expr_in(dir: &Path, origin: &str, expr: &str) -> Option<PathBuf> {
+    let index = KEPT_EXPRS.fetch_add(1, Ordering::Relaxed) + 1;
+    let path = dir.join(format!("{:03}.nix", index));
+    let content = format!("# {}\n{}\n", origin.replace('\n', " "), expr.trim());
+
+    let written = fs::create_dir_all(dir).and_then(|_| fs::write(&path, content));
+    match written {
+        Ok(()) => {
+            eprintln!("trix: kept expression in {}", path.display());
+            Some(path)
+        }
+        Err(e) => {
+            eprintln!(
+                "warning: failed to keep expression in {}: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_older_than() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("trix-old"), "").unwrap();
+        fs::create_dir(dir.path().join("trix-old-dir")).unwrap();
+        fs::write(dir.path().join("trix-old-dir").join("file"), "").unwrap();
+        fs::write(dir.path().join("not-ours"), "").unwrap();
+
+        // Nothing is stale yet
+        remove_older_than(dir.path(), STALE_AGE, SystemTime::now());
+        assert!(dir.path().join("trix-old").exists());
+        assert!(dir.path().join("trix-old-dir").exists());
+
+        // Pretend two days have passed
+        let later = SystemTime::now() + Duration::from_secs(2 * 24 * 60 * 60);
+        remove_older_than(dir.path(), STALE_AGE, later);
+        assert!(!dir.path().join("trix-old").exists());
+        assert!(!dir.path().join("trix-old-dir").exists());
+        assert!(dir.path().join("not-ours").exists());
+    }
+
+    #[test]
+    fn test_keep_expr() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(keep_expr("nix-instantiate", "1 + 1").is_none());
+
+        let path = keep_expr_in(dir.path(), "nix-instantiate --eval", "1 + 1").unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "# nix-instantiate --eval\n1 + 1\n");
+        assert!(path.extension().is_some_and(|e| e == "nix"));
+    }
+}
@@ -49,14 +49,22 @@ pub fn detect_shebang(args: &[String]) -> Option<ShebangScript> {
         "run",
         "copy",
         "log",
+        "builds",
+        "debug-build",
         "repl",
         "why-depends",
         "shell",
         "flake",
         "profile",
         "registry",
+        "ws",
+        "home",
+        "darwin",
         "hash",
         "fmt",
+        "schema",
+        "self-test",
+        "setup",
         "completion",
         "-h",
         "--help",
@@ -65,19 +73,60 @@ pub fn detect_shebang(args: &[String]) -> Option<ShebangScript> {
     ];
 
     // Global flags that can appear before the script
-    let global_flags = ["-v", "--verbose"];
+    let global_flags = [
+        "-v",
+        "-vv",
+        "-vvv",
+        "--verbose",
+        "-q",
+        "--quiet",
+        "--gha",
+        "--explain-resolution",
+        "--legacy-only",
+        "--native-fetch",
+        "--accept-flake-config",
+        "--no-warn-dirty",
+        "--no-trust-check",
+        "--no-color",
+    ];
+
+    // Global flags that take values, and how many
+    let global_value_flags = [
+        ("--keep-expr", 1),
+        ("--override-flake", 2),
+        ("--mirror", 2),
+        ("--color", 1),
+        ("--retries", 1),
+    ];
 
     // Find the first non-flag argument that could be a script
     let mut script_index = None;
     let mut global_flag_indices = Vec::new();
 
-    for (i, arg) in args.iter().enumerate().skip(1) {
-        if subcommands.contains(&arg.as_str()) {
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        if subcommands.contains(&arg) {
             // Found a subcommand, not shebang mode
             return None;
         }
-        if global_flags.contains(&arg.as_str()) {
+        if global_flags.contains(&arg) {
             global_flag_indices.push(i);
+            i += 1;
+            continue;
+        }
+        // `--flag=value` carries its only value along
+        if let Some((flag, _)) = arg.split_once('=') {
+            if global_value_flags.contains(&(flag, 1)) {
+                global_flag_indices.push(i);
+                i += 1;
+                continue;
+            }
+        }
+        if let Some(&(_, values)) = global_value_flags.iter().find(|(flag, _)| *flag == arg) {
+            let end = (i + values + 1).min(args.len());
+            global_flag_indices.extend(i..end);
+            i = end;
             continue;
         }
         // This could be the script path
@@ -242,7 +291,7 @@ mod tests {
         writeln!(file, "#!/usr/bin/env trix").unwrap();
         writeln!(file, "#!trix develop -i python3").unwrap();
         writeln!(file, "#!trix --pure").unwrap();
-        writeln!(file, "").unwrap();
+        writeln!(file).unwrap();
         writeln!(file, "print('hello')").unwrap();
         file.flush().unwrap();
 
@@ -316,6 +365,39 @@ mod tests {
         assert_eq!(shebang.script_index, 2);
     }
 
+    #[test]
+    fn test_detect_shebang_with_flag_values() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "#!/usr/bin/env trix").unwrap();
+        writeln!(file, "#!trix develop -i bash").unwrap();
+        file.flush().unwrap();
+
+        let script = file.path().to_string_lossy().to_string();
+        let args: Vec<String> = [
+            "trix",
+            "--override-flake",
+            "nixpkgs",
+            "/src/nixpkgs",
+            "--retries",
+            "5",
+            "--color=never",
+            &script,
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let shebang = detect_shebang(&args).unwrap();
+        assert_eq!(shebang.global_args, args[1..7].to_vec());
+        assert_eq!(shebang.script_index, 7);
+
+        // A flag value that looks like a subcommand is not one
+        let args: Vec<String> = ["trix", "--keep-expr", "build", &script]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(detect_shebang(&args).unwrap().script_index, 3);
+    }
+
     #[test]
     fn test_detect_shebang_verbose_before_subcommand() {
         // Ensure -v before a subcommand doesn't trigger shebang detection
@@ -0,0 +1,158 @@
+//! Trust decisions for configuration that comes from a flake.
+//!
+//! Like nix, settings from a flake's `nixConfig` (and trix's project-local
+//! registries) are only applied once the user agrees. Decisions can be
+//! remembered per flake in `$XDG_STATE_HOME/trix/trust.json`;
+//! `--accept-flake-config` trusts everything, for CI.
+
+use crate::common::confirm;
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Flake directory -> setting -> value -> trusted
+type TrustStore = BTreeMap<String, BTreeMap<String, BTreeMap<String, bool>>>;
+
+/// (flake directory, setting, value)
+type TrustKey = (String, String, String);
+
+/// Whether `--accept-flake-config` is in effect
+static ACCEPT_FLAKE_CONFIG: AtomicBool = AtomicBool::new(false);
+
+/// Answers given during this run that were not made permanent.
+static SESSION: Lazy<Mutex<HashMap<TrustKey, bool>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Trust all flake configuration without asking (`--accept-flake-config`).
+pub fn set_accept_flake_config(accept: bool) {
+    ACCEPT_FLAKE_CONFIG.store(accept, Ordering::Relaxed);
+}
+
+/// Get the path of the persistent trust decisions.
+fn get_trust_path() -> Option<PathBuf> {
+    crate::paths::state_dir().map(|d| d.join("trust.json"))
+}
+
+fn load_store(path: &Path) -> TrustStore {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_store(path: &Path, store: &TrustStore) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(store)?)?;
+    Ok(())
+}
+
+/// A remembered decision for `setting = value` in `flake`.
+fn recorded(store: &TrustStore, flake: &str, setting: &str, value: &str) -> Option<bool> {
+    store.get(flake)?.get(setting)?.get(value).copied()
+}
+
+fn record(store: &mut TrustStore, flake: &str, setting: &str, value: &str, trusted: bool) {
+    store
+        .entry(flake.to_string())
+        .or_default()
+        .entry(setting.to_string())
+        .or_default()
+        .insert(value.to_string(), trusted);
+}
+
+/// Whether `setting = value`, requested by the flake in `flake_dir`, may be applied.
+///
+/// Uses `--accept-flake-config`, then a remembered decision, then asks
+/// if stdin is a terminal. Without a terminal the setting is ignored.
+pub fn allow(flake_dir: &Path, setting: &str, value: &str) -> bool {
+    if ACCEPT_FLAKE_CONFIG.load(Ordering::Relaxed) {
+        return true;
+    }
+
+    let flake = flake_dir
+        .canonicalize()
+        .unwrap_or_else(|_| flake_dir.to_path_buf())
+        .display()
+        .to_string();
+    let key = (flake.clone(), setting.to_string(), value.to_string());
+    // Held until the answer is recorded, so parallel evaluations asking the
+    // same question wait for the first one instead of prompting again
+    let mut session = SESSION.lock().unwrap();
+    if let Some(&trusted) = session.get(&key) {
+        return trusted;
+    }
+
+    let path = get_trust_path();
+    let mut store = path.as_deref().map(load_store).unwrap_or_default();
+    if let Some(trusted) = recorded(&store, &flake, setting, value) {
+        return trusted;
+    }
+
+    if !std::io::stdin().is_terminal() {
+        crate::nix::warn(&format!(
+            "ignoring untrusted flake configuration setting '{}' from {}.\n\
+             Pass '--accept-flake-config' to trust it",
+            setting, flake
+        ));
+        session.insert(key, false);
+        return false;
+    }
+
+    eprintln!("The flake at {} requests configuration:", flake);
+    let trusted = confirm(&format!(
+        "do you want to allow '{}' to be set to '{}'?",
+        setting, value
+    ));
+    if confirm("do you want to permanently remember this decision?") {
+        record(&mut store, &flake, setting, value, trusted);
+        if let Some(path) = &path {
+            if let Err(e) = save_store(path, &store) {
+                crate::nix::warn(&format!("failed to save trust decision: {}", e));
+            }
+        }
+    }
+
+    session.insert(key, trusted);
+    trusted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trix").join("trust.json");
+
+        let mut store = load_store(&path);
+        assert_eq!(
+            recorded(&store, "/src/proj", "extra-substituters", "a"),
+            None
+        );
+
+        record(&mut store, "/src/proj", "extra-substituters", "a", true);
+        record(&mut store, "/src/proj", "extra-substituters", "b", false);
+        save_store(&path, &store).unwrap();
+
+        let store = load_store(&path);
+        assert_eq!(
+            recorded(&store, "/src/proj", "extra-substituters", "a"),
+            Some(true)
+        );
+        assert_eq!(
+            recorded(&store, "/src/proj", "extra-substituters", "b"),
+            Some(false)
+        );
+        assert_eq!(
+            recorded(&store, "/src/other", "extra-substituters", "a"),
+            None
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Workspaces: several flakes driven together.
+//!
+//! A `trix-workspace.toml` lists member flake directories, relative to the
+//! file:
+//!
+//! ```toml
+//! members = ["app", "libs/common", "../infra"]
+//! ```
+//!
+//! `trix ws` commands find the nearest workspace file above the current
+//! directory and run once per member.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the workspace file.
+pub const WORKSPACE_FILE: &str = "trix-workspace.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkspaceFile {
+    members: Vec<String>,
+}
+
+/// A member flake of a workspace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    /// Name as written in the workspace file
+    pub name: String,
+    /// Flake directory
+    pub dir: PathBuf,
+}
+
+/// A loaded workspace.
+#[derive(Debug)]
+pub struct Workspace {
+    /// Directory containing the workspace file
+    pub root: PathBuf,
+    pub members: Vec<Member>,
+}
+
+/// Find the nearest workspace file in `start` or its ancestors.
+///
+/// Unlike flake lookup this does not stop at a git repository, since
+/// members are often separate repositories next to each other.
+pub fn find_workspace(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(WORKSPACE_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Parse a workspace file, checking that every member is a flake.
+pub fn load_workspace(path: &Path) -> Result<Workspace> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let file: WorkspaceFile =
+        toml::from_str(&content).with_context(|| format!("Invalid {}", path.display()))?;
+    let root = path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let mut members = Vec::new();
+    for name in file.members {
+        let dir = root.join(&name);
+        if !dir.join("flake.nix").exists() {
+            anyhow::bail!(
+                "Workspace member '{}' has no flake.nix (looked in {})",
+                name,
+                dir.display()
+            );
+        }
+        if members.iter().any(|m: &Member| m.name == name) {
+            anyhow::bail!("Workspace member '{}' is listed twice", name);
+        }
+        members.push(Member { name, dir });
+    }
+    if members.is_empty() {
+        anyhow::bail!("{} lists no members", path.display());
+    }
+
+    Ok(Workspace { root, members })
+}
+
+/// Load the workspace containing the current directory.
+pub fn current_workspace() -> Result<Workspace> {
+    let cwd = std::env::current_dir().context("Could not get current directory")?;
+    let path = find_workspace(&cwd).with_context(|| {
+        format!(
+            "No {} found in {} or its parent directories",
+            WORKSPACE_FILE,
+            cwd.display()
+        )
+    })?;
+    load_workspace(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        for member in ["app", "libs/common"] {
+            fs::create_dir_all(dir.path().join(member)).unwrap();
+            fs::write(dir.path().join(member).join("flake.nix"), "{ }").unwrap();
+        }
+        let path = dir.path().join(WORKSPACE_FILE);
+        fs::write(&path, "members = [\"app\", \"libs/common\"]\n").unwrap();
+
+        let nested = dir.path().join("libs/common");
+        assert_eq!(find_workspace(&nested), Some(path.clone()));
+
+        let ws = load_workspace(&path).unwrap();
+        assert_eq!(ws.root, dir.path());
+        assert_eq!(
+            ws.members,
+            vec![
+                Member {
+                    name: "app".to_string(),
+                    dir: dir.path().join("app"),
+                },
+                Member {
+                    name: "libs/common".to_string(),
+                    dir: dir.path().join("libs/common"),
+                },
+            ]
+        );
+
+        fs::write(&path, "members = [\"app\", \"missing\"]\n").unwrap();
+        let err = load_workspace(&path).unwrap_err();
+        assert!(err.to_string().contains("'missing'"));
+    }
+}
@@ -65,6 +65,104 @@ fn test_eval_basic() {
     assert.success().stdout(predicate::str::contains("42"));
 }
 
+#[test]
+fn test_eval_exotic_directory() {
+    let parent = tempdir().unwrap();
+    let dir = parent.path().join(r#"my flake's "${dir}" café"#);
+    fs::create_dir(&dir).unwrap();
+    fs::write(
+        dir.join("flake.nix"),
+        r#"{
+  outputs = { self }: {
+    where = builtins.baseNameOf self.outPath;
+  };
+}"#,
+    )
+    .unwrap();
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("trix");
+    let assert = cmd
+        .args(["eval", ".#where", "--raw"])
+        .current_dir(&dir)
+        .assert();
+
+    let output = assert.get_output();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && (stderr.contains("not found") || stderr.contains("No such file"))
+    {
+        eprintln!("Skipping test_eval_exotic_directory: nix command not found");
+        return;
+    }
+
+    assert
+        .success()
+        .stdout(predicate::eq("my flake's \"${dir}\" café\n"));
+}
+
+#[test]
+fn test_eval_attr_names() {
+    let dir = tempdir().unwrap();
+    let flake_nix = dir.path().join("flake.nix");
+    fs::write(
+        &flake_nix,
+        r#"{
+  outputs = { self }: {
+    lib = { a = 1; b = throw "not evaluated"; };
+  };
+}"#,
+    )
+    .unwrap();
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("trix");
+    let assert = cmd
+        .args(["eval", ".#lib", "--attr-names"])
+        .current_dir(dir.path())
+        .assert();
+
+    let output = assert.get_output();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && (stderr.contains("not found") || stderr.contains("No such file"))
+    {
+        eprintln!("Skipping test_eval_attr_names: nix command not found");
+        return;
+    }
+
+    assert.success().stdout(predicate::eq("a\nb\n"));
+}
+
+#[test]
+fn test_eval_file_attr() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("default.nix"),
+        r#"{ greeting ? "hi" }: { pkgs.hello.version = "2.12"; inherit greeting; }"#,
+    )
+    .unwrap();
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("trix");
+    let assert = cmd
+        .args([
+            "eval",
+            "--file",
+            "default.nix",
+            "--attr",
+            "pkgs.hello.version",
+            "--raw",
+        ])
+        .current_dir(dir.path())
+        .assert();
+
+    let output = assert.get_output();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && (stderr.contains("not found") || stderr.contains("No such file"))
+    {
+        eprintln!("Skipping test_eval_file_attr: nix command not found");
+        return;
+    }
+
+    assert.success().stdout(predicate::eq("2.12\n"));
+}
+
 #[test]
 fn test_lock_basic() {
     let dir = tempdir().unwrap();
@@ -92,6 +190,34 @@ fn test_lock_basic() {
     assert!(dir.path().join("flake.lock").exists());
 }
 
+#[test]
+fn test_profile_apply_dry_run_leaves_flake_alone() {
+    let dir = tempdir().unwrap();
+    let home = tempdir().unwrap();
+    fs::write(
+        dir.path().join("flake.nix"),
+        r#"{
+  inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";
+  outputs = { self, nixpkgs }: { trixProfiles = { }; };
+}"#,
+    )
+    .unwrap();
+
+    // Whether or not evaluation gets anywhere, nothing may be written
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("trix");
+    cmd.args(["profile", "apply", "--dry-run"])
+        .current_dir(dir.path())
+        .env("HOME", home.path())
+        .output()
+        .unwrap();
+
+    let entries: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, vec![std::ffi::OsString::from("flake.nix")]);
+}
+
 #[test]
 fn test_fmt_help() {
     let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("trix");
@@ -107,3 +107,40 @@ fn test_fmt_with_flake() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_fmt_with_formatter() -> Result<()> {
+    let dir = tempdir()?;
+    let dir_path = dir.path();
+
+    let file_to_format = dir_path.join("file.txt");
+    fs::write(&file_to_format, "content")?;
+
+    // A project without a formatter output, and a separate flake providing one
+    fs::write(dir_path.join("flake.nix"), "{ outputs = { self }: { }; }")?;
+    let tools = dir_path.join("tools");
+    fs::create_dir(&tools)?;
+    let tools_flake = r#"
+        {
+          outputs = { self }: {
+            packages.${builtins.currentSystem}.fmt = (import <nixpkgs> {}).writeShellScriptBin "fmt-explicit" ''
+              for file in "$@"; do
+                echo "formatted-by-explicit" >> "$file"
+              done
+            '';
+          };
+        }
+    "#;
+    fs::write(tools.join("flake.nix"), tools_flake)?;
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("trix");
+    cmd.current_dir(dir_path)
+        .args(["fmt", "--formatter", "./tools#fmt", "--", "file.txt"]);
+
+    cmd.assert().success();
+
+    let content = fs::read_to_string(file_to_format)?;
+    assert!(content.contains("formatted-by-explicit"));
+
+    Ok(())
+}